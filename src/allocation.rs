@@ -0,0 +1,186 @@
+/*
+ * Device allocations the host registers before a launch. Once any is registered, every global and
+ * SMEM access of a launch has to fall inside one that allows it, anything else is a memory
+ * violation reported with the nearest allocation. Launches take a snapshot when they start, the
+ * kernarg buffer is readable without being registered. RAW_MEMORY=1 keeps dereferencing whatever
+ * address a kernel computes, like launches without registered allocations do.
+ */
+use crate::capture::kernarg_size;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+pub const READ: u32 = 1;
+pub const WRITE: u32 = 2;
+
+/* remu_register_allocation result for an empty or overlapping allocation or unknown permissions */
+pub const BAD_ALLOCATION: i32 = 12;
+
+lazy_static::lazy_static! {
+    pub static ref RAW_MEMORY: bool = std::env::var("RAW_MEMORY").map(|v| v == "1").unwrap_or(false);
+    static ref REGISTERED: Mutex<Allocations> = Mutex::new(Allocations::default());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub base: u64,
+    pub size: u64,
+    pub perms: u32,
+}
+
+impl Allocation {
+    fn end(&self) -> u64 {
+        self.base + self.size
+    }
+
+    /* how far from the base addr is, negative before it */
+    pub fn offset(&self, addr: u64) -> i128 {
+        addr as i128 - self.base as i128
+    }
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = if self.perms & READ != 0 { "r" } else { "-" };
+        let w = if self.perms & WRITE != 0 { "w" } else { "-" };
+        write!(f, "0x{:x}+0x{:x} ({r}{w})", self.base, self.size)
+    }
+}
+
+/* non-overlapping allocations by base */
+#[derive(Debug, Clone, Default)]
+pub struct Allocations {
+    map: BTreeMap<u64, Allocation>,
+}
+
+impl Allocations {
+    pub fn insert(&mut self, a: Allocation) -> Result<(), i32> {
+        if a.size == 0 || a.perms & !(READ | WRITE) != 0 || a.base.checked_add(a.size).is_none() {
+            return Err(BAD_ALLOCATION);
+        }
+        // the last one starting before the end is the only one that can overlap
+        let below = self.map.range(..a.end()).next_back();
+        if below.is_some_and(|(_, b)| a.base < b.end()) {
+            return Err(BAD_ALLOCATION);
+        }
+        self.map.insert(a.base, a);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, base: u64) -> Result<(), i32> {
+        self.map.remove(&base).map(|_| ()).ok_or(BAD_ALLOCATION)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /* [addr, addr+len) sits in one allocation that allows reading, or writing for a store */
+    pub fn allows(&self, addr: u64, len: u64, store: bool) -> bool {
+        let need = if store { WRITE } else { READ };
+        let a = self.map.range(..=addr).next_back().map(|(_, a)| a);
+        a.is_some_and(|a| a.perms & need != 0 && addr.saturating_add(len) <= a.end())
+    }
+
+    /* the allocation addr is in, or the closest one */
+    pub fn nearest(&self, addr: u64) -> Option<Allocation> {
+        let below = self.map.range(..=addr).next_back().map(|(_, a)| *a);
+        let above = self.map.range(addr..).next().map(|(_, a)| *a);
+        match (below, above) {
+            (Some(b), _) if addr < b.end() => Some(b),
+            (Some(b), Some(a)) => match addr - b.end() < a.base - addr {
+                true => Some(b),
+                false => Some(a),
+            },
+            (b, a) => b.or(a),
+        }
+    }
+}
+
+pub fn register(a: Allocation) -> Result<(), i32> {
+    REGISTERED.lock().unwrap().insert(a)
+}
+
+pub fn unregister(base: u64) -> Result<(), i32> {
+    REGISTERED.lock().unwrap().remove(base)
+}
+
+/* what a launch checks its accesses against, None when nothing is checked */
+pub fn for_launch(args: *const u64, code: &[u32]) -> Option<Arc<Allocations>> {
+    let mut allocations = REGISTERED.lock().unwrap().clone();
+    if *RAW_MEMORY || allocations.is_empty() {
+        return None;
+    }
+    let size = kernarg_size(code);
+    if !args.is_null() && size != 0 && !allocations.allows(args as u64, size, false) {
+        let kernargs = Allocation {
+            base: args as u64,
+            size,
+            perms: READ,
+        };
+        // a kernarg buffer inside a registered allocation is already covered by it
+        let _ = allocations.insert(kernargs);
+    }
+    Some(Arc::new(allocations))
+}
+
+#[cfg(test)]
+mod test_allocation {
+    use super::*;
+
+    fn alloc(base: u64, size: u64, perms: u32) -> Allocation {
+        Allocation { base, size, perms }
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut allocs = Allocations::default();
+        allocs.insert(alloc(0x1000, 0x100, READ | WRITE)).unwrap();
+        allocs.insert(alloc(0x1100, 0x10, READ)).unwrap();
+        assert_eq!(
+            allocs.insert(alloc(0x10f0, 0x20, READ)),
+            Err(BAD_ALLOCATION)
+        );
+        assert_eq!(
+            allocs.insert(alloc(0x800, 0x801, READ)),
+            Err(BAD_ALLOCATION)
+        );
+        assert_eq!(allocs.insert(alloc(0x2000, 0, READ)), Err(BAD_ALLOCATION));
+        assert_eq!(allocs.insert(alloc(0x2000, 4, 4)), Err(BAD_ALLOCATION));
+        allocs.insert(alloc(0x800, 0x800, 0)).unwrap();
+        assert_eq!(allocs.remove(0x900), Err(BAD_ALLOCATION));
+        allocs.remove(0x800).unwrap();
+    }
+
+    #[test]
+    fn test_allows() {
+        let mut allocs = Allocations::default();
+        allocs.insert(alloc(0x1000, 0x100, READ | WRITE)).unwrap();
+        allocs.insert(alloc(0x1100, 0x10, READ)).unwrap();
+        assert!(allocs.allows(0x1000, 4, true));
+        assert!(allocs.allows(0x10fc, 4, false));
+        // one past the end, across two allocations and into a read-only one
+        assert!(!allocs.allows(0x1110, 4, false));
+        assert!(!allocs.allows(0x10fc, 8, false));
+        assert!(!allocs.allows(0x1100, 4, true));
+        assert!(!allocs.allows(0xffc, 4, false));
+    }
+
+    #[test]
+    fn test_nearest() {
+        let mut allocs = Allocations::default();
+        assert_eq!(allocs.nearest(0x1000), None);
+        let (a, b) = (
+            alloc(0x1000, 0x100, READ | WRITE),
+            alloc(0x2000, 0x10, READ),
+        );
+        allocs.insert(a).unwrap();
+        allocs.insert(b).unwrap();
+        assert_eq!(allocs.nearest(0x1100), Some(a));
+        assert_eq!(allocs.nearest(0x1ff0), Some(b));
+        assert_eq!(allocs.nearest(0x2004), Some(b));
+        assert_eq!(allocs.nearest(0x10), Some(a));
+        assert_eq!(a.offset(0xff8), -8);
+        assert_eq!(b.to_string(), "0x2000+0x10 (r-)");
+    }
+}
@@ -0,0 +1,417 @@
+/*
+ * CAPTURE=<dir> writes one self-contained bundle per run_asm launch: the code object, launch dims,
+ * the kernarg blob, every page of host memory the kernel touched as it was before the launch and
+ * the final contents of every byte range it stored to. remu_replay re-executes a bundle in fresh
+ * memory and diffs those stores.
+ */
+use crate::decode::{encoding, instr_len, Encoding};
+use crate::utils::sign_ext;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 8] = b"REMUCAP1";
+const PAGE: u64 = 4096;
+/* run_asm result for a replay whose memory differs from the capture */
+pub const MISMATCH: i32 = 3;
+/* remu_replay result for a file that isn't a capture bundle */
+pub const BAD_BUNDLE: i32 = 4;
+
+lazy_static::lazy_static! {
+    pub static ref CAPTURE: Option<PathBuf> = std::env::var("CAPTURE").ok().map(PathBuf::from);
+    /* buffers the host registered with remu_register_buffer, addr -> size */
+    pub static ref REGISTERED: Mutex<BTreeMap<u64, u64>> = Mutex::new(BTreeMap::new());
+}
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static LAUNCHES: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static STATE: RefCell<Option<Capture>> = const { RefCell::new(None) };
+}
+
+#[derive(Default)]
+struct Capture {
+    /* page address -> contents before the launch first touched it */
+    pages: BTreeMap<u64, Vec<u8>>,
+    /* addr -> len of every store */
+    stores: BTreeMap<u64, u64>,
+    kernargs: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub addr: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+    pub lib: Vec<u8>,
+    pub dims: [u32; 6],
+    pub args: u64,
+    pub kernargs: Vec<u8>,
+    /* bytes of LDS the launch added to the kernel's own */
+    pub dynamic_lds: u32,
+    /* memory before the launch */
+    pub regions: Vec<Region>,
+    /* what the launch stored */
+    pub outputs: Vec<Region>,
+}
+
+/* launches on this thread record the memory they touch */
+pub fn active() -> bool {
+    ACTIVE.load(Relaxed) != 0 && STATE.with(|s| s.borrow().is_some())
+}
+
+unsafe fn read(addr: u64, len: u64) -> Vec<u8> {
+    std::slice::from_raw_parts(addr as *const u8, len as usize).to_vec()
+}
+
+/* snapshot the pages of [addr, addr+len) before the kernel reads or writes them */
+pub fn touch(addr: u64, len: u64, store: bool) {
+    if ACTIVE.load(Relaxed) == 0 {
+        return;
+    }
+    STATE.with(|s| {
+        if let Some(capture) = s.borrow_mut().as_mut() {
+            let end = addr + len.max(1);
+            for page in (addr & !(PAGE - 1)..end).step_by(PAGE as usize) {
+                capture
+                    .pages
+                    .entry(page)
+                    .or_insert_with(|| unsafe { read(page, PAGE) });
+            }
+            if store {
+                let end = capture.stores.entry(addr).or_insert(0);
+                *end = (*end).max(len);
+            }
+        }
+    });
+}
+
+/* bytes of kernargs the kernel loads through s[0:1] */
+pub fn kernarg_size(code: &[u32]) -> u64 {
+    let mut pc = 0;
+    let mut size = 0;
+    while pc < code.len() {
+        let instr = ((*code.get(pc + 1).unwrap_or(&0) as u64) << 32) | code[pc] as u64;
+        if encoding(code[pc]) == Encoding::Smem && instr & 0x3f == 0 {
+            let (op, offset) = ((instr >> 18) & 0xff, sign_ext((instr >> 32) & 0x1fffff, 21));
+            if op <= 4 && offset >= 0 {
+                size = size.max(offset as u64 + (4 << op));
+            }
+        }
+        pc += instr_len(&code[pc..]);
+    }
+    size
+}
+
+pub fn kernargs(args: *const u64, code: &[u32]) {
+    if !active() || args.is_null() {
+        return;
+    }
+    let blob = unsafe { read(args as u64, kernarg_size(code)) };
+    STATE.with(|s| s.borrow_mut().as_mut().unwrap().kernargs = blob);
+}
+
+/* run a launch with capture on and write its bundle to dir */
+pub fn record(
+    dir: &Path,
+    lib: &[u8],
+    dims: [u32; 6],
+    args: *const u64,
+    dynamic_lds: u32,
+    launch: impl FnOnce() -> i32,
+) -> i32 {
+    STATE.with(|s| *s.borrow_mut() = Some(Capture::default()));
+    ACTIVE.fetch_add(1, Relaxed);
+    for (addr, size) in REGISTERED.lock().unwrap().clone() {
+        touch(addr, size, false);
+    }
+    let ret = launch();
+    ACTIVE.fetch_sub(1, Relaxed);
+    let capture = STATE.with(|s| s.borrow_mut().take()).unwrap();
+
+    let mut regions: Vec<Region> = vec![];
+    for (page, data) in capture.pages {
+        match regions.last_mut() {
+            Some(r) if r.addr + r.data.len() as u64 == page => r.data.extend(data),
+            _ => regions.push(Region { addr: page, data }),
+        }
+    }
+    let mut stores: Vec<(u64, u64)> = vec![];
+    for (addr, len) in capture.stores {
+        match stores.last_mut() {
+            Some((_, end)) if addr <= *end => *end = (*end).max(addr + len),
+            _ => stores.push((addr, addr + len)),
+        }
+    }
+    let outputs = stores
+        .into_iter()
+        .map(|(addr, end)| Region {
+            addr,
+            data: unsafe { read(addr, end - addr) },
+        })
+        .collect();
+    let bundle = Bundle {
+        lib: lib.to_vec(),
+        dims,
+        args: args as u64,
+        kernargs: capture.kernargs,
+        dynamic_lds,
+        regions,
+        outputs,
+    };
+    let path = dir.join(format!(
+        "launch_{}_{}.remu",
+        std::process::id(),
+        LAUNCHES.fetch_add(1, Relaxed)
+    ));
+    match fs::create_dir_all(dir).and_then(|_| fs::write(&path, bundle.to_bytes())) {
+        Ok(_) => println!("[remu] captured launch to {}", path.display()),
+        Err(e) => println!("[remu] capture failed: {e}"),
+    }
+    ret
+}
+
+impl Bundle {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = MAGIC.to_vec();
+        let bytes = |b: &mut Vec<u8>, data: &[u8]| {
+            b.extend((data.len() as u64).to_le_bytes());
+            b.extend(data);
+        };
+        bytes(&mut b, &self.lib);
+        self.dims.iter().for_each(|d| b.extend(d.to_le_bytes()));
+        b.extend(self.args.to_le_bytes());
+        bytes(&mut b, &self.kernargs);
+        for regions in [&self.regions, &self.outputs] {
+            b.extend((regions.len() as u64).to_le_bytes());
+            for r in regions {
+                b.extend(r.addr.to_le_bytes());
+                bytes(&mut b, &r.data);
+            }
+        }
+        b.extend(self.dynamic_lds.to_le_bytes());
+        b
+    }
+
+    pub fn from_bytes(b: &[u8]) -> Option<Bundle> {
+        let mut r = Reader { b, off: 0 };
+        if r.take(8)? != MAGIC {
+            return None;
+        }
+        let lib = r.bytes()?;
+        let mut dims = [0; 6];
+        for d in dims.iter_mut() {
+            *d = r.u32()?;
+        }
+        let args = r.u64()?;
+        let kernargs = r.bytes()?;
+        let regions = r.regions()?;
+        let outputs = r.regions()?;
+        // bundles from before dynamic LDS end here
+        let dynamic_lds = match r.off == b.len() {
+            true => 0,
+            false => r.u32()?,
+        };
+        Some(Bundle {
+            lib,
+            dims,
+            args,
+            kernargs,
+            dynamic_lds,
+            regions,
+            outputs,
+        })
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    pub b: &'a [u8],
+    pub off: usize,
+}
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let s = self.b.get(self.off..self.off.checked_add(n)?)?;
+        self.off += n;
+        Some(s)
+    }
+    pub fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+    pub fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+    fn bytes(&mut self) -> Option<Vec<u8>> {
+        let len = self.u64()?;
+        Some(self.take(len as usize)?.to_vec())
+    }
+    fn regions(&mut self) -> Option<Vec<Region>> {
+        (0..self.u64()?)
+            .map(|_| {
+                Some(Region {
+                    addr: self.u64()?,
+                    data: self.bytes()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/* re-run a bundle with every region copied to fresh memory, kernarg pointers into them are relocated */
+pub fn replay(
+    bundle: &Bundle,
+    launch: impl FnOnce(&[u8], [u32; 6], *const u64, u32) -> i32,
+) -> i32 {
+    let mut mems = bundle
+        .regions
+        .iter()
+        .map(|r| vec![0u64; (r.data.len() + PAGE as usize) / 8])
+        .collect::<Vec<_>>();
+    let bases = bundle
+        .regions
+        .iter()
+        .zip(mems.iter_mut())
+        .map(|(r, mem)| {
+            let base = (mem.as_mut_ptr() as u64 + PAGE - 1) & !(PAGE - 1);
+            unsafe {
+                std::ptr::copy_nonoverlapping(r.data.as_ptr(), base as *mut u8, r.data.len())
+            };
+            base
+        })
+        .collect::<Vec<_>>();
+    let relocate = |addr: u64| {
+        let hit = bundle
+            .regions
+            .iter()
+            .zip(&bases)
+            .find(|(r, _)| (r.addr..r.addr + r.data.len() as u64).contains(&addr));
+        match hit {
+            Some((r, base)) => base + (addr - r.addr),
+            None => addr,
+        }
+    };
+    let mut kernargs = vec![0u64; bundle.kernargs.len().div_ceil(8)];
+    for (i, w) in bundle.kernargs.chunks(8).enumerate() {
+        let mut word = [0; 8];
+        word[..w.len()].copy_from_slice(w);
+        kernargs[i] = relocate(u64::from_le_bytes(word));
+    }
+    let args = match kernargs.is_empty() {
+        true => relocate(bundle.args) as *const u64,
+        false => kernargs.as_ptr(),
+    };
+    let ret = launch(&bundle.lib, bundle.dims, args, bundle.dynamic_lds);
+    if ret != 0 {
+        return ret;
+    }
+
+    let mut mismatches = 0;
+    for out in &bundle.outputs {
+        let got = unsafe { read(relocate(out.addr), out.data.len() as u64) };
+        for (i, (want, got)) in out.data.iter().zip(got).enumerate() {
+            if *want != got {
+                if mismatches < 8 {
+                    println!(
+                        "[remu] replay: 0x{:x} expected 0x{want:02x} got 0x{got:02x}",
+                        out.addr + i as u64,
+                    );
+                }
+                mismatches += 1;
+            }
+        }
+    }
+    match mismatches {
+        0 => 0,
+        n => {
+            println!("[remu] replay: {n} byte(s) differ from the capture");
+            MISMATCH
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_capture {
+    use super::*;
+    use crate::utils::END_PRG;
+
+    #[test]
+    fn test_kernarg_size() {
+        // s_load_b64 s[2:3], s[0:1], null
+        assert_eq!(kernarg_size(&[0xF4040080, 0xF8000000, END_PRG]), 8);
+        // s_load_b32 s4, s[0:1], 0x10 after a literal that looks like s_load
+        let code = [0x7E0202FF, 0xF4040080, 0xF4000100, 0xF8000010, END_PRG];
+        assert_eq!(kernarg_size(&code), 0x14);
+    }
+
+    #[test]
+    fn test_bundle_bytes() {
+        let bundle = Bundle {
+            lib: vec![0x00, 0x00, 0xb0, 0xbf],
+            dims: [2, 1, 1, 32, 1, 1],
+            args: 0x1000,
+            kernargs: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            dynamic_lds: 0x800,
+            regions: vec![Region {
+                addr: 0x2000,
+                data: vec![0; 8],
+            }],
+            outputs: vec![Region {
+                addr: 0x2004,
+                data: vec![1; 4],
+            }],
+        };
+        let bytes = bundle.to_bytes();
+        assert_eq!(Bundle::from_bytes(&bytes), Some(bundle));
+        assert_eq!(Bundle::from_bytes(&bytes[..bytes.len() - 1]), None);
+        // bundles written before dynamic LDS was recorded
+        let old = Bundle::from_bytes(&bytes[..bytes.len() - 4]).unwrap();
+        assert_eq!(old.dynamic_lds, 0);
+        assert_eq!(Bundle::from_bytes(b"not a bundle"), None);
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let kernel: Vec<u32> = vec![
+            0xF4040080, // s_load_b64 s[2:3], s[0:1], null
+            0xF8000000, 0xBF89FC07, // s_waitcnt lgkmcnt(0)
+            0x7E02020F, // v_mov_b32 v1, s15
+            0x30040282, // v_lshlrev_b32 v2, 2, v1
+            0xDC6A0000, // global_store_b32 v2, v1, s[2:3]
+            0x00020102, END_PRG,
+        ];
+        let lib = kernel
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<u8>>();
+        let mut out = vec![u32::MAX; 16];
+        let args = [out.as_mut_ptr() as u64];
+        let dims = [16, 1, 1, 1, 1, 1];
+        let dir = std::env::temp_dir().join(format!("remu_capture_{}", std::process::id()));
+        let ret = record(&dir, &lib, dims, args.as_ptr(), 0, || {
+            crate::launch(&lib, dims, args.as_ptr(), 0)
+        });
+        assert_eq!(ret, 0);
+        assert_eq!(out, (0..16).collect::<Vec<u32>>());
+
+        let path = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        let mut bundle = Bundle::from_bytes(&fs::read(&path).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(bundle.kernargs, args[0].to_le_bytes());
+        // the replay runs in its own memory
+        out.fill(u32::MAX);
+        assert_eq!(replay(&bundle, crate::launch), 0);
+        assert_eq!(out, vec![u32::MAX; 16]);
+
+        assert_eq!(bundle.outputs.len(), 1);
+        assert_eq!(bundle.outputs[0].addr, out.as_ptr() as u64);
+        assert_eq!(bundle.outputs[0].data.len(), 64);
+        bundle.outputs[0].data[4] ^= 1;
+        assert_eq!(replay(&bundle, crate::launch), MISMATCH);
+    }
+}
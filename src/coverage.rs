@@ -0,0 +1,234 @@
+/*
+ * COVERAGE=<file> counts every encoding and opcode launches execute, which of them remu doesn't
+ * implement yet and the abs/neg/opsel/clamp combinations VOP3 and VOP3P ops ran with. An
+ * unimplemented instruction is skipped instead of ending the launch so one run finds all of them,
+ * the launch still fails. The table in the file is merged with every launch, across processes too.
+ */
+use crate::decode::{encoding, Encoding};
+use crate::mnemonic::{mnemonic, opcode};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    pub static ref COVERAGE: Option<PathBuf> = std::env::var("COVERAGE").ok().map(PathBuf::from);
+    /* launches on other threads merge into the same file */
+    static ref TABLE: Mutex<()> = Mutex::new(());
+}
+
+const HEADER: &str = "# encoding\topcode\tmnemonic\texecuted\tunimplemented\tmodifiers";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Row {
+    pub mnemonic: String,
+    /* waves that ran it */
+    pub executed: u64,
+    pub unimplemented: u64,
+    /* modifier combination -> times it ran with it */
+    pub modifiers: BTreeMap<String, u64>,
+}
+
+/* rows by lowercase encoding name and opcode */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub rows: BTreeMap<(String, u32), Row>,
+}
+
+/* the modifiers set on a VOP3 or VOP3P instruction, "none" for anything else */
+pub fn modifiers(stream: &[u32]) -> String {
+    let instr = ((*stream.get(1).unwrap_or(&0) as u64) << 32) | stream[0] as u64;
+    let mut mods = vec![];
+    let mut field = |name: &str, value: u64| {
+        if value != 0 {
+            mods.push(format!("{name}={value}"));
+        }
+    };
+    match encoding(stream[0]) {
+        Encoding::Vop3 => {
+            // VOP3SD ops keep their sdst where abs and opsel would be
+            if !matches!(opcode(stream), 288 | 289 | 764..=769) {
+                field("abs", (instr >> 8) & 0x7);
+                field("opsel", (instr >> 11) & 0xf);
+            }
+            field("neg", (instr >> 61) & 0x7);
+            field("clamp", (instr >> 15) & 0x1);
+        }
+        Encoding::Vop3p => {
+            field("neg_hi", (instr >> 8) & 0x7);
+            field("opsel", (instr >> 11) & 0x7);
+            field(
+                "opsel_hi",
+                ((instr >> 59) & 0x3) | (((instr >> 14) & 0x1) << 2),
+            );
+            field("neg", (instr >> 61) & 0x7);
+            field("clamp", (instr >> 15) & 0x1);
+        }
+        _ => {}
+    }
+    match mods.is_empty() {
+        true => "none".to_string(),
+        false => mods.join(","),
+    }
+}
+
+impl Coverage {
+    fn row(&mut self, stream: &[u32]) -> &mut Row {
+        let enc = format!("{:?}", encoding(stream[0])).to_lowercase();
+        self.rows
+            .entry((enc, opcode(stream)))
+            .or_insert_with(|| Row {
+                mnemonic: mnemonic(stream),
+                ..Default::default()
+            })
+    }
+
+    pub fn record(&mut self, stream: &[u32]) {
+        let mods = modifiers(stream);
+        let row = self.row(stream);
+        row.executed += 1;
+        *row.modifiers.entry(mods).or_default() += 1;
+    }
+
+    pub fn unimplemented(&mut self, stream: &[u32]) {
+        self.row(stream).unimplemented += 1;
+    }
+
+    pub fn merge(&mut self, other: Coverage) {
+        for (key, row) in other.rows {
+            let mine = self.rows.entry(key).or_insert_with(|| Row {
+                mnemonic: row.mnemonic.clone(),
+                ..Default::default()
+            });
+            mine.executed += row.executed;
+            mine.unimplemented += row.unimplemented;
+            for (mods, n) in row.modifiers {
+                *mine.modifiers.entry(mods).or_default() += n;
+            }
+        }
+    }
+
+    /* mnemonics of the unimplemented instructions */
+    pub fn missing(&self) -> Vec<&str> {
+        let rows = self.rows.values().filter(|r| r.unimplemented != 0);
+        rows.map(|r| r.mnemonic.as_str()).collect()
+    }
+
+    /* one tab separated row per opcode, the most hit unimplemented ones first */
+    pub fn to_table(&self) -> String {
+        let mut rows = self.rows.iter().collect::<Vec<_>>();
+        rows.sort_by_key(|(key, r)| (std::cmp::Reverse((r.unimplemented, r.executed)), *key));
+        let mut table = format!("{HEADER}\n");
+        for ((enc, op), r) in rows {
+            let mods = r.modifiers.iter().map(|(m, n)| format!("{m}:{n}"));
+            table += &format!(
+                "{enc}\t{op}\t{}\t{}\t{}\t{}\n",
+                r.mnemonic,
+                r.executed,
+                r.unimplemented,
+                mods.collect::<Vec<_>>().join(" ")
+            );
+        }
+        table
+    }
+
+    pub fn parse(table: &str) -> Option<Coverage> {
+        let mut coverage = Coverage::default();
+        for line in table
+            .lines()
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        {
+            let cols = line.split('\t').collect::<Vec<_>>();
+            let [enc, op, mnemonic, executed, unimplemented, mods] = cols[..] else {
+                return None;
+            };
+            let modifiers = mods.split(' ').filter(|m| !m.is_empty()).map(|m| {
+                let (m, n) = m.rsplit_once(':')?;
+                Some((m.to_string(), n.parse().ok()?))
+            });
+            let row = Row {
+                mnemonic: mnemonic.to_string(),
+                executed: executed.parse().ok()?,
+                unimplemented: unimplemented.parse().ok()?,
+                modifiers: modifiers.collect::<Option<_>>()?,
+            };
+            coverage
+                .rows
+                .insert((enc.to_string(), op.parse().ok()?), row);
+        }
+        Some(coverage)
+    }
+}
+
+/* print what a launch ran and merge it into the COVERAGE table */
+pub fn save(launch: Coverage) {
+    let Some(path) = &*COVERAGE else {
+        return;
+    };
+    let missing = launch.missing().join(", ");
+    match missing.is_empty() {
+        true => println!("[remu] coverage: {} opcode(s)", launch.rows.len()),
+        false => println!(
+            "[remu] coverage: {} opcode(s), unimplemented: {missing}",
+            launch.rows.len()
+        ),
+    }
+    let _lock = TABLE.lock().unwrap();
+    let mut table = match fs::read_to_string(path) {
+        Ok(s) => Coverage::parse(&s).unwrap_or_else(|| {
+            println!(
+                "[remu] coverage: {} isn't a coverage table, starting over",
+                path.display()
+            );
+            Coverage::default()
+        }),
+        Err(_) => Coverage::default(),
+    };
+    table.merge(launch);
+    if let Err(e) = fs::write(path, table.to_table()) {
+        println!("[remu] coverage: {e}");
+    }
+}
+
+#[cfg(test)]
+mod test_coverage {
+    use super::*;
+
+    #[test]
+    fn test_modifiers() {
+        // v_add_f32_e64 v0, |v1|, -v2 clamp
+        assert_eq!(
+            modifiers(&[0xD5030100 | 0x8000, 0x40020501]),
+            "abs=1,neg=2,clamp=1"
+        );
+        // v_add_co_u32 v10, s13, v10, v1, the sdst isn't abs or opsel
+        assert_eq!(modifiers(&[0xD7000D0A, 0x0002010A]), "none");
+        // v_pk_add_f16 v0, v1, v2 op_sel_hi:[1,1]
+        assert_eq!(modifiers(&[0xCC0F0000, 0x18020501]), "opsel_hi=3");
+        assert_eq!(modifiers(&[0x7E020281]), "none");
+    }
+
+    #[test]
+    fn test_table() {
+        let mut a = Coverage::default();
+        a.record(&[0x7E020281]);
+        a.record(&[0x7E020281]);
+        a.record(&[0xD5030100, 0x40020501]);
+        let mut b = Coverage::default();
+        b.record(&[0xD5030000, 0x00020501]);
+        b.unimplemented(&[0xD5030000, 0x00020501]);
+        a.merge(b);
+        assert_eq!(a.missing(), ["v_add_f32_e64"]);
+        let table = a.to_table();
+        assert_eq!(
+            table,
+            format!(
+                "{HEADER}\n\
+                 vop3\t259\tv_add_f32_e64\t2\t1\tabs=1,neg=2:1 none:1\n\
+                 vop1\t1\tv_mov_b32\t2\t0\tnone:2\n"
+            )
+        );
+        assert_eq!(Coverage::parse(&table), Some(a));
+        assert_eq!(Coverage::parse("vop1\t1\tv_mov_b32\t2"), None);
+    }
+}
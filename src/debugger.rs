@@ -0,0 +1,1068 @@
+/*
+ * A step debugger over a launch: breakpoints on a pc or mnemonic, optionally limited to one
+ * workgroup or wave, watchpoints, continue and single-step, stepping back through recorded waves,
+ * and register/LDS access for a stopped workgroup.
+ * Workgroups run one at a time in SCHEDULE order. PCs are byte offsets into the kernel.
+ */
+use crate::error::{self, guard, RemuError, PANICKED};
+use crate::memory::{self, Memory, MAX_LDS};
+use crate::mnemonic::mnemonic;
+use crate::program::Program;
+use crate::schedule::{Order, Schedule};
+use crate::undo::UndoLog;
+use crate::utils::{SCHEDULE, WATCH};
+use crate::watch::{Hit, Space, Target, Watchpoint};
+use crate::work_group::{WaveState, WaveStatus, WorkGroup};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::slice;
+use std::sync::Arc;
+
+/* debugger result for a wave, lane, register or breakpoint that doesn't exist */
+pub const BAD_TARGET: i32 = 5;
+/* debugger result for stepping back past what a wave has recorded */
+pub const NO_HISTORY: i32 = 7;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Pc(usize),
+    Mnemonic(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub at: Location,
+    pub workgroup: Option<[u32; 3]>,
+    pub wave: Option<usize>,
+}
+
+impl Breakpoint {
+    /* pc is a word index into code */
+    pub fn hits(&self, workgroup: [u32; 3], wave: usize, pc: usize, code: &[u32]) -> bool {
+        self.workgroup.is_none_or(|w| w == workgroup)
+            && self.wave.is_none_or(|w| w == wave)
+            && match &self.at {
+                Location::Pc(addr) => *addr == pc * 4,
+                // either half of a VOPD pair matches
+                Location::Mnemonic(name) => mnemonic(&code[pc..]).split("::").any(|m| m == name),
+            }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Break {
+        workgroup: [u32; 3],
+        wave: usize,
+        pc: usize,
+    },
+    Step {
+        workgroup: [u32; 3],
+        wave: usize,
+        pc: usize,
+    },
+    /* a stopping watchpoint, the wave is past the instruction that hit it */
+    Watch(Hit),
+    Done,
+}
+
+pub struct Session {
+    program: Arc<Program>,
+    dims: [u32; 6],
+    args: *const u64,
+    memory: Arc<dyn Memory>,
+    order: Order,
+    next: u32,
+    wg: Option<WorkGroup>,
+    /* deleted breakpoints leave a hole so ids stay stable */
+    breakpoints: Vec<Option<Breakpoint>>,
+    watchpoints: Vec<Option<Watchpoint>>,
+    /* workgroup and wave filter and the entries each matching wave keeps */
+    record: Option<(Option<[u32; 3]>, Option<usize>, usize)>,
+    /* how waves of a workgroup interleave, SCHEDULE by default */
+    pub schedule: Schedule,
+    /* LDS the launch adds to what the kernel descriptor asks for */
+    pub dynamic_lds: u32,
+}
+
+impl Session {
+    pub fn new(lib: &[u8], dims: [u32; 6], args: *const u64) -> Result<Self, RemuError> {
+        let count = crate::wg_count(dims)?;
+        let program = crate::load(lib, dims)?;
+        Ok(Session {
+            memory: memory::for_launch(args, &program.code),
+            program,
+            dims,
+            args,
+            order: crate::wg_order(count),
+            next: 0,
+            wg: None,
+            breakpoints: vec![],
+            watchpoints: vec![],
+            record: None,
+            schedule: *SCHEDULE,
+            dynamic_lds: 0,
+        })
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(Some(breakpoint));
+        self.breakpoints.len() - 1
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> Result<(), i32> {
+        match self.breakpoints.get_mut(id).and_then(|b| b.take()) {
+            Some(_) => Ok(()),
+            None => Err(BAD_TARGET),
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(Some(watchpoint));
+        self.watchpoints.len() - 1
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> Result<(), i32> {
+        match self.watchpoints.get_mut(id).and_then(|w| w.take()) {
+            Some(_) => Ok(()),
+            None => Err(BAD_TARGET),
+        }
+    }
+
+    /* keep the last limit instructions of matching waves for stepping back, limit 0 stops recording */
+    pub fn record(&mut self, workgroup: Option<[u32; 3]>, wave: Option<usize>, limit: usize) {
+        self.record = (limit != 0).then_some((workgroup, wave, limit));
+        if limit == 0 {
+            if let Some(wg) = self.wg.as_mut() {
+                wg.waves.iter_mut().for_each(|w| w.undo = None);
+            }
+        }
+    }
+
+    /* the workgroup being debugged, starting the next one if the last finished */
+    fn current(&mut self) -> Option<&mut WorkGroup> {
+        if self.wg.is_none() && self.next < self.order.count {
+            let [.., lx, ly, lz] = self.dims;
+            let mut wg = WorkGroup::new(
+                crate::dispatch_dim(self.dims),
+                crate::wg_id(self.dims, self.order.at(self.next)),
+                [lx, ly, lz],
+                self.program.clone(),
+                self.args,
+                // debugging an oversized launch still gets the most LDS there is
+                crate::lds_size(&self.program, self.dynamic_lds).unwrap_or(MAX_LDS),
+            );
+            wg.schedule = self.schedule;
+            wg.memory = self.memory.clone();
+            wg.init_waves();
+            self.wg = Some(wg);
+        }
+        self.wg.as_mut()
+    }
+
+    fn finish(&mut self) {
+        self.wg = None;
+        self.next += 1;
+    }
+
+    /* the current workgroup with the breakpoints and watchpoints set right now */
+    fn armed(&mut self) -> Option<&mut WorkGroup> {
+        let breakpoints = self.breakpoints.iter().flatten().cloned().collect();
+        let watchpoints = self.watchpoints.iter().flatten().cloned();
+        let watchpoints = WATCH.iter().cloned().chain(watchpoints).collect();
+        let record = self.record;
+        let wg = self.current()?;
+        wg.breakpoints = breakpoints;
+        wg.watcher.points = watchpoints;
+        if let Some((workgroup, wave, limit)) = record {
+            if workgroup.is_none_or(|w| w == wg.id) {
+                for (i, w) in wg.waves.iter_mut().enumerate() {
+                    if wave.is_none_or(|wave| wave == i) && w.undo.is_none() {
+                        w.undo = Some(UndoLog::new(limit));
+                    }
+                }
+            }
+        }
+        Some(wg)
+    }
+
+    /* run until a breakpoint or watchpoint stops a wave or every workgroup is done */
+    pub fn cont(&mut self) -> Result<Event, i32> {
+        while let Some(wg) = self.armed() {
+            if let Some(wave) = wg.resume()? {
+                if let Some(hit) = wg.watch_hit.clone() {
+                    return Ok(Event::Watch(hit));
+                }
+                let pc = wg.waves[wave].pc * 4;
+                return Ok(Event::Break {
+                    workgroup: wg.id,
+                    wave,
+                    pc,
+                });
+            }
+            self.finish();
+        }
+        Ok(Event::Done)
+    }
+
+    /* run one instruction of a wave in the current workgroup */
+    pub fn step(&mut self, wave: usize) -> Result<Event, i32> {
+        let Some(wg) = self.armed() else {
+            return Ok(Event::Done);
+        };
+        if wave >= wg.waves.len() {
+            return Err(BAD_TARGET);
+        }
+        wg.step(wave)?;
+        let event = match wg.watch_hit.take() {
+            Some(hit) => Event::Watch(hit),
+            None => Event::Step {
+                workgroup: wg.id,
+                wave,
+                pc: wg.waves[wave].pc * 4,
+            },
+        };
+        if wg.waves.iter().all(|w| w.status == WaveStatus::Done) {
+            self.finish();
+        }
+        Ok(event)
+    }
+
+    /* undo the last recorded instruction of a wave */
+    pub fn step_back(&mut self, wave: usize) -> Result<Event, i32> {
+        let wg = self.wg.as_mut().ok_or(BAD_TARGET)?;
+        if wave >= wg.waves.len() {
+            return Err(BAD_TARGET);
+        }
+        if !wg.step_back(wave) {
+            return Err(NO_HISTORY);
+        }
+        Ok(Event::Step {
+            workgroup: wg.id,
+            wave,
+            pc: wg.waves[wave].pc * 4,
+        })
+    }
+
+    /* step a wave back to just before the instruction that last wrote a register or address */
+    pub fn back_to_write(&mut self, wave: usize, target: Target) -> Result<Event, i32> {
+        let undo = self.wave(wave)?.undo.as_ref();
+        let steps = undo.and_then(|u| u.last_write(&target)).ok_or(NO_HISTORY)?;
+        for _ in 1..steps {
+            self.step_back(wave)?;
+        }
+        self.step_back(wave)
+    }
+
+    /* instructions a wave can step back */
+    pub fn history(&self, wave: usize) -> Result<usize, i32> {
+        Ok(self.wave(wave)?.undo.as_ref().map_or(0, |u| u.len()))
+    }
+
+    fn wave(&self, wave: usize) -> Result<&WaveState, i32> {
+        self.wg
+            .as_ref()
+            .and_then(|wg| wg.waves.get(wave))
+            .ok_or(BAD_TARGET)
+    }
+
+    fn wave_mut(&mut self, wave: usize) -> Result<&mut WaveState, i32> {
+        self.wg
+            .as_mut()
+            .and_then(|wg| wg.waves.get_mut(wave))
+            .ok_or(BAD_TARGET)
+    }
+
+    /* waves in the current workgroup, 0 once every workgroup is done */
+    pub fn waves(&mut self) -> usize {
+        self.current().map_or(0, |wg| wg.waves.len())
+    }
+
+    pub fn workgroup(&self) -> Option<[u32; 3]> {
+        self.wg.as_ref().map(|wg| wg.id)
+    }
+
+    pub fn code(&self) -> &[u32] {
+        &self.program.code
+    }
+
+    pub fn pc(&self, wave: usize) -> Result<usize, i32> {
+        Ok(self.wave(wave)?.pc * 4)
+    }
+
+    pub fn set_pc(&mut self, wave: usize, pc: usize) -> Result<(), i32> {
+        if !pc.is_multiple_of(4) || pc / 4 >= self.program.code.len() {
+            return Err(BAD_TARGET);
+        }
+        self.wave_mut(wave)?.pc = pc / 4;
+        Ok(())
+    }
+
+    pub fn lanes(&self, wave: usize) -> Result<usize, i32> {
+        Ok(self.wave(wave)?.threads.len())
+    }
+
+    pub fn sgpr(&self, wave: usize, idx: usize) -> Result<u32, i32> {
+        self.wave(wave)?
+            .scalar_reg
+            .get(idx)
+            .copied()
+            .ok_or(BAD_TARGET)
+    }
+
+    pub fn set_sgpr(&mut self, wave: usize, idx: usize, value: u32) -> Result<(), i32> {
+        let reg = self.wave_mut(wave)?.scalar_reg.get_mut(idx);
+        *reg.ok_or(BAD_TARGET)? = value;
+        Ok(())
+    }
+
+    fn lane(wave: &WaveState, lane: usize, idx: usize) -> Result<(), i32> {
+        match lane < wave.threads.len() && idx < 256 {
+            true => Ok(()),
+            false => Err(BAD_TARGET),
+        }
+    }
+
+    pub fn vgpr(&self, wave: usize, lane: usize, idx: usize) -> Result<u32, i32> {
+        let wave = self.wave(wave)?;
+        Self::lane(wave, lane, idx)?;
+        Ok(wave.vec_reg.get_lane(lane)[idx])
+    }
+
+    pub fn set_vgpr(
+        &mut self,
+        wave: usize,
+        lane: usize,
+        idx: usize,
+        value: u32,
+    ) -> Result<(), i32> {
+        let wave = self.wave_mut(wave)?;
+        Self::lane(wave, lane, idx)?;
+        wave.vec_reg.get_lane_mut(lane)[idx] = value;
+        Ok(())
+    }
+
+    pub fn exec(&self, wave: usize) -> Result<u32, i32> {
+        Ok(self.wave(wave)?.exec.value)
+    }
+
+    pub fn set_exec(&mut self, wave: usize, value: u32) -> Result<(), i32> {
+        self.wave_mut(wave)?.exec.value = value;
+        Ok(())
+    }
+
+    pub fn vcc(&self, wave: usize) -> Result<u32, i32> {
+        Ok(self.wave(wave)?.vcc.value)
+    }
+
+    pub fn set_vcc(&mut self, wave: usize, value: u32) -> Result<(), i32> {
+        self.wave_mut(wave)?.vcc.value = value;
+        Ok(())
+    }
+
+    pub fn scc(&self, wave: usize) -> Result<u32, i32> {
+        Ok(self.wave(wave)?.scc)
+    }
+
+    pub fn set_scc(&mut self, wave: usize, value: u32) -> Result<(), i32> {
+        self.wave_mut(wave)?.scc = value & 1;
+        Ok(())
+    }
+
+    /* LDS a kernel hasn't written yet reads as zero */
+    pub fn lds(&self, addr: usize, len: usize) -> Result<Vec<u8>, i32> {
+        let lds = &self.wg.as_ref().ok_or(BAD_TARGET)?.lds.data;
+        Ok((addr..addr + len)
+            .map(|i| lds.get(i).copied().unwrap_or(0))
+            .collect())
+    }
+
+    /* fails past the end of the LDS the workgroup has */
+    pub fn set_lds(&mut self, addr: usize, bytes: &[u8]) -> Result<(), i32> {
+        let lds = &mut self.wg.as_mut().ok_or(BAD_TARGET)?.lds;
+        if !lds.contains(addr as u64, bytes.len() as u64) {
+            return Err(BAD_TARGET);
+        }
+        lds.write_bytes(addr, bytes);
+        Ok(())
+    }
+}
+
+/* where remu_debug_continue and remu_debug_step stopped, kind is one of STOP_* */
+#[repr(C)]
+pub struct RemuStop {
+    pub kind: u32,
+    pub workgroup: [u32; 3],
+    pub wave: u32,
+    pub pc: u64,
+    /* STOP_WATCH only: the lane or -1, the address or register index and up to 8 bytes around it */
+    pub lane: i32,
+    pub watch: u64,
+    pub old: u64,
+    pub new: u64,
+}
+
+pub const STOP_DONE: u32 = 0;
+pub const STOP_BREAK: u32 = 1;
+pub const STOP_STEP: u32 = 2;
+pub const STOP_WATCH: u32 = 3;
+
+fn le64(bytes: &[u8]) -> u64 {
+    let bytes = bytes.iter().take(8).enumerate();
+    bytes.fold(0, |v, (i, b)| v | (*b as u64) << (8 * i))
+}
+
+impl From<Event> for RemuStop {
+    fn from(event: Event) -> Self {
+        let stop = |kind, workgroup, wave: usize, pc: usize| RemuStop {
+            kind,
+            workgroup,
+            wave: wave as u32,
+            pc: pc as u64,
+            lane: -1,
+            watch: 0,
+            old: 0,
+            new: 0,
+        };
+        match event {
+            Event::Done => stop(STOP_DONE, [0; 3], 0, 0),
+            Event::Break {
+                workgroup,
+                wave,
+                pc,
+            } => stop(STOP_BREAK, workgroup, wave, pc),
+            Event::Step {
+                workgroup,
+                wave,
+                pc,
+            } => stop(STOP_STEP, workgroup, wave, pc),
+            Event::Watch(hit) => RemuStop {
+                lane: hit.lane.map_or(-1, |l| l as i32),
+                watch: match hit.what {
+                    Target::Memory { addr, .. } => addr,
+                    Target::Sgpr(idx) | Target::Vgpr { idx, .. } => idx as u64,
+                },
+                old: le64(&hit.old),
+                new: le64(&hit.new),
+                ..stop(STOP_WATCH, hit.workgroup, hit.wave, hit.pc)
+            },
+        }
+    }
+}
+
+fn session<'a>(s: *mut Session) -> &'a mut Session {
+    assert!(!s.is_null(), "null debug session");
+    unsafe { &mut *s }
+}
+
+/* 0 and the value of ret through out, or the error code. A null out fails before ret runs */
+fn out<T>(out: *mut T, ret: impl FnOnce() -> Result<T, i32>) -> i32 {
+    if out.is_null() {
+        return BAD_TARGET;
+    }
+    match ret() {
+        Ok(value) => {
+            unsafe { *out = value };
+            0
+        }
+        Err(err) => err,
+    }
+}
+
+fn code(ret: Result<(), i32>) -> i32 {
+    ret.err().unwrap_or(0)
+}
+
+/* workgroup is null or points at 3 ids, wave -1 means any wave */
+fn filter(workgroup: *const u32, wave: i32) -> (Option<[u32; 3]>, Option<usize>) {
+    let workgroup = match workgroup.is_null() {
+        true => None,
+        false => Some(
+            unsafe { slice::from_raw_parts(workgroup, 3) }
+                .try_into()
+                .unwrap(),
+        ),
+    };
+    (workgroup, usize::try_from(wave).ok())
+}
+
+fn breakpoint(at: Location, workgroup: *const u32, wave: i32) -> Breakpoint {
+    let (workgroup, wave) = filter(workgroup, wave);
+    Breakpoint {
+        at,
+        workgroup,
+        wave,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_new(
+    lib: *const c_char,
+    lib_sz: u32,
+    gx: u32,
+    gy: u32,
+    gz: u32,
+    lx: u32,
+    ly: u32,
+    lz: u32,
+    args_ptr: *const u64,
+) -> *mut Session {
+    guard(std::ptr::null_mut(), || {
+        error::set_last(None);
+        let dims = [gx, gy, gz, lx, ly, lz];
+        if let Some(why) = crate::invalid_kernel(lib, lib_sz).or_else(|| crate::invalid_dims(dims))
+        {
+            crate::invalid_launch(why);
+            return std::ptr::null_mut();
+        }
+        let lib = unsafe { slice::from_raw_parts(lib as *const u8, lib_sz as usize) };
+        match Session::new(lib, dims, args_ptr) {
+            Ok(session) => Box::into_raw(Box::new(session)),
+            Err(err) => {
+                crate::failed(err);
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_free(s: *mut Session) {
+    guard((), || {
+        if !s.is_null() {
+            drop(unsafe { Box::from_raw(s) });
+        }
+    })
+}
+
+/* returns the breakpoint id */
+#[no_mangle]
+pub extern "C" fn remu_debug_break_pc(
+    s: *mut Session,
+    pc: u64,
+    workgroup: *const u32,
+    wave: i32,
+) -> i32 {
+    guard(-1, || {
+        let bp = breakpoint(Location::Pc(pc as usize), workgroup, wave);
+        session(s).add_breakpoint(bp) as i32
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_break_mnemonic(
+    s: *mut Session,
+    name: *const c_char,
+    workgroup: *const u32,
+    wave: i32,
+) -> i32 {
+    guard(-1, || {
+        let name = unsafe { CStr::from_ptr(name) }
+            .to_string_lossy()
+            .into_owned();
+        let bp = breakpoint(Location::Mnemonic(name), workgroup, wave);
+        session(s).add_breakpoint(bp) as i32
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_delete(s: *mut Session, id: u32) -> i32 {
+    guard(PANICKED, || code(session(s).remove_breakpoint(id as usize)))
+}
+
+/* space is 0 global, 1 LDS, 2 scratch, returns the watchpoint id or -1 */
+#[no_mangle]
+pub extern "C" fn remu_debug_watch_mem(
+    s: *mut Session,
+    space: u32,
+    addr: u64,
+    len: u64,
+    stop: bool,
+) -> i32 {
+    guard(-1, || {
+        let space = match space {
+            0 => Space::Global,
+            1 => Space::Lds,
+            2 => Space::Scratch,
+            _ => return -1,
+        };
+        let target = Target::Memory { space, addr, len };
+        session(s).add_watchpoint(Watchpoint { target, stop }) as i32
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_watch_sgpr(s: *mut Session, idx: u32, stop: bool) -> i32 {
+    guard(-1, || {
+        if idx >= 128 {
+            return -1;
+        }
+        let target = Target::Sgpr(idx as usize);
+        session(s).add_watchpoint(Watchpoint { target, stop }) as i32
+    })
+}
+
+/* lane -1 watches every lane */
+#[no_mangle]
+pub extern "C" fn remu_debug_watch_vgpr(s: *mut Session, idx: u32, lane: i32, stop: bool) -> i32 {
+    guard(-1, || {
+        if idx >= 256 || lane >= 32 {
+            return -1;
+        }
+        let lane = usize::try_from(lane).ok();
+        let target = Target::Vgpr {
+            idx: idx as usize,
+            lane,
+        };
+        session(s).add_watchpoint(Watchpoint { target, stop }) as i32
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_unwatch(s: *mut Session, id: u32) -> i32 {
+    guard(PANICKED, || code(session(s).remove_watchpoint(id as usize)))
+}
+
+/* workgroup null and wave -1 record every wave, limit 0 stops recording */
+#[no_mangle]
+pub extern "C" fn remu_debug_record(s: *mut Session, workgroup: *const u32, wave: i32, limit: u32) {
+    guard((), || {
+        let (workgroup, wave) = filter(workgroup, wave);
+        session(s).record(workgroup, wave, limit as usize);
+    })
+}
+
+/* how many instructions a wave can step back, or -error */
+#[no_mangle]
+pub extern "C" fn remu_debug_history(s: *mut Session, wave: u32) -> i32 {
+    guard(-PANICKED, || {
+        session(s)
+            .history(wave as usize)
+            .map_or_else(|e| -e, |n| n as i32)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_step_back(s: *mut Session, wave: u32, stop: *mut RemuStop) -> i32 {
+    guard(PANICKED, || {
+        out(stop, || {
+            session(s).step_back(wave as usize).map(RemuStop::from)
+        })
+    })
+}
+
+/* kind is 0 global, 1 LDS, 2 scratch with addr and len, 3 SGPR or 4 VGPR with addr as the index */
+#[no_mangle]
+pub extern "C" fn remu_debug_back_to_write(
+    s: *mut Session,
+    wave: u32,
+    kind: u32,
+    addr: u64,
+    len: u64,
+    lane: i32,
+    stop: *mut RemuStop,
+) -> i32 {
+    guard(PANICKED, || {
+        let target = match kind {
+            0..=2 => Target::Memory {
+                space: [Space::Global, Space::Lds, Space::Scratch][kind as usize],
+                addr,
+                len,
+            },
+            3 => Target::Sgpr(addr as usize),
+            4 => Target::Vgpr {
+                idx: addr as usize,
+                lane: usize::try_from(lane).ok(),
+            },
+            _ => return BAD_TARGET,
+        };
+        out(stop, || {
+            session(s)
+                .back_to_write(wave as usize, target)
+                .map(RemuStop::from)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_continue(s: *mut Session, stop: *mut RemuStop) -> i32 {
+    guard(PANICKED, || {
+        out(stop, || session(s).cont().map(RemuStop::from))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_step(s: *mut Session, wave: u32, stop: *mut RemuStop) -> i32 {
+    guard(PANICKED, || {
+        out(stop, || session(s).step(wave as usize).map(RemuStop::from))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_read_sgpr(s: *mut Session, wave: u32, idx: u32, val: *mut u32) -> i32 {
+    guard(PANICKED, || {
+        out(val, || session(s).sgpr(wave as usize, idx as usize))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_write_sgpr(s: *mut Session, wave: u32, idx: u32, val: u32) -> i32 {
+    guard(PANICKED, || {
+        code(session(s).set_sgpr(wave as usize, idx as usize, val))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_read_vgpr(
+    s: *mut Session,
+    wave: u32,
+    lane: u32,
+    idx: u32,
+    val: *mut u32,
+) -> i32 {
+    guard(PANICKED, || {
+        out(val, || {
+            session(s).vgpr(wave as usize, lane as usize, idx as usize)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_write_vgpr(
+    s: *mut Session,
+    wave: u32,
+    lane: u32,
+    idx: u32,
+    val: u32,
+) -> i32 {
+    guard(PANICKED, || {
+        code(session(s).set_vgpr(wave as usize, lane as usize, idx as usize, val))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_read_pc(s: *mut Session, wave: u32, val: *mut u64) -> i32 {
+    guard(PANICKED, || {
+        out(val, || session(s).pc(wave as usize).map(|pc| pc as u64))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_read_exec(s: *mut Session, wave: u32, val: *mut u32) -> i32 {
+    guard(PANICKED, || out(val, || session(s).exec(wave as usize)))
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_write_exec(s: *mut Session, wave: u32, val: u32) -> i32 {
+    guard(PANICKED, || code(session(s).set_exec(wave as usize, val)))
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_read_vcc(s: *mut Session, wave: u32, val: *mut u32) -> i32 {
+    guard(PANICKED, || out(val, || session(s).vcc(wave as usize)))
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_write_vcc(s: *mut Session, wave: u32, val: u32) -> i32 {
+    guard(PANICKED, || code(session(s).set_vcc(wave as usize, val)))
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_read_scc(s: *mut Session, wave: u32, val: *mut u32) -> i32 {
+    guard(PANICKED, || out(val, || session(s).scc(wave as usize)))
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_write_scc(s: *mut Session, wave: u32, val: u32) -> i32 {
+    guard(PANICKED, || code(session(s).set_scc(wave as usize, val)))
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_read_lds(s: *mut Session, addr: u32, buf: *mut u8, len: u32) -> i32 {
+    guard(PANICKED, || {
+        match session(s).lds(addr as usize, len as usize) {
+            Ok(bytes) => {
+                unsafe { slice::from_raw_parts_mut(buf, len as usize) }.copy_from_slice(&bytes);
+                0
+            }
+            Err(err) => err,
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_debug_write_lds(
+    s: *mut Session,
+    addr: u32,
+    buf: *const u8,
+    len: u32,
+) -> i32 {
+    guard(PANICKED, || {
+        let bytes = unsafe { slice::from_raw_parts(buf, len as usize) };
+        code(session(s).set_lds(addr as usize, bytes))
+    })
+}
+
+#[cfg(test)]
+mod test_debugger {
+    use super::*;
+    use crate::utils::END_PRG;
+
+    fn session(kernel: &[u32], local: u32) -> Session {
+        let lib = kernel
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        Session::new(&lib, [2, 1, 1, local, 1, 1], std::ptr::null()).unwrap()
+    }
+
+    fn at(pc: usize) -> Breakpoint {
+        Breakpoint {
+            at: Location::Pc(pc),
+            workgroup: None,
+            wave: None,
+        }
+    }
+
+    #[test]
+    fn test_c_arguments() {
+        let new = |lib: &[u32], len: u32, lx: u32| {
+            let lib = lib.as_ptr() as *const c_char;
+            remu_debug_new(lib, len, 1, 1, 1, lx, 1, 1, std::ptr::null())
+        };
+        let kernel = [END_PRG];
+        assert!(new(&[], 0, 1).is_null());
+        assert!(new(&kernel, 3, 1).is_null());
+        assert!(new(&kernel, 4, 0).is_null());
+        assert_eq!(error::last().map(|e| e.code()), Some(error::INVALID_LAUNCH));
+        let s = new(&kernel, 4, 1);
+        assert!(!s.is_null());
+        assert_eq!(remu_debug_continue(s, std::ptr::null_mut()), BAD_TARGET);
+        assert_eq!(remu_debug_read_pc(s, 0, std::ptr::null_mut()), BAD_TARGET);
+        remu_debug_free(s);
+    }
+
+    #[test]
+    fn test_break_pc() {
+        let kernel = [
+            0x7E020281, // v_mov_b32 v1, 1
+            0xBE850087, // s_mov_b32 s5, 7
+            END_PRG,
+        ];
+        let mut s = session(&kernel, 64);
+        s.add_breakpoint(at(4));
+        let mut stops = vec![];
+        while let Event::Break {
+            workgroup,
+            wave,
+            pc,
+        } = s.cont().unwrap()
+        {
+            assert_eq!(s.vgpr(wave, 5, 1), Ok(1));
+            assert_eq!(s.sgpr(wave, 5), Ok(0));
+            stops.push((workgroup[0], wave, pc));
+        }
+        // every wave of every workgroup stops once, in whatever order SCHEDULE runs them
+        stops.sort();
+        assert_eq!(stops, [(0, 0, 4), (0, 1, 4), (1, 0, 4), (1, 1, 4)]);
+    }
+
+    #[test]
+    fn test_break_mnemonic_filtered() {
+        let kernel = [
+            0x7E020281, // v_mov_b32 v1, 1
+            0xBE850087, // s_mov_b32 s5, 7
+            END_PRG,
+        ];
+        let mut s = session(&kernel, 64);
+        let id = s.add_breakpoint(Breakpoint {
+            at: Location::Mnemonic("s_mov_b32".into()),
+            workgroup: Some([1, 0, 0]),
+            wave: Some(1),
+        });
+        let stop = s.cont().unwrap();
+        assert_eq!(
+            stop,
+            Event::Break {
+                workgroup: [1, 0, 0],
+                wave: 1,
+                pc: 4
+            }
+        );
+        assert_eq!(s.remove_breakpoint(id), Ok(()));
+        assert_eq!(s.remove_breakpoint(id), Err(BAD_TARGET));
+        assert_eq!(s.cont().unwrap(), Event::Done);
+        assert_eq!(s.sgpr(0, 5), Err(BAD_TARGET));
+    }
+
+    #[test]
+    fn test_step_and_write() {
+        let kernel = [
+            0xBE850087, // s_mov_b32 s5, 7
+            0x80060505, // s_add_u32 s6, s5, s5
+            END_PRG,
+        ];
+        let mut s = session(&kernel, 1);
+        s.add_breakpoint(Breakpoint {
+            at: Location::Mnemonic("s_add_u32".into()),
+            workgroup: None,
+            wave: None,
+        });
+        let Event::Break { workgroup, pc, .. } = s.cont().unwrap() else {
+            panic!("no break")
+        };
+        assert_eq!(pc, 4);
+        assert_eq!(s.sgpr(0, 5), Ok(7));
+        s.set_sgpr(0, 5, 20).unwrap();
+        let step = s.step(0).unwrap();
+        assert!(matches!(step, Event::Step { wave: 0, pc: 8, .. }));
+        assert_eq!(s.sgpr(0, 6), Ok(40));
+        assert_eq!(s.scc(0), Ok(0));
+        s.set_lds(6, &[1, 2]).unwrap();
+        assert_eq!(s.lds(4, 6), Ok(vec![0, 0, 1, 2, 0, 0]));
+        assert_eq!(s.vgpr(0, 1, 0), Err(BAD_TARGET));
+        // the breakpoint stops the next workgroup as well
+        match s.cont().unwrap() {
+            Event::Break {
+                workgroup: next, ..
+            } => assert_ne!(next, workgroup),
+            stop => panic!("{stop:?}"),
+        }
+    }
+
+    #[test]
+    fn test_watch_global() {
+        let kernel = [
+            0xF4040080, // s_load_b64 s[2:3], s[0:1], null
+            0xF8000000, 0xBF89FC07, // s_waitcnt lgkmcnt(0)
+            0x7E02020F, // v_mov_b32 v1, s15
+            0x30040282, // v_lshlrev_b32 v2, 2, v1
+            0xDC6A0000, // global_store_b32 v2, v1, s[2:3]
+            0x00020102, END_PRG,
+        ];
+        let mut out = [u32::MAX; 4];
+        let args = [out.as_mut_ptr() as u64];
+        let lib = kernel
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut s = Session::new(&lib, [4, 1, 1, 1, 1, 1], args.as_ptr()).unwrap();
+        let addr = out.as_ptr() as u64 + 8;
+        let target = Target::Memory {
+            space: Space::Global,
+            addr,
+            len: 4,
+        };
+        s.add_watchpoint(Watchpoint { target, stop: true });
+        let Event::Watch(hit) = s.cont().unwrap() else {
+            panic!("no watch")
+        };
+        assert_eq!(hit.workgroup, [2, 0, 0]);
+        assert_eq!((hit.wave, hit.lane, hit.pc), (0, Some(0), 20));
+        assert_eq!(
+            (hit.what, &hit.old[..], &hit.new[..]),
+            (target, &[0xff; 4][..], &[2, 0, 0, 0][..])
+        );
+        assert_eq!(s.pc(0), Ok(28));
+        assert_eq!(s.cont().unwrap(), Event::Done);
+        assert_eq!(out, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_watch_registers() {
+        let kernel = [
+            0x7E020281, // v_mov_b32 v1, 1
+            0xBE850087, // s_mov_b32 s5, 7
+            END_PRG,
+        ];
+        let mut s = session(&kernel, 4);
+        let v1 = Target::Vgpr {
+            idx: 1,
+            lane: Some(3),
+        };
+        s.add_watchpoint(Watchpoint {
+            target: v1,
+            stop: true,
+        });
+        let s5 = s.add_watchpoint(Watchpoint {
+            target: Target::Sgpr(5),
+            stop: true,
+        });
+        let Event::Watch(hit) = s.step(0).unwrap() else {
+            panic!("no watch")
+        };
+        assert_eq!((hit.what, hit.lane, hit.pc), (v1, Some(3), 0));
+        assert_eq!((hit.old, hit.new), (vec![0; 4], vec![1, 0, 0, 0]));
+        let Event::Watch(hit) = s.cont().unwrap() else {
+            panic!("no watch")
+        };
+        assert_eq!((hit.what, hit.lane, hit.pc), (Target::Sgpr(5), None, 4));
+        s.remove_watchpoint(s5).unwrap();
+        // the next workgroup only stops on v1
+        let Event::Watch(hit) = s.cont().unwrap() else {
+            panic!("no watch")
+        };
+        assert_eq!(hit.what, v1);
+        assert_eq!(s.cont().unwrap(), Event::Done);
+    }
+
+    #[test]
+    fn test_step_back() {
+        let kernel = [
+            0xBE850087, // s_mov_b32 s5, 7
+            0x80060505, // s_add_u32 s6, s5, s5
+            0x7E020281, // v_mov_b32 v1, 1
+            END_PRG,
+        ];
+        let mut s = session(&kernel, 2);
+        s.record(None, Some(0), 16);
+        s.add_breakpoint(at(12));
+        assert!(matches!(s.cont().unwrap(), Event::Break { pc: 12, .. }));
+        assert_eq!(s.history(0), Ok(3));
+        let workgroup = s.workgroup().unwrap();
+        let step = |pc| Event::Step {
+            workgroup,
+            wave: 0,
+            pc,
+        };
+        assert_eq!(s.step_back(0), Ok(step(8)));
+        assert_eq!(s.vgpr(0, 1, 1), Ok(0));
+        assert_eq!(s.sgpr(0, 6), Ok(14));
+        assert_eq!(s.back_to_write(0, Target::Sgpr(5)), Ok(step(0)));
+        assert_eq!((s.sgpr(0, 5), s.sgpr(0, 6)), (Ok(0), Ok(0)));
+        assert_eq!(s.step_back(0), Err(NO_HISTORY));
+        // running forward again replays the same instructions
+        assert_eq!(s.step(0), Ok(step(4)));
+        assert!(matches!(s.cont().unwrap(), Event::Break { pc: 12, .. }));
+        assert_eq!(s.sgpr(0, 6), Ok(14));
+        assert_eq!(s.history(0), Ok(3));
+    }
+
+    #[test]
+    fn test_step_back_store() {
+        let kernel = [
+            0x7E020281, // v_mov_b32 v1, 1
+            0x7E040280, // v_mov_b32 v2, 0
+            0xD8340000, 0x00000102, // ds_store_b32 v2, v1
+            END_PRG,
+        ];
+        let mut s = session(&kernel, 2);
+        s.record(None, None, 16);
+        s.add_breakpoint(at(16));
+        assert!(matches!(s.cont().unwrap(), Event::Break { pc: 16, .. }));
+        assert_eq!(s.lds(0, 4), Ok(vec![1, 0, 0, 0]));
+        let lds = Target::Memory {
+            space: crate::watch::Space::Lds,
+            addr: 0,
+            len: 4,
+        };
+        assert!(matches!(
+            s.back_to_write(0, lds),
+            Ok(Event::Step { pc: 8, .. })
+        ));
+        assert_eq!(s.lds(0, 4), Ok(vec![0, 0, 0, 0]));
+        assert_eq!(s.vgpr(0, 0, 1), Ok(1));
+        s.record(None, None, 0);
+        assert_eq!(s.history(0), Ok(0));
+    }
+}
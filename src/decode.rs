@@ -0,0 +1,782 @@
+use crate::mnemonic::opcode;
+use crate::thread::{SGPR_COUNT, VGPR_COUNT};
+
+/* scalar operand codes that alias wave state */
+pub const VCC: usize = 106;
+pub const M0: usize = 125;
+pub const EXEC: usize = 126;
+pub const SCC: usize = 253;
+const NULL: usize = 124;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Smem,
+    Sop1,
+    Sopc,
+    Sopp,
+    Sopk,
+    Sop2,
+    Vop3p,
+    Vop1,
+    Vopd,
+    Vopc,
+    Vop2,
+    Vop3,
+    Ds,
+    Flat,
+    Mubuf,
+    Mtbuf,
+    Mimg,
+    Exp,
+    Vinterp,
+    Ldsdir,
+    Unknown,
+}
+
+/* the encoding an instruction word decodes as, thread::handler dispatches on it */
+pub fn encoding(instruction: u32) -> Encoding {
+    match instruction {
+        _ if instruction >> 26 == 0b111101 => Encoding::Smem,
+        _ if instruction >> 23 == 0b10_1111101 => Encoding::Sop1,
+        _ if (instruction >> 23) & 0x3ff == 0b101111110 => Encoding::Sopc,
+        _ if instruction >> 23 == 0b10_1111111 => Encoding::Sopp,
+        _ if instruction >> 28 == 0b1011 => Encoding::Sopk,
+        _ if instruction >> 30 == 0b10 => Encoding::Sop2,
+        _ if instruction >> 24 == 0b11001100 => Encoding::Vop3p,
+        _ if instruction >> 25 == 0b0111111 => Encoding::Vop1,
+        _ if instruction >> 26 == 0b110010 => Encoding::Vopd,
+        _ if instruction >> 25 == 0b0111110 => Encoding::Vopc,
+        _ if instruction >> 31 == 0b0 => Encoding::Vop2,
+        _ if instruction >> 26 == 0b110101 => Encoding::Vop3,
+        _ if instruction >> 26 == 0b110110 => Encoding::Ds,
+        _ if instruction >> 26 == 0b110111 => Encoding::Flat,
+        // valid encodings remu doesn't execute
+        _ if instruction >> 26 == 0b111000 => Encoding::Mubuf,
+        _ if instruction >> 26 == 0b111010 => Encoding::Mtbuf,
+        _ if instruction >> 26 == 0b111100 => Encoding::Mimg,
+        _ if instruction >> 26 == 0b111110 => Encoding::Exp,
+        _ if instruction >> 24 == 0b11001101 => Encoding::Vinterp,
+        _ if instruction >> 24 == 0b11001110 => Encoding::Ldsdir,
+        _ => Encoding::Unknown,
+    }
+}
+
+pub fn is_scalar(instruction: u32) -> bool {
+    matches!(
+        encoding(instruction),
+        Encoding::Smem
+            | Encoding::Sop1
+            | Encoding::Sopc
+            | Encoding::Sopp
+            | Encoding::Sopk
+            | Encoding::Sop2
+    )
+}
+
+/* v_readfirstlane, v_readlane and v_writelane run once whatever exec is */
+pub fn ignores_exec(instruction: u32) -> bool {
+    match encoding(instruction) {
+        Encoding::Vop1 => (instruction >> 9) & 0xff == 2,
+        Encoding::Vop3 => matches!((instruction >> 16) & 0x3ff, 386 | 864 | 865),
+        _ => false,
+    }
+}
+
+/* instruction length in dwords, including the trailing literal constant */
+pub fn instr_len(stream: &[u32]) -> usize {
+    let (base, literal) = lengths(stream);
+    base + literal as usize
+}
+
+/* dwords before the literal constant, and whether one follows them */
+fn lengths(stream: &[u32]) -> (usize, bool) {
+    const LITERAL: u64 = 255;
+    let instruction = stream[0];
+    let instr = u64_instr(stream);
+    let src = |n: u32, mask: u64| (instr >> n) & mask == LITERAL;
+    match encoding(instruction) {
+        Encoding::Sop1 => (1, src(0, 0xff)),
+        Encoding::Sop2 | Encoding::Sopc => (1, src(0, 0xff) || src(8, 0xff)),
+        Encoding::Sopk => (1, (instruction >> 23) & 0x1f == 19),
+        Encoding::Sopp | Encoding::Ldsdir | Encoding::Unknown => (1, false),
+        Encoding::Vop1 | Encoding::Vopc => (1, src(0, 0x1ff)),
+        Encoding::Vop2 => (
+            1,
+            src(0, 0x1ff) || matches!((instruction >> 25) & 0x3f, 44 | 45 | 55 | 56),
+        ),
+        Encoding::Vop3 | Encoding::Vop3p => (2, src(32, 0x1ff) || src(41, 0x1ff) || src(50, 0x1ff)),
+        Encoding::Vopd => {
+            let (opx, opy) = ((instr >> 22) & 0xf, (instr >> 17) & 0x1f);
+            (
+                2,
+                src(0, 0x1ff) || src(32, 0x1ff) || matches!(opx, 1 | 2) || matches!(opy, 1 | 2),
+            )
+        }
+        Encoding::Smem
+        | Encoding::Ds
+        | Encoding::Flat
+        | Encoding::Mubuf
+        | Encoding::Mtbuf
+        | Encoding::Mimg
+        | Encoding::Exp
+        | Encoding::Vinterp => (2, false),
+    }
+}
+
+fn u64_instr(stream: &[u32]) -> u64 {
+    ((*stream.get(1).unwrap_or(&0) as u64) << 32) | stream[0] as u64
+}
+
+/* the fields thread::Handler runs an instruction with, split out of the code once by Inst::decode */
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Operands {
+    /* both dwords of 64-bit encodings, modifiers and offsets are read from here */
+    pub instr: u64,
+    pub op: u32,
+    /* operand codes, VOPD's are srcx0, vsrcx1, srcy0 and vsrcy1 */
+    pub src: [usize; 4],
+    /* vdst or sdst, then the sdst of VOP3SD or vdsty of VOPD */
+    pub dst: [usize; 2],
+    pub literal: Option<u32>,
+}
+impl Operands {
+    pub fn decode(stream: &[u32]) -> Self {
+        let instr = u64_instr(stream);
+        let f = |n: u32, mask: u64| ((instr >> n) & mask) as usize;
+        let (src, dst) = match encoding(stream[0]) {
+            Encoding::Smem => ([f(0, 0x3f) * 2, f(57, 0x7f), 0, 0], [f(6, 0x7f), 0]),
+            Encoding::Sop1 => ([f(0, 0xff), 0, 0, 0], [f(16, 0x7f), 0]),
+            Encoding::Sopc => ([f(0, 0xff), f(8, 0xff), 0, 0], [0, 0]),
+            Encoding::Sopk => ([0; 4], [f(16, 0x7f), 0]),
+            Encoding::Sop2 => ([f(0, 0xff), f(8, 0xff), 0, 0], [f(16, 0x7f), 0]),
+            Encoding::Vop1 => ([f(0, 0x1ff), 0, 0, 0], [f(17, 0xff), 0]),
+            Encoding::Vopc => ([f(0, 0x1ff), f(9, 0xff), 0, 0], [0, 0]),
+            Encoding::Vop2 => ([f(0, 0x1ff), f(9, 0xff), 0, 0], [f(17, 0xff), 0]),
+            Encoding::Vop3 | Encoding::Vop3p => (
+                [f(32, 0x1ff), f(41, 0x1ff), f(50, 0x1ff), 0],
+                [f(0, 0xff), f(8, 0x7f)],
+            ),
+            Encoding::Vopd => {
+                let vdstx = f(56, 0xff);
+                // LSB is the opposite of VDSTX[0]
+                let vdsty = f(49, 0x7f) << 1 | ((vdstx & 1) ^ 1);
+                (
+                    [f(0, 0x1ff), f(9, 0xff), f(32, 0x1ff), f(41, 0xff)],
+                    [vdstx, vdsty],
+                )
+            }
+            Encoding::Ds => ([f(32, 0xff), f(40, 0xff), f(48, 0xff), 0], [f(56, 0xff), 0]),
+            Encoding::Flat => ([f(32, 0xff), f(40, 0xff), f(48, 0x7f), 0], [f(56, 0xff), 0]),
+            _ => ([0; 4], [0; 2]),
+        };
+        let (base, literal) = lengths(stream);
+        Operands {
+            instr,
+            op: opcode(stream),
+            src,
+            dst,
+            literal: literal.then(|| stream.get(base).copied()).flatten(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegFile {
+    Sgpr,
+    Vgpr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegRange {
+    pub file: RegFile,
+    pub idx: usize,
+    pub len: usize,
+}
+impl RegRange {
+    pub fn sgpr(idx: usize, len: usize) -> Self {
+        Self {
+            file: RegFile::Sgpr,
+            idx,
+            len,
+        }
+    }
+    pub fn vgpr(idx: usize, len: usize) -> Self {
+        Self {
+            file: RegFile::Vgpr,
+            idx,
+            len,
+        }
+    }
+    pub fn overlaps(&self, other: &RegRange) -> bool {
+        self.file == other.file
+            && self.idx < other.idx + other.len
+            && other.idx < self.idx + self.len
+    }
+    pub fn regs(&self) -> impl Iterator<Item = RegRange> + '_ {
+        (self.idx..self.idx + self.len).map(|idx| RegRange {
+            idx,
+            len: 1,
+            ..*self
+        })
+    }
+}
+impl std::fmt::Display for RegRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = |idx: usize| match (self.file, idx) {
+            (RegFile::Vgpr, _) => format!("v{idx}"),
+            (RegFile::Sgpr, 0..=SGPR_COUNT) => format!("s{idx}"),
+            (RegFile::Sgpr, 106) => "vcc_lo".to_string(),
+            (RegFile::Sgpr, 107) => "vcc_hi".to_string(),
+            (RegFile::Sgpr, 108..=123) => format!("ttmp{}", idx - 108),
+            (RegFile::Sgpr, M0) => "m0".to_string(),
+            (RegFile::Sgpr, EXEC) => "exec_lo".to_string(),
+            (RegFile::Sgpr, 127) => "exec_hi".to_string(),
+            (RegFile::Sgpr, SCC) => "scc".to_string(),
+            _ => format!("src{idx}"),
+        };
+        match self.len {
+            1 => write!(f, "{}", name(self.idx)),
+            _ => {
+                let prefix = match self.file {
+                    RegFile::Sgpr => "s",
+                    RegFile::Vgpr => "v",
+                };
+                match self.idx <= SGPR_COUNT || self.file == RegFile::Vgpr {
+                    true => write!(f, "{prefix}[{}:{}]", self.idx, self.idx + self.len - 1),
+                    false => write!(f, "{}", name(self.idx)),
+                }
+            }
+        }
+    }
+}
+
+/* registers an instruction reads and writes, including implicit exec, vcc and scc */
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Footprint {
+    pub reads: Vec<RegRange>,
+    pub writes: Vec<RegRange>,
+}
+impl Footprint {
+    fn read_src(&mut self, code: usize, len: usize) {
+        if let Some(r) = src_range(code, len) {
+            self.reads.push(r);
+        }
+    }
+    fn write_sdst(&mut self, code: usize, len: usize) {
+        if code != NULL && code < 128 {
+            self.writes.push(RegRange::sgpr(code, len));
+        }
+    }
+}
+
+fn src_range(code: usize, len: usize) -> Option<RegRange> {
+    match code {
+        NULL => None,
+        0..=127 => Some(RegRange::sgpr(code, len)),
+        // src_vccz, src_execz, src_scc
+        251 => Some(RegRange::sgpr(VCC, 1)),
+        252 => Some(RegRange::sgpr(EXEC, 1)),
+        253 => Some(RegRange::sgpr(SCC, 1)),
+        VGPR_COUNT..=511 => Some(RegRange::vgpr(code - VGPR_COUNT, len)),
+        _ => None,
+    }
+}
+
+/* operand widths in dwords [src0, src1, src2, dst] for an op in the VOP3 opcode space */
+fn vop_widths(op: u32) -> [usize; 4] {
+    match op {
+        // VOPC f64, i64, u64
+        32..=47 | 160..=175 | 80..=95 | 208..=223 => [2, 2, 1, 1],
+        127 | 255 => [2, 1, 1, 1],
+        // VOP1 promoted to VOP3
+        384..=511 => match op - 384 {
+            3 | 15 | 21 | 60 => [2, 1, 1, 1],
+            4 | 16 | 22 => [1, 1, 1, 2],
+            23..=26 | 47 | 49 | 52 | 61 | 62 => [2, 1, 1, 2],
+            _ => [1, 1, 1, 1],
+        },
+        532 | 552 | 568 | 765 => [2, 2, 2, 2],
+        807..=810 => [2, 2, 1, 2],
+        811 => [2, 1, 1, 2],
+        828..=830 => [1, 2, 1, 2],
+        766 | 767 => [1, 1, 2, 2],
+        _ => [1, 1, 1, 1],
+    }
+}
+
+pub fn footprint(stream: &[u32]) -> Footprint {
+    let instruction = stream[0];
+    let instr = u64_instr(stream);
+    let mut fp = Footprint::default();
+    let exec = RegRange::sgpr(EXEC, 1);
+    let vcc = RegRange::sgpr(VCC, 1);
+    let scc = RegRange::sgpr(SCC, 1);
+    let odd = |op: u32| (op & 1) as usize + 1;
+
+    match encoding(instruction) {
+        Encoding::Smem => {
+            let op = (instr >> 18) & 0xff;
+            let sbase = ((instr & 0x3f) * 2) as usize;
+            fp.reads
+                .push(RegRange::sgpr(sbase, if op >= 8 { 4 } else { 2 }));
+            fp.read_src(((instr >> 57) & 0x7f) as usize, 1);
+            let sdata = ((instr >> 6) & 0x7f) as usize;
+            fp.write_sdst(sdata, 1 << (op & 0x7));
+        }
+        Encoding::Sop1 => {
+            let (src, op) = ((instruction & 0xff) as usize, (instruction >> 8) & 0xff);
+            let sdst = ((instruction >> 16) & 0x7f) as usize;
+            let (src_len, dst_len) = match op {
+                9 | 11 | 13 | 23 | 25 => (2, 1),
+                20 => (1, 2),
+                0..=55 | 65 | 67 => (odd(op), odd(op)),
+                71 => (0, 2),
+                72..=74 => (2, 0),
+                _ => (1, 1),
+            };
+            if src_len != 0 {
+                fp.read_src(src, src_len);
+            }
+            if matches!(op, 2 | 3) {
+                fp.reads.push(scc);
+            }
+            if matches!(op, 16..=19) {
+                fp.read_src(sdst, dst_len);
+            }
+            if (32..=55).contains(&op) {
+                fp.reads.push(exec);
+                fp.writes.push(RegRange::sgpr(EXEC, dst_len));
+            }
+            if matches!(op, 21..=33) || (32..=55).contains(&op) {
+                fp.writes.push(scc);
+            }
+            if dst_len != 0 {
+                fp.write_sdst(sdst, dst_len);
+            }
+        }
+        Encoding::Sopc => {
+            let op = (instruction >> 16) & 0x7f;
+            let (l0, l1) = match op {
+                14 | 15 => (2, 1),
+                16 | 17 => (2, 2),
+                _ => (1, 1),
+            };
+            fp.read_src((instruction & 0xff) as usize, l0);
+            fp.read_src(((instruction >> 8) & 0xff) as usize, l1);
+            fp.writes.push(scc);
+        }
+        Encoding::Sopp => match (instruction >> 16) & 0x7f {
+            33 | 34 => fp.reads.push(scc),
+            35 | 36 => fp.reads.push(vcc),
+            37 | 38 => fp.reads.push(exec),
+            _ => {}
+        },
+        Encoding::Sopk => {
+            let sdst = ((instruction >> 16) & 0x7f) as usize;
+            match (instruction >> 23) & 0x1f {
+                0 | 17 => fp.write_sdst(sdst, 1),
+                2 => {
+                    fp.reads.push(scc);
+                    fp.write_sdst(sdst, 1);
+                }
+                3..=14 => {
+                    fp.read_src(sdst, 1);
+                    fp.writes.push(scc);
+                }
+                15 => {
+                    fp.read_src(sdst, 1);
+                    fp.write_sdst(sdst, 1);
+                    fp.writes.push(scc);
+                }
+                16 => {
+                    fp.read_src(sdst, 1);
+                    fp.write_sdst(sdst, 1);
+                }
+                18 | 24..=27 => fp.read_src(sdst, 1),
+                _ => {}
+            }
+        }
+        Encoding::Sop2 => {
+            let op = (instruction >> 23) & 0xff;
+            let (l0, l1, ld) = match op {
+                9 | 11 | 13 => (2, 1, 2),
+                23..=37 | 49 => (odd(op), odd(op), odd(op)),
+                40 | 41 => (2, 1, 2),
+                43 => (1, 1, 2),
+                _ => (1, 1, 1),
+            };
+            fp.read_src((instruction & 0xff) as usize, l0);
+            fp.read_src(((instruction >> 8) & 0xff) as usize, l1);
+            if matches!(op, 4 | 5 | 48 | 49) {
+                fp.reads.push(scc);
+            }
+            if !matches!(op, 42..=53) {
+                fp.writes.push(scc);
+            }
+            fp.write_sdst(((instruction >> 16) & 0x7f) as usize, ld);
+        }
+        Encoding::Vop1 => {
+            let op = (instruction >> 9) & 0xff;
+            let vdst = ((instruction >> 17) & 0xff) as usize;
+            let [l0, _, _, ld] = vop_widths(op + 384);
+            fp.reads.push(exec);
+            fp.read_src((instruction & 0x1ff) as usize, l0);
+            match op {
+                2 => fp.write_sdst(vdst, 1),
+                0 => {}
+                _ => fp.writes.push(RegRange::vgpr(vdst, ld)),
+            }
+        }
+        Encoding::Vop2 => {
+            let op = (instruction >> 25) & 0x3f;
+            let vdst = ((instruction >> 17) & 0xff) as usize;
+            fp.reads.push(exec);
+            fp.read_src((instruction & 0x1ff) as usize, 1);
+            fp.reads
+                .push(RegRange::vgpr(((instruction >> 9) & 0xff) as usize, 1));
+            if matches!(op, 1 | 32..=34) {
+                fp.reads.push(vcc);
+            }
+            if matches!(op, 2 | 43 | 54) {
+                fp.reads.push(RegRange::vgpr(vdst, 1));
+            }
+            if matches!(op, 32..=34) {
+                fp.writes.push(vcc);
+            }
+            fp.writes.push(RegRange::vgpr(vdst, 1));
+        }
+        Encoding::Vopc => {
+            let op = (instruction >> 17) & 0xff;
+            let [l0, l1, _, _] = vop_widths(op);
+            fp.reads.push(exec);
+            fp.read_src((instruction & 0x1ff) as usize, l0);
+            fp.reads
+                .push(RegRange::vgpr(((instruction >> 9) & 0xff) as usize, l1));
+            fp.writes.push(if op >= 128 { exec } else { vcc });
+        }
+        Encoding::Vopd => {
+            let vdstx = ((instr >> 56) & 0xff) as usize;
+            let vdsty = ((((instr >> 49) & 0x7f) << 1) | ((vdstx as u64 & 1) ^ 1)) as usize;
+            let (opx, opy) = ((instr >> 22) & 0xf, (instr >> 17) & 0x1f);
+            fp.reads.push(exec);
+            for (op, src0, vsrc1, vdst) in [
+                (opx, instr & 0x1ff, (instr >> 9) & 0xff, vdstx),
+                (opy, (instr >> 32) & 0x1ff, (instr >> 41) & 0xff, vdsty),
+            ] {
+                fp.read_src(src0 as usize, 1);
+                fp.reads.push(RegRange::vgpr(vsrc1 as usize, 1));
+                match op {
+                    0 | 12 | 13 => fp.reads.push(RegRange::vgpr(vdst, 1)),
+                    9 => fp.reads.push(vcc),
+                    _ => {}
+                }
+                fp.writes.push(RegRange::vgpr(vdst, 1));
+            }
+        }
+        Encoding::Vop3 => {
+            let op = ((instr >> 16) & 0x3ff) as u32;
+            let vdst = (instr & 0xff) as usize;
+            let srcs = [32, 41, 50].map(|n| ((instr >> n) & 0x1ff) as usize);
+            let [l0, l1, l2, ld] = vop_widths(op);
+            fp.reads.push(exec);
+            match op {
+                0..=255 => {
+                    fp.read_src(srcs[0], l0);
+                    fp.read_src(srcs[1], l1);
+                    match op >= 128 {
+                        true => fp.writes.push(exec),
+                        false => fp.write_sdst(vdst, 1),
+                    }
+                }
+                // VOP3SD
+                288..=290 | 764..=770 => {
+                    let sdst = ((instr >> 8) & 0x7f) as usize;
+                    fp.read_src(srcs[0], l0);
+                    fp.read_src(srcs[1], l1);
+                    if !matches!(op, 768..=770) {
+                        fp.read_src(srcs[2], l2);
+                    }
+                    fp.writes.push(RegRange::vgpr(vdst, ld));
+                    fp.write_sdst(sdst, 1);
+                }
+                864 => {
+                    fp.read_src(srcs[0], 1);
+                    fp.read_src(srcs[1], 1);
+                    fp.write_sdst(vdst, 1);
+                }
+                _ => {
+                    let nsrc = match op {
+                        384..=511 => 1,
+                        256..=383 | 768..=863 | 865 => 2,
+                        _ => 3,
+                    };
+                    for (i, len) in [l0, l1, l2].iter().enumerate().take(nsrc) {
+                        fp.read_src(srcs[i], *len);
+                    }
+                    if op == 257 {
+                        fp.read_src(srcs[2], 1);
+                    }
+                    if op == 299 {
+                        fp.reads.push(RegRange::vgpr(vdst, 1));
+                    }
+                    fp.writes.push(RegRange::vgpr(vdst, ld));
+                }
+            }
+        }
+        Encoding::Vop3p => {
+            let op = ((instr >> 16) & 0x7f) as u32;
+            let vdst = (instr & 0xff) as usize;
+            let srcs = [32, 41, 50].map(|n| ((instr >> n) & 0x1ff) as usize);
+            fp.reads.push(exec);
+            match op {
+                64..=69 => {
+                    let ab = match op {
+                        68 => 4,
+                        69 => 2,
+                        _ => 8,
+                    };
+                    fp.read_src(srcs[0], ab);
+                    fp.read_src(srcs[1], ab);
+                    fp.read_src(srcs[2], 8);
+                    fp.writes.push(RegRange::vgpr(vdst, 8));
+                }
+                _ => {
+                    srcs.iter().for_each(|s| fp.read_src(*s, 1));
+                    fp.writes.push(RegRange::vgpr(vdst, 1));
+                }
+            }
+        }
+        Encoding::Ds => {
+            let op = ((instr >> 18) & 0xff) as u32;
+            let (data, ret) = ds_widths(op);
+            fp.reads.push(exec);
+            fp.reads
+                .push(RegRange::vgpr(((instr >> 32) & 0xff) as usize, 1));
+            if data.0 != 0 {
+                fp.reads
+                    .push(RegRange::vgpr(((instr >> 40) & 0xff) as usize, data.0));
+            }
+            if data.1 != 0 {
+                fp.reads
+                    .push(RegRange::vgpr(((instr >> 48) & 0xff) as usize, data.1));
+            }
+            if ret != 0 {
+                fp.writes
+                    .push(RegRange::vgpr(((instr >> 56) & 0xff) as usize, ret));
+            }
+        }
+        Encoding::Flat => {
+            let seg = (instr >> 16) & 0x3;
+            let op = ((instr >> 18) & 0x7f) as u32;
+            let addr = ((instr >> 32) & 0xff) as usize;
+            let saddr = ((instr >> 48) & 0x7f) as usize;
+            let saddr_off = saddr == NULL || saddr == 0x7f;
+            fp.reads.push(exec);
+            match seg {
+                1 => {
+                    if (instr >> 50) & 0x1 != 0 {
+                        fp.reads.push(RegRange::vgpr(addr, 1));
+                    }
+                    if !saddr_off {
+                        fp.reads.push(RegRange::sgpr(saddr, 1));
+                    }
+                }
+                _ => match saddr_off {
+                    true => fp.reads.push(RegRange::vgpr(addr, 2)),
+                    false => {
+                        fp.reads.push(RegRange::vgpr(addr, 1));
+                        fp.reads.push(RegRange::sgpr(saddr, 2));
+                    }
+                },
+            }
+            let (data, ret) = flat_widths(op, (instr >> 14) & 0x1 != 0);
+            if data != 0 {
+                fp.reads
+                    .push(RegRange::vgpr(((instr >> 40) & 0xff) as usize, data));
+            }
+            if ret != 0 {
+                fp.writes
+                    .push(RegRange::vgpr(((instr >> 56) & 0xff) as usize, ret));
+            }
+        }
+        Encoding::Mubuf
+        | Encoding::Mtbuf
+        | Encoding::Mimg
+        | Encoding::Exp
+        | Encoding::Vinterp
+        | Encoding::Ldsdir
+        | Encoding::Unknown => {}
+    }
+    fp
+}
+
+/* f32/f64 VALU ops that can raise IEEE exceptions */
+pub fn is_float(stream: &[u32]) -> bool {
+    let instruction = stream[0];
+    let vop1 = |op: u32| matches!(op, 23..=26 | 32..=37 | 39 | 42 | 43 | 46 | 47 | 49 | 51..=54);
+    let vop2 = |op: u32| matches!(op, 3 | 4 | 5 | 8 | 15 | 16 | 43..=45);
+    match encoding(instruction) {
+        Encoding::Vop1 => vop1((instruction >> 9) & 0xff),
+        Encoding::Vop2 => vop2((instruction >> 25) & 0x3f),
+        Encoding::Vop3 => match (instruction >> 16) & 0x3ff {
+            op @ 256..=319 => vop2(op - 256),
+            op @ 384..=511 => vop1(op - 384),
+            op => matches!(op, 531 | 532 | 537 | 540 | 551 | 567 | 568 | 807..=811),
+        },
+        Encoding::Vopd => {
+            let instr = u64_instr(stream);
+            let float = |op: u64| matches!(op, 0..=6 | 10 | 11);
+            float((instr >> 22) & 0xf) || float((instr >> 17) & 0x1f)
+        }
+        _ => false,
+    }
+}
+
+/* ((data0, data1), return) widths in dwords for a DS op */
+fn ds_widths(op: u32) -> ((usize, usize), usize) {
+    match op {
+        13 | 30 | 31 | 160 | 161 => ((1, 0), 0),
+        14..=16 => ((1, 1), 0),
+        0..=12 => ((1, 0), 0),
+        32..=45 | 53 => ((1, 0), 1),
+        48 => ((1, 1), 1),
+        54 | 57..=60 | 162..=167 => ((0, 0), 1),
+        55 | 56 => ((0, 0), 2),
+        61 | 62 => ((0, 0), 1),
+        77 => ((2, 0), 0),
+        78..=80 => ((2, 2), 0),
+        64..=76 => ((2, 0), 0),
+        96..=112 => ((2, 0), 2),
+        118 => ((0, 0), 2),
+        119 | 120 => ((0, 0), 4),
+        178 | 179 => ((1, 0), 1),
+        222 => ((3, 0), 0),
+        223 => ((4, 0), 0),
+        254 => ((0, 0), 3),
+        255 => ((0, 0), 4),
+        _ => ((0, 0), 0),
+    }
+}
+
+/* (data, return) widths in dwords for a FLAT/GLOBAL/SCRATCH op */
+fn flat_widths(op: u32, glc: bool) -> (usize, usize) {
+    match op {
+        16..=19 | 30..=35 => (0, 1),
+        20..=23 => (0, op as usize - 19),
+        24 | 25 | 36 | 37 => (1, 0),
+        26..=29 => (op as usize - 25, 0),
+        // atomics return the pre-op value with glc set
+        51..=63 => (1, glc as usize),
+        64..=95 => (2, glc as usize * 2),
+        _ => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod test_decode {
+    use super::*;
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(encoding(0xF4040000), Encoding::Smem);
+        assert_eq!(encoding(0xBF89FC07), Encoding::Sopp);
+        assert_eq!(encoding(0xBC7C0000), Encoding::Sopk);
+        assert_eq!(encoding(0xCA100080), Encoding::Vopd);
+        assert_eq!(encoding(0xDC6D000A), Encoding::Flat);
+        assert_eq!(encoding(0xD8D80100), Encoding::Ds);
+        assert_eq!(encoding(0x8D02027E), Encoding::Sop2);
+        assert_eq!(encoding(0xE0500000), Encoding::Mubuf);
+        assert_eq!(encoding(0xF8000000), Encoding::Exp);
+        assert_eq!(encoding(0xFC000000), Encoding::Unknown);
+    }
+
+    #[test]
+    fn test_instr_len() {
+        assert_eq!(instr_len(&[0xBF89FC07]), 1);
+        // s_mov_b32 s2, 0x3f800000
+        assert_eq!(instr_len(&[0xBE8200FF, 0x3F800000]), 2);
+        // v_fmaak_f32 v0, v1, v2, 0x3f800000
+        assert_eq!(instr_len(&[0x5A000501, 0x3F800000]), 2);
+        // v_add_f64 v[0:1], v[2:3], v[4:5]
+        assert_eq!(instr_len(&[0xD7270000, 0x00020902]), 2);
+        // v_fma_f32 v0, v1, 0x3f800000, v2
+        assert_eq!(instr_len(&[0xD6130000, 0x0409FF01, 0x3F800000]), 3);
+        // s_setreg_imm32_b32 hwreg(HW_REG_MODE, 12, 7), 0x4
+        assert_eq!(instr_len(&[0xB9803301, 0x4]), 2);
+        assert_eq!(instr_len(&[0xDC520000, 0x01020000]), 2);
+    }
+
+    #[test]
+    fn test_operands() {
+        // s_mov_b32 s2, 0x3f800000
+        let ops = Operands::decode(&[0xBE8200FF, 0x3F800000]);
+        assert_eq!((ops.op, ops.src[0], ops.dst[0]), (0, 255, 2));
+        assert_eq!(ops.literal, Some(0x3F800000));
+        // v_fma_f32 v0, v1, 0x3f800000, v2
+        let ops = Operands::decode(&[0xD6130000, 0x0409FF01, 0x3F800000]);
+        assert_eq!(
+            (ops.op, ops.src[..3].to_vec(), ops.dst[0]),
+            (531, vec![257, 255, 258], 0)
+        );
+        assert_eq!(ops.literal, Some(0x3F800000));
+        // v_add_f32 v0, s2, v1
+        assert_eq!(Operands::decode(&[0x06000202]).literal, None);
+    }
+
+    #[test]
+    fn test_is_float() {
+        // v_add_f32 v0, s2, v1
+        assert!(is_float(&[0x06000202]));
+        // v_add_nc_u32 v0, s2, v1
+        assert!(!is_float(&[0x4A000202]));
+        // v_add_f64 v[0:1], v[2:3], v[4:5]
+        assert!(is_float(&[0xD7270000, 0x00020902]));
+        // v_rcp_f32 v1, v0
+        assert!(is_float(&[0x7E025500]));
+    }
+
+    #[test]
+    fn test_footprint_smem() {
+        // s_load_b64 s[2:3], s[0:1], null
+        let fp = footprint(&[0xF4040080, 0xF8000000]);
+        assert_eq!(fp.reads, vec![RegRange::sgpr(0, 2)]);
+        assert_eq!(fp.writes, vec![RegRange::sgpr(2, 2)]);
+    }
+
+    #[test]
+    fn test_footprint_global_load() {
+        // global_load_b32 v1, v0, s[2:3]
+        let fp = footprint(&[0xDC520000, 0x01020000]);
+        assert!(fp.reads.contains(&RegRange::vgpr(0, 1)));
+        assert!(fp.reads.contains(&RegRange::sgpr(2, 2)));
+        assert_eq!(fp.writes, vec![RegRange::vgpr(1, 1)]);
+    }
+
+    #[test]
+    fn test_footprint_vop2() {
+        // v_add_f32 v0, s2, v1
+        let fp = footprint(&[0x06000202]);
+        assert!(fp.reads.contains(&RegRange::sgpr(2, 1)));
+        assert!(fp.reads.contains(&RegRange::vgpr(1, 1)));
+        assert!(fp.reads.contains(&RegRange::sgpr(EXEC, 1)));
+        assert_eq!(fp.writes, vec![RegRange::vgpr(0, 1)]);
+    }
+
+    #[test]
+    fn test_footprint_f64() {
+        // v_add_f64 v[0:1], v[2:3], v[4:5]
+        let fp = footprint(&[0xD7270000, 0x00020902]);
+        assert!(fp.reads.contains(&RegRange::vgpr(2, 2)));
+        assert!(fp.reads.contains(&RegRange::vgpr(4, 2)));
+        assert_eq!(fp.writes, vec![RegRange::vgpr(0, 2)]);
+    }
+
+    #[test]
+    fn test_reg_range_display() {
+        assert_eq!(RegRange::sgpr(2, 2).to_string(), "s[2:3]");
+        assert_eq!(RegRange::vgpr(7, 1).to_string(), "v7");
+        assert_eq!(RegRange::sgpr(VCC, 1).to_string(), "vcc_lo");
+        assert!(RegRange::vgpr(0, 4).overlaps(&RegRange::vgpr(3, 1)));
+        assert!(!RegRange::vgpr(0, 4).overlaps(&RegRange::sgpr(3, 1)));
+    }
+}
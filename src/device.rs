@@ -0,0 +1,171 @@
+/*
+ * A device address space remu owns, so kernels can run fully sandboxed. Buffers come from
+ * remu_device_alloc at addresses in the upper half of the 48-bit space, where no host pointer can
+ * be, and the host fills and reads them with the copy functions. A launch whose kernarg pointer is
+ * a device address runs in it: every global and SMEM access has to land in a live buffer.
+ * Addresses are never handed out twice, a freed buffer faults instead of aliasing a new one.
+ */
+use crate::allocation::{Allocation, Allocations, READ, WRITE};
+use crate::memory::Memory;
+use std::cell::UnsafeCell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub const DEVICE_BASE: u64 = 0x8000_0000_0000;
+const DEVICE_END: u64 = 0x1_0000_0000_0000;
+/* buffers start page aligned with an unused page after each */
+const PAGE: u64 = 0x1000;
+
+/* result of a device function given an address range outside the live buffers */
+pub const BAD_ADDRESS: i32 = 13;
+
+lazy_static::lazy_static! {
+    static ref DEVICE: Mutex<Device> = Mutex::new(Device::default());
+}
+
+/* lanes race on it like they do on host memory */
+struct Buffer(UnsafeCell<Box<[u8]>>);
+
+unsafe impl Sync for Buffer {}
+
+#[derive(Clone, Default)]
+pub struct Device {
+    allocations: Allocations,
+    buffers: BTreeMap<u64, Arc<Buffer>>,
+    /* where the next buffer goes, from DEVICE_BASE */
+    next: u64,
+}
+
+impl Device {
+    pub fn alloc(&mut self, size: u64) -> Option<u64> {
+        let addr = DEVICE_BASE + self.next;
+        let end = addr.checked_add(size).filter(|&end| end <= DEVICE_END)?;
+        let mut data = vec![];
+        data.try_reserve_exact(size as usize).ok()?;
+        data.resize(size as usize, 0);
+        let allocation = Allocation {
+            base: addr,
+            size,
+            perms: READ | WRITE,
+        };
+        self.allocations.insert(allocation).ok()?;
+        let buffer = Buffer(UnsafeCell::new(data.into_boxed_slice()));
+        self.buffers.insert(addr, Arc::new(buffer));
+        self.next = (end - DEVICE_BASE).div_ceil(PAGE) * PAGE + PAGE;
+        Some(addr)
+    }
+
+    pub fn free(&mut self, addr: u64) -> Result<(), i32> {
+        self.allocations.remove(addr).map_err(|_| BAD_ADDRESS)?;
+        self.buffers.remove(&addr);
+        Ok(())
+    }
+
+    pub fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), i32> {
+        let src = self
+            .host(addr, buf.len() as u64, false)
+            .ok_or(BAD_ADDRESS)?;
+        unsafe { std::ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), buf.len()) };
+        Ok(())
+    }
+
+    pub fn write(&self, addr: u64, data: &[u8]) -> Result<(), i32> {
+        let dst = self
+            .host(addr, data.len() as u64, true)
+            .ok_or(BAD_ADDRESS)?;
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len()) };
+        Ok(())
+    }
+
+    pub fn fill(&self, addr: u64, value: u8, len: u64) -> Result<(), i32> {
+        let dst = self.host(addr, len, true).ok_or(BAD_ADDRESS)?;
+        unsafe { std::ptr::write_bytes(dst, value, len as usize) };
+        Ok(())
+    }
+}
+
+impl Memory for Device {
+    fn host(&self, addr: u64, len: u64, store: bool) -> Option<*mut u8> {
+        if !self.allocations.allows(addr, len, store) {
+            return None;
+        }
+        let (base, buffer) = self.buffers.range(..=addr).next_back()?;
+        Some(unsafe { (*buffer.0.get()).as_mut_ptr().add((addr - base) as usize) })
+    }
+
+    fn nearest(&self, addr: u64) -> Option<Allocation> {
+        self.allocations.nearest(addr)
+    }
+}
+
+/* addresses only the device can hand out */
+pub fn owns(addr: u64) -> bool {
+    (DEVICE_BASE..DEVICE_END).contains(&addr)
+}
+
+/* the buffers a launch can access, freeing one later doesn't pull it out from under the launch */
+pub fn snapshot() -> Arc<Device> {
+    Arc::new(DEVICE.lock().unwrap().clone())
+}
+
+pub fn alloc(size: u64) -> Option<u64> {
+    DEVICE.lock().unwrap().alloc(size)
+}
+
+pub fn free(addr: u64) -> Result<(), i32> {
+    DEVICE.lock().unwrap().free(addr)
+}
+
+pub fn copy_h2d(dst: u64, src: &[u8]) -> Result<(), i32> {
+    DEVICE.lock().unwrap().write(dst, src)
+}
+
+pub fn copy_d2h(dst: &mut [u8], src: u64) -> Result<(), i32> {
+    DEVICE.lock().unwrap().read(src, dst)
+}
+
+pub fn memset(dst: u64, value: u8, len: u64) -> Result<(), i32> {
+    DEVICE.lock().unwrap().fill(dst, value, len)
+}
+
+#[cfg(test)]
+mod test_device {
+    use super::*;
+
+    #[test]
+    fn test_alloc() {
+        let mut device = Device::default();
+        let a = device.alloc(10).unwrap();
+        let b = device.alloc(PAGE).unwrap();
+        assert_eq!((a, b), (DEVICE_BASE, DEVICE_BASE + 2 * PAGE));
+        assert!(owns(a) && !owns(0x7f00_0000_0000));
+        assert_eq!(device.alloc(0), None);
+        assert_eq!(device.alloc(DEVICE_END), None);
+        device.free(a).unwrap();
+        assert_eq!(device.free(a), Err(BAD_ADDRESS));
+        // freed addresses aren't reused
+        assert_eq!(device.alloc(4), Some(DEVICE_BASE + 4 * PAGE));
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut device = Device::default();
+        let a = device.alloc(8).unwrap();
+        device.write(a + 2, &[1, 2, 3]).unwrap();
+        device.fill(a + 5, 0xff, 3).unwrap();
+        let mut buf = [0; 8];
+        device.read(a, &mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 1, 2, 3, 0xff, 0xff, 0xff]);
+        assert_eq!(device.read(a + 4, &mut buf), Err(BAD_ADDRESS));
+        assert_eq!(device.fill(a + 8, 0, 1), Err(BAD_ADDRESS));
+        // a snapshot shares the buffers, and keeps a freed one alive
+        let launch = device.clone();
+        launch.write(a, &[9]).unwrap();
+        device.read(a, &mut buf[..1]).unwrap();
+        assert_eq!(buf[0], 9);
+        device.free(a).unwrap();
+        assert_eq!(device.read(a, &mut buf), Err(BAD_ADDRESS));
+        launch.read(a, &mut buf).unwrap();
+        assert_eq!(launch.nearest(a + 20).map(|n| n.base), Some(a));
+    }
+}
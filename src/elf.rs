@@ -0,0 +1,246 @@
+/* just enough of ELF64 to pull kernel and trap handler code out of an AMDGPU code object */
+pub const TRAP_HANDLER: &str = "trap_handler";
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const SHT_SYMTAB: u32 = 2;
+const STT_FUNC: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeObject {
+    pub name: String,
+    pub code: Vec<u32>,
+    pub trap_handler: Option<usize>,
+    /* group_segment_fixed_size of the kernel descriptor */
+    pub group_segment_size: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct Symbol {
+    name: String,
+    kind: u8,
+    shndx: usize,
+    value: u64,
+    size: u64,
+}
+
+/* every offset comes from the file, a malformed one is out of range rather than a panic */
+fn bytes_at(b: &[u8], off: usize, len: usize) -> Option<&[u8]> {
+    b.get(off..off.checked_add(len)?)
+}
+fn u16_at(b: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes_at(b, off, 2)?.try_into().ok()?))
+}
+fn u32_at(b: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes_at(b, off, 4)?.try_into().ok()?))
+}
+fn u64_at(b: &[u8], off: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes_at(b, off, 8)?.try_into().ok()?))
+}
+fn cstr(b: &[u8], off: usize) -> Option<String> {
+    let s = b.get(off..)?;
+    let end = s.iter().position(|c| *c == 0)?;
+    Some(String::from_utf8_lossy(&s[..end]).to_string())
+}
+
+pub fn is_elf(lib: &[u8]) -> bool {
+    lib.starts_with(&ELF_MAGIC)
+}
+
+/* (sh_addr, sh_offset) */
+type Section = (u64, u64);
+
+fn sections(lib: &[u8]) -> Option<(Vec<Section>, Vec<Symbol>)> {
+    let shoff = u64_at(lib, 0x28)? as usize;
+    let shentsize = u16_at(lib, 0x3a)? as usize;
+    let shnum = u16_at(lib, 0x3c)? as usize;
+    let mut sections = vec![];
+    let mut symbols = vec![];
+    for i in 0..shnum {
+        let sh = shoff.checked_add(i.checked_mul(shentsize)?)?;
+        let header = bytes_at(lib, sh, 0x40)?;
+        sections.push((u64_at(header, 0x10)?, u64_at(header, 0x18)?));
+        if u32_at(header, 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let (offset, size) = (
+            u64_at(header, 0x18)? as usize,
+            u64_at(header, 0x20)? as usize,
+        );
+        let link = (u32_at(header, 0x28)? as usize).checked_mul(shentsize)?;
+        let strtab = u64_at(lib, shoff.checked_add(link)?.checked_add(0x18)?)? as usize;
+        for sym in bytes_at(lib, offset, size)?.chunks_exact(24).skip(1) {
+            symbols.push(Symbol {
+                name: cstr(lib, strtab.checked_add(u32_at(sym, 0)? as usize)?)?,
+                kind: sym[4] & 0xf,
+                shndx: u16_at(sym, 6)? as usize,
+                value: u64_at(sym, 8)?,
+                size: u64_at(sym, 16)?,
+            });
+        }
+    }
+    Some((sections, symbols))
+}
+
+fn words(lib: &[u8], sections: &[Section], sym: &Symbol) -> Option<Vec<u32>> {
+    let (addr, offset) = sections.get(sym.shndx)?;
+    let start = offset.checked_add(sym.value.checked_sub(*addr)?)?;
+    let code = bytes_at(lib, start as usize, sym.size as usize)?;
+    Some(
+        code.chunks_exact(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect(),
+    )
+}
+
+/* the kernel is the function with a matching .kd descriptor, the trap handler is appended after it */
+pub fn read_code_object(lib: &[u8]) -> Option<CodeObject> {
+    if !is_elf(lib) {
+        return None;
+    }
+    let (sections, symbols) = sections(lib)?;
+    let funcs = symbols.iter().filter(|s| s.kind == STT_FUNC);
+    let kernel = funcs
+        .clone()
+        .find(|f| symbols.iter().any(|s| s.name == format!("{}.kd", f.name)))
+        .or(funcs.clone().find(|f| f.name != TRAP_HANDLER))?;
+    let mut code = words(lib, &sections, kernel)?;
+    // the descriptor starts with the static LDS size
+    let descriptor = symbols
+        .iter()
+        .find(|s| s.name == format!("{}.kd", kernel.name));
+    let group_segment_size = descriptor.and_then(|kd| words(lib, &sections, kd)?.first().copied());
+    let trap_handler = match funcs.clone().find(|f| f.name == TRAP_HANDLER) {
+        Some(sym) => {
+            let handler = words(lib, &sections, sym)?;
+            let start = code.len();
+            code.extend(handler);
+            Some(start)
+        }
+        None => None,
+    };
+    Some(CodeObject {
+        name: kernel.name.clone(),
+        code,
+        trap_handler,
+        group_segment_size,
+    })
+}
+
+#[cfg(test)]
+mod test_elf {
+    use super::*;
+
+    /* .text, .symtab and .strtab with the given (name, kind, code) symbols */
+    fn build_elf(symbols: &[(&str, u8, &[u32])]) -> Vec<u8> {
+        let text = symbols
+            .iter()
+            .flat_map(|(_, _, code)| code.iter().flat_map(|w| w.to_le_bytes()))
+            .collect::<Vec<u8>>();
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; 24];
+        let mut value = 0x1000u64;
+        for (name, kind, code) in symbols {
+            symtab.extend((strtab.len() as u32).to_le_bytes());
+            symtab.extend([*kind, 0]);
+            symtab.extend(1u16.to_le_bytes());
+            symtab.extend(value.to_le_bytes());
+            symtab.extend((code.len() as u64 * 4).to_le_bytes());
+            strtab.extend(name.bytes().chain([0]));
+            value += code.len() as u64 * 4;
+        }
+        let mut lib = vec![0u8; 64];
+        lib[..4].copy_from_slice(&ELF_MAGIC);
+        let mut headers = vec![0u8; 64];
+        for (kind, addr, data, link) in [
+            (1u32, 0x1000u64, &text, 0u32),
+            (SHT_SYMTAB, 0, &symtab, 3),
+            (3, 0, &strtab, 0),
+        ] {
+            let mut sh = vec![0u8; 64];
+            sh[4..8].copy_from_slice(&kind.to_le_bytes());
+            sh[0x10..0x18].copy_from_slice(&addr.to_le_bytes());
+            sh[0x18..0x20].copy_from_slice(&(lib.len() as u64).to_le_bytes());
+            sh[0x20..0x28].copy_from_slice(&(data.len() as u64).to_le_bytes());
+            sh[0x28..0x2c].copy_from_slice(&link.to_le_bytes());
+            lib.extend(data.iter());
+            headers.extend(sh);
+        }
+        let shoff = lib.len() as u64;
+        lib.extend(headers);
+        lib[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        lib[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        lib[0x3c..0x3e].copy_from_slice(&4u16.to_le_bytes());
+        lib
+    }
+
+    #[test]
+    fn test_raw_code_is_not_elf() {
+        assert_eq!(read_code_object(&[0x00, 0x00, 0xb0, 0xbf]), None);
+    }
+
+    #[test]
+    fn test_kernel_only() {
+        let lib = build_elf(&[
+            ("E_4", STT_FUNC, &[0xBE8200FF, 0x3F800000, 0xBFB00000]),
+            ("E_4.kd", 1, &[]),
+        ]);
+        let co = read_code_object(&lib).unwrap();
+        assert_eq!(co.name, "E_4");
+        assert_eq!(co.code, vec![0xBE8200FF, 0x3F800000, 0xBFB00000]);
+        assert_eq!(co.trap_handler, None);
+        assert_eq!(co.group_segment_size, None);
+    }
+
+    #[test]
+    fn test_group_segment_size() {
+        let lib = build_elf(&[
+            ("E_4", STT_FUNC, &[0xBFB00000]),
+            ("E_4.kd", 1, &[0x1000, 0, 0x10, 0]),
+        ]);
+        let co = read_code_object(&lib).unwrap();
+        assert_eq!(co.code, vec![0xBFB00000]);
+        assert_eq!(co.group_segment_size, Some(0x1000));
+    }
+
+    #[test]
+    fn test_trap_handler_appended() {
+        let lib = build_elf(&[
+            (TRAP_HANDLER, STT_FUNC, &[0xBE804A6C]),
+            ("E_4", STT_FUNC, &[0xBF900001, 0xBFB00000]),
+            ("E_4.kd", 1, &[]),
+        ]);
+        let co = read_code_object(&lib).unwrap();
+        assert_eq!(co.name, "E_4");
+        assert_eq!(co.code, vec![0xBF900001, 0xBFB00000, 0xBE804A6C]);
+        assert_eq!(co.trap_handler, Some(2));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let lib = build_elf(&[("E_4", STT_FUNC, &[0xBFB00000]), ("E_4.kd", 1, &[])]);
+        let shoff = u64_at(&lib, 0x28).unwrap() as usize;
+        let symtab = u64_at(&lib, shoff + 128 + 0x18).unwrap() as usize;
+        let corrupt = |off: usize, value: u64| {
+            let mut lib = lib.clone();
+            lib[off..off + 8].copy_from_slice(&value.to_le_bytes());
+            read_code_object(&lib)
+        };
+        // section headers and a symbol table past the end of the file
+        assert_eq!(corrupt(0x28, u64::MAX), None);
+        assert_eq!(corrupt(shoff + 128 + 0x20, u64::MAX), None);
+        // a symbol before the start of its section
+        assert_eq!(corrupt(symtab + 24 + 8, 0), None);
+        assert!(corrupt(symtab + 24 + 16, 4).is_some());
+        // a truncated symbol table entry
+        assert_eq!(read_code_object(&lib[..symtab + 28]), None);
+    }
+
+    #[test]
+    fn test_malformed_launch() {
+        let mut lib = build_elf(&[("E_4", STT_FUNC, &[0xBFB00000]), ("E_4.kd", 1, &[])]);
+        lib[0x28..0x30].copy_from_slice(&u64::MAX.to_le_bytes());
+        // the header doesn't run as raw instruction words
+        let (ptr, len) = (lib.as_ptr() as *const _, lib.len() as u32);
+        let ret = crate::run_asm(ptr, len, 1, 1, 1, 1, 1, 1, std::ptr::null());
+        assert_eq!(ret, crate::error::INVALID_LAUNCH);
+    }
+}
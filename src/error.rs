@@ -0,0 +1,244 @@
+/*
+ * What went wrong in the last launch on this thread, with where it happened. run_asm keeps
+ * returning an error code, remu_last_error has the message. Launches return the error of the
+ * workgroup that comes first in dispatch order.
+ */
+use crate::allocation::Allocation;
+use crate::mnemonic::mnemonic;
+use crate::trap::{Trap, FAULT};
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/* run_asm result for a null or misaligned kernel or an empty or oversized workgroup */
+pub const INVALID_LAUNCH: i32 = 9;
+/* run_asm result for a launch that ran past TIMEOUT */
+pub const TIMED_OUT: i32 = 10;
+/* result of an entry point that panicked, a bug in remu rather than in the kernel */
+pub const PANICKED: i32 = 11;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<RemuError>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /* byte offset into the kernel */
+    pub pc: usize,
+    pub word: u32,
+    /* None before the launch ran anything */
+    pub workgroup: Option<[u32; 3]>,
+    pub wave: Option<usize>,
+    pub lane: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemuError {
+    Unimplemented(Location),
+    /* nearest is the registered allocation closest to addr */
+    MemoryFault {
+        at: Location,
+        addr: u64,
+        nearest: Option<Allocation>,
+    },
+    /* an illegal instruction without a trap handler */
+    Decode(Location),
+    /* s_trap, s_sendmsg or a float exception without a trap handler */
+    Trap {
+        at: Location,
+        trap: Trap,
+    },
+    InvalidLaunch(String),
+    Timeout(Location),
+    /* where the launch was if it was running an instruction */
+    Panic {
+        message: String,
+        at: Option<Location>,
+    },
+}
+
+impl RemuError {
+    /* a trap that ends the launch */
+    pub fn fault(at: Location, trap: Trap) -> Self {
+        match trap {
+            Trap::MemoryViolation(addr) => RemuError::MemoryFault {
+                at,
+                addr,
+                nearest: None,
+            },
+            Trap::IllegalInstruction => RemuError::Decode(at),
+            trap => RemuError::Trap { at, trap },
+        }
+    }
+
+    /* what run_asm returns for it */
+    pub fn code(&self) -> i32 {
+        match self {
+            RemuError::Unimplemented(_) => 1,
+            RemuError::MemoryFault { .. } | RemuError::Decode(_) | RemuError::Trap { .. } => FAULT,
+            RemuError::InvalidLaunch(_) => INVALID_LAUNCH,
+            RemuError::Timeout(_) => TIMED_OUT,
+            RemuError::Panic { .. } => PANICKED,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = mnemonic(&[self.word]);
+        write!(f, "{name} (0x{:08X}) at pc=0x{:x}", self.word, self.pc)?;
+        if let Some(workgroup) = self.workgroup {
+            write!(f, " workgroup {workgroup:?}")?;
+        }
+        if let Some(wave) = self.wave {
+            write!(f, " wave {wave}")?;
+        }
+        if let Some(lane) = self.lane {
+            write!(f, " lane {lane}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RemuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RemuError::Unimplemented(at) => write!(f, "unimplemented instruction {at}"),
+            RemuError::MemoryFault { at, addr, nearest } => {
+                write!(f, "memory violation at 0x{addr:x} by {at}")?;
+                match nearest {
+                    Some(a) => write!(f, ", offset {} of allocation {a}", a.offset(*addr)),
+                    None => Ok(()),
+                }
+            }
+            RemuError::Decode(at) => write!(f, "illegal instruction {at}"),
+            RemuError::Trap { at, trap } => write!(f, "{trap} without a trap handler by {at}"),
+            RemuError::InvalidLaunch(why) => write!(f, "invalid launch: {why}"),
+            RemuError::Timeout(at) => write!(f, "timed out at {at}"),
+            RemuError::Panic { message, at: None } => write!(f, "panic: {message}"),
+            RemuError::Panic {
+                message,
+                at: Some(at),
+            } => write!(f, "panic: {message} at {at}"),
+        }
+    }
+}
+
+pub fn set_last(err: Option<RemuError>) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = err);
+}
+
+pub fn last() -> Option<RemuError> {
+    LAST_ERROR.with(|e| e.borrow().clone())
+}
+
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(s), _) => s.to_string(),
+        (_, Some(s)) => s.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+/*
+ * every exported function runs in here, a panic unwinding out of an extern "C" function aborts the
+ * host process. It becomes fallback and the last error instead.
+ */
+pub fn guard<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(ret) => ret,
+        Err(payload) => {
+            let err = RemuError::Panic {
+                message: panic_message(&*payload),
+                at: None,
+            };
+            println!("[remu] {err}");
+            set_last(Some(err));
+            fallback
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_error {
+    use super::*;
+
+    fn at(lane: Option<usize>) -> Location {
+        Location {
+            pc: 4,
+            word: 0xBE850801,
+            workgroup: Some([1, 0, 0]),
+            wave: Some(0),
+            lane,
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let err = RemuError::Unimplemented(at(None));
+        assert_eq!(
+            err.to_string(),
+            "unimplemented instruction s_ctz_i32_b32 (0xBE850801) at pc=0x4 workgroup [1, 0, 0] wave 0"
+        );
+        let err = RemuError::fault(at(Some(3)), Trap::MemoryViolation(0x10));
+        assert_eq!(err.code(), FAULT);
+        assert!(err
+            .to_string()
+            .starts_with("memory violation at 0x10 by s_ctz_i32_b32"));
+        assert!(err.to_string().ends_with("wave 0 lane 3"));
+        let err = RemuError::MemoryFault {
+            at: at(Some(3)),
+            addr: 0x1104,
+            nearest: Some(Allocation {
+                base: 0x1000,
+                size: 0x100,
+                perms: crate::allocation::READ,
+            }),
+        };
+        assert!(err
+            .to_string()
+            .ends_with("lane 3, offset 260 of allocation 0x1000+0x100 (r-)"));
+        let err = RemuError::fault(at(None), Trap::Software(2));
+        assert!(err
+            .to_string()
+            .starts_with("s_trap 2 without a trap handler"));
+    }
+
+    #[test]
+    fn test_last() {
+        set_last(Some(RemuError::InvalidLaunch("kernel is null".into())));
+        assert_eq!(last().map(|e| e.code()), Some(INVALID_LAUNCH));
+        // every thread has its own
+        std::thread::spawn(|| assert_eq!(last(), None))
+            .join()
+            .unwrap();
+        set_last(None);
+        assert_eq!(last(), None);
+    }
+
+    #[test]
+    fn test_guard() {
+        assert_eq!(guard(PANICKED, || 0), 0);
+        assert_eq!(last(), None);
+        let ret = guard(PANICKED, || -> i32 { todo!("resolve_src=300") });
+        assert_eq!(ret, PANICKED);
+        assert_eq!(
+            last().map(|e| e.to_string()),
+            Some("panic: not yet implemented: resolve_src=300".to_string())
+        );
+        let at = Some(at(Some(1)));
+        let err = RemuError::Panic {
+            message: "index out of bounds".into(),
+            at,
+        };
+        assert_eq!(err.code(), PANICKED);
+        assert!(err
+            .to_string()
+            .ends_with("s_ctz_i32_b32 (0xBE850801) at pc=0x4 workgroup [1, 0, 0] wave 0 lane 1"));
+        set_last(None);
+    }
+}
@@ -0,0 +1,710 @@
+/*
+ * GDB=<host:port> or GDB=unix:<path> serves each run_asm launch to a debugger over the GDB remote
+ * serial protocol. Waves of the current workgroup are threads 1.., registers follow the amdgcn
+ * layout in target.xml, the kernel is mapped at address 0, LDS at LDS_BASE and everything else is
+ * host (global) memory. Z2 write watchpoints work on global and LDS ranges. Every wave keeps the
+ * last REWIND instructions so reverse-step and reverse-continue work on the selected thread.
+ * Detaching or closing the connection runs the launch to completion.
+ */
+use crate::debugger::{Breakpoint, Event, Location, Session};
+use crate::decode::M0;
+use crate::device;
+use crate::trap::invalid_address;
+use crate::watch::{Space, Target, Watchpoint};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::os::unix::fs::FileExt;
+use std::os::unix::net::UnixListener;
+
+/* run_asm result for a launch killed from the debugger */
+pub const KILLED: i32 = 6;
+pub const LDS_BASE: u64 = 1 << 60;
+/* instructions each wave can step back */
+const REWIND: usize = 4096;
+
+const SGPRS: usize = 106;
+const PC: usize = SGPRS;
+const EXEC: usize = PC + 1;
+const VCC: usize = PC + 2;
+const REG_M0: usize = PC + 3;
+const STATUS: usize = PC + 4;
+const VGPR0: usize = PC + 5;
+const REGS: usize = VGPR0 + 256;
+const LANES: usize = 32;
+
+trait Conn: Read + Write {}
+impl<T: Read + Write> Conn for T {}
+
+pub fn serve(addr: &str, mut session: Session) -> i32 {
+    println!("[remu] gdb: waiting for a connection on {addr}");
+    let conn: std::io::Result<Box<dyn Conn>> = match addr.strip_prefix("unix:") {
+        Some(path) => {
+            let _ = fs::remove_file(path);
+            UnixListener::bind(path)
+                .and_then(|l| l.accept())
+                .map(|(c, _)| Box::new(c) as Box<dyn Conn>)
+        }
+        None => TcpListener::bind(addr)
+            .and_then(|l| l.accept())
+            .map(|(c, _)| Box::new(c) as Box<dyn Conn>),
+    };
+    match conn {
+        Ok(conn) => {
+            let ret = Stub::new(session, conn).run();
+            if let Some(path) = addr.strip_prefix("unix:") {
+                let _ = fs::remove_file(path);
+            }
+            ret
+        }
+        Err(err) => {
+            println!("[remu] gdb: {err}, running without a debugger");
+            run(&mut session)
+        }
+    }
+}
+
+fn run(session: &mut Session) -> i32 {
+    loop {
+        match session.cont() {
+            Ok(Event::Done) => return 0,
+            Ok(_) => {}
+            Err(err) => return err,
+        }
+    }
+}
+
+pub struct Stub<C: Read + Write> {
+    session: Session,
+    conn: C,
+    no_ack: bool,
+    /* the thread register and memory packets act on, a wave index */
+    wave: usize,
+    /* Z0 address -> session breakpoint id */
+    breakpoints: HashMap<u64, usize>,
+    /* Z2 address and length -> session watchpoint id */
+    watchpoints: HashMap<(u64, u64), usize>,
+    /* the launch's run_asm result once it finished */
+    result: Option<i32>,
+}
+
+impl<C: Read + Write> Stub<C> {
+    pub fn new(mut session: Session, conn: C) -> Self {
+        session.record(None, None, REWIND);
+        Stub {
+            session,
+            conn,
+            no_ack: false,
+            wave: 0,
+            breakpoints: HashMap::new(),
+            watchpoints: HashMap::new(),
+            result: None,
+        }
+    }
+
+    pub fn run(&mut self) -> i32 {
+        while let Some(packet) = self.read_packet() {
+            match packet.as_str() {
+                "k" => return self.result.unwrap_or(KILLED),
+                p if p.starts_with('D') => {
+                    self.write_packet("OK");
+                    break;
+                }
+                p => {
+                    let reply = self.handle(p);
+                    self.write_packet(&reply);
+                }
+            }
+        }
+        self.finish()
+    }
+
+    /* run whatever is left of the launch without breakpoints */
+    fn finish(&mut self) -> i32 {
+        if let Some(ret) = self.result {
+            return ret;
+        }
+        for (_, id) in self.breakpoints.drain() {
+            let _ = self.session.remove_breakpoint(id);
+        }
+        for (_, id) in self.watchpoints.drain() {
+            let _ = self.session.remove_watchpoint(id);
+        }
+        run(&mut self.session)
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut b = [0];
+        match self.conn.read(&mut b) {
+            Ok(1) => Some(b[0]),
+            _ => None,
+        }
+    }
+
+    /* the payload of the next $<data>#<checksum> packet, None once the connection closed */
+    fn read_packet(&mut self) -> Option<String> {
+        loop {
+            // acks and interrupts while the target is already stopped
+            if self.read_byte()? != b'$' {
+                continue;
+            }
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    b => data.push(b),
+                }
+            }
+            let sum = [self.read_byte()?, self.read_byte()?];
+            let ok = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+                .is_some_and(|s| s == checksum(&data));
+            if !self.no_ack {
+                let _ = self.conn.write_all(if ok { b"+" } else { b"-" });
+            }
+            if ok {
+                return Some(String::from_utf8_lossy(&data).into_owned());
+            }
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) {
+        let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+        let _ = self.conn.write_all(packet.as_bytes());
+        let _ = self.conn.flush();
+    }
+
+    pub fn handle(&mut self, packet: &str) -> String {
+        let Some(cmd) = packet.chars().next() else {
+            return String::new();
+        };
+        let rest = &packet[1..];
+        let reply = match cmd {
+            '?' => match self.session.waves() {
+                0 => Ok(self.exited(0)),
+                _ => Ok(format!("T05thread:{:x};", self.wave + 1)),
+            },
+            'g' => (0..REGS)
+                .map(|n| self.reg(n).map(|b| hex(&b)))
+                .collect::<Result<String, _>>(),
+            'G' => self.set_regs(&unhex(rest)),
+            'p' => parse(rest).and_then(|n| self.reg(n as usize).map(|b| hex(&b))),
+            'P' => match rest.split_once('=') {
+                Some((n, v)) => parse(n).and_then(|n| self.set_reg(n as usize, &unhex(v))),
+                None => Err(0),
+            },
+            'm' => match rest.split_once(',').map(|(a, l)| (parse(a), parse(l))) {
+                Some((Ok(addr), Ok(len))) => self.read_mem(addr, len as usize).map(|b| hex(&b)),
+                _ => Err(0),
+            },
+            'M' => match rest.split_once(':') {
+                Some((range, data)) => match range.split_once(',').map(|(a, _)| parse(a)) {
+                    Some(Ok(addr)) => self.write_mem(addr, &unhex(data)),
+                    _ => Err(0),
+                },
+                None => Err(0),
+            },
+            'Z' | 'z' => self.breakpoint(cmd == 'Z', rest),
+            'c' => Ok(self.resume(None)),
+            'b' => Ok(self.reverse(rest == "s")),
+            's' => Ok(self.resume(Some(self.wave))),
+            'H' => {
+                if let Ok(id) = parse(rest.get(1..).unwrap_or("")) {
+                    // 0 and -1 mean any thread
+                    if id > 0 && (id as usize) <= self.session.waves() {
+                        self.wave = id as usize - 1;
+                    }
+                }
+                Ok("OK".into())
+            }
+            'T' => match parse(rest) {
+                Ok(id) if id > 0 && (id as usize) <= self.session.waves() => Ok("OK".into()),
+                _ => Err(1),
+            },
+            _ => Ok(self.query(packet)),
+        };
+        reply.unwrap_or_else(|err| format!("E{:02x}", err & 0xff))
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if let Some(annex) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_xml();
+            let (off, len) = annex.split_once(',').unwrap_or(("0", "0"));
+            let off = (parse(off).unwrap_or(0) as usize).min(xml.len());
+            let end = (off + parse(len).unwrap_or(0) as usize).min(xml.len());
+            let more = if end < xml.len() { 'm' } else { 'l' };
+            return format!("{more}{}", &xml[off..end]);
+        }
+        if let Some(id) = packet.strip_prefix("qThreadExtraInfo,") {
+            let wave = parse(id).unwrap_or(1).saturating_sub(1);
+            let [x, y, z] = self.session.workgroup().unwrap_or_default();
+            return hex(format!("workgroup {x},{y},{z} wave {wave}").as_bytes());
+        }
+        if let Some(actions) = packet.strip_prefix("vCont;") {
+            // only a single-step action names a thread, everything else continues
+            let waves = self.session.waves();
+            let step = actions.split(';').find_map(|a| {
+                let (action, thread) = a.split_once(':').unwrap_or((a, ""));
+                match (action, parse(thread)) {
+                    ("s" | "S", _) if thread.is_empty() => Some(Ok(self.wave)),
+                    ("s" | "S", Ok(id)) if id > 0 && (id as usize) <= waves => {
+                        Some(Ok(id as usize - 1))
+                    }
+                    ("s" | "S", _) => Some(Err(())),
+                    _ => None,
+                }
+            });
+            return match step.transpose() {
+                Ok(step) => self.resume(step),
+                Err(()) => "E01".into(),
+            };
+        }
+        match packet {
+            p if p.starts_with("qSupported") => concat!(
+                "PacketSize=20000;QStartNoAckMode+;qXfer:features:read+;swbreak+;",
+                "vContSupported+;ReverseStep+;ReverseContinue+"
+            )
+            .into(),
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".into()
+            }
+            "qfThreadInfo" => {
+                let ids = (1..=self.session.waves()).map(|i| format!("{i:x}"));
+                format!("m{}", ids.collect::<Vec<_>>().join(","))
+            }
+            "qsThreadInfo" => "l".into(),
+            "qC" => format!("QC{:x}", self.wave + 1),
+            "qAttached" => "1".into(),
+            "vCont?" => "vCont;c;C;s;S".into(),
+            _ => String::new(),
+        }
+    }
+
+    /* continue, or single-step one wave, and report where the launch stopped */
+    fn resume(&mut self, step: Option<usize>) -> String {
+        if let Some(ret) = self.result {
+            return self.exited(ret);
+        }
+        let event = match step {
+            Some(wave) => self.session.step(wave),
+            None => self.session.cont(),
+        };
+        match event {
+            Ok(Event::Break { wave, .. }) => {
+                self.wave = wave;
+                format!("T05thread:{:x};swbreak:;", wave + 1)
+            }
+            Ok(Event::Step { wave, .. }) => {
+                self.wave = wave;
+                format!("T05thread:{:x};", wave + 1)
+            }
+            Ok(Event::Watch(hit)) => {
+                self.wave = hit.wave;
+                let addr = match hit.what {
+                    Target::Memory {
+                        space: Space::Lds,
+                        addr,
+                        ..
+                    } => LDS_BASE + addr,
+                    Target::Memory { addr, .. } => addr,
+                    _ => 0,
+                };
+                format!("T05thread:{:x};watch:{addr:x};", hit.wave + 1)
+            }
+            Ok(Event::Done) => self.exited(0),
+            Err(err) => self.exited(err),
+        }
+    }
+
+    /* bs steps the selected wave back one instruction, bc until it's back at a breakpoint */
+    fn reverse(&mut self, step: bool) -> String {
+        let wave = self.wave;
+        loop {
+            if self.session.step_back(wave).is_err() {
+                return format!("T05thread:{:x};replaylog:begin;", wave + 1);
+            }
+            let pc = self.session.pc(wave).unwrap_or(0) as u64;
+            if step {
+                return format!("T05thread:{:x};", wave + 1);
+            }
+            if self.breakpoints.contains_key(&pc) {
+                return format!("T05thread:{:x};swbreak:;", wave + 1);
+            }
+        }
+    }
+
+    fn exited(&mut self, ret: i32) -> String {
+        self.result = Some(ret);
+        format!("W{:02x}", ret & 0xff)
+    }
+
+    /* Z0/Z1,<addr>,<kind>, Z2,<addr>,<len> and their z counterparts */
+    fn breakpoint(&mut self, insert: bool, args: &str) -> Result<String, i32> {
+        let mut args = args.split(',');
+        let kind = args.next();
+        if !matches!(kind, Some("0" | "1" | "2")) {
+            return Ok(String::new());
+        }
+        let addr = parse(args.next().unwrap_or(""))?;
+        if kind == Some("2") {
+            let len = parse(args.next().unwrap_or(""))?;
+            return self.watchpoint(insert, addr, len);
+        }
+        match insert {
+            true if !self.breakpoints.contains_key(&addr) => {
+                let id = self.session.add_breakpoint(Breakpoint {
+                    at: Location::Pc(addr as usize),
+                    workgroup: None,
+                    wave: None,
+                });
+                self.breakpoints.insert(addr, id);
+            }
+            true => {}
+            false => {
+                if let Some(id) = self.breakpoints.remove(&addr) {
+                    self.session.remove_breakpoint(id)?;
+                }
+            }
+        }
+        Ok("OK".into())
+    }
+
+    fn watchpoint(&mut self, insert: bool, addr: u64, len: u64) -> Result<String, i32> {
+        match insert {
+            true if !self.watchpoints.contains_key(&(addr, len)) => {
+                let (space, start) = match addr >= LDS_BASE {
+                    true => (Space::Lds, addr - LDS_BASE),
+                    false => (Space::Global, addr),
+                };
+                let target = Target::Memory {
+                    space,
+                    addr: start,
+                    len,
+                };
+                let id = self
+                    .session
+                    .add_watchpoint(Watchpoint { target, stop: true });
+                self.watchpoints.insert((addr, len), id);
+            }
+            true => {}
+            false => {
+                if let Some(id) = self.watchpoints.remove(&(addr, len)) {
+                    self.session.remove_watchpoint(id)?;
+                }
+            }
+        }
+        Ok("OK".into())
+    }
+
+    /* register n of the selected wave as little endian bytes */
+    fn reg(&self, n: usize) -> Result<Vec<u8>, i32> {
+        let (s, w) = (&self.session, self.wave);
+        Ok(match n {
+            0..SGPRS => s.sgpr(w, n)?.to_le_bytes().to_vec(),
+            PC => (s.pc(w)? as u64).to_le_bytes().to_vec(),
+            EXEC => s.exec(w)?.to_le_bytes().to_vec(),
+            VCC => s.vcc(w)?.to_le_bytes().to_vec(),
+            REG_M0 => s.sgpr(w, M0)?.to_le_bytes().to_vec(),
+            STATUS => s.scc(w)?.to_le_bytes().to_vec(),
+            VGPR0..REGS => {
+                let lanes = s.lanes(w)?;
+                // lanes past the end of a partial wave read as zero
+                (0..LANES)
+                    .flat_map(|l| match l < lanes {
+                        true => s.vgpr(w, l, n - VGPR0).unwrap_or(0).to_le_bytes(),
+                        false => [0; 4],
+                    })
+                    .collect()
+            }
+            _ => return Err(0x16),
+        })
+    }
+
+    fn set_reg(&mut self, n: usize, bytes: &[u8]) -> Result<String, i32> {
+        let word = |i: usize| -> Result<u32, i32> {
+            match bytes.get(i * 4..i * 4 + 4) {
+                Some(b) => Ok(u32::from_le_bytes(b.try_into().unwrap())),
+                None => Err(0x16),
+            }
+        };
+        let (s, w) = (&mut self.session, self.wave);
+        match n {
+            0..SGPRS => s.set_sgpr(w, n, word(0)?)?,
+            PC => s.set_pc(w, word(0)? as usize)?,
+            EXEC => s.set_exec(w, word(0)?)?,
+            VCC => s.set_vcc(w, word(0)?)?,
+            REG_M0 => s.set_sgpr(w, M0, word(0)?)?,
+            STATUS => s.set_scc(w, word(0)?)?,
+            VGPR0..REGS => {
+                for lane in 0..s.lanes(w)? {
+                    s.set_vgpr(w, lane, n - VGPR0, word(lane)?)?;
+                }
+            }
+            _ => return Err(0x16),
+        }
+        Ok("OK".into())
+    }
+
+    fn set_regs(&mut self, mut bytes: &[u8]) -> Result<String, i32> {
+        for n in 0..REGS {
+            let len = self.reg(n)?.len();
+            if bytes.len() < len {
+                break;
+            }
+            self.set_reg(n, &bytes[..len])?;
+            bytes = &bytes[len..];
+        }
+        Ok("OK".into())
+    }
+
+    fn read_mem(&self, addr: u64, len: usize) -> Result<Vec<u8>, i32> {
+        let code = self.session.code();
+        if addr >= LDS_BASE {
+            return self.session.lds((addr - LDS_BASE) as usize, len);
+        }
+        if addr as usize + len <= code.len() * 4 {
+            let bytes = code.iter().flat_map(|w| w.to_le_bytes());
+            return Ok(bytes.skip(addr as usize).take(len).collect());
+        }
+        let mut buf = vec![0; len];
+        match device::owns(addr) {
+            true => device::copy_d2h(&mut buf, addr).map_err(|_| 0x0e)?,
+            false => host_mem(addr, len, |mem| mem.read_exact_at(&mut buf, addr))?,
+        }
+        Ok(buf)
+    }
+
+    fn write_mem(&mut self, addr: u64, bytes: &[u8]) -> Result<String, i32> {
+        if addr >= LDS_BASE {
+            self.session.set_lds((addr - LDS_BASE) as usize, bytes)?;
+        } else if addr < self.session.code().len() as u64 * 4 {
+            // breakpoints go through Z0 instead of patching the kernel
+            return Err(0x0d);
+        } else if device::owns(addr) {
+            device::copy_h2d(addr, bytes).map_err(|_| 0x0e)?;
+        } else {
+            host_mem(addr, bytes.len(), |mem| mem.write_all_at(bytes, addr))?;
+        }
+        Ok("OK".into())
+    }
+}
+
+/* /proc/self/mem reports unmapped host memory as an error rather than faulting */
+fn host_mem(
+    addr: u64,
+    len: usize,
+    f: impl FnOnce(&fs::File) -> std::io::Result<()>,
+) -> Result<(), i32> {
+    if invalid_address(addr, len as u64) {
+        return Err(0x0e);
+    }
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/proc/self/mem")
+        .and_then(|mem| f(&mem))
+        .map_err(|_| 0x0e)
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn parse(s: &str) -> Result<u64, i32> {
+    match s {
+        "-1" => Ok(u64::MAX),
+        _ => u64::from_str_radix(s, 16).map_err(|_| 0x16),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Vec<u8> {
+    (0..s.len() / 2)
+        .filter_map(|i| u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok())
+        .collect()
+}
+
+fn target_xml() -> String {
+    let mut regs = (0..SGPRS)
+        .map(|i| format!("<reg name=\"s{i}\" bitsize=\"32\" type=\"uint32\"/>"))
+        .collect::<Vec<_>>();
+    regs.push("<reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/>".into());
+    for name in ["exec", "vcc", "m0", "status"] {
+        regs.push(format!(
+            "<reg name=\"{name}\" bitsize=\"32\" type=\"uint32\"/>"
+        ));
+    }
+    regs.extend((0..256).map(|i| format!("<reg name=\"v{i}\" bitsize=\"1024\" type=\"v32u32\"/>")));
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target version=\"1.0\">\
+         <architecture>amdgcn:gfx1100</architecture><feature name=\"org.gnu.gdb.amdgpu.core\">\
+         <vector id=\"v32u32\" type=\"uint32\" count=\"32\"/>{}</feature></target>",
+        regs.concat()
+    )
+}
+
+#[cfg(test)]
+mod test_gdb {
+    use super::*;
+    use crate::schedule::Schedule;
+    use crate::utils::END_PRG;
+    use std::os::unix::net::UnixStream;
+
+    fn stub(kernel: &[u32], args: *const u64) -> Stub<std::io::Cursor<Vec<u8>>> {
+        let lib = kernel
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut session = Session::new(&lib, [1, 1, 1, 33, 1, 1], args).unwrap();
+        // the tests expect the first wave to stop first
+        session.schedule = Schedule::Wave;
+        Stub::new(session, std::io::Cursor::new(vec![]))
+    }
+
+    const KERNEL: [u32; 4] = [
+        0x7E020281, // v_mov_b32 v1, 1
+        0xBE850087, // s_mov_b32 s5, 7
+        0x80060505, // s_add_u32 s6, s5, s5
+        END_PRG,
+    ];
+
+    #[test]
+    fn test_breakpoint_and_registers() {
+        let mut s = stub(&KERNEL, std::ptr::null());
+        assert_eq!(s.handle("?"), "T05thread:1;");
+        assert_eq!(s.handle("qfThreadInfo"), "m1,2");
+        assert_eq!(s.handle("Z0,8,4"), "OK");
+        assert_eq!(s.handle("c"), "T05thread:1;swbreak:;");
+        assert_eq!(s.handle(&format!("p{PC:x}")), "0800000000000000");
+        assert_eq!(s.handle("p5"), "07000000");
+        assert_eq!(
+            s.handle(&format!("p{:x}", VGPR0 + 1)),
+            "01000000".repeat(32)
+        );
+        assert_eq!(s.handle("P5=0a000000"), "OK");
+        assert_eq!(s.handle("z0,8,4"), "OK");
+        assert_eq!(s.handle("s"), "T05thread:1;");
+        assert_eq!(s.handle("p6"), "14000000");
+        for bad in ["vCont;s:0", "vCont;s:-1", "vCont;s:3", "vCont;s:x"] {
+            assert_eq!(s.handle(bad), "E01");
+        }
+        assert_eq!(s.handle("Hg2"), "OK");
+        // the second wave only has lane 0
+        let v1 = VGPR0 + 1;
+        assert_eq!(
+            s.handle(&format!("P{v1:x}={}", "02000000".repeat(32))),
+            "OK"
+        );
+        let lanes = format!("02000000{}", "00000000".repeat(31));
+        assert_eq!(s.handle(&format!("p{v1:x}")), lanes);
+        assert_eq!(s.handle("g").len(), (SGPRS + 4) * 8 + 16 + 256 * 256);
+        assert_eq!(s.handle("c"), "W00");
+        assert_eq!(s.handle("p5"), "E05");
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut buf = [1u32, 2, 3, 4];
+        let mut s = stub(&KERNEL, std::ptr::null());
+        assert_eq!(s.handle("?"), "T05thread:1;");
+        assert_eq!(s.handle("m4,4"), "870085be");
+        assert_eq!(s.handle("M4,4:00000000"), "E0d");
+        let addr = buf.as_mut_ptr() as u64;
+        assert_eq!(s.handle(&format!("m{addr:x},8")), "0100000002000000");
+        assert_eq!(s.handle(&format!("M{:x},4:ff000000", addr + 4)), "OK");
+        assert_eq!(buf, [1, 255, 3, 4]);
+        assert_eq!(s.handle("m10,4"), "E0e");
+        assert_eq!(s.handle(&format!("M{:x},2:0102", LDS_BASE + 2)), "OK");
+        assert_eq!(s.handle(&format!("m{LDS_BASE:x},4")), "00000102");
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let kernel = [
+            0xF4040080, // s_load_b64 s[2:3], s[0:1], null
+            0xF8000000, 0xBF89FC07, // s_waitcnt lgkmcnt(0)
+            0x7E020281, // v_mov_b32 v1, 1
+            0x7E040280, // v_mov_b32 v2, 0
+            0xDC6A0000, // global_store_b32 v2, v1, s[2:3]
+            0x00020102, END_PRG,
+        ];
+        let mut out = [0u32; 2];
+        let args = [out.as_mut_ptr() as u64];
+        let mut s = stub(&kernel, args.as_ptr());
+        let addr = out.as_ptr() as u64;
+        assert_eq!(s.handle(&format!("Z2,{addr:x},4")), "OK");
+        assert_eq!(s.handle("c"), format!("T05thread:1;watch:{addr:x};"));
+        assert_eq!(s.handle(&format!("z2,{addr:x},4")), "OK");
+        assert_eq!(s.handle("c"), "W00");
+        assert_eq!(out, [1, 0]);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut s = stub(&KERNEL, std::ptr::null());
+        assert_eq!(s.handle("Z0,4,4"), "OK");
+        assert_eq!(s.handle("c"), "T05thread:1;swbreak:;");
+        assert_eq!(s.handle("s"), "T05thread:1;");
+        assert_eq!(s.handle("s"), "T05thread:1;");
+        assert_eq!(s.handle("p6"), "0e000000");
+        assert_eq!(s.handle("bs"), "T05thread:1;");
+        assert_eq!(s.handle("p6"), "00000000");
+        assert_eq!(s.handle("bc"), "T05thread:1;swbreak:;");
+        assert_eq!(s.handle("p5"), "00000000");
+        assert_eq!(s.handle("bc"), "T05thread:1;replaylog:begin;");
+        assert_eq!(
+            s.handle(&format!("p{:x}", VGPR0 + 1)),
+            "00000000".repeat(32)
+        );
+        assert_eq!(s.handle("z0,4,4"), "OK");
+        assert_eq!(s.handle("c"), "W00");
+    }
+
+    #[test]
+    fn test_target_xml() {
+        let mut s = stub(&KERNEL, std::ptr::null());
+        let head = s.handle("qXfer:features:read:target.xml:0,15");
+        assert_eq!(head, "m<?xml version=\"1.0\"?>");
+        let len = target_xml().len();
+        assert_eq!(
+            s.handle(&format!("qXfer:features:read:target.xml:{len:x},20")),
+            "l"
+        );
+    }
+
+    #[test]
+    fn test_socket_session() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let lib = KERNEL
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        let stub = std::thread::spawn(move || {
+            let session = Session::new(&lib, [1, 1, 1, 1, 1, 1], std::ptr::null()).unwrap();
+            Stub::new(session, server).run()
+        });
+        let mut exchange = |data: &str| {
+            let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+            client.write_all(packet.as_bytes()).unwrap();
+            let mut reply = vec![];
+            let mut b = [0];
+            while reply.len() < 4 || reply[reply.len() - 3] != b'#' {
+                client.read_exact(&mut b).unwrap();
+                reply.push(b[0]);
+            }
+            String::from_utf8(reply).unwrap()
+        };
+        assert_eq!(exchange("Z0,4,4"), "+$OK#9a");
+        assert!(exchange("c").starts_with("+$T05thread:1;swbreak:;#"));
+        assert_eq!(exchange("QStartNoAckMode"), "+$OK#9a");
+        assert_eq!(exchange("p5"), "$00000000#80");
+        assert_eq!(exchange("D"), "$OK#9a");
+        assert_eq!(stub.join().unwrap(), 0);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::decode::{encoding, footprint, Encoding, RegRange};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/*
+ * Issue model used by the checker: every instruction takes one cycle, s_nop N takes N+1.
+ * Results become visible VALU_LATENCY/TRANS_LATENCY/SALU_LATENCY cycles after issue,
+ * which is the window s_delay_alu VALU_DEP_1..4, TRANS32_DEP_1..3 and SALU_CYCLE_1..3 cover.
+ */
+pub const VALU_LATENCY: usize = 5;
+pub const TRANS_LATENCY: usize = 10;
+pub const SALU_LATENCY: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Valu,
+    Trans,
+    Salu,
+}
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Unit::Valu => "VALU",
+            Unit::Trans => "TRANS",
+            Unit::Salu => "SALU",
+        };
+        write!(f, "{name}")
+    }
+}
+
+pub fn unit(stream: &[u32]) -> Option<Unit> {
+    let instruction = stream[0];
+    let trans = |op: u32| matches!(op, 37..=43 | 46 | 51 | 53 | 54 | 84..=88 | 97 | 98);
+    match encoding(instruction) {
+        Encoding::Sop1 | Encoding::Sop2 | Encoding::Sopc | Encoding::Sopk => Some(Unit::Salu),
+        Encoding::Vop1 if trans((instruction >> 9) & 0xff) => Some(Unit::Trans),
+        Encoding::Vop3
+            if matches!((stream[0] >> 16) & 0x3ff, 384..=511)
+                && trans(((stream[0] >> 16) & 0x3ff) - 384) =>
+        {
+            Some(Unit::Trans)
+        }
+        Encoding::Vop1
+        | Encoding::Vop2
+        | Encoding::Vopc
+        | Encoding::Vop3
+        | Encoding::Vop3p
+        | Encoding::Vopd => Some(Unit::Valu),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Producer {
+    pc: usize,
+    unit: Unit,
+    ready: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hazard {
+    pub pc: usize,
+    pub reg: RegRange,
+    pub producer_pc: usize,
+    pub producer: Unit,
+    pub cycles: usize,
+}
+impl fmt::Display for Hazard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pc=0x{:x} reads {} {} cycle(s) before the {} write at pc=0x{:x} is visible",
+            self.pc * 4,
+            self.reg,
+            self.cycles,
+            self.producer,
+            self.producer_pc * 4,
+        )
+    }
+}
+
+/* s_delay_alu and ALU result latency of a single wave */
+#[derive(Debug, Clone, Default)]
+pub struct HazardTracker {
+    cycle: usize,
+    writes: HashMap<RegRange, Producer>,
+    // ready cycle of the most recent VALU and TRANS instructions, newest first
+    valu: VecDeque<usize>,
+    trans: VecDeque<usize>,
+    // (instructions left before it applies, instid)
+    delays: Vec<(usize, u32)>,
+}
+
+impl HazardTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn delay(&mut self, instid: u32) {
+        let nth = |q: &VecDeque<usize>, n: usize| q.get(n).copied().unwrap_or(0);
+        self.cycle = match instid {
+            1..=4 => self.cycle.max(nth(&self.valu, instid as usize - 1)),
+            5..=7 => self.cycle.max(nth(&self.trans, instid as usize - 5)),
+            9..=11 => self.cycle + (instid as usize - 8),
+            _ => self.cycle,
+        };
+    }
+
+    /* advance the tracker over the instruction at `stream[0]`, returning the stale reads it performs */
+    pub fn step(&mut self, stream: &[u32], pc: usize) -> Vec<Hazard> {
+        let instruction = stream[0];
+        if encoding(instruction) == Encoding::Sopp {
+            let simm16 = instruction & 0xffff;
+            match (instruction >> 16) & 0x7f {
+                // s_nop
+                0 => {
+                    self.cycle += (simm16 as usize & 0xf) + 1;
+                    return vec![];
+                }
+                // s_delay_alu
+                7 => {
+                    let skip = (simm16 >> 4) & 0x7;
+                    self.delays.push((0, simm16 & 0xf));
+                    self.delays.push((skip as usize, (simm16 >> 7) & 0xf));
+                    return vec![];
+                }
+                _ => {}
+            }
+        }
+
+        let mut pending = vec![];
+        for (remaining, instid) in std::mem::take(&mut self.delays) {
+            match remaining {
+                0 => self.delay(instid),
+                n => pending.push((n - 1, instid)),
+            }
+        }
+        self.delays = pending;
+
+        let unit = unit(stream);
+        let fp = footprint(stream);
+        let mut hazards = vec![];
+        if matches!(unit, Some(Unit::Valu | Unit::Trans)) {
+            for reg in fp.reads.iter().flat_map(|r| r.regs()) {
+                if let Some(p) = self.writes.get(&reg) {
+                    if p.ready > self.cycle && !hazards.iter().any(|h: &Hazard| h.reg == reg) {
+                        hazards.push(Hazard {
+                            pc,
+                            reg,
+                            producer_pc: p.pc,
+                            producer: p.unit,
+                            cycles: p.ready - self.cycle,
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Some(unit) = unit {
+            let ready = self.cycle
+                + match unit {
+                    Unit::Valu => VALU_LATENCY,
+                    Unit::Trans => TRANS_LATENCY,
+                    Unit::Salu => SALU_LATENCY,
+                };
+            for reg in fp.writes.iter().flat_map(|r| r.regs()) {
+                self.writes.insert(reg, Producer { pc, unit, ready });
+            }
+            let recent = match unit {
+                Unit::Trans => Some(&mut self.trans),
+                Unit::Valu => Some(&mut self.valu),
+                Unit::Salu => None,
+            };
+            if let Some(recent) = recent {
+                recent.push_front(ready);
+                recent.truncate(4);
+            }
+            // the VALU queue orders TRANS ops too
+            if unit == Unit::Trans {
+                self.valu.push_front(ready);
+                self.valu.truncate(4);
+            }
+        }
+        self.cycle += 1;
+        hazards
+    }
+}
+
+#[cfg(test)]
+mod test_hazard {
+    use super::*;
+
+    const V_MOV_V1_1: u32 = 0x7E020281;
+    const V_ADD_V3_V1_V1: u32 = 0x06060301;
+    const V_MOV_V5_0: u32 = 0x7E0A0280;
+
+    fn run(prg: &[u32]) -> Vec<Hazard> {
+        let mut t = HazardTracker::new();
+        (0..prg.len())
+            .flat_map(|pc| t.step(&prg[pc..], pc))
+            .collect()
+    }
+
+    #[test]
+    fn test_valu_raw() {
+        let hazards = run(&[V_MOV_V1_1, V_ADD_V3_V1_V1]);
+        assert_eq!(hazards.len(), 1);
+        assert_eq!(hazards[0].reg, RegRange::vgpr(1, 1));
+        assert_eq!(hazards[0].producer, Unit::Valu);
+        assert_eq!(hazards[0].cycles, 4);
+        assert_eq!(
+            hazards[0].to_string(),
+            "pc=0x4 reads v1 4 cycle(s) before the VALU write at pc=0x0 is visible"
+        );
+    }
+
+    #[test]
+    fn test_valu_delay_alu() {
+        // s_delay_alu instid0(VALU_DEP_1)
+        assert!(run(&[V_MOV_V1_1, 0xBF870001, V_ADD_V3_V1_V1]).is_empty());
+        // VALU_DEP_2 waits on the v_mov too
+        assert!(run(&[V_MOV_V1_1, V_MOV_V5_0, 0xBF870002, V_ADD_V3_V1_V1]).is_empty());
+    }
+
+    #[test]
+    fn test_valu_distance() {
+        let prg = [V_MOV_V1_1, V_MOV_V5_0, V_MOV_V5_0, V_MOV_V5_0];
+        assert_eq!(run(&[&prg[..], &[V_ADD_V3_V1_V1]].concat()).len(), 1);
+        let prg = [&prg[..], &[V_MOV_V5_0, V_ADD_V3_V1_V1]].concat();
+        assert!(run(&prg).is_empty());
+    }
+
+    #[test]
+    fn test_delay_alu_instskip() {
+        // s_delay_alu instid0(NO_DEP) | instskip(NEXT) | instid1(VALU_DEP_2)
+        let prg = [V_MOV_V1_1, 0xBF870110, V_MOV_V5_0, V_ADD_V3_V1_V1];
+        assert!(run(&prg).is_empty());
+        let prg = [V_MOV_V1_1, V_MOV_V5_0, V_ADD_V3_V1_V1];
+        assert_eq!(run(&prg).len(), 1);
+    }
+
+    #[test]
+    fn test_salu_exec() {
+        // s_mov_b32 exec_lo, s0 ; v_mov_b32 v0, v1
+        let hazards = run(&[0xBEFE0000, 0x7E000301]);
+        assert_eq!(hazards[0].producer, Unit::Salu);
+        assert_eq!(hazards[0].reg.to_string(), "exec_lo");
+        // s_delay_alu instid0(SALU_CYCLE_1)
+        assert!(run(&[0xBEFE0000, 0xBF870009, 0x7E000301]).is_empty());
+        // s_nop 0
+        assert!(run(&[0xBEFE0000, 0xBF800000, 0x7E000301]).is_empty());
+    }
+
+    #[test]
+    fn test_trans() {
+        // v_exp_f32 v1, v0
+        let hazards = run(&[0x7E024B00, V_MOV_V5_0, V_ADD_V3_V1_V1]);
+        assert_eq!(hazards[0].producer, Unit::Trans);
+        // s_delay_alu instid0(TRANS32_DEP_1)
+        assert!(run(&[0x7E024B00, V_MOV_V5_0, 0xBF870005, V_ADD_V3_V1_V1]).is_empty());
+    }
+}
@@ -1,14 +1,59 @@
-use crate::utils::{GLOBAL_COUNTER, OSX, PROFILE};
+use crate::allocation::Allocation;
+use crate::capture::{Bundle, BAD_BUNDLE, CAPTURE, MISMATCH, REGISTERED};
+use crate::coverage::Coverage;
+use crate::debugger::Session;
+use crate::device::BAD_ADDRESS;
+use crate::error::{guard, Location, RemuError, PANICKED};
+use crate::memory::MAX_LDS;
+use crate::program::Program;
+use crate::schedule::{Order, Schedule};
+use crate::trace::TRACE;
+use crate::trace_diff::BAD_TRACE;
+use crate::utils::{
+    GDB, GLOBAL_COUNTER, GLOBAL_DEBUG, LAUNCH_TIMEOUT, OSX, PERF, PREFLIGHT, PROFILE, SCHEDULE,
+    SERIAL, THREADS,
+};
 use crate::work_group::WorkGroup;
-use std::os::raw::c_char;
+use std::ffi::CStr;
+use std::fs;
+use std::os::raw::{c_char, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+mod allocation;
+mod capture;
+mod coverage;
+mod debugger;
+mod decode;
+mod device;
 mod dtype;
+mod elf;
+mod error;
+mod gdb;
+mod hazard;
 mod memory;
+mod mnemonic;
+mod perf;
+mod preflight;
+mod program;
+mod schedule;
 mod state;
 mod thread;
+mod trace;
+mod trace_diff;
+mod trap;
+mod undo;
+mod uninit;
 mod utils;
+mod waitcnt;
+mod watch;
 mod work_group;
 
+/* threads a workgroup can have */
+const MAX_WORKGROUP_SIZE: u64 = 1024;
+
 #[no_mangle]
 pub extern "C" fn run_asm(
     lib: *const c_char,
@@ -21,43 +66,671 @@ pub extern "C" fn run_asm(
     lz: u32,
     args_ptr: *const u64,
 ) -> i32 {
+    run_asm_lds(lib, lib_sz, gx, gy, gz, lx, ly, lz, args_ptr, 0)
+}
+
+/* run_asm with dynamic_lds bytes of LDS on top of what the kernel descriptor asks for */
+#[no_mangle]
+pub extern "C" fn run_asm_lds(
+    lib: *const c_char,
+    lib_sz: u32,
+    gx: u32,
+    gy: u32,
+    gz: u32,
+    lx: u32,
+    ly: u32,
+    lz: u32,
+    args_ptr: *const u64,
+    dynamic_lds: u32,
+) -> i32 {
+    guard(PANICKED, || {
+        error::set_last(None);
+        let dims = [gx, gy, gz, lx, ly, lz];
+        if let Some(why) = invalid_kernel(lib, lib_sz).or_else(|| invalid_dims(dims)) {
+            return invalid_launch(why);
+        }
+        let lib_bytes = unsafe { slice::from_raw_parts(lib as *const u8, lib_sz as usize) };
+        if let Some(addr) = &*GDB {
+            let mut session = match Session::new(lib_bytes, dims, args_ptr) {
+                Ok(session) => session,
+                Err(err) => return failed(err),
+            };
+            session.dynamic_lds = dynamic_lds;
+            return gdb::serve(addr, session);
+        }
+        match &*CAPTURE {
+            // bundles hold host memory
+            Some(_) if device::owns(args_ptr as u64) => {
+                println!("[remu] capture: skipping a launch in device memory");
+                launch(lib_bytes, dims, args_ptr, dynamic_lds)
+            }
+            Some(dir) => capture::record(dir, lib_bytes, dims, args_ptr, dynamic_lds, || {
+                launch(lib_bytes, dims, args_ptr, dynamic_lds)
+            }),
+            None => launch(lib_bytes, dims, args_ptr, dynamic_lds),
+        }
+    })
+}
+
+/* re-run a bundle written with CAPTURE=<dir> and diff the memory it leaves behind */
+#[no_mangle]
+pub extern "C" fn remu_replay(path: *const c_char) -> i32 {
+    guard(PANICKED, || {
+        error::set_last(None);
+        let Some(path) = c_path(path) else {
+            println!("[remu] replay path is null");
+            return BAD_BUNDLE;
+        };
+        match fs::read(&path).ok().and_then(|b| Bundle::from_bytes(&b)) {
+            Some(bundle) => {
+                // a bundle is checked like the run_asm call it was written from
+                let invalid = match u32::try_from(bundle.lib.len()) {
+                    Ok(len) => invalid_kernel(bundle.lib.as_ptr() as *const c_char, len),
+                    Err(_) => Some(format!("kernel length {} is over 4 GiB", bundle.lib.len())),
+                };
+                match invalid.or_else(|| invalid_dims(bundle.dims)) {
+                    Some(why) => invalid_launch(why),
+                    None => capture::replay(&bundle, launch),
+                }
+            }
+            None => {
+                println!("[remu] {path} is not a capture bundle");
+                BAD_BUNDLE
+            }
+        }
+    })
+}
+
+/* compare two traces written with TRACE and print the first instruction where they diverge */
+#[no_mangle]
+pub extern "C" fn remu_trace_diff(a: *const c_char, b: *const c_char, context: u32) -> i32 {
+    guard(PANICKED, || {
+        let read = |path: *const c_char| {
+            let Some(path) = c_path(path) else {
+                println!("[remu] trace path is null");
+                return Err(BAD_TRACE);
+            };
+            let records = fs::read(&path).ok().and_then(|b| trace::parse(&b));
+            if records.is_none() {
+                println!("[remu] {path} is not a trace");
+            }
+            records.ok_or(BAD_TRACE)
+        };
+        let (a, b) = match (read(a), read(b)) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(err), _) | (_, Err(err)) => return err,
+        };
+        match trace_diff::diff(&a, &b, context as usize) {
+            Some(divergence) => {
+                for line in divergence.to_string().lines() {
+                    println!("[remu] {line}");
+                }
+                MISMATCH
+            }
+            None => {
+                println!("[remu] traces match over {} instructions", a.len());
+                0
+            }
+        }
+    })
+}
+
+/* estimated cycles of the last launch run with PERF=1 */
+#[no_mangle]
+pub extern "C" fn remu_perf_cycles() -> u64 {
+    guard(0, || perf::LAST_CYCLES.load(Relaxed))
+}
+
+/*
+ * list every instruction of the kernel remu can't execute, returns how many there are or a negative
+ * error code for a kernel run_asm would reject
+ */
+#[no_mangle]
+pub extern "C" fn remu_preflight(lib: *const c_char, lib_sz: u32) -> i32 {
+    guard(-PANICKED, || {
+        error::set_last(None);
+        if let Some(why) = invalid_kernel(lib, lib_sz) {
+            return -invalid_launch(why);
+        }
+        let lib_bytes = unsafe { slice::from_raw_parts(lib as *const u8, lib_sz as usize) };
+        let program = match load(lib_bytes, [1; 6]) {
+            Ok(program) => program,
+            Err(err) => return -failed(err),
+        };
+        let unsupported = preflight::scan(&program);
+        if preflight::report(&unsupported) {
+            println!("[remu] preflight: every instruction is supported");
+        }
+        unsupported.len() as i32
+    })
+}
+
+/*
+ * the message of the error the last run_asm or remu_replay on this thread ended with, written to
+ * buf as a NUL terminated string cut to len bytes. Returns the length of the whole message, 0 if
+ * the launch succeeded.
+ */
+#[no_mangle]
+pub extern "C" fn remu_last_error(buf: *mut c_char, len: u32) -> u32 {
+    guard(0, || {
+        let message = error::last().map_or(String::new(), |e| e.to_string());
+        if !buf.is_null() && len > 0 {
+            let n = message.len().min(len as usize - 1);
+            let out = unsafe { slice::from_raw_parts_mut(buf as *mut u8, n + 1) };
+            out[..n].copy_from_slice(&message.as_bytes()[..n]);
+            out[n] = 0;
+        }
+        message.len() as u32
+    })
+}
+
+/* captures include the whole buffer, not just the pages a launch touched */
+#[no_mangle]
+pub extern "C" fn remu_register_buffer(ptr: *const u8, size: u64) {
+    guard((), || {
+        REGISTERED.lock().unwrap().insert(ptr as u64, size);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_unregister_buffer(ptr: *const u8) {
+    guard((), || {
+        REGISTERED.lock().unwrap().remove(&(ptr as u64));
+    })
+}
+
+/*
+ * global and SMEM accesses of later launches have to stay inside registered allocations, perms is
+ * 1 read, 2 write or 3 both. Returns 0 or BAD_ALLOCATION for an empty or overlapping one.
+ */
+#[no_mangle]
+pub extern "C" fn remu_register_allocation(base: *const u8, size: u64, perms: u32) -> i32 {
+    guard(PANICKED, || {
+        let a = Allocation {
+            base: base as u64,
+            size,
+            perms,
+        };
+        allocation::register(a).err().unwrap_or(0)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_unregister_allocation(base: *const u8) -> i32 {
+    guard(PANICKED, || {
+        allocation::unregister(base as u64).err().unwrap_or(0)
+    })
+}
+
+/* a zeroed buffer in remu's own device memory, returns its device address or 0 */
+#[no_mangle]
+pub extern "C" fn remu_device_alloc(size: u64) -> u64 {
+    guard(0, || device::alloc(size).unwrap_or(0))
+}
+
+/* returns 0 or BAD_ADDRESS if addr isn't a live buffer */
+#[no_mangle]
+pub extern "C" fn remu_device_free(addr: u64) -> i32 {
+    guard(PANICKED, || device::free(addr).err().unwrap_or(0))
+}
+
+/* copies fail with BAD_ADDRESS unless the whole device range is in one buffer */
+#[no_mangle]
+pub extern "C" fn remu_device_copy_h2d(dst: u64, src: *const c_void, len: u64) -> i32 {
+    guard(PANICKED, || {
+        if src.is_null() {
+            return BAD_ADDRESS;
+        }
+        let src = unsafe { slice::from_raw_parts(src as *const u8, len as usize) };
+        device::copy_h2d(dst, src).err().unwrap_or(0)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_device_copy_d2h(dst: *mut c_void, src: u64, len: u64) -> i32 {
+    guard(PANICKED, || {
+        if dst.is_null() {
+            return BAD_ADDRESS;
+        }
+        let dst = unsafe { slice::from_raw_parts_mut(dst as *mut u8, len as usize) };
+        device::copy_d2h(dst, src).err().unwrap_or(0)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remu_device_memset(dst: u64, value: u8, len: u64) -> i32 {
+    guard(PANICKED, || {
+        device::memset(dst, value, len).err().unwrap_or(0)
+    })
+}
+
+/* why lib can't be a kernel */
+fn invalid_kernel(lib: *const c_char, lib_sz: u32) -> Option<String> {
+    match () {
+        _ if lib.is_null() => Some("kernel pointer is null".to_string()),
+        _ if lib_sz == 0 => Some("kernel is empty".to_string()),
+        _ if !*OSX && lib_sz % 4 != 0 => {
+            Some(format!("kernel length {lib_sz} isn't a multiple of 4"))
+        }
+        _ => None,
+    }
+}
+
+/* why a launch can't have these global and local sizes */
+fn invalid_dims(dims: [u32; 6]) -> Option<String> {
+    let [.., lx, ly, lz] = dims;
+    match () {
+        _ if dims.contains(&0) => Some(format!("empty global or local size {dims:?}")),
+        _ if lx as u64 * ly as u64 * lz as u64 > MAX_WORKGROUP_SIZE => Some(format!(
+            "local size {lx}x{ly}x{lz} is over {MAX_WORKGROUP_SIZE} threads"
+        )),
+        _ => None,
+    }
+}
+
+/* print why a launch is invalid and make it the last error, returns its code */
+fn invalid_launch(why: String) -> i32 {
+    failed(RemuError::InvalidLaunch(why))
+}
+
+/* print err and make it the last error, returns its code */
+fn failed(err: RemuError) -> i32 {
+    println!("[remu] {err}");
+    let code = err.code();
+    error::set_last(Some(err));
+    code
+}
+
+/* a path argument from C, None when it's null */
+fn c_path(path: *const c_char) -> Option<String> {
+    match path.is_null() {
+        true => None,
+        false => Some(
+            unsafe { CStr::from_ptr(path) }
+                .to_string_lossy()
+                .into_owned(),
+        ),
+    }
+}
+
+/* the decoded kernel of an OSX asm dump, a code object or raw instruction words */
+fn load(lib: &[u8], [gx, gy, gz, lx, ly, lz]: [u32; 6]) -> Result<Arc<Program>, RemuError> {
+    let (mut trap_handler, mut group_segment_size) = (None, None);
     let kernel = match *OSX {
         true => {
-            let mut lib_bytes: Vec<u8> = Vec::with_capacity(lib_sz as usize);
-            unsafe {
-                lib_bytes.extend((0..lib_sz).map(|i| *lib.offset(i as isize) as u8));
-            }
-            let (kernel, function_name) = utils::read_asm(&lib_bytes);
+            let (kernel, function_name) = utils::read_asm(&lib.to_vec());
             println!(
                 "[remu] launching kernel {function_name} with global_size {gx} {gy} {gz} local_size {lx} {ly} {lz}"
             );
             kernel
         }
-        false => {
-            if lib.is_null() || (lib_sz % 4) != 0 {
-                panic!("Pointer is null or length is not properly aligned to 4 bytes");
+        false => match elf::read_code_object(lib) {
+            Some(co) => {
+                trap_handler = co.trap_handler;
+                group_segment_size = co.group_segment_size;
+                co.code
             }
-            unsafe { slice::from_raw_parts(lib as *const u32, (lib_sz / 4) as usize).to_vec() }
-        }
+            // the header isn't code
+            None if elf::is_elf(lib) => {
+                return Err(RemuError::InvalidLaunch(
+                    "kernel is an ELF file without a code object remu can read".to_string(),
+                ))
+            }
+            None => lib
+                .chunks_exact(4)
+                .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+                .collect(),
+        },
+    };
+    Ok(Program::cached(kernel, trap_handler, group_segment_size))
+}
+
+/* LDS every workgroup of a launch gets, all of it for code without a kernel descriptor */
+fn lds_size(program: &Program, dynamic_lds: u32) -> Result<usize, RemuError> {
+    let Some(fixed) = program.group_segment_size else {
+        return Ok(MAX_LDS);
     };
-    let dispatch_dim = match (gy != 1, gz != 1) {
+    match fixed as usize + dynamic_lds as usize {
+        size if size > MAX_LDS => Err(RemuError::InvalidLaunch(format!(
+            "{fixed} bytes of LDS plus {dynamic_lds} dynamic is over {MAX_LDS}"
+        ))),
+        size => Ok(size),
+    }
+}
+
+fn dispatch_dim([_, gy, gz, ..]: [u32; 6]) -> u32 {
+    match (gy != 1, gz != 1) {
         (true, true) => 3,
         (true, false) => 2,
         _ => 1,
+    }
+}
+
+/* workgroup i in the serial gx, gy, gz order */
+fn wg_id([_, gy, gz, ..]: [u32; 6], i: u32) -> [u32; 3] {
+    [i / (gy * gz), (i / gz) % gy, i % gz]
+}
+
+/* workgroups in the grid, which wg_id numbers with a u32 */
+fn wg_count([gx, gy, gz, ..]: [u32; 6]) -> Result<u32, RemuError> {
+    let count = (gx as u64)
+        .checked_mul(gy as u64)
+        .and_then(|n| n.checked_mul(gz as u64));
+    count.and_then(|n| u32::try_from(n).ok()).ok_or_else(|| {
+        RemuError::InvalidLaunch(format!(
+            "global size {gx}x{gy}x{gz} is over {} workgroups",
+            u32::MAX
+        ))
+    })
+}
+
+/* the order SCHEDULE hands count workgroups out in */
+fn wg_order(count: u32) -> Order {
+    if let Schedule::Random(seed) = *SCHEDULE {
+        println!("[remu] schedule random:{seed}");
+    }
+    Order::new(count, *SCHEDULE)
+}
+
+fn launch(lib: &[u8], dims: [u32; 6], args_ptr: *const u64, dynamic_lds: u32) -> i32 {
+    let loaded = wg_count(dims).and_then(|count| {
+        let program = load(lib, dims)?;
+        Ok((count, lds_size(&program, dynamic_lds)?, program))
+    });
+    let (count, lds, program) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => return failed(err),
+    };
+    // the same error an unimplemented instruction ends a launch with, before any of it ran
+    if *PREFLIGHT {
+        let unsupported = preflight::scan(&program);
+        if !preflight::report(&unsupported) {
+            let first = &unsupported[0];
+            error::set_last(Some(RemuError::Unimplemented(Location {
+                pc: first.pc,
+                word: first.words[0],
+                workgroup: None,
+                wave: None,
+                lane: None,
+            })));
+            return 1;
+        }
+    }
+    capture::kernargs(args_ptr, &program.code);
+    let [.., lx, ly, lz] = dims;
+    let args = args_ptr as usize;
+    let stats = Mutex::new(perf::Launch::default());
+    let covered = Mutex::new(Coverage::default());
+    let errors = Mutex::new(vec![]);
+    let deadline = LAUNCH_TIMEOUT.map(|t| Instant::now() + t);
+    let memory = memory::for_launch(args_ptr, &program.code);
+    let order = wg_order(count);
+    // n is the place of the workgroup in the launch order
+    let run = |n: u32| {
+        let i = order.at(n);
+        let mut wg = WorkGroup::new(
+            dispatch_dim(dims),
+            wg_id(dims, i),
+            [lx, ly, lz],
+            program.clone(),
+            args as *const u64,
+            lds,
+        );
+        wg.deadline = deadline;
+        wg.memory = memory.clone();
+        // a bug in one workgroup fails the launch like a fault, the other workers keep going
+        let ret = match catch_unwind(AssertUnwindSafe(|| wg.exec_waves())) {
+            Ok(ret) => ret,
+            Err(payload) => {
+                let err = wg.panicked(error::panic_message(&*payload));
+                println!("[remu] {err}");
+                wg.error = Some(err);
+                Err(PANICKED)
+            }
+        };
+        if let Some(err) = wg.error.take() {
+            errors.lock().unwrap().push((n, err));
+        }
+        if let Some(coverage) = wg.coverage.take() {
+            covered.lock().unwrap().merge(coverage);
+        }
+        if *PERF {
+            let waves = wg.waves.iter().map(|w| &w.perf).collect::<Vec<_>>();
+            // what a descriptor declares is what occupancy goes by on hardware
+            let used = match program.group_segment_size {
+                Some(_) => lds,
+                None => perf::lds_bytes(&wg.lds),
+            };
+            stats.lock().unwrap().add(i, &waves, used);
+        }
+        ret
+    };
+    let serial = *SERIAL || *GLOBAL_DEBUG || capture::active() || TRACE.is_some();
+    let workers = match serial || *SCHEDULE != Schedule::Wave {
+        true => 1,
+        false => (*THREADS).clamp(1, count.max(1) as usize),
     };
-    for gx in 0..gx {
-        for gy in 0..gy {
-            for gz in 0..gz {
-                let mut wg =
-                    WorkGroup::new(dispatch_dim, [gx, gy, gz], [lx, ly, lz], &kernel, args_ptr);
-                if let Err(err) = wg.exec_waves() {
-                    return err;
+    let ret = match workers {
+        1 => (0..count).try_for_each(run),
+        _ => {
+            let next = AtomicU32::new(0);
+            // the failing workgroup that comes first in serial order wins
+            let failed = Mutex::new(None::<(u32, i32)>);
+            std::thread::scope(|s| {
+                for _ in 0..workers {
+                    s.spawn(|| loop {
+                        let i = next.fetch_add(1, Relaxed);
+                        if i >= count || failed.lock().unwrap().is_some_and(|(f, _)| f < i) {
+                            break;
+                        }
+                        if let Err(err) = run(i) {
+                            let mut failed = failed.lock().unwrap();
+                            if failed.is_none_or(|(f, _)| i < f) {
+                                *failed = Some((i, err));
+                            }
+                        }
+                    });
                 }
+            });
+            match failed.into_inner().unwrap() {
+                Some((_, err)) => Err(err),
+                None => Ok(()),
             }
         }
+    };
+    trace::flush();
+    coverage::save(covered.into_inner().unwrap());
+    if *PERF {
+        stats
+            .into_inner()
+            .unwrap()
+            .report(perf::vgprs(&program.code));
+    }
+    if let Err(err) = ret {
+        // the workgroup launch order puts first, like the error code
+        let errors = errors.into_inner().unwrap();
+        let first = errors.into_iter().min_by_key(|(n, _)| *n);
+        error::set_last(first.map(|(_, err)| err));
+        return err;
     }
     if *PROFILE {
-        println!("{:?}", GLOBAL_COUNTER.lock().unwrap());
+        println!("{:?}", GLOBAL_COUNTER);
     }
     0
 }
+
+#[cfg(test)]
+mod test_run_asm {
+    use super::*;
+
+    #[test]
+    fn test_parallel_workgroups() {
+        let kernel: Vec<u32> = vec![
+            0xF4040080, // s_load_b64 s[2:3], s[0:1], null
+            0xF8000000,
+            0xBF89FC07, // s_waitcnt lgkmcnt(0)
+            0x7E02020F, // v_mov_b32 v1, s15
+            0x30040282, // v_lshlrev_b32 v2, 2, v1
+            0xDC6A0000, // global_store_b32 v2, v1, s[2:3]
+            0x00020102,
+            utils::END_PRG,
+        ];
+        let mut out = vec![u32::MAX; 64];
+        let args = [out.as_mut_ptr() as u64];
+        let lib = kernel.as_ptr() as *const c_char;
+        let ret = run_asm(
+            lib,
+            kernel.len() as u32 * 4,
+            64,
+            1,
+            1,
+            1,
+            1,
+            1,
+            args.as_ptr(),
+        );
+        assert_eq!(ret, 0);
+        assert_eq!(out, (0..64).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_device_memory() {
+        let kernel: Vec<u32> = vec![
+            0xF4040080, // s_load_b64 s[2:3], s[0:1], null
+            0xF8000000,
+            0xBF89FC07, // s_waitcnt lgkmcnt(0)
+            0x7E02020F, // v_mov_b32 v1, s15
+            0x30040282, // v_lshlrev_b32 v2, 2, v1
+            0xDC6A0000, // global_store_b32 v2, v1, s[2:3]
+            0x00020102,
+            utils::END_PRG,
+        ];
+        let lib = kernel.as_ptr() as *const c_char;
+        let run = |gx: u32, args: u64| {
+            run_asm(
+                lib,
+                kernel.len() as u32 * 4,
+                gx,
+                1,
+                1,
+                1,
+                1,
+                1,
+                args as *const u64,
+            )
+        };
+        let out = remu_device_alloc(64 * 4);
+        let args = remu_device_alloc(8);
+        assert!(device::owns(out) && device::owns(args));
+        assert_eq!(remu_device_memset(out, 0xff, 64 * 4), 0);
+        assert_eq!(
+            remu_device_copy_h2d(args, out.to_le_bytes().as_ptr() as *const c_void, 8),
+            0
+        );
+        assert_eq!(run(64, args), 0);
+        let mut host = vec![0u32; 64];
+        let ret = remu_device_copy_d2h(host.as_mut_ptr() as *mut c_void, out, 64 * 4);
+        assert_eq!(ret, 0);
+        assert_eq!(host, (0..64).collect::<Vec<u32>>());
+        // workgroup 64 stores past the end of the buffer
+        assert_eq!(run(65, args), trap::FAULT);
+        let err = error::last().unwrap().to_string();
+        assert!(err.ends_with(&format!("offset 256 of allocation 0x{out:x}+0x100 (rw)")));
+        assert_eq!(remu_device_free(out), 0);
+        assert_eq!(remu_device_free(out), BAD_ADDRESS);
+        assert_eq!(run(1, args), trap::FAULT);
+        assert_eq!(
+            remu_device_copy_d2h(host.as_mut_ptr() as *mut c_void, out, 4),
+            BAD_ADDRESS
+        );
+    }
+
+    #[test]
+    fn test_last_error() {
+        let message = || {
+            let mut buf = [0 as c_char; 64];
+            let len = remu_last_error(buf.as_mut_ptr(), 24);
+            let s = unsafe { CStr::from_ptr(buf.as_ptr()) };
+            (len, s.to_str().unwrap().to_string())
+        };
+        let kernel: Vec<u32> = vec![
+            0xBE850801, // s_ctz_i32_b32 s5, s1
+            utils::END_PRG,
+        ];
+        let lib = kernel.as_ptr() as *const c_char;
+        let ret = run_asm(lib, 8, 1, 1, 1, 0, 1, 1, std::ptr::null());
+        assert_eq!(ret, error::INVALID_LAUNCH);
+        assert_eq!(message(), (61, "invalid launch: empty g".to_string()));
+        let ret = run_asm(lib, 8, 1, 1, 1, 1, 1, 1, std::ptr::null());
+        assert_eq!(ret, 1);
+        let (len, cut) = message();
+        assert_eq!(cut, "unimplemented instructi");
+        let full = error::last().unwrap().to_string();
+        assert_eq!(len as usize, full.len());
+        assert!(full.ends_with("at pc=0x0 workgroup [0, 0, 0] wave 0"));
+        let end = kernel[1..].as_ptr() as *const c_char;
+        assert_eq!(run_asm(end, 4, 1, 1, 1, 1, 1, 1, std::ptr::null()), 0);
+        assert_eq!(remu_last_error(std::ptr::null_mut(), 0), 0);
+        // a grid with more workgroups than a u32 counts
+        let big = 0x10000;
+        let ret = run_asm(end, 4, big, big, 1, 1, 1, 1, std::ptr::null());
+        assert_eq!(ret, error::INVALID_LAUNCH);
+        assert_eq!(wg_count([big, big - 1, 1, 1, 1, 1]), Ok(0xFFFF0000));
+    }
+
+    #[test]
+    fn test_lds_size() {
+        let mut program = Program::new(vec![utils::END_PRG], None);
+        assert_eq!(lds_size(&program, 0x100), Ok(MAX_LDS));
+        program.group_segment_size = Some(0x8000);
+        assert_eq!(lds_size(&program, 0), Ok(0x8000));
+        assert_eq!(lds_size(&program, 0x8000), Ok(MAX_LDS));
+        let err = lds_size(&program, 0x8001).unwrap_err();
+        assert_eq!(err.code(), error::INVALID_LAUNCH);
+        assert_eq!(
+            err.to_string(),
+            "invalid launch: 32768 bytes of LDS plus 32769 dynamic is over 65536"
+        );
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        assert_eq!(remu_replay(std::ptr::null()), BAD_BUNDLE);
+        let path = c"/nonexistent".as_ptr();
+        assert_eq!(remu_trace_diff(path, std::ptr::null(), 0), BAD_TRACE);
+        assert_eq!(remu_trace_diff(std::ptr::null(), path, 0), BAD_TRACE);
+        assert_eq!(remu_preflight(std::ptr::null(), 8), -error::INVALID_LAUNCH);
+        let kernel = [utils::END_PRG];
+        let lib = kernel.as_ptr() as *const c_char;
+        assert_eq!(remu_preflight(lib, 0), -error::INVALID_LAUNCH);
+        assert_eq!(remu_preflight(lib, 4), 0);
+    }
+
+    #[test]
+    fn test_replay_validation() {
+        let path = std::env::temp_dir().join(format!("remu_replay_{}", std::process::id()));
+        let replay = |lib: Vec<u8>, dims: [u32; 6]| {
+            let bundle = Bundle {
+                lib,
+                dims,
+                args: 0,
+                kernargs: vec![],
+                dynamic_lds: 0,
+                regions: vec![],
+                outputs: vec![],
+            };
+            fs::write(&path, bundle.to_bytes()).unwrap();
+            let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+            remu_replay(c_path.as_ptr())
+        };
+        let lib = utils::END_PRG.to_le_bytes().to_vec();
+        assert_eq!(replay(lib.clone(), [1; 6]), 0);
+        assert_eq!(replay(vec![], [1; 6]), error::INVALID_LAUNCH);
+        assert_eq!(
+            replay(lib.clone(), [1, 1, 1, 0, 1, 1]),
+            error::INVALID_LAUNCH
+        );
+        assert_eq!(replay(lib, [1, 1, 1, 2048, 1, 1]), error::INVALID_LAUNCH);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,30 +1,108 @@
+use crate::allocation::{self, Allocation, Allocations};
+use crate::capture;
+use crate::device;
+use crate::trap::invalid_address;
+use std::sync::Arc;
+
+/* what global and SMEM accesses of a launch go through */
+pub trait Memory: Send + Sync {
+    /* where the host can read or write [addr, addr+len), None if the kernel can't access it */
+    fn host(&self, addr: u64, len: u64, store: bool) -> Option<*mut u8>;
+    /* the allocation a fault at addr reports */
+    fn nearest(&self, addr: u64) -> Option<Allocation>;
+}
+
+/* kernel addresses are host pointers, checked against the registered allocations if there are any */
+#[derive(Debug, Clone, Default)]
+pub struct Raw {
+    pub allocations: Option<Arc<Allocations>>,
+}
+
+impl Memory for Raw {
+    fn host(&self, addr: u64, len: u64, store: bool) -> Option<*mut u8> {
+        let outside = (self.allocations.as_ref()).is_some_and(|a| !a.allows(addr, len, store));
+        if invalid_address(addr, len) || outside {
+            return None;
+        }
+        capture::touch(addr, len, store);
+        Some(addr as *mut u8)
+    }
+
+    fn nearest(&self, addr: u64) -> Option<Allocation> {
+        self.allocations.as_ref()?.nearest(addr)
+    }
+}
+
+/* a launch with its kernargs in device memory runs sandboxed in it */
+pub fn for_launch(args: *const u64, code: &[u32]) -> Arc<dyn Memory> {
+    match device::owns(args as u64) {
+        true => device::snapshot(),
+        false => Arc::new(Raw {
+            allocations: allocation::for_launch(args, code),
+        }),
+    }
+}
+
+/* LDS a workgroup can allocate */
+pub const MAX_LDS: usize = 64 * 1024;
+
 #[derive(Clone, Debug)]
 pub struct VecDataStore {
     pub data: Vec<u8>,
+    /*
+     * LDS has the size the launch allocated, a dword that doesn't fit in it reads as 0 and a store
+     * to it is dropped whole. Scratch grows.
+     */
+    fixed: bool,
 }
 
 impl VecDataStore {
     pub fn new() -> Self {
-        Self { data: Vec::new() }
+        Self {
+            data: Vec::new(),
+            fixed: false,
+        }
     }
-    pub fn write(&mut self, addr: usize, val: u32) {
-        if addr + 4 >= self.data.len() {
-            self.data.resize(self.data.len() + addr + 5, 0);
+    pub fn fixed(size: usize) -> Self {
+        Self {
+            data: vec![0; size],
+            fixed: true,
+        }
+    }
+    /* [addr, addr+len) is inside a fixed allocation */
+    pub fn contains(&self, addr: u64, len: u64) -> bool {
+        !self.fixed || addr.saturating_add(len) <= self.data.len() as u64
+    }
+    pub fn write_bytes(&mut self, addr: usize, bytes: &[u8]) {
+        if !self.contains(addr as u64, bytes.len() as u64) {
+            return;
+        }
+        let end = addr + bytes.len();
+        if end > self.data.len() {
+            self.data.resize(end, 0);
         }
-        self.data[addr..addr + 4]
-            .iter_mut()
-            .enumerate()
-            .for_each(|(i, x)| {
-                *x = val.to_le_bytes()[i];
-            });
+        self.data[addr..end].copy_from_slice(bytes);
+    }
+    pub fn write(&mut self, addr: usize, val: u32) {
+        self.write_bytes(addr, &val.to_le_bytes());
     }
     pub fn write64(&mut self, addr: usize, val: u64) {
         self.write(addr, (val & 0xffffffff) as u32);
         self.write(addr + 4, ((val & (0xffffffff << 32)) >> 32) as u32);
     }
     pub fn read(&self, addr: usize) -> u32 {
+        self.read_bytes(addr, 4)
+    }
+    /* up to 4 bytes zero-extended */
+    pub fn read_bytes(&self, addr: usize, len: usize) -> u32 {
+        if !self.contains(addr as u64, len as u64) {
+            return 0;
+        }
+        // nothing was written past the end of scratch yet
         let mut bytes: [u8; 4] = [0; 4];
-        bytes.copy_from_slice(&self.data[addr + 0..addr + 4]);
+        for (i, b) in bytes[..len].iter_mut().enumerate() {
+            *b = self.data.get(addr.saturating_add(i)).copied().unwrap_or(0);
+        }
         u32::from_le_bytes(bytes)
     }
     pub fn read64(&mut self, addr: usize) -> u64 {
@@ -33,3 +111,31 @@ impl VecDataStore {
         ((msb as u64) << 32) | lsb as u64
     }
 }
+
+#[cfg(test)]
+mod test_memory {
+    use super::*;
+
+    #[test]
+    fn test_fixed() {
+        let mut lds = VecDataStore::fixed(8);
+        // unaligned is fine, a dword straddling the end is dropped whole
+        lds.write(1, 0x44332211);
+        lds.write64(6, u64::MAX);
+        lds.write_bytes(7, &[0xff]);
+        assert_eq!(lds.data, [0, 0x11, 0x22, 0x33, 0x44, 0, 0, 0xff]);
+        assert_eq!(lds.read(1), 0x44332211);
+        assert_eq!(lds.read(5), 0);
+        assert_eq!(lds.read(usize::MAX - 1), 0);
+        assert_eq!(lds.read_bytes(7, 1), 0xff);
+        // the dword of a b64 that fits is still stored
+        lds.write64(4, u64::MAX);
+        assert_eq!(lds.read(4), u32::MAX);
+        assert_eq!(lds.read(6), 0);
+        assert!(lds.contains(4, 4) && !lds.contains(5, 4));
+        let mut scratch = VecDataStore::new();
+        scratch.write_bytes(10, &[1, 2]);
+        assert_eq!(scratch.data.len(), 12);
+        assert!(scratch.contains(100, 4));
+    }
+}
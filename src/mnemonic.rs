@@ -0,0 +1,829 @@
+use crate::decode::{encoding, Encoding};
+
+/* RDNA3 opcode names by encoding */
+const SOP1: &[(u32, &str)] = &[
+    (0, "s_mov_b32"),
+    (1, "s_mov_b64"),
+    (2, "s_cmov_b32"),
+    (3, "s_cmov_b64"),
+    (4, "s_brev_b32"),
+    (5, "s_brev_b64"),
+    (8, "s_ctz_i32_b32"),
+    (9, "s_ctz_i32_b64"),
+    (10, "s_clz_i32_u32"),
+    (11, "s_clz_i32_u64"),
+    (12, "s_cls_i32"),
+    (13, "s_cls_i32_i64"),
+    (14, "s_sext_i32_i8"),
+    (15, "s_sext_i32_i16"),
+    (16, "s_bitset0_b32"),
+    (17, "s_bitset0_b64"),
+    (18, "s_bitset1_b32"),
+    (19, "s_bitset1_b64"),
+    (20, "s_bitreplicate_b64_b32"),
+    (21, "s_abs_i32"),
+    (22, "s_bcnt0_i32_b32"),
+    (23, "s_bcnt0_i32_b64"),
+    (24, "s_bcnt1_i32_b32"),
+    (25, "s_bcnt1_i32_b64"),
+    (26, "s_quadmask_b32"),
+    (27, "s_quadmask_b64"),
+    (28, "s_wqm_b32"),
+    (29, "s_wqm_b64"),
+    (30, "s_not_b32"),
+    (31, "s_not_b64"),
+    (32, "s_and_saveexec_b32"),
+    (33, "s_and_saveexec_b64"),
+    (34, "s_or_saveexec_b32"),
+    (35, "s_or_saveexec_b64"),
+    (36, "s_xor_saveexec_b32"),
+    (37, "s_xor_saveexec_b64"),
+    (38, "s_nand_saveexec_b32"),
+    (39, "s_nand_saveexec_b64"),
+    (40, "s_nor_saveexec_b32"),
+    (41, "s_nor_saveexec_b64"),
+    (42, "s_xnor_saveexec_b32"),
+    (43, "s_xnor_saveexec_b64"),
+    (44, "s_and_not0_saveexec_b32"),
+    (45, "s_and_not0_saveexec_b64"),
+    (46, "s_or_not0_saveexec_b32"),
+    (47, "s_or_not0_saveexec_b64"),
+    (48, "s_and_not1_saveexec_b32"),
+    (49, "s_and_not1_saveexec_b64"),
+    (50, "s_or_not1_saveexec_b32"),
+    (51, "s_or_not1_saveexec_b64"),
+    (52, "s_and_not0_wrexec_b32"),
+    (53, "s_and_not0_wrexec_b64"),
+    (54, "s_and_not1_wrexec_b32"),
+    (55, "s_and_not1_wrexec_b64"),
+    (64, "s_movrels_b32"),
+    (65, "s_movrels_b64"),
+    (66, "s_movreld_b32"),
+    (67, "s_movreld_b64"),
+    (68, "s_movrelsd_2_b32"),
+    (71, "s_getpc_b64"),
+    (72, "s_setpc_b64"),
+    (73, "s_swappc_b64"),
+    (74, "s_rfe_b64"),
+    (76, "s_sendmsg_rtn_b32"),
+    (77, "s_sendmsg_rtn_b64"),
+];
+
+const SOP2: &[(u32, &str)] = &[
+    (0, "s_add_u32"),
+    (1, "s_sub_u32"),
+    (2, "s_add_i32"),
+    (3, "s_sub_i32"),
+    (4, "s_addc_u32"),
+    (5, "s_subb_u32"),
+    (6, "s_absdiff_i32"),
+    (8, "s_lshl_b32"),
+    (9, "s_lshl_b64"),
+    (10, "s_lshr_b32"),
+    (11, "s_lshr_b64"),
+    (12, "s_ashr_i32"),
+    (13, "s_ashr_i64"),
+    (14, "s_lshl1_add_u32"),
+    (15, "s_lshl2_add_u32"),
+    (16, "s_lshl3_add_u32"),
+    (17, "s_lshl4_add_u32"),
+    (18, "s_min_i32"),
+    (19, "s_min_u32"),
+    (20, "s_max_i32"),
+    (21, "s_max_u32"),
+    (22, "s_and_b32"),
+    (23, "s_and_b64"),
+    (24, "s_or_b32"),
+    (25, "s_or_b64"),
+    (26, "s_xor_b32"),
+    (27, "s_xor_b64"),
+    (28, "s_nand_b32"),
+    (29, "s_nand_b64"),
+    (30, "s_nor_b32"),
+    (31, "s_nor_b64"),
+    (32, "s_xnor_b32"),
+    (33, "s_xnor_b64"),
+    (34, "s_and_not1_b32"),
+    (35, "s_and_not1_b64"),
+    (36, "s_or_not1_b32"),
+    (37, "s_or_not1_b64"),
+    (38, "s_bfe_u32"),
+    (39, "s_bfe_i32"),
+    (40, "s_bfe_u64"),
+    (41, "s_bfe_i64"),
+    (42, "s_bfm_b32"),
+    (43, "s_bfm_b64"),
+    (44, "s_mul_i32"),
+    (45, "s_mul_hi_u32"),
+    (46, "s_mul_hi_i32"),
+    (48, "s_cselect_b32"),
+    (49, "s_cselect_b64"),
+    (50, "s_pack_ll_b32_b16"),
+    (51, "s_pack_lh_b32_b16"),
+    (52, "s_pack_hh_b32_b16"),
+    (53, "s_pack_hl_b32_b16"),
+];
+
+const SOPC: &[(u32, &str)] = &[
+    (0, "s_cmp_eq_i32"),
+    (1, "s_cmp_lg_i32"),
+    (2, "s_cmp_gt_i32"),
+    (3, "s_cmp_ge_i32"),
+    (4, "s_cmp_lt_i32"),
+    (5, "s_cmp_le_i32"),
+    (6, "s_cmp_eq_u32"),
+    (7, "s_cmp_lg_u32"),
+    (8, "s_cmp_gt_u32"),
+    (9, "s_cmp_ge_u32"),
+    (10, "s_cmp_lt_u32"),
+    (11, "s_cmp_le_u32"),
+    (12, "s_bitcmp0_b32"),
+    (13, "s_bitcmp1_b32"),
+    (14, "s_bitcmp0_b64"),
+    (15, "s_bitcmp1_b64"),
+    (16, "s_cmp_eq_u64"),
+    (17, "s_cmp_lg_u64"),
+];
+
+const SOPK: &[(u32, &str)] = &[
+    (0, "s_movk_i32"),
+    (1, "s_version"),
+    (2, "s_cmovk_i32"),
+    (3, "s_cmpk_eq_i32"),
+    (4, "s_cmpk_lg_i32"),
+    (5, "s_cmpk_gt_i32"),
+    (6, "s_cmpk_ge_i32"),
+    (7, "s_cmpk_lt_i32"),
+    (8, "s_cmpk_le_i32"),
+    (9, "s_cmpk_eq_u32"),
+    (10, "s_cmpk_lg_u32"),
+    (11, "s_cmpk_gt_u32"),
+    (12, "s_cmpk_ge_u32"),
+    (13, "s_cmpk_lt_u32"),
+    (14, "s_cmpk_le_u32"),
+    (15, "s_addk_i32"),
+    (16, "s_mulk_i32"),
+    (17, "s_getreg_b32"),
+    (18, "s_setreg_b32"),
+    (19, "s_setreg_imm32_b32"),
+    (20, "s_call_b64"),
+    (22, "s_waitcnt_vscnt"),
+    (23, "s_waitcnt_vmcnt"),
+    (24, "s_waitcnt_expcnt"),
+    (25, "s_waitcnt_lgkmcnt"),
+];
+
+const SOPP: &[(u32, &str)] = &[
+    (0, "s_nop"),
+    (1, "s_setkill"),
+    (2, "s_sethalt"),
+    (3, "s_sleep"),
+    (4, "s_set_inst_prefetch_distance"),
+    (5, "s_clause"),
+    (7, "s_delay_alu"),
+    (8, "s_waitcnt_depctr"),
+    (9, "s_waitcnt"),
+    (10, "s_wait_idle"),
+    (11, "s_wait_event"),
+    (16, "s_trap"),
+    (17, "s_round_mode"),
+    (18, "s_denorm_mode"),
+    (31, "s_code_end"),
+    (32, "s_branch"),
+    (33, "s_cbranch_scc0"),
+    (34, "s_cbranch_scc1"),
+    (35, "s_cbranch_vccz"),
+    (36, "s_cbranch_vccnz"),
+    (37, "s_cbranch_execz"),
+    (38, "s_cbranch_execnz"),
+    (39, "s_cbranch_cdbgsys"),
+    (40, "s_cbranch_cdbguser"),
+    (41, "s_cbranch_cdbgsys_or_user"),
+    (42, "s_cbranch_cdbgsys_and_user"),
+    (48, "s_endpgm"),
+    (49, "s_endpgm_saved"),
+    (50, "s_endpgm_ordered_ps_done"),
+    (52, "s_wakeup"),
+    (53, "s_setprio"),
+    (54, "s_sendmsg"),
+    (55, "s_sendmsghalt"),
+    (56, "s_incperflevel"),
+    (57, "s_decperflevel"),
+    (58, "s_ttracedata"),
+    (59, "s_ttracedata_imm"),
+    (60, "s_icache_inv"),
+    (61, "s_barrier"),
+];
+
+const SMEM: &[(u32, &str)] = &[
+    (0, "s_load_b32"),
+    (1, "s_load_b64"),
+    (2, "s_load_b128"),
+    (3, "s_load_b256"),
+    (4, "s_load_b512"),
+    (8, "s_buffer_load_b32"),
+    (9, "s_buffer_load_b64"),
+    (10, "s_buffer_load_b128"),
+    (11, "s_buffer_load_b256"),
+    (12, "s_buffer_load_b512"),
+];
+
+const VOP1: &[(u32, &str)] = &[
+    (0, "v_nop"),
+    (1, "v_mov_b32"),
+    (2, "v_readfirstlane_b32"),
+    (3, "v_cvt_i32_f64"),
+    (4, "v_cvt_f64_i32"),
+    (5, "v_cvt_f32_i32"),
+    (6, "v_cvt_f32_u32"),
+    (7, "v_cvt_u32_f32"),
+    (8, "v_cvt_i32_f32"),
+    (10, "v_cvt_f16_f32"),
+    (11, "v_cvt_f32_f16"),
+    (12, "v_cvt_nearest_i32_f32"),
+    (13, "v_cvt_floor_i32_f32"),
+    (14, "v_cvt_off_f32_i4"),
+    (15, "v_cvt_f32_f64"),
+    (16, "v_cvt_f64_f32"),
+    (17, "v_cvt_f32_ubyte0"),
+    (18, "v_cvt_f32_ubyte1"),
+    (19, "v_cvt_f32_ubyte2"),
+    (20, "v_cvt_f32_ubyte3"),
+    (21, "v_cvt_u32_f64"),
+    (22, "v_cvt_f64_u32"),
+    (23, "v_trunc_f64"),
+    (24, "v_ceil_f64"),
+    (25, "v_rndne_f64"),
+    (26, "v_floor_f64"),
+    (27, "v_pipeflush"),
+    (28, "v_mov_b16"),
+    (32, "v_fract_f32"),
+    (33, "v_trunc_f32"),
+    (34, "v_ceil_f32"),
+    (35, "v_rndne_f32"),
+    (36, "v_floor_f32"),
+    (37, "v_exp_f32"),
+    (39, "v_log_f32"),
+    (42, "v_rcp_f32"),
+    (43, "v_rcp_iflag_f32"),
+    (46, "v_rsq_f32"),
+    (47, "v_rcp_f64"),
+    (49, "v_rsq_f64"),
+    (51, "v_sqrt_f32"),
+    (52, "v_sqrt_f64"),
+    (53, "v_sin_f32"),
+    (54, "v_cos_f32"),
+    (55, "v_not_b32"),
+    (56, "v_bfrev_b32"),
+    (57, "v_clz_i32_u32"),
+    (58, "v_ctz_i32_b32"),
+    (59, "v_cls_i32"),
+    (60, "v_frexp_exp_i32_f64"),
+    (61, "v_frexp_mant_f64"),
+    (62, "v_fract_f64"),
+    (63, "v_frexp_exp_i32_f32"),
+    (64, "v_frexp_mant_f32"),
+    (65, "v_movreld_b32"),
+    (66, "v_movrels_b32"),
+    (67, "v_movrelsd_b32"),
+    (72, "v_movrelsd_2_b32"),
+    (80, "v_cvt_f16_u16"),
+    (81, "v_cvt_f16_i16"),
+    (82, "v_cvt_u16_f16"),
+    (83, "v_cvt_i16_f16"),
+    (84, "v_rcp_f16"),
+    (85, "v_sqrt_f16"),
+    (86, "v_rsq_f16"),
+    (87, "v_log_f16"),
+    (88, "v_exp_f16"),
+    (89, "v_frexp_mant_f16"),
+    (90, "v_frexp_exp_i16_f16"),
+    (91, "v_floor_f16"),
+    (92, "v_ceil_f16"),
+    (93, "v_trunc_f16"),
+    (94, "v_rndne_f16"),
+    (95, "v_fract_f16"),
+    (96, "v_sin_f16"),
+    (97, "v_cos_f16"),
+    (98, "v_sat_pk_u8_i16"),
+    (99, "v_cvt_norm_i16_f16"),
+    (100, "v_cvt_norm_u16_f16"),
+    (101, "v_swap_b32"),
+    (102, "v_swap_b16"),
+    (103, "v_permlane64_b32"),
+    (104, "v_swaprel_b32"),
+    (105, "v_not_b16"),
+    (106, "v_cvt_i32_i16"),
+    (107, "v_cvt_u32_u16"),
+];
+
+const VOP2: &[(u32, &str)] = &[
+    (1, "v_cndmask_b32"),
+    (2, "v_dot2acc_f32_f16"),
+    (3, "v_add_f32"),
+    (4, "v_sub_f32"),
+    (5, "v_subrev_f32"),
+    (6, "v_fmac_dx9_zero_f32"),
+    (7, "v_mul_dx9_zero_f32"),
+    (8, "v_mul_f32"),
+    (9, "v_mul_i32_i24"),
+    (10, "v_mul_hi_i32_i24"),
+    (11, "v_mul_u32_u24"),
+    (12, "v_mul_hi_u32_u24"),
+    (15, "v_min_f32"),
+    (16, "v_max_f32"),
+    (17, "v_min_i32"),
+    (18, "v_max_i32"),
+    (19, "v_min_u32"),
+    (20, "v_max_u32"),
+    (24, "v_lshlrev_b32"),
+    (25, "v_lshrrev_b32"),
+    (26, "v_ashrrev_i32"),
+    (27, "v_and_b32"),
+    (28, "v_or_b32"),
+    (29, "v_xor_b32"),
+    (30, "v_xnor_b32"),
+    (32, "v_add_co_ci_u32"),
+    (33, "v_sub_co_ci_u32"),
+    (34, "v_subrev_co_ci_u32"),
+    (37, "v_add_nc_u32"),
+    (38, "v_sub_nc_u32"),
+    (39, "v_subrev_nc_u32"),
+    (43, "v_fmac_f32"),
+    (44, "v_fmamk_f32"),
+    (45, "v_fmaak_f32"),
+    (47, "v_cvt_pk_rtz_f16_f32"),
+    (50, "v_add_f16"),
+    (51, "v_sub_f16"),
+    (52, "v_subrev_f16"),
+    (53, "v_mul_f16"),
+    (54, "v_fmac_f16"),
+    (55, "v_fmamk_f16"),
+    (56, "v_fmaak_f16"),
+    (57, "v_max_f16"),
+    (58, "v_min_f16"),
+    (59, "v_ldexp_f16"),
+    (60, "v_pk_fmac_f16"),
+];
+
+const VOP3P: &[(u32, &str)] = &[
+    (0, "v_pk_mad_i16"),
+    (1, "v_pk_mul_lo_u16"),
+    (2, "v_pk_add_i16"),
+    (3, "v_pk_sub_i16"),
+    (4, "v_pk_lshlrev_b16"),
+    (5, "v_pk_lshrrev_b16"),
+    (6, "v_pk_ashrrev_i16"),
+    (7, "v_pk_max_i16"),
+    (8, "v_pk_min_i16"),
+    (9, "v_pk_mad_u16"),
+    (10, "v_pk_add_u16"),
+    (11, "v_pk_sub_u16"),
+    (12, "v_pk_max_u16"),
+    (13, "v_pk_min_u16"),
+    (14, "v_pk_fma_f16"),
+    (15, "v_pk_add_f16"),
+    (16, "v_pk_mul_f16"),
+    (17, "v_pk_min_f16"),
+    (18, "v_pk_max_f16"),
+    (19, "v_dot2_f32_f16"),
+    (22, "v_dot4_i32_iu8"),
+    (23, "v_dot4_u32_u8"),
+    (24, "v_dot8_i32_iu4"),
+    (25, "v_dot8_u32_u4"),
+    (26, "v_dot2_f32_bf16"),
+    (32, "v_fma_mix_f32"),
+    (33, "v_fma_mixlo_f16"),
+    (34, "v_fma_mixhi_f16"),
+];
+
+const WMMA: &[(u32, &str)] = &[
+    (0, "v_wmma_f32_16x16x16_f16"),
+    (1, "v_wmma_f32_16x16x16_bf16"),
+    (2, "v_wmma_f16_16x16x16_f16"),
+    (3, "v_wmma_bf16_16x16x16_bf16"),
+    (4, "v_wmma_i32_16x16x16_iu8"),
+    (5, "v_wmma_i32_16x16x16_iu4"),
+];
+
+const VOPD: &[(u32, &str)] = &[
+    (0, "v_dual_fmac_f32"),
+    (1, "v_dual_fmaak_f32"),
+    (2, "v_dual_fmamk_f32"),
+    (3, "v_dual_mul_f32"),
+    (4, "v_dual_add_f32"),
+    (5, "v_dual_sub_f32"),
+    (6, "v_dual_subrev_f32"),
+    (7, "v_dual_mul_dx9_zero_f32"),
+    (8, "v_dual_mov_b32"),
+    (9, "v_dual_cndmask_b32"),
+    (10, "v_dual_max_f32"),
+    (11, "v_dual_min_f32"),
+    (12, "v_dual_dot2acc_f32_f16"),
+    (13, "v_dual_dot2acc_f32_bf16"),
+    (16, "v_dual_add_nc_u32"),
+    (17, "v_dual_lshlrev_b32"),
+    (18, "v_dual_and_b32"),
+];
+
+const VOP3: &[(u32, &str)] = &[
+    (521, "v_fma_dx9_zero_f32"),
+    (522, "v_mad_i32_i24"),
+    (523, "v_mad_u32_u24"),
+    (524, "v_cubeid_f32"),
+    (525, "v_cubesc_f32"),
+    (526, "v_cubetc_f32"),
+    (527, "v_cubema_f32"),
+    (528, "v_bfe_u32"),
+    (529, "v_bfe_i32"),
+    (530, "v_bfi_b32"),
+    (531, "v_fma_f32"),
+    (532, "v_fma_f64"),
+    (533, "v_lerp_u8"),
+    (534, "v_alignbit_b32"),
+    (535, "v_alignbyte_b32"),
+    (536, "v_mullit_f32"),
+    (537, "v_min3_f32"),
+    (538, "v_min3_i32"),
+    (539, "v_min3_u32"),
+    (540, "v_max3_f32"),
+    (541, "v_max3_i32"),
+    (542, "v_max3_u32"),
+    (543, "v_med3_f32"),
+    (544, "v_med3_i32"),
+    (545, "v_med3_u32"),
+    (546, "v_sad_u8"),
+    (547, "v_sad_hi_u8"),
+    (548, "v_sad_u16"),
+    (549, "v_sad_u32"),
+    (550, "v_cvt_pk_u8_f32"),
+    (551, "v_div_fixup_f32"),
+    (552, "v_div_fixup_f64"),
+    (567, "v_div_fmas_f32"),
+    (568, "v_div_fmas_f64"),
+    (569, "v_msad_u8"),
+    (570, "v_qsad_pk_u16_u8"),
+    (571, "v_mqsad_pk_u16_u8"),
+    (573, "v_mqsad_u32_u8"),
+    (576, "v_xor3_b32"),
+    (577, "v_mad_u16"),
+    (580, "v_perm_b32"),
+    (581, "v_xad_u32"),
+    (582, "v_lshl_add_u32"),
+    (583, "v_add_lshl_u32"),
+    (584, "v_fma_f16"),
+    (585, "v_min3_f16"),
+    (586, "v_min3_i16"),
+    (587, "v_min3_u16"),
+    (588, "v_max3_f16"),
+    (589, "v_max3_i16"),
+    (590, "v_max3_u16"),
+    (591, "v_med3_f16"),
+    (592, "v_med3_i16"),
+    (593, "v_med3_u16"),
+    (595, "v_mad_i16"),
+    (596, "v_div_fixup_f16"),
+    (597, "v_add3_u32"),
+    (598, "v_lshl_or_b32"),
+    (599, "v_and_or_b32"),
+    (600, "v_or3_b32"),
+    (601, "v_mad_u32_u16"),
+    (602, "v_mad_i32_i16"),
+    (603, "v_permlane16_b32"),
+    (604, "v_permlanex16_b32"),
+    (605, "v_cndmask_b16"),
+    (606, "v_maxmin_f32"),
+    (607, "v_minmax_f32"),
+    (608, "v_maxmin_f16"),
+    (609, "v_minmax_f16"),
+    (610, "v_maxmin_u32"),
+    (611, "v_minmax_u32"),
+    (612, "v_maxmin_i32"),
+    (613, "v_minmax_i32"),
+    (614, "v_dot2_f16_f16"),
+    (615, "v_dot2_bf16_bf16"),
+    (764, "v_div_scale_f32"),
+    (765, "v_div_scale_f64"),
+    (766, "v_mad_u64_u32"),
+    (767, "v_mad_i64_i32"),
+    (768, "v_add_co_u32"),
+    (769, "v_sub_co_u32"),
+    (770, "v_subrev_co_u32"),
+    (771, "v_add_nc_u16"),
+    (772, "v_sub_nc_u16"),
+    (773, "v_mul_lo_u16"),
+    (774, "v_cvt_pk_i16_f32"),
+    (775, "v_cvt_pk_u16_f32"),
+    (777, "v_max_u16"),
+    (778, "v_max_i16"),
+    (779, "v_min_u16"),
+    (780, "v_min_i16"),
+    (781, "v_add_nc_i16"),
+    (782, "v_sub_nc_i16"),
+    (785, "v_pack_b32_f16"),
+    (786, "v_cvt_pk_norm_i16_f16"),
+    (787, "v_cvt_pk_norm_u16_f16"),
+    (796, "v_ldexp_f32"),
+    (797, "v_bfm_b32"),
+    (798, "v_bcnt_u32_b32"),
+    (799, "v_mbcnt_lo_u32_b32"),
+    (800, "v_mbcnt_hi_u32_b32"),
+    (801, "v_cvt_pk_norm_i16_f32"),
+    (802, "v_cvt_pk_norm_u16_f32"),
+    (803, "v_cvt_pk_u16_u32"),
+    (804, "v_cvt_pk_i16_i32"),
+    (805, "v_sub_nc_i32"),
+    (806, "v_add_nc_i32"),
+    (807, "v_add_f64"),
+    (808, "v_mul_f64"),
+    (809, "v_min_f64"),
+    (810, "v_max_f64"),
+    (811, "v_ldexp_f64"),
+    (812, "v_mul_lo_u32"),
+    (813, "v_mul_hi_u32"),
+    (814, "v_mul_hi_i32"),
+    (815, "v_trig_preop_f64"),
+    (824, "v_lshlrev_b16"),
+    (825, "v_lshrrev_b16"),
+    (826, "v_ashrrev_i16"),
+    (828, "v_lshlrev_b64"),
+    (829, "v_lshrrev_b64"),
+    (830, "v_ashrrev_i64"),
+    (864, "v_readlane_b32"),
+    (865, "v_writelane_b32"),
+    (866, "v_and_b16"),
+    (867, "v_or_b16"),
+    (868, "v_xor_b16"),
+];
+
+const DS: &[(u32, &str)] = &[
+    (0, "ds_add_u32"),
+    (1, "ds_sub_u32"),
+    (2, "ds_rsub_u32"),
+    (3, "ds_inc_u32"),
+    (4, "ds_dec_u32"),
+    (5, "ds_min_i32"),
+    (6, "ds_max_i32"),
+    (7, "ds_min_u32"),
+    (8, "ds_max_u32"),
+    (9, "ds_and_b32"),
+    (10, "ds_or_b32"),
+    (11, "ds_xor_b32"),
+    (12, "ds_mskor_b32"),
+    (13, "ds_store_b32"),
+    (14, "ds_store_2addr_b32"),
+    (15, "ds_store_2addr_stride64_b32"),
+    (16, "ds_cmpstore_b32"),
+    (17, "ds_cmpstore_f32"),
+    (18, "ds_min_f32"),
+    (19, "ds_max_f32"),
+    (20, "ds_nop"),
+    (21, "ds_add_f32"),
+    (30, "ds_store_b8"),
+    (31, "ds_store_b16"),
+    (32, "ds_add_rtn_u32"),
+    (33, "ds_sub_rtn_u32"),
+    (34, "ds_rsub_rtn_u32"),
+    (35, "ds_inc_rtn_u32"),
+    (36, "ds_dec_rtn_u32"),
+    (37, "ds_min_rtn_i32"),
+    (38, "ds_max_rtn_i32"),
+    (39, "ds_min_rtn_u32"),
+    (40, "ds_max_rtn_u32"),
+    (41, "ds_and_rtn_b32"),
+    (42, "ds_or_rtn_b32"),
+    (43, "ds_xor_rtn_b32"),
+    (44, "ds_mskor_rtn_b32"),
+    (45, "ds_storexchg_rtn_b32"),
+    (46, "ds_storexchg_2addr_rtn_b32"),
+    (47, "ds_storexchg_2addr_stride64_rtn_b32"),
+    (48, "ds_cmpstore_rtn_b32"),
+    (49, "ds_cmpstore_rtn_f32"),
+    (50, "ds_min_rtn_f32"),
+    (51, "ds_max_rtn_f32"),
+    (52, "ds_wrap_rtn_b32"),
+    (53, "ds_swizzle_b32"),
+    (54, "ds_load_b32"),
+    (55, "ds_load_2addr_b32"),
+    (56, "ds_load_2addr_stride64_b32"),
+    (57, "ds_load_i8"),
+    (58, "ds_load_u8"),
+    (59, "ds_load_i16"),
+    (60, "ds_load_u16"),
+    (61, "ds_consume"),
+    (62, "ds_append"),
+    (63, "ds_ordered_count"),
+    (64, "ds_add_u64"),
+    (65, "ds_sub_u64"),
+    (66, "ds_rsub_u64"),
+    (67, "ds_inc_u64"),
+    (68, "ds_dec_u64"),
+    (69, "ds_min_i64"),
+    (70, "ds_max_i64"),
+    (71, "ds_min_u64"),
+    (72, "ds_max_u64"),
+    (73, "ds_and_b64"),
+    (74, "ds_or_b64"),
+    (75, "ds_xor_b64"),
+    (76, "ds_mskor_b64"),
+    (77, "ds_store_b64"),
+    (78, "ds_store_2addr_b64"),
+    (79, "ds_store_2addr_stride64_b64"),
+    (80, "ds_cmpstore_b64"),
+    (81, "ds_cmpstore_f64"),
+    (82, "ds_min_f64"),
+    (83, "ds_max_f64"),
+    (118, "ds_load_b64"),
+    (119, "ds_load_2addr_b64"),
+    (120, "ds_load_2addr_stride64_b64"),
+    (160, "ds_store_b8_d16_hi"),
+    (161, "ds_store_b16_d16_hi"),
+    (162, "ds_load_u8_d16"),
+    (163, "ds_load_u8_d16_hi"),
+    (164, "ds_load_i8_d16"),
+    (165, "ds_load_i8_d16_hi"),
+    (166, "ds_load_u16_d16"),
+    (167, "ds_load_u16_d16_hi"),
+    (222, "ds_store_b96"),
+    (223, "ds_store_b128"),
+    (254, "ds_load_b96"),
+    (255, "ds_load_b128"),
+];
+
+const FLAT: &[(u32, &str)] = &[
+    (16, "load_u8"),
+    (17, "load_i8"),
+    (18, "load_u16"),
+    (19, "load_i16"),
+    (20, "load_b32"),
+    (21, "load_b64"),
+    (22, "load_b96"),
+    (23, "load_b128"),
+    (24, "store_b8"),
+    (25, "store_b16"),
+    (26, "store_b32"),
+    (27, "store_b64"),
+    (28, "store_b96"),
+    (29, "store_b128"),
+    (30, "load_d16_u8"),
+    (31, "load_d16_i8"),
+    (32, "load_d16_b16"),
+    (33, "load_d16_hi_u8"),
+    (34, "load_d16_hi_i8"),
+    (35, "load_d16_hi_b16"),
+    (36, "store_d16_hi_b8"),
+    (37, "store_d16_hi_b16"),
+    (51, "atomic_swap_b32"),
+    (52, "atomic_cmpswap_b32"),
+    (53, "atomic_add_u32"),
+    (54, "atomic_sub_u32"),
+    (55, "atomic_csub_u32"),
+    (56, "atomic_min_i32"),
+    (57, "atomic_min_u32"),
+    (58, "atomic_max_i32"),
+    (59, "atomic_max_u32"),
+    (60, "atomic_and_b32"),
+    (61, "atomic_or_b32"),
+    (62, "atomic_xor_b32"),
+    (63, "atomic_inc_u32"),
+    (64, "atomic_dec_u32"),
+    (81, "atomic_cmpswap_f32"),
+    (82, "atomic_min_f32"),
+    (83, "atomic_max_f32"),
+    (86, "atomic_add_f32"),
+];
+
+const CMP: [&str; 16] = [
+    "f", "lt", "eq", "le", "gt", "lg", "ge", "o", "u", "nge", "nlg", "ngt", "nle", "neq", "nlt",
+    "t",
+];
+const ICMP: [&str; 8] = ["f", "lt", "eq", "le", "gt", "ne", "ge", "t"];
+
+fn lookup(names: &[(u32, &'static str)], op: u32) -> Option<String> {
+    names
+        .iter()
+        .find(|(o, _)| *o == op)
+        .map(|(_, n)| n.to_string())
+}
+
+fn vopc(op: u32) -> Option<String> {
+    let (prefix, op) = match op >= 128 {
+        true => ("v_cmpx", op - 128),
+        false => ("v_cmp", op),
+    };
+    let (cond, ty) = match op {
+        0..=15 => (CMP[op as usize], "f16"),
+        16..=31 => (CMP[op as usize - 16], "f32"),
+        32..=47 => (CMP[op as usize - 32], "f64"),
+        49..=54 => (ICMP[op as usize - 48], "i16"),
+        57..=62 => (ICMP[op as usize - 56], "u16"),
+        64..=71 => (ICMP[op as usize - 64], "i32"),
+        72..=79 => (ICMP[op as usize - 72], "u32"),
+        80..=87 => (ICMP[op as usize - 80], "i64"),
+        88..=95 => (ICMP[op as usize - 88], "u64"),
+        125 => ("class", "f16"),
+        126 => ("class", "f32"),
+        127 => ("class", "f64"),
+        _ => return None,
+    };
+    Some(format!("{prefix}_{cond}_{ty}"))
+}
+
+/* promoted VOP1/VOP2/VOPC ops keep their name, with an _e64 suffix like the assembler prints */
+fn vop3(op: u32) -> Option<String> {
+    let promoted = match op {
+        0..=255 => vopc(op),
+        256..=319 => lookup(VOP2, op - 256),
+        384..=511 => lookup(VOP1, op - 384),
+        _ => return lookup(VOP3, op),
+    };
+    promoted.map(|n| n + "_e64")
+}
+
+/* the opcode field of the instruction at stream[0], VOPD packs OPX above the 5 bits of OPY */
+pub fn opcode(stream: &[u32]) -> u32 {
+    let instruction = stream[0];
+    match encoding(instruction) {
+        Encoding::Smem | Encoding::Ds | Encoding::Mubuf | Encoding::Mimg => {
+            (instruction >> 18) & 0xff
+        }
+        Encoding::Sop1 => (instruction >> 8) & 0xff,
+        Encoding::Sopc | Encoding::Sopp | Encoding::Vop3p => (instruction >> 16) & 0x7f,
+        Encoding::Sopk => (instruction >> 23) & 0x1f,
+        Encoding::Sop2 => (instruction >> 23) & 0x7f,
+        Encoding::Vop1 => (instruction >> 9) & 0xff,
+        Encoding::Vopd => (((instruction >> 22) & 0xf) << 5) | ((instruction >> 17) & 0x1f),
+        Encoding::Vopc => (instruction >> 17) & 0xff,
+        Encoding::Vop2 => (instruction >> 25) & 0x3f,
+        Encoding::Vop3 => (instruction >> 16) & 0x3ff,
+        Encoding::Flat => (instruction >> 18) & 0x7f,
+        Encoding::Mtbuf => (instruction >> 15) & 0xf,
+        _ => 0,
+    }
+}
+
+/* assembler name of the instruction at stream[0], VOPD pairs are joined with "::" */
+pub fn mnemonic(stream: &[u32]) -> String {
+    let instruction = stream[0];
+    let (enc, op) = (encoding(instruction), opcode(stream));
+    let name = match enc {
+        Encoding::Smem => lookup(SMEM, op),
+        Encoding::Sop1 => lookup(SOP1, op),
+        Encoding::Sopc => lookup(SOPC, op),
+        Encoding::Sopp => lookup(SOPP, op),
+        Encoding::Sopk => lookup(SOPK, op),
+        Encoding::Sop2 => lookup(SOP2, op),
+        Encoding::Vop3p => match op {
+            64..=69 => lookup(WMMA, op - 64),
+            _ => lookup(VOP3P, op),
+        },
+        Encoding::Vop1 => lookup(VOP1, op),
+        Encoding::Vopd => lookup(VOPD, op >> 5)
+            .zip(lookup(VOPD, op & 0x1f))
+            .map(|(x, y)| format!("{x}::{y}")),
+        Encoding::Vopc => vopc(op),
+        Encoding::Vop2 => lookup(VOP2, op),
+        Encoding::Vop3 => vop3(op),
+        Encoding::Ds => lookup(DS, op),
+        Encoding::Flat => {
+            let seg = ["flat", "scratch", "global", ""][((instruction >> 16) & 0x3) as usize];
+            lookup(FLAT, op)
+                .filter(|_| !seg.is_empty())
+                .map(|n| format!("{seg}_{n}"))
+        }
+        _ => None,
+    };
+    name.unwrap_or_else(|| format!("{}_{instruction:08x}", format!("{enc:?}").to_lowercase()))
+}
+
+#[cfg(test)]
+mod test_mnemonic {
+    use super::*;
+
+    #[test]
+    fn test_scalar() {
+        assert_eq!(mnemonic(&[0xBE8200FF, 0x3F800000]), "s_mov_b32");
+        assert_eq!(mnemonic(&[0xBFB00000]), "s_endpgm");
+        assert_eq!(mnemonic(&[0xBFBD0000]), "s_barrier");
+        assert_eq!(mnemonic(&[0xBF89FC07]), "s_waitcnt");
+        assert_eq!(mnemonic(&[0xBE804A6C]), "s_rfe_b64");
+        assert_eq!(mnemonic(&[0x81048104]), "s_add_i32");
+        assert_eq!(mnemonic(&[0xBF068304]), "s_cmp_eq_u32");
+        assert_eq!(mnemonic(&[0xBFA2FFFD]), "s_cbranch_scc1");
+        assert_eq!(mnemonic(&[0xB9803301, 0x5]), "s_setreg_imm32_b32");
+        assert_eq!(mnemonic(&[0xF4040080, 0xF8000000]), "s_load_b64");
+    }
+
+    #[test]
+    fn test_vector() {
+        assert_eq!(mnemonic(&[0x7E025500]), "v_rcp_f32");
+        assert_eq!(mnemonic(&[0x4A0A0004]), "v_add_nc_u32");
+        assert_eq!(mnemonic(&[0x7C94010A]), "v_cmp_eq_u32");
+        assert_eq!(mnemonic(&[0x7D9C010A]), "v_cmpx_ge_u32");
+        assert_eq!(mnemonic(&[0xD7000D0A, 0x0002010A]), "v_add_co_u32");
+        assert_eq!(mnemonic(&[0xD5010000, 0]), "v_cndmask_b32_e64");
+        assert_eq!(mnemonic(&[0xD8340000, 0x00000001]), "ds_store_b32");
+        assert_eq!(mnemonic(&[0xDC6A0000, 0x00020102]), "global_store_b32");
+        assert_eq!(mnemonic(&[0xFC000000]), "unknown_fc000000");
+    }
+}
@@ -0,0 +1,297 @@
+/*
+ * PERF=1 estimates cycles with a cycle-approximate model of one RDNA3 wave: instructions issue in
+ * order, each occupies the wave for its issue cycles and its results are ready latency cycles after
+ * it issued, a read of a register that isn't ready stalls. VOPD issues both of its ops at once.
+ * Waves of a workgroup meet at s_barrier. The kernel estimate spreads workgroups over the device
+ * at the occupancy their VGPR and LDS usage allow. Rough figures, meant to rank variants.
+ */
+use crate::decode::{encoding, footprint, instr_len, Encoding, RegFile, RegRange};
+use crate::hazard::{SALU_LATENCY, TRANS_LATENCY, VALU_LATENCY};
+use crate::memory::VecDataStore;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+const WMMA_ISSUE: usize = 16;
+const WMMA_LATENCY: usize = 32;
+const TRANS_ISSUE: usize = 4;
+const SMEM_LATENCY: usize = 40;
+const LDS_ISSUE: usize = 2;
+const LDS_LATENCY: usize = 32;
+const VMEM_ISSUE: usize = 4;
+const VMEM_LATENCY: usize = 350;
+/* extra cycles for every dword after the first a memory op returns per lane */
+const LDS_DWORD: usize = 2;
+const VMEM_DWORD: usize = 8;
+
+/* a 7900 XTX class device */
+const WGPS: usize = 48;
+const SIMDS_PER_WGP: usize = 4;
+const MAX_WAVES_PER_SIMD: usize = 16;
+const VGPRS_PER_SIMD: usize = 1536;
+const VGPR_GRANULE: usize = 8;
+const LDS_PER_WGP: usize = 128 * 1024;
+
+/* the last launch's kernel estimate, 0 without PERF */
+pub static LAST_CYCLES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Salu,
+    Valu,
+    Trans,
+    Wmma,
+    Vopd,
+    Smem,
+    Lds,
+    Vmem,
+    Other,
+}
+
+pub fn class(stream: &[u32]) -> Class {
+    let instruction = stream[0];
+    match encoding(instruction) {
+        Encoding::Sop1 | Encoding::Sop2 | Encoding::Sopc | Encoding::Sopk => Class::Salu,
+        Encoding::Smem => Class::Smem,
+        Encoding::Vopd => Class::Vopd,
+        Encoding::Vop3p if matches!((instruction >> 16) & 0x7f, 64..=69) => Class::Wmma,
+        Encoding::Ds | Encoding::Ldsdir => Class::Lds,
+        Encoding::Flat | Encoding::Mubuf | Encoding::Mtbuf | Encoding::Mimg => Class::Vmem,
+        _ => match crate::hazard::unit(stream) {
+            Some(crate::hazard::Unit::Trans) => Class::Trans,
+            Some(_) => Class::Valu,
+            None => Class::Other,
+        },
+    }
+}
+
+/* issue and result latency in cycles */
+fn cost(stream: &[u32], class: Class, dwords: usize) -> (usize, usize) {
+    let instruction = stream[0];
+    let extra = dwords.saturating_sub(1);
+    match class {
+        Class::Salu => (1, SALU_LATENCY),
+        Class::Valu | Class::Vopd => (1, VALU_LATENCY),
+        Class::Trans => (TRANS_ISSUE, TRANS_LATENCY),
+        Class::Wmma => (WMMA_ISSUE, WMMA_LATENCY),
+        Class::Smem => (1, SMEM_LATENCY),
+        Class::Lds => (LDS_ISSUE, LDS_LATENCY + LDS_DWORD * extra),
+        Class::Vmem => (VMEM_ISSUE, VMEM_LATENCY + VMEM_DWORD * extra),
+        Class::Other => match (instruction >> 16) & 0x7f {
+            // s_nop
+            0 => ((instruction & 0xf) as usize + 1, 0),
+            // s_delay_alu never issues, the dependency stall covers it
+            7 => (0, 0),
+            _ => (1, 0),
+        },
+    }
+}
+
+/* the cycles of one wave */
+#[derive(Debug, Clone, Default)]
+pub struct PerfTracker {
+    /* when the next instruction can issue */
+    pub cycle: usize,
+    /* cycles spent issuing, what the wave needs of its SIMD */
+    pub busy: usize,
+    pub instructions: usize,
+    pub dual_issued: usize,
+    ready: HashMap<RegRange, usize>,
+}
+
+impl PerfTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(&mut self, stream: &[u32]) {
+        let class = class(stream);
+        let fp = footprint(stream);
+        let dwords = fp.writes.iter().map(|r| r.len).max().unwrap_or(1);
+        let (issue, latency) = cost(stream, class, dwords);
+        let reads = fp.reads.iter().flat_map(|r| r.regs());
+        let start = reads.fold(self.cycle, |start, reg| {
+            start.max(self.ready.get(&reg).copied().unwrap_or(0))
+        });
+        for reg in fp.writes.iter().flat_map(|r| r.regs()) {
+            self.ready.insert(reg, start + latency);
+        }
+        self.cycle = start + issue;
+        self.busy += issue;
+        self.instructions += 1;
+        if class == Class::Vopd {
+            self.dual_issued += 1;
+        }
+    }
+}
+
+/* VGPRs a kernel uses, from the highest one any instruction touches */
+pub fn vgprs(code: &[u32]) -> usize {
+    let mut pc = 0;
+    let mut count = 0;
+    while pc < code.len() {
+        let fp = footprint(&code[pc..]);
+        for r in fp.reads.iter().chain(&fp.writes) {
+            if r.file == RegFile::Vgpr {
+                count = count.max(r.idx + r.len);
+            }
+        }
+        pc += instr_len(&code[pc..]);
+    }
+    count
+}
+
+/* LDS a workgroup used, up to its last nonzero byte */
+pub fn lds_bytes(lds: &VecDataStore) -> usize {
+    lds.data.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1)
+}
+
+/* waves per SIMD and workgroups per WGP */
+pub fn occupancy(vgprs: usize, lds: usize, waves_per_wg: usize) -> (usize, usize) {
+    let alloc = vgprs.max(1).div_ceil(VGPR_GRANULE) * VGPR_GRANULE;
+    let waves = (VGPRS_PER_SIMD / alloc).clamp(1, MAX_WAVES_PER_SIMD);
+    let by_waves = waves * SIMDS_PER_WGP / waves_per_wg.max(1);
+    let by_lds = LDS_PER_WGP.checked_div(lds).unwrap_or(usize::MAX);
+    (waves, by_waves.min(by_lds).max(1))
+}
+
+/* what the workgroups of a launch took */
+#[derive(Debug, Default)]
+pub struct Launch {
+    /* dispatch index and cycles of every workgroup */
+    pub workgroups: Vec<(u32, usize)>,
+    pub waves: Vec<usize>,
+    pub busy: usize,
+    pub instructions: usize,
+    pub dual_issued: usize,
+    pub waves_per_wg: usize,
+    pub lds: usize,
+}
+
+impl Launch {
+    pub fn add(&mut self, i: u32, waves: &[&PerfTracker], lds: usize) {
+        let cycles = waves.iter().map(|w| w.cycle).max().unwrap_or(0);
+        self.workgroups.push((i, cycles));
+        for w in waves {
+            self.waves.push(w.cycle);
+            self.busy += w.busy;
+            self.instructions += w.instructions;
+            self.dual_issued += w.dual_issued;
+        }
+        self.waves_per_wg = self.waves_per_wg.max(waves.len());
+        self.lds = self.lds.max(lds);
+    }
+
+    /*
+     * workgroups go to the first free slot in dispatch order, every WGP holds as many as
+     * occupancy allows, and the device can't issue faster than its SIMDs
+     */
+    pub fn kernel_cycles(&mut self, vgprs: usize) -> usize {
+        let (_, per_wgp) = occupancy(vgprs, self.lds, self.waves_per_wg);
+        let mut slots = BinaryHeap::new();
+        slots.extend((0..WGPS * per_wgp).map(|_| Reverse(0)));
+        self.workgroups.sort();
+        let mut end = 0;
+        for &(_, cycles) in &self.workgroups {
+            let Reverse(free) = slots.pop().unwrap();
+            end = end.max(free + cycles);
+            slots.push(Reverse(free + cycles));
+        }
+        end.max(self.busy.div_ceil(WGPS * SIMDS_PER_WGP))
+    }
+
+    pub fn report(mut self, vgprs: usize) {
+        if self.waves.is_empty() {
+            return;
+        }
+        let (waves, per_wgp) = occupancy(vgprs, self.lds, self.waves_per_wg);
+        let kernel = self.kernel_cycles(vgprs);
+        LAST_CYCLES.store(kernel as u64, Relaxed);
+        println!(
+            "[remu] perf: {} workgroup(s) x {} wave(s), {vgprs} vgprs, {} B lds, {waves} waves/simd, {per_wgp} workgroup(s)/wgp",
+            self.workgroups.len(),
+            self.waves_per_wg,
+            self.lds
+        );
+        let (min, max) = (self.waves.iter().min(), self.waves.iter().max());
+        let avg = self.waves.iter().sum::<usize>() / self.waves.len();
+        println!(
+            "[remu] perf: {} instructions, {} dual issued, cycles per wave min {} avg {avg} max {}, ~{kernel} cycles",
+            self.instructions,
+            self.dual_issued,
+            min.unwrap(),
+            max.unwrap()
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_perf {
+    use super::*;
+
+    fn run(prg: &[u32]) -> PerfTracker {
+        let mut t = PerfTracker::new();
+        let mut pc = 0;
+        while pc < prg.len() {
+            t.step(&prg[pc..]);
+            pc += instr_len(&prg[pc..]);
+        }
+        t
+    }
+
+    #[test]
+    fn test_dependency_stall() {
+        // v_mov_b32 v1, 1; v_mov_b32 v5, 0
+        assert_eq!(run(&[0x7E020281, 0x7E0A0280]).cycle, 2);
+        // v_mov_b32 v1, 1; v_add_f32 v3, v1, v1 waits for v1
+        assert_eq!(run(&[0x7E020281, 0x06060301]).cycle, VALU_LATENCY + 1);
+        // s_nop 3
+        assert_eq!(run(&[0xBF800003]).cycle, 4);
+    }
+
+    #[test]
+    fn test_memory_latency() {
+        // global_load_b32 v1, v0, s[2:3]; v_add_f32 v3, v1, v1
+        let t = run(&[0xDC520000, 0x01020000, 0x06060301]);
+        assert_eq!(t.cycle, VMEM_LATENCY + 1);
+        assert_eq!(t.busy, VMEM_ISSUE + 1);
+        // global_load_b128 returns 4 dwords per lane
+        let t = run(&[0xDC5E0000, 0x01020000, 0x06060301]);
+        assert_eq!(t.cycle, VMEM_LATENCY + 3 * VMEM_DWORD + 1);
+    }
+
+    #[test]
+    fn test_vopd_dual_issue() {
+        // v_dual_mov_b32 v0, 1 :: v_dual_mov_b32 v1, 2
+        let t = run(&[0xCA100081, 0x00000082]);
+        assert_eq!((t.cycle, t.instructions, t.dual_issued), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_occupancy() {
+        assert_eq!(occupancy(24, 0, 2), (16, 32));
+        assert_eq!(occupancy(256, 0, 2), (6, 12));
+        assert_eq!(occupancy(24, 64 * 1024, 2), (16, 2));
+        assert_eq!(occupancy(256, 0, 32), (6, 1));
+    }
+
+    #[test]
+    fn test_kernel_cycles() {
+        let mut launch = Launch {
+            waves_per_wg: 1,
+            ..Default::default()
+        };
+        let wave = PerfTracker {
+            cycle: 100,
+            busy: 4,
+            ..Default::default()
+        };
+        for i in 0..WGPS as u32 * 64 * 3 {
+            launch.add(i, &[&wave], 64 * 1024);
+        }
+        // two workgroups fit a WGP by LDS, so every slot runs 192 / 2 workgroups in a row
+        assert_eq!(launch.kernel_cycles(24), 96 * 100);
+        launch.lds = 0;
+        assert_eq!(launch.kernel_cycles(24), 3 * 100);
+    }
+}
@@ -0,0 +1,208 @@
+/*
+ * A pre-flight scan of a whole kernel for instructions remu can't execute, so a launch fails
+ * before emulating anything instead of when a wave first reaches one. PREFLIGHT=1 runs it before
+ * every launch. The tables mirror the opcodes the handlers in thread.rs implement, an op added
+ * there has to be added here too.
+ */
+use crate::decode::{encoding, Encoding};
+use crate::mnemonic::{mnemonic, opcode};
+use crate::program::{Kind, Program};
+use crate::thread::SGPR_COUNT;
+use std::fmt;
+
+const NULL_SRC: u64 = 124;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsupported {
+    /* byte offset into the kernel */
+    pub pc: usize,
+    pub words: Vec<u32>,
+    pub mnemonic: String,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words = self.words.iter().map(|w| format!("{w:08X}"));
+        let words = words.collect::<Vec<_>>().join(" ");
+        write!(f, "pc=0x{:x} {} ({words})", self.pc, self.mnemonic)
+    }
+}
+
+fn vopc(op: u32) -> bool {
+    matches!(
+        op,
+        0..=47 | 49..=54 | 57..=62 | 64..=95 | 125..=143 | 144..=174 | 177..=182 | 185..=190 | 192..=223
+    )
+}
+
+fn vopd(op: u32) -> bool {
+    matches!(op, 0..=6 | 8..=11 | 16..=18)
+}
+
+fn vop3(instr: u64) -> bool {
+    let op = ((instr >> 16) & 0x3ff) as u32;
+    let vdst = (instr & 0xff) as usize;
+    let neg = (instr >> 61) & 0x7;
+    // no output modifiers or clamping, and no opsel outside of VOP3SD where the sdst sits
+    let opsel = (instr >> 11) & 0xf;
+    if (instr >> 59) & 0x3 != 0 || (instr >> 15) & 0x1 != 0 {
+        return false;
+    }
+    let int = matches!(
+        op,
+        283..=286 | 522 | 523 | 528..=530 | 534 | 541 | 544 | 576 | 580..=583 | 597..=600
+            | 798 | 812..=814
+    );
+    match op {
+        // VOP3SD
+        288 | 289 | 764..=766 | 768 | 769 => true,
+        _ if opsel != 0 => false,
+        0..=255 => vopc(op) && matches!(vdst, 0..=SGPR_COUNT | 106 | 126),
+        257 | 259..=261 | 264 | 272 | 299 | 392 | 531 | 537 | 540 | 551 | 567 | 796 => true,
+        306 | 313 | 584 | 585 | 588 | 596 => true,
+        394 | 395 | 407 | 467 | 532 | 552 | 568 | 785 | 807..=811 | 828..=830 => true,
+        577 | 771..=773 | 777..=782 | 824..=826 | 864 | 865 => true,
+        // integer ops don't take neg
+        _ => int && neg == 0,
+    }
+}
+
+fn flat(instr: u64) -> bool {
+    let op = (instr >> 18) & 0x7f;
+    let saddr = (instr >> 48) & 0x7f;
+    match (instr >> 16) & 0x3 {
+        // scratch only with a constant address
+        1 => {
+            let sve = (instr >> 50) & 0x1 != 0;
+            sve && matches!(saddr, NULL_SRC | 0x7f) && matches!(op, 20..=23 | 26..=29)
+        }
+        2 => matches!(op, 16..=29 | 35 | 37),
+        _ => false,
+    }
+}
+
+/* whether thread.rs implements the instruction at stream[0] */
+pub fn supported(stream: &[u32]) -> bool {
+    let instr = ((*stream.get(1).unwrap_or(&0) as u64) << 32) | stream[0] as u64;
+    let op = opcode(stream);
+    match encoding(stream[0]) {
+        Encoding::Smem => op <= 4,
+        Encoding::Sop1 => matches!(op, 0 | 1 | 4 | 10 | 12 | 14..=16 | 18 | 30 | 32 | 34 | 48),
+        Encoding::Sopc => matches!(op, 0..=12 | 16 | 17),
+        // s_code_end only pads the code object
+        Encoding::Sopp => matches!(op, 31..=38),
+        Encoding::Sopk => matches!(op, 0 | 3..=5 | 7 | 9 | 10 | 13 | 15..=19),
+        Encoding::Sop2 => matches!(
+            op,
+            0..=5 | 8..=13 | 18..=20 | 22..=27 | 34 | 36 | 38..=41 | 44..=46 | 48
+        ),
+        Encoding::Vop3p => {
+            (instr >> 15) & 0x1 == 0 && matches!(op, 1 | 4 | 9..=11 | 14..=18 | 32..=34 | 64 | 66)
+        }
+        Encoding::Vop1 => matches!(
+            op,
+            1..=8 | 10 | 11 | 15..=23 | 25 | 26 | 35 | 37 | 39 | 42 | 43 | 47 | 49 | 51
+                | 55..=57 | 59..=61 | 80..=85 | 87 | 88
+        ),
+        Encoding::Vopd => vopd(op >> 5) && vopd(op & 0x1f),
+        Encoding::Vopc => vopc(op),
+        Encoding::Vop2 => matches!(
+            op,
+            1..=5 | 8 | 9 | 11 | 15 | 16 | 18..=20 | 24..=29 | 32..=34 | 37..=39 | 43..=45
+                | 50 | 51 | 53..=58
+        ),
+        Encoding::Vop3 => vop3(instr),
+        // not on GDS
+        Encoding::Ds => {
+            (instr >> 17) & 0x1 == 0
+                && matches!(
+                    op,
+                    13 | 14 | 30 | 31 | 54 | 55 | 57
+                        ..=60 | 77 | 78 | 118 | 119 | 222 | 223 | 254 | 255
+                )
+        }
+        Encoding::Flat => flat(instr),
+        // raises an illegal instruction trap
+        Encoding::Unknown => true,
+        _ => false,
+    }
+}
+
+/* every instruction of the kernel a launch would fail on, in pc order */
+pub fn scan(program: &Program) -> Vec<Unsupported> {
+    let insts = program.insts().iter().filter(|i| i.kind == Kind::Exec);
+    let unsupported = insts.filter(|i| !supported(&program.code[i.pc..]));
+    unsupported
+        .map(|i| Unsupported {
+            pc: i.pc * 4,
+            words: program.code[i.pc..i.pc + i.len].to_vec(),
+            mnemonic: mnemonic(&program.code[i.pc..]),
+        })
+        .collect()
+}
+
+/* print what scan found, true if the kernel can run */
+pub fn report(unsupported: &[Unsupported]) -> bool {
+    if unsupported.is_empty() {
+        return true;
+    }
+    println!(
+        "[remu] preflight: {} unsupported instruction(s)",
+        unsupported.len()
+    );
+    for u in unsupported {
+        println!("[remu]     {u}");
+    }
+    false
+}
+
+#[cfg(test)]
+mod test_preflight {
+    use super::*;
+    use crate::utils::END_PRG;
+
+    #[test]
+    fn test_supported() {
+        // v_mov_b32 v1, 1
+        assert!(supported(&[0x7E020281]));
+        // s_ctz_i32_b32 s5, s1
+        assert!(!supported(&[0xBE850801]));
+        // v_dual_mov_b32 v0, 1 :: v_dual_mov_b32 v1, 2
+        assert!(supported(&[0xCA100081, 0x00000082]));
+        // v_add_f32_e64 v0, |v1|, -v2 takes modifiers, v_xor3_b32 doesn't
+        assert!(supported(&[0xD5030100, 0x40020501]));
+        assert!(supported(&[0xD6400000, 0x04060501]));
+        assert!(!supported(&[0xD6400000, 0x24060501]));
+        // v_add_f32_e64 v0, v1, v2 mul:2
+        assert!(!supported(&[0xD5030000, 0x08020501]));
+        // global_load_b32 v1, v0, s[2:3] and a flat load
+        assert!(supported(&[0xDC520000, 0x01020000]));
+        assert!(!supported(&[0xDC500000, 0x01020000]));
+        // buffer_load_b32
+        assert!(!supported(&[0xE0500000, 0x80000100]));
+    }
+
+    #[test]
+    fn test_scan() {
+        let program = Program::new(
+            vec![
+                0x7E020281, // v_mov_b32 v1, 1
+                0xBE850801, // s_ctz_i32_b32 s5, s1
+                0xBF800000, // s_nop 0
+                0xE0500000, // buffer_load_b32 v1, off, s[0:3], 0
+                0x80000100, END_PRG,
+            ],
+            None,
+        );
+        let found = scan(&program);
+        let pcs = found.iter().map(|u| u.pc).collect::<Vec<_>>();
+        assert_eq!(pcs, [4, 12]);
+        assert_eq!(found[0].to_string(), "pc=0x4 s_ctz_i32_b32 (BE850801)");
+        assert_eq!(found[1].words, [0xE0500000, 0x80000100]);
+        assert!(!report(&found));
+        assert!(report(&scan(&Program::new(
+            vec![0x7E020281, END_PRG],
+            None
+        ))));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::decode::{encoding, ignores_exec, instr_len, is_float, is_scalar, Encoding, Operands};
+use crate::thread::{handler, Handler};
+use crate::trap::Trap;
+use crate::utils::END_PRG;
+use lazy_static::lazy_static;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+pub const S_BARRIER: u32 = 0xBFBD0000;
+const SYNCS: [u32; 4] = [0xBF89FC07, 0xBC7C0000, 0xBF890007, 0xBFB60003];
+/* programs kept alive across run_asm calls */
+const CACHE_SIZE: usize = 64;
+
+lazy_static! {
+    static ref PROGRAMS: Mutex<Cache> = Mutex::new(Cache::default());
+}
+
+/* programs by hash with the tick they were last used at, the least recently used one goes first */
+#[derive(Default)]
+struct Cache {
+    programs: HashMap<u64, (Arc<Program>, u64)>,
+    tick: u64,
+}
+
+impl Cache {
+    fn get(&mut self, key: u64) -> Option<Arc<Program>> {
+        self.tick += 1;
+        let (program, used) = self.programs.get_mut(&key)?;
+        *used = self.tick;
+        Some(program.clone())
+    }
+
+    fn insert(&mut self, key: u64, program: Arc<Program>) {
+        if self.programs.len() >= CACHE_SIZE && !self.programs.contains_key(&key) {
+            let oldest = self.programs.iter().min_by_key(|(_, (_, used))| *used);
+            if let Some(&oldest) = oldest.map(|(k, _)| k) {
+                self.programs.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.programs.insert(key, (program, self.tick));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    EndPgm,
+    Barrier,
+    /* waits, s_nop and friends the emulator runs in order anyway */
+    Skip,
+    Trap(Trap),
+    /* s_rfe_b64 with the sgpr pair holding the return address */
+    Rfe(usize),
+    Exec,
+}
+
+#[derive(Clone, Copy)]
+pub struct Inst {
+    pub pc: usize,
+    pub len: usize,
+    pub word: u32,
+    pub enc: Encoding,
+    pub kind: Kind,
+    pub handler: Handler,
+    pub operands: Operands,
+    pub scalar: bool,
+    /* a vector instruction that runs once per wave like a scalar one */
+    pub ignores_exec: bool,
+    pub float: bool,
+}
+
+impl Inst {
+    pub fn decode(code: &[u32], pc: usize) -> Self {
+        let stream = &code[pc..];
+        let word = stream[0];
+        let (enc, op) = (encoding(word), (word >> 16) & 0x7f);
+        let kind = match enc {
+            // cut off by the end of the kernel
+            _ if instr_len(stream) > stream.len() => Kind::Trap(Trap::IllegalInstruction),
+            _ if word == END_PRG => Kind::EndPgm,
+            _ if word == S_BARRIER => Kind::Barrier,
+            _ if SYNCS.contains(&word) || word >> 20 == 0xbf8 || word == 0x7E000000 => Kind::Skip,
+            // s_trap, s_sendmsg, s_sendmsghalt
+            Encoding::Sopp if op == 16 => Kind::Trap(Trap::Software(word & 0xffff)),
+            Encoding::Sopp if matches!(op, 54 | 55) => Kind::Trap(Trap::Message(word & 0xff)),
+            // s_rfe_b64
+            Encoding::Sop1 if (word >> 8) & 0xff == 74 => Kind::Rfe((word & 0xff) as usize),
+            _ => Kind::Exec,
+        };
+        Inst {
+            pc,
+            len: instr_len(stream).min(stream.len()),
+            word,
+            enc,
+            kind,
+            handler: handler(enc),
+            operands: Operands::decode(stream),
+            scalar: is_scalar(word),
+            ignores_exec: ignores_exec(word),
+            float: is_float(stream),
+        }
+    }
+
+    /* control may continue somewhere other than the next instruction */
+    fn ends_block(&self) -> bool {
+        match self.enc {
+            _ if self.kind != Kind::Exec => true,
+            // s_branch, s_cbranch_*
+            Encoding::Sopp => matches!((self.word >> 16) & 0x7f, 32..=42),
+            // s_setpc_b64, s_swappc_b64
+            Encoding::Sop1 => matches!((self.word >> 8) & 0xff, 72 | 73),
+            _ => false,
+        }
+    }
+
+    fn branch_target(&self) -> Option<usize> {
+        match self.enc == Encoding::Sopp && matches!((self.word >> 16) & 0x7f, 32..=42) {
+            true => Some((self.pc as isize + 1 + (self.word & 0xffff) as i16 as isize) as usize),
+            false => None,
+        }
+    }
+}
+
+/* a kernel decoded once into basic blocks of instructions with their handlers and operands */
+pub struct Program {
+    pub code: Vec<u32>,
+    pub trap_handler: Option<usize>,
+    /* static LDS from the kernel descriptor, None for code without one */
+    pub group_segment_size: Option<u32>,
+    insts: Vec<Inst>,
+    /* instruction index of every pc that starts an instruction */
+    index: Vec<Option<usize>>,
+    /* one past the last instruction of the block each instruction belongs to */
+    block_end: Vec<usize>,
+}
+
+impl Program {
+    pub fn new(code: Vec<u32>, trap_handler: Option<usize>) -> Self {
+        let mut index = vec![None; code.len()];
+        let mut insts = vec![];
+        let mut pc = 0;
+        while pc < code.len() {
+            let inst = Inst::decode(&code, pc);
+            index[pc] = Some(insts.len());
+            let next = pc + inst.len.max(1);
+            // data in front of the handler must not swallow its first instruction
+            pc = match trap_handler {
+                Some(h) if pc < h && h < next => h,
+                _ => next,
+            };
+            insts.push(inst);
+        }
+        let mut leaders = vec![false; insts.len()];
+        for target in insts
+            .iter()
+            .filter_map(|i| i.branch_target())
+            .chain(trap_handler)
+        {
+            if let Some(Some(i)) = index.get(target) {
+                leaders[*i] = true;
+            }
+        }
+        let mut block_end = vec![insts.len(); insts.len()];
+        for i in (0..insts.len().saturating_sub(1)).rev() {
+            block_end[i] = match insts[i].ends_block() || leaders[i + 1] {
+                true => i + 1,
+                false => block_end[i + 1],
+            };
+        }
+        Program {
+            code,
+            trap_handler,
+            group_segment_size: None,
+            insts,
+            index,
+            block_end,
+        }
+    }
+
+    /* shared decode of the same code object across launches */
+    pub fn cached(
+        code: Vec<u32>,
+        trap_handler: Option<usize>,
+        group_segment_size: Option<u32>,
+    ) -> Arc<Program> {
+        let mut hasher = DefaultHasher::new();
+        (&code, trap_handler, group_segment_size).hash(&mut hasher);
+        let key = hasher.finish();
+        let mut programs = PROGRAMS.lock().unwrap();
+        if let Some(p) = programs.get(key) {
+            let same = (p.trap_handler, p.group_segment_size) == (trap_handler, group_segment_size);
+            if p.code == code && same {
+                return p;
+            }
+        }
+        let mut program = Program::new(code, trap_handler);
+        program.group_segment_size = group_segment_size;
+        let program = Arc::new(program);
+        programs.insert(key, program.clone());
+        program
+    }
+
+    pub fn insts(&self) -> &[Inst] {
+        &self.insts
+    }
+
+    /* the instructions from pc to the end of its basic block, None if pc is not an instruction boundary */
+    pub fn block(&self, pc: usize) -> Option<&[Inst]> {
+        let i = (*self.index.get(pc)?)?;
+        Some(&self.insts[i..self.block_end[i]])
+    }
+}
+
+#[cfg(test)]
+mod test_program {
+    use super::*;
+
+    #[test]
+    fn test_literals_are_not_instructions() {
+        // s_mov_b32 s2, 1.0 ; v_mov_b32 v1, 0x2a ; s_endpgm
+        let p = Program::new(
+            vec![0xBE8200FF, 0x3F800000, 0x7E0202FF, 0x2A, END_PRG],
+            None,
+        );
+        let pcs = p.block(0).unwrap().iter().map(|i| i.pc).collect::<Vec<_>>();
+        assert_eq!(pcs, vec![0, 2, 4]);
+        assert!(p.block(1).is_none());
+        assert_eq!(p.block(4).unwrap()[0].kind, Kind::EndPgm);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let p = Program::new(
+            vec![
+                0xBE840080, // s_mov_b32 s4, 0
+                0x81048104, // loop: s_add_i32 s4, s4, 1
+                0xBF068304, // s_cmp_eq_u32 s4, 3
+                0xBFA2FFFD, // s_cbranch_scc1 loop
+                S_BARRIER, END_PRG,
+            ],
+            None,
+        );
+        assert_eq!(p.block(0).unwrap().len(), 1);
+        assert_eq!(p.block(1).unwrap().len(), 3);
+        assert_eq!(p.block(2).unwrap().len(), 2);
+        assert_eq!(p.block(4).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_trap_kinds() {
+        let p = Program::new(vec![0xBF900002, END_PRG, 0xBE804A6C], Some(2));
+        assert_eq!(p.block(0).unwrap()[0].kind, Kind::Trap(Trap::Software(2)));
+        assert_eq!(p.block(2).unwrap()[0].kind, Kind::Rfe(108));
+    }
+
+    #[test]
+    fn test_cached() {
+        let code = vec![0x7E0202FF, 0x2A, END_PRG];
+        let a = Program::cached(code.clone(), None, None);
+        let b = Program::cached(code.clone(), None, None);
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(
+            &a,
+            &Program::cached(code.clone(), Some(2), None)
+        ));
+        assert!(!Arc::ptr_eq(&a, &Program::cached(code, None, Some(16))));
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut cache = Cache::default();
+        let program = Arc::new(Program::new(vec![END_PRG], None));
+        for key in 0..CACHE_SIZE as u64 {
+            cache.insert(key, program.clone());
+        }
+        cache.get(0).unwrap();
+        cache.insert(CACHE_SIZE as u64, program);
+        assert_eq!(cache.programs.len(), CACHE_SIZE);
+        assert!(cache.get(0).is_some() && cache.get(1).is_none());
+    }
+}
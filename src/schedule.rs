@@ -0,0 +1,152 @@
+/* order in which waves of a workgroup and the workgroups of a launch run */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /* each wave runs up to its next s_barrier before the next wave starts */
+    Wave,
+    /* every wave runs one instruction in turn */
+    RoundRobin,
+    /* one instruction of a randomly picked wave at a time, workgroups shuffled */
+    Random(u64),
+    /* like Wave with the highest wave and workgroup ids first */
+    Reverse,
+}
+
+impl Schedule {
+    /* SCHEDULE=wave|round_robin|reverse|random[:seed] */
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.split_once(':') {
+            Some(("random", seed)) => seed.parse().ok().map(Schedule::Random),
+            _ => match s {
+                "" | "wave" => Some(Schedule::Wave),
+                "round_robin" => Some(Schedule::RoundRobin),
+                "reverse" => Some(Schedule::Reverse),
+                "random" => Some(Schedule::Random(time_seed())),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn time_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+/* splitmix64, enough to pick waves reproducibly from a seed */
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+    pub fn shuffle<T>(&mut self, xs: &mut [T]) {
+        for i in (1..xs.len()).rev() {
+            xs.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/* the order a schedule hands out count workgroups in, worked out by index rather than stored */
+#[derive(Debug, Clone, Copy)]
+pub struct Order {
+    pub count: u32,
+    schedule: Schedule,
+}
+
+impl Order {
+    pub fn new(count: u32, schedule: Schedule) -> Self {
+        Order { count, schedule }
+    }
+    /* the workgroup that runs n-th */
+    pub fn at(&self, n: u32) -> u32 {
+        match self.schedule {
+            Schedule::Reverse => self.count - 1 - n,
+            Schedule::Random(seed) => permute(seed, n, self.count),
+            _ => n,
+        }
+    }
+}
+
+/* a seeded bijection on 0..count, a feistel network over the next even power of two cycle walked into range */
+fn permute(seed: u64, n: u32, count: u32) -> u32 {
+    let half = (32 - count.saturating_sub(1).leading_zeros())
+        .div_ceil(2)
+        .max(1);
+    let mask = (1 << half) - 1;
+    let mut x = n as u64;
+    loop {
+        let (mut l, mut r) = (x >> half, x & mask);
+        for round in 0..4 {
+            let f = Rng::new(seed ^ (round << 32) ^ r).next() & mask;
+            (l, r) = (r, l ^ f);
+        }
+        x = l << half | r;
+        if x < count as u64 {
+            return x as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_schedule {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Schedule::parse("wave"), Some(Schedule::Wave));
+        assert_eq!(Schedule::parse("round_robin"), Some(Schedule::RoundRobin));
+        assert_eq!(Schedule::parse("reverse"), Some(Schedule::Reverse));
+        assert_eq!(Schedule::parse("random:42"), Some(Schedule::Random(42)));
+        assert!(matches!(
+            Schedule::parse("random"),
+            Some(Schedule::Random(_))
+        ));
+        assert_eq!(Schedule::parse("random:x"), None);
+        assert_eq!(Schedule::parse("fifo"), None);
+    }
+
+    #[test]
+    fn test_rng_reproducible() {
+        let mut xs = (0..16).collect::<Vec<u32>>();
+        let mut ys = xs.clone();
+        Rng::new(7).shuffle(&mut xs);
+        Rng::new(7).shuffle(&mut ys);
+        assert_eq!(xs, ys);
+        assert_ne!(xs, (0..16).collect::<Vec<u32>>());
+        let mut sorted = xs.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..16).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_order() {
+        let order = |count, schedule| {
+            let order = Order::new(count, schedule);
+            (0..count).map(|n| order.at(n)).collect::<Vec<_>>()
+        };
+        assert_eq!(order(4, Schedule::Wave), [0, 1, 2, 3]);
+        assert_eq!(order(4, Schedule::Reverse), [3, 2, 1, 0]);
+        for count in [1, 2, 5, 16, 1000] {
+            let mut xs = order(count, Schedule::Random(7));
+            assert_eq!(xs, order(count, Schedule::Random(7)));
+            xs.sort();
+            assert_eq!(xs, (0..count).collect::<Vec<_>>());
+        }
+        assert_ne!(order(16, Schedule::Random(7)), order(16, Schedule::Wave));
+        // the last workgroup of the largest grid is still in range
+        let order = Order::new(u32::MAX, Schedule::Random(7));
+        assert!(order.at(u32::MAX - 1) < u32::MAX);
+    }
+}
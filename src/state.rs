@@ -1,5 +1,6 @@
 use crate::utils::{GLOBAL_COUNTER, PROFILE};
 use std::ops::{Index, IndexMut};
+use std::sync::atomic::Ordering::Relaxed;
 
 pub trait Register {
     fn read64(&self, idx: usize) -> u64;
@@ -34,7 +35,7 @@ impl Index<usize> for VGPR {
 impl IndexMut<usize> for VGPR {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         if *PROFILE {
-            GLOBAL_COUNTER.lock().unwrap().vgpr_used += 1;
+            GLOBAL_COUNTER.vgpr_used.fetch_add(1, Relaxed);
         }
         &mut self.values[self.default_lane.unwrap()][index]
     }
@@ -1,17 +1,25 @@
+use crate::decode::{encoding, Encoding, Operands};
 use crate::dtype::{extract_mantissa, ldexp, IEEEClass, VOPModifier};
-use crate::memory::VecDataStore;
+use crate::memory::{Memory, VecDataStore};
 use crate::state::{Register, Value, WaveValue, VGPR};
 use crate::todo_instr;
+use crate::trap::{Trap, HW_REG_STATUS};
+use crate::undo::Delta;
+use crate::uninit::Access;
 use crate::utils::{
     f16_hi, f16_lo, nth, sign_ext, Colorize, GLOBAL_COUNTER, GLOBAL_DEBUG, PROFILE,
 };
+use crate::watch::{Space, Target, Watcher};
 use half::f16;
 use ndarray::Array;
 use num_traits::Float;
+use std::sync::atomic::Ordering::Relaxed;
 
 pub const SGPR_COUNT: usize = 105;
 pub const VGPR_COUNT: usize = 256;
 const NULL_SRC: u32 = 124;
+pub const SHARED_BASE: u64 = 0x1_0000_0000_0000;
+pub const PRIVATE_BASE: u64 = 0x2_0000_0000_0000;
 
 pub struct Thread<'a> {
     pub scalar_reg: &'a mut Vec<u32>,
@@ -22,1629 +30,1851 @@ pub struct Thread<'a> {
     pub exec: &'a mut WaveValue,
 
     pub lds: &'a mut VecDataStore,
-    pub sds: &'a mut VecDataStore,
+    pub sds: &'a mut [VecDataStore],
 
+    /* a taken branch moves the next pc by this many dwords */
     pub pc_offset: usize,
-    pub stream: Vec<u32>,
-    pub simm: Option<u32>,
+    pub operands: Operands,
     pub sgpr_co: &'a mut Option<(usize, WaveValue)>,
     pub warp_size: usize,
     pub scalar: bool,
+
+    pub hw_reg: &'a mut [u32; 64],
+    pub trap: Option<Trap>,
+    /* set while memory watchpoints are armed */
+    pub watch: Option<&'a mut Watcher>,
+    /* set while the wave is recorded for reverse debugging or traced */
+    pub stores: Option<&'a mut Vec<Delta>>,
+    /* set while CHECK_UNINIT or CHECK_LDS look at LDS and scratch accesses */
+    pub accesses: Option<&'a mut Vec<Access>>,
+    /* global and SMEM accesses go through it */
+    pub memory: &'a dyn Memory,
+}
+
+/* executes the instruction in Thread::operands for the current lane */
+pub type Handler = fn(&mut Thread<'_>) -> Result<(), i32>;
+
+pub fn handler(encoding: Encoding) -> Handler {
+    match encoding {
+        Encoding::Smem => |t| t.smem(),
+        Encoding::Sop1 => |t| t.sop1(),
+        Encoding::Sopc => |t| t.sopc(),
+        Encoding::Sopp => |t| t.sopp(),
+        Encoding::Sopk => |t| t.sopk(),
+        Encoding::Sop2 => |t| t.sop2(),
+        Encoding::Vop3p => |t| t.vopp(),
+        Encoding::Vop1 => |t| t.vop1(),
+        Encoding::Vopd => |t| t.vopd(),
+        Encoding::Vopc => |t| t.vopc(),
+        Encoding::Vop2 => |t| t.vop2(),
+        Encoding::Vop3 => |t| t.vop3(),
+        Encoding::Ds => |t| t.ds(),
+        Encoding::Flat => |t| t.flat(),
+        Encoding::Unknown => |t| t.illegal(),
+        _ => |t| t.unimplemented(),
+    }
 }
 
 impl<'a> Thread<'a> {
+    /* point the thread at a lane before interpreting the instruction again */
+    pub fn set_lane(&mut self, lane_id: usize) {
+        self.vec_reg.default_lane = Some(lane_id);
+        self.vcc.default_lane = Some(lane_id);
+        self.exec.default_lane = Some(lane_id);
+        self.pc_offset = 0;
+    }
+
     pub fn interpret(&mut self) -> Result<(), i32> {
-        let instruction = self.stream[self.pc_offset];
-        // smem
-        if instruction >> 26 == 0b111101 {
-            let instr = self.u64_instr();
-            /* addr: s[sbase:sbase+1] */
-            let sbase = (instr & 0x3f) * 2;
-            let sdata = ((instr >> 6) & 0x7f) as usize;
-            let op = (instr >> 18) & 0xff;
-            let offset = sign_ext((instr >> 32) & 0x1fffff, 21);
-            let soffset = match self.val(((instr >> 57) & 0x7f) as usize) {
-                NULL_SRC => 0,
-                val => val,
-            };
+        handler(encoding(self.operands.instr as u32))(self)
+    }
+
+    fn smem(&mut self) -> Result<(), i32> {
+        let Operands {
+            instr,
+            op,
+            src,
+            dst,
+            ..
+        } = self.operands;
+        let instruction = instr as u32;
+        /* addr: s[sbase:sbase+1] */
+        let (sbase, sdata) = (src[0], dst[0]);
+        let offset = sign_ext((instr >> 32) & 0x1fffff, 21);
+        let soffset = match self.val(src[1]) {
+            NULL_SRC => 0,
+            val => val,
+        };
 
-            if *GLOBAL_DEBUG {
-                println!(
-                    "{} sbase={sbase} sdata={sdata} op={op} offset={offset} soffset={soffset}",
-                    "SMEM".color("blue"),
-                );
-            }
-            let base_addr = self.scalar_reg.read64(sbase as usize);
-            let addr = (base_addr as i64 + offset + soffset as i64) as u64;
+        if *GLOBAL_DEBUG {
+            println!(
+                "{} sbase={sbase} sdata={sdata} op={op} offset={offset} soffset={soffset}",
+                "SMEM".color("blue"),
+            );
+        }
+        // s_load_b32 to s_load_b512, before the size of a bad op is worked out
+        if op > 4 {
+            return todo_instr!(instruction);
+        }
+        let base_addr = self.scalar_reg.read64(sbase);
+        let addr = (base_addr as i64 + offset + soffset as i64) as u64;
+        self.scalar = true;
+        let Some(host) = self.mem_host(addr, 4 << op, false) else {
+            return Ok(());
+        };
+        (0..1_usize << op).for_each(|i| unsafe {
+            self.scalar_reg[sdata + i] = *(host.add(4 * i) as *const u32);
+        });
+        Ok(())
+    }
 
-            match op {
-                0..=4 => (0..2_usize.pow(op as u32)).for_each(|i| unsafe {
-                    self.scalar_reg[sdata + i] = *((addr + (4 * i as u64)) as *const u32);
-                }),
-                _ => todo_instr!(instruction)?,
-            };
-            self.scalar = true;
+    fn sop1(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands { op, src, dst, .. } = self.operands;
+        let (src, sdst) = (src[0], dst[0] as u32);
+
+        if *GLOBAL_DEBUG {
+            println!("{} src={src} sdst={sdst} op={op}", "SOP1".color("blue"));
         }
-        // sop1
-        else if instruction >> 23 == 0b10_1111101 {
-            let src = (instruction & 0xFF) as usize;
-            let op = (instruction >> 8) & 0xFF;
-            let sdst = (instruction >> 16) & 0x7F;
-
-            if *GLOBAL_DEBUG {
-                println!("{} src={src} sdst={sdst} op={op}", "SOP1".color("blue"));
-            }
 
-            match op {
-                1 => {
-                    let s0 = self.val(src);
-                    let ret = match op {
-                        1 => s0,
-                        _ => todo_instr!(instruction)?,
-                    };
-                    self.scalar_reg.write64(sdst as usize, ret);
-                }
-                _ => {
-                    let s0 = self.val(src);
-                    let ret = match op {
-                        0 => s0,
-                        10 => self.clz_i32_u32(s0),
-                        12 => self.cls_i32(s0),
-                        4 => s0.reverse_bits(),
-                        14 => s0 as i8 as i32 as u32,
-                        15 => s0 as i16 as i32 as u32,
-                        16 | 18 => {
-                            let sdst: u32 = self.val(sdst as usize);
-                            if op == 16 {
-                                sdst & !(1 << (s0 & 0x1f))
-                            } else {
-                                sdst | (1 << (s0 & 0x1f))
-                            }
-                        }
-                        30 => {
-                            let ret = !s0;
-                            *self.scc = (ret != 0) as u32;
-                            ret
-                        }
-                        32 | 34 | 48 => {
-                            let saveexec = self.exec.value;
-                            self.exec.value = match op {
-                                32 => s0 & saveexec,
-                                34 => s0 | saveexec,
-                                48 => s0 & !saveexec,
-                                _ => todo_instr!(instruction)?,
-                            };
-                            *self.scc = (self.exec.value != 0) as u32;
-                            saveexec
+        match op {
+            1 => {
+                let s0 = self.val(src);
+                let ret = match op {
+                    1 => s0,
+                    _ => todo_instr!(instruction)?,
+                };
+                self.write_to_sdst64(sdst, ret);
+            }
+            _ => {
+                let s0 = self.val(src);
+                let ret = match op {
+                    0 => s0,
+                    10 => self.clz_i32_u32(s0),
+                    12 => self.cls_i32(s0),
+                    4 => s0.reverse_bits(),
+                    14 => s0 as i8 as i32 as u32,
+                    15 => s0 as i16 as i32 as u32,
+                    16 | 18 => {
+                        let sdst: u32 = self.val(sdst as usize);
+                        if op == 16 {
+                            sdst & !(1 << (s0 & 0x1f))
+                        } else {
+                            sdst | (1 << (s0 & 0x1f))
                         }
-                        _ => todo_instr!(instruction)?,
-                    };
+                    }
+                    30 => {
+                        let ret = !s0;
+                        *self.scc = (ret != 0) as u32;
+                        ret
+                    }
+                    32 | 34 | 48 => {
+                        let saveexec = self.exec.value;
+                        self.exec.value = match op {
+                            32 => s0 & saveexec,
+                            34 => s0 | saveexec,
+                            48 => s0 & !saveexec,
+                            _ => todo_instr!(instruction)?,
+                        };
+                        *self.scc = (self.exec.value != 0) as u32;
+                        saveexec
+                    }
+                    _ => todo_instr!(instruction)?,
+                };
 
-                    self.write_to_sdst(sdst, ret);
-                }
-            };
-            self.scalar = true;
-        }
-        // sopc
-        else if (instruction >> 23) & 0x3ff == 0b101111110 {
-            let s0 = (instruction & 0xff) as usize;
-            let s1 = ((instruction >> 8) & 0xff) as usize;
-            let op = (instruction >> 16) & 0x7f;
-
-            if *GLOBAL_DEBUG {
-                println!("{} s0={s0} ssrc1={s1} op={op}", "SOPC".color("blue"));
+                self.write_to_sdst(sdst, ret);
             }
+        };
+        self.scalar = true;
+        Ok(())
+    }
 
-            fn scmp<T>(s0: T, s1: T, offset: u32, op: u32) -> bool
-            where
-                T: PartialOrd + PartialEq,
-            {
-                match op - offset {
-                    0 => s0 == s1,
-                    1 => s0 != s1,
-                    2 => s0 > s1,
-                    3 => s0 >= s1,
-                    4 => s0 < s1,
-                    _ => s0 <= s1,
-                }
+    fn sopc(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands { op, src, .. } = self.operands;
+        let (s0, s1) = (src[0], src[1]);
+
+        if *GLOBAL_DEBUG {
+            println!("{} s0={s0} ssrc1={s1} op={op}", "SOPC".color("blue"));
+        }
+
+        fn scmp<T>(s0: T, s1: T, offset: u32, op: u32) -> bool
+        where
+            T: PartialOrd + PartialEq,
+        {
+            match op - offset {
+                0 => s0 == s1,
+                1 => s0 != s1,
+                2 => s0 > s1,
+                3 => s0 >= s1,
+                4 => s0 < s1,
+                _ => s0 <= s1,
             }
-            *self.scc = match op {
-                0..=5 => {
-                    let (s0, s1): (u32, u32) = (self.val(s0), self.val(s1));
-                    scmp(s0 as i32, s1 as i32, 0, op)
-                }
-                6..=11 => {
-                    let (s0, s1): (u32, u32) = (self.val(s0), self.val(s1));
-                    scmp(s0, s1, 6, op)
-                }
-                12 => {
-                    let (s0, s1): (u32, u32) = (self.val(s0), self.val(s1));
-                    s0 & (1 << (s1 & 0x1F)) == 0
-                }
-                16 | 17 => {
-                    let (s0, s1): (u64, u64) = (self.val(s0), self.val(s1));
-                    scmp(s0, s1, 16, op)
-                }
-                _ => todo_instr!(instruction)?,
-            } as u32;
-            self.scalar = true;
         }
-        // sopp
-        else if instruction >> 23 == 0b10_1111111 {
-            let simm16 = (instruction & 0xffff) as i16;
-            let op = (instruction >> 16) & 0x7f;
-            if *GLOBAL_DEBUG {
-                println!("{} simm16={simm16} op={op}", "SOPP".color("blue"),);
+        *self.scc = match op {
+            0..=5 => {
+                let (s0, s1): (u32, u32) = (self.val(s0), self.val(s1));
+                scmp(s0 as i32, s1 as i32, 0, op)
+            }
+            6..=11 => {
+                let (s0, s1): (u32, u32) = (self.val(s0), self.val(s1));
+                scmp(s0, s1, 6, op)
+            }
+            12 => {
+                let (s0, s1): (u32, u32) = (self.val(s0), self.val(s1));
+                s0 & (1 << (s1 & 0x1F)) == 0
+            }
+            16 | 17 => {
+                let (s0, s1): (u64, u64) = (self.val(s0), self.val(s1));
+                scmp(s0, s1, 16, op)
             }
+            _ => todo_instr!(instruction)?,
+        } as u32;
+        self.scalar = true;
+        Ok(())
+    }
 
-            match op {
-                32..=42 => {
-                    let should_jump = match op {
-                        32 => true,
-                        33 => *self.scc == 0,
-                        34 => *self.scc == 1,
-                        35 => self.vcc.value == 0,
-                        36 => self.vcc.value != 0,
-                        37 => self.exec.value == 0,
-                        38 => self.exec.value != 0,
-                        _ => todo_instr!(instruction)?,
-                    };
-                    if should_jump {
-                        self.pc_offset = (self.pc_offset as i64 + simm16 as i64) as usize;
-                    }
-                }
-                _ => todo_instr!(instruction)?,
-            };
-            self.scalar = true;
+    fn sopp(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let simm16 = (instruction & 0xffff) as i16;
+        let op = self.operands.op;
+        if *GLOBAL_DEBUG {
+            println!("{} simm16={simm16} op={op}", "SOPP".color("blue"),);
         }
-        // sopk
-        else if instruction >> 28 == 0b1011 {
-            let simm = instruction & 0xffff;
-            let sdst = ((instruction >> 16) & 0x7f) as usize;
-            let op = (instruction >> 23) & 0x1f;
-            let s0: u32 = self.val(sdst);
-
-            if *GLOBAL_DEBUG {
-                println!(
-                    "{} simm={simm} sdst={sdst} s0={s0} op={op}",
-                    "SOPK".color("blue"),
-                );
-            }
 
-            match op {
-                0 => self.write_to_sdst(sdst as u32, simm as i16 as i32 as u32),
-                3..=8 => {
-                    let s1 = simm as i16 as i64;
-                    let s0 = s0 as i32 as i64;
-                    *self.scc = match op {
-                        3 => s0 == s1,
-                        4 => s0 != s1,
-                        5 => s0 > s1,
-                        7 => s0 < s1,
-                        _ => todo_instr!(instruction)?,
-                    } as u32
-                }
-                9..=14 => {
-                    let s1 = simm as u16 as u32;
-                    *self.scc = match op {
-                        9 => s0 == s1,
-                        10 => s0 != s1,
-                        13 => s0 < s1,
-                        _ => todo_instr!(instruction)?,
-                    } as u32
-                }
-                15 => {
-                    let temp = s0 as i32;
-                    let simm16 = simm as i16;
-                    let dest = (temp as i64 + simm16 as i64) as i32;
-                    self.write_to_sdst(sdst as u32, dest as u32);
-                    let temp_sign = ((temp >> 31) & 1) as u32;
-                    let simm_sign = ((simm16 >> 15) & 1) as u32;
-                    let dest_sign = ((dest >> 31) & 1) as u32;
-                    *self.scc = ((temp_sign == simm_sign) && (temp_sign != dest_sign)) as u32;
-                }
-                16 => {
-                    let simm16 = simm as i16;
-                    let ret = (s0 as i32 * simm16 as i32) as u32;
-                    self.write_to_sdst(sdst as u32, ret);
+        match op {
+            32..=42 => {
+                let should_jump = match op {
+                    32 => true,
+                    33 => *self.scc == 0,
+                    34 => *self.scc == 1,
+                    35 => self.vcc.value == 0,
+                    36 => self.vcc.value != 0,
+                    37 => self.exec.value == 0,
+                    38 => self.exec.value != 0,
+                    _ => todo_instr!(instruction)?,
+                };
+                if should_jump {
+                    self.pc_offset = (self.pc_offset as i64 + simm16 as i64) as usize;
                 }
-                _ => todo_instr!(instruction)?,
-            };
-            self.scalar = true;
-        }
-        // sop2
-        else if instruction >> 30 == 0b10 {
-            let s0 = (instruction & 0xFF) as usize;
-            let s1 = ((instruction >> 8) & 0xFF) as usize;
-            let sdst = (instruction >> 16) & 0x7F;
-            let op = (instruction >> 23) & 0xFF;
-
-            if *GLOBAL_DEBUG {
-                println!(
-                    "{} s0={s0} s1={s1} sdst={sdst} op={op}",
-                    "SOP2".color("blue"),
-                );
             }
+            _ => todo_instr!(instruction)?,
+        };
+        self.scalar = true;
+        Ok(())
+    }
 
-            match op {
-                23 | 25 | 27 => {
-                    let (s0, s1): (u64, u64) = (self.val(s0), self.val(s1));
-                    let ret = match op {
-                        23 => s0 & s1,
-                        25 => s0 | s1,
-                        27 => s0 ^ s1,
-                        _ => todo_instr!(instruction)?,
-                    };
-                    self.scalar_reg.write64(sdst as usize, ret);
-                    *self.scc = (ret != 0) as u32;
-                }
-                9 | 13 | 11 | 40 | 41 => {
-                    let (s0, s1): (u64, u32) = (self.val(s0), self.val(s1));
-                    let ret = match op {
-                        9 => {
-                            let ret = s0 << (s1 & 0x3f);
-                            (ret, Some(ret != 0))
-                        }
-                        11 => {
-                            let ret = s0 >> (s1 & 0x3f);
-                            (ret as u64, Some(ret != 0))
-                        }
-                        13 => {
-                            let ret = (s0 as i64) >> (s1 & 0x3f);
-                            (ret as u64, Some(ret != 0))
-                        }
-                        40 => {
-                            let ret = (s0 >> (s1 & 0x3f)) & ((1 << ((s1 >> 16) & 0x7f)) - 1);
-                            (ret as u64, Some(ret != 0))
-                        }
-                        41 => {
-                            let s0 = s0 as i64;
-                            let mut ret = (s0 >> (s1 & 0x3f)) & ((1 << ((s1 >> 16) & 0x7f)) - 1);
-                            let shift = 64 - ((s1 >> 16) & 0x7f);
-                            ret = (ret << shift) >> shift;
-                            (ret as u64, Some(ret != 0))
-                        }
-                        _ => todo_instr!(instruction)?,
-                    };
-                    self.scalar_reg.write64(sdst as usize, ret.0);
-                    if let Some(val) = ret.1 {
-                        *self.scc = val as u32
+    fn sopk(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let simm = instruction & 0xffff;
+        let Operands { op, dst, .. } = self.operands;
+        let sdst = dst[0];
+        let s0: u32 = self.val(sdst);
+
+        if *GLOBAL_DEBUG {
+            println!(
+                "{} simm={simm} sdst={sdst} s0={s0} op={op}",
+                "SOPK".color("blue"),
+            );
+        }
+
+        match op {
+            0 => self.write_to_sdst(sdst as u32, simm as i16 as i32 as u32),
+            3..=8 => {
+                let s1 = simm as i16 as i64;
+                let s0 = s0 as i32 as i64;
+                *self.scc = match op {
+                    3 => s0 == s1,
+                    4 => s0 != s1,
+                    5 => s0 > s1,
+                    7 => s0 < s1,
+                    _ => todo_instr!(instruction)?,
+                } as u32
+            }
+            9..=14 => {
+                let s1 = simm as u16 as u32;
+                *self.scc = match op {
+                    9 => s0 == s1,
+                    10 => s0 != s1,
+                    13 => s0 < s1,
+                    _ => todo_instr!(instruction)?,
+                } as u32
+            }
+            15 => {
+                let temp = s0 as i32;
+                let simm16 = simm as i16;
+                let dest = (temp as i64 + simm16 as i64) as i32;
+                self.write_to_sdst(sdst as u32, dest as u32);
+                let temp_sign = ((temp >> 31) & 1) as u32;
+                let simm_sign = ((simm16 >> 15) & 1) as u32;
+                let dest_sign = ((dest >> 31) & 1) as u32;
+                *self.scc = ((temp_sign == simm_sign) && (temp_sign != dest_sign)) as u32;
+            }
+            16 => {
+                let simm16 = simm as i16;
+                let ret = (s0 as i32 * simm16 as i32) as u32;
+                self.write_to_sdst(sdst as u32, ret);
+            }
+            17..=19 => {
+                /* hwreg(id, offset, size) */
+                let id = (simm & 0x3f) as usize;
+                let offset = (simm >> 6) & 0x1f;
+                let mask = (u64::MAX >> (63 - ((simm >> 11) & 0x1f))) as u32;
+                match op {
+                    17 => {
+                        let val = match id {
+                            HW_REG_STATUS => {
+                                *self.scc
+                                    | ((self.exec.value == 0) as u32) << 9
+                                    | ((self.vcc.value == 0) as u32) << 10
+                            }
+                            _ => self.hw_reg[id],
+                        };
+                        self.write_to_sdst(sdst as u32, (val >> offset) & mask);
+                    }
+                    _ => {
+                        let val = match op {
+                            18 => s0,
+                            _ => self.simm(),
+                        };
+                        self.hw_reg[id] =
+                            (self.hw_reg[id] & !(mask << offset)) | ((val & mask) << offset);
                     }
                 }
-                _ => {
-                    let (s0, s1): (u32, u32) = (self.val(s0), self.val(s1));
-                    let ret = match op {
-                        0 | 4 => {
-                            let (s0, s1) = (s0 as u64, s1 as u64);
-                            let ret = match op {
-                                0 => s0 + s1,
-                                4 => s0 + s1 + *self.scc as u64,
-                                _ => todo_instr!(instruction)?,
-                            };
-                            (ret as u32, Some(ret >= 0x100000000))
-                        }
-                        1 => (s0 - s1, Some(s1 > s0)),
-                        5 => (
-                            s0 - s1 - *self.scc,
-                            Some((s1 as u64 + *self.scc as u64) > s0 as u64),
-                        ),
-                        2 | 3 => {
-                            let s0 = s0 as i32 as i64;
-                            let s1 = s1 as i32 as i64;
-                            let ret = match op {
-                                2 => s0 + s1,
-                                3 => s0 - s1,
-                                _ => todo_instr!(instruction)?,
-                            };
-                            let overflow = (nth(s0 as u32, 31) == nth(s1 as u32, 31))
-                                && (nth(s0 as u32, 31) != nth(ret as u32, 31));
+            }
+            _ => todo_instr!(instruction)?,
+        };
+        self.scalar = true;
+        Ok(())
+    }
 
-                            (ret as i32 as u32, Some(overflow))
-                        }
-                        (8..=17) => {
-                            let s1 = s1 & 0x1f;
-                            let ret = match op {
-                                8 => s0 << s1,
-                                10 => s0 >> s1,
-                                12 => ((s0 as i32) >> (s1 as i32)) as u32,
-                                _ => todo_instr!(instruction)?,
-                            };
-                            (ret, Some(ret != 0))
-                        }
-                        (18..=21) => {
-                            let scc = match op {
-                                18 => (s0 as i32) < (s1 as i32),
-                                19 => s0 < s1,
-                                20 => (s0 as i32) > (s1 as i32),
-                                _ => todo_instr!(instruction)?,
-                            };
-                            let ret = match scc {
-                                true => s0,
-                                false => s1,
-                            };
-                            (ret, Some(scc))
-                        }
-                        (22..=26) | 34 | 36 => {
-                            let ret = match op {
-                                22 => s0 & s1,
-                                24 => s0 | s1,
-                                26 => s0 ^ s1,
-                                34 => s0 & !s1,
-                                36 => s0 | !s1,
-                                _ => todo_instr!(instruction)?,
-                            };
-                            (ret, Some(ret != 0))
-                        }
-                        38 => {
-                            let ret = (s0 >> (s1 & 0x1f)) & ((1 << ((s1 >> 16) & 0x7f)) - 1);
-                            (ret, Some(ret != 0))
-                        }
-                        39 => {
-                            let s0 = s0 as i32;
-                            let mut ret = (s0 >> (s1 & 0x1f)) & ((1 << ((s1 >> 16) & 0x1f)) - 1);
-                            let shift = 32 - ((s1 >> 16) & 0x7f);
-                            ret = (ret << shift) >> shift;
-                            (ret as u32, Some(ret != 0))
-                        }
-                        44 => (((s0 as i32) * (s1 as i32)) as u32, None),
-                        45 => (((s0 as u64) * (s1 as u64) >> 32) as u32, None),
-                        46 => (
-                            (((s0 as i32 as i64 * s1 as i32 as i64) as u64) >> 32u64) as i32 as u32,
-                            None,
-                        ),
-                        48 => match *self.scc != 0 {
-                            true => (s0, None),
-                            false => (s1, None),
-                        },
-                        _ => todo_instr!(instruction)?,
-                    };
+    fn sop2(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands { op, src, dst, .. } = self.operands;
+        let (s0, s1, sdst) = (src[0], src[1], dst[0] as u32);
+
+        if *GLOBAL_DEBUG {
+            println!(
+                "{} s0={s0} s1={s1} sdst={sdst} op={op}",
+                "SOP2".color("blue"),
+            );
+        }
 
-                    self.write_to_sdst(sdst, ret.0);
-                    if let Some(val) = ret.1 {
-                        *self.scc = val as u32
+        match op {
+            23 | 25 | 27 => {
+                let (s0, s1): (u64, u64) = (self.val(s0), self.val(s1));
+                let ret = match op {
+                    23 => s0 & s1,
+                    25 => s0 | s1,
+                    27 => s0 ^ s1,
+                    _ => todo_instr!(instruction)?,
+                };
+                self.write_to_sdst64(sdst, ret);
+                *self.scc = (ret != 0) as u32;
+            }
+            9 | 13 | 11 | 40 | 41 => {
+                let (s0, s1): (u64, u32) = (self.val(s0), self.val(s1));
+                let ret = match op {
+                    9 => {
+                        let ret = s0 << (s1 & 0x3f);
+                        (ret, Some(ret != 0))
+                    }
+                    11 => {
+                        let ret = s0 >> (s1 & 0x3f);
+                        (ret as u64, Some(ret != 0))
+                    }
+                    13 => {
+                        let ret = (s0 as i64) >> (s1 & 0x3f);
+                        (ret as u64, Some(ret != 0))
                     }
+                    40 => {
+                        let ret = (s0 >> (s1 & 0x3f)) & ((1 << ((s1 >> 16) & 0x7f)) - 1);
+                        (ret as u64, Some(ret != 0))
+                    }
+                    41 => {
+                        let s0 = s0 as i64;
+                        let mut ret = (s0 >> (s1 & 0x3f)) & ((1 << ((s1 >> 16) & 0x7f)) - 1);
+                        let shift = 64 - ((s1 >> 16) & 0x7f);
+                        ret = (ret << shift) >> shift;
+                        (ret as u64, Some(ret != 0))
+                    }
+                    _ => todo_instr!(instruction)?,
+                };
+                self.write_to_sdst64(sdst, ret.0);
+                if let Some(val) = ret.1 {
+                    *self.scc = val as u32
                 }
-            };
-            self.scalar = true;
-        }
-        // vopp
-        else if instruction >> 24 == 0b11001100 {
-            let instr = self.u64_instr();
-            let vdst = (instr & 0xff) as usize;
-            let clmp = (instr >> 15) & 0x1;
-            assert_eq!([clmp], [0]);
-            let op = (instr >> 16) & 0x7f;
-
-            let mut src = |x: usize| -> (u16, u16, u32) {
-                let val: u32 = self.val(x);
-                match x {
-                    255 => {
-                        let val_lo: u16 = self.val(x);
-                        (val_lo, val_lo, val)
+            }
+            _ => {
+                let (s0, s1): (u32, u32) = (self.val(s0), self.val(s1));
+                let ret = match op {
+                    0 | 4 => {
+                        let (s0, s1) = (s0 as u64, s1 as u64);
+                        let ret = match op {
+                            0 => s0 + s1,
+                            4 => s0 + s1 + *self.scc as u64,
+                            _ => todo_instr!(instruction)?,
+                        };
+                        (ret as u32, Some(ret >= 0x100000000))
                     }
-                    (240..=247) => {
-                        let val_lo: u16 = self.val(x);
-                        (val_lo, f16::from_bits(0).to_bits(), val)
+                    1 => (s0 - s1, Some(s1 > s0)),
+                    5 => (
+                        s0 - s1 - *self.scc,
+                        Some((s1 as u64 + *self.scc as u64) > s0 as u64),
+                    ),
+                    2 | 3 => {
+                        let s0 = s0 as i32 as i64;
+                        let s1 = s1 as i32 as i64;
+                        let ret = match op {
+                            2 => s0 + s1,
+                            3 => s0 - s1,
+                            _ => todo_instr!(instruction)?,
+                        };
+                        let overflow = (nth(s0 as u32, 31) == nth(s1 as u32, 31))
+                            && (nth(s0 as u32, 31) != nth(ret as u32, 31));
+
+                        (ret as i32 as u32, Some(overflow))
                     }
-                    _ => ((val & 0xffff) as u16, ((val >> 16) & 0xffff) as u16, val),
+                    (8..=17) => {
+                        let s1 = s1 & 0x1f;
+                        let ret = match op {
+                            8 => s0 << s1,
+                            10 => s0 >> s1,
+                            12 => ((s0 as i32) >> (s1 as i32)) as u32,
+                            _ => todo_instr!(instruction)?,
+                        };
+                        (ret, Some(ret != 0))
+                    }
+                    (18..=21) => {
+                        let scc = match op {
+                            18 => (s0 as i32) < (s1 as i32),
+                            19 => s0 < s1,
+                            20 => (s0 as i32) > (s1 as i32),
+                            _ => todo_instr!(instruction)?,
+                        };
+                        let ret = match scc {
+                            true => s0,
+                            false => s1,
+                        };
+                        (ret, Some(scc))
+                    }
+                    (22..=26) | 34 | 36 => {
+                        let ret = match op {
+                            22 => s0 & s1,
+                            24 => s0 | s1,
+                            26 => s0 ^ s1,
+                            34 => s0 & !s1,
+                            36 => s0 | !s1,
+                            _ => todo_instr!(instruction)?,
+                        };
+                        (ret, Some(ret != 0))
+                    }
+                    38 => {
+                        let ret = (s0 >> (s1 & 0x1f)) & ((1 << ((s1 >> 16) & 0x7f)) - 1);
+                        (ret, Some(ret != 0))
+                    }
+                    39 => {
+                        let s0 = s0 as i32;
+                        let mut ret = (s0 >> (s1 & 0x1f)) & ((1 << ((s1 >> 16) & 0x1f)) - 1);
+                        let shift = 32 - ((s1 >> 16) & 0x7f);
+                        ret = (ret << shift) >> shift;
+                        (ret as u32, Some(ret != 0))
+                    }
+                    44 => (((s0 as i32) * (s1 as i32)) as u32, None),
+                    45 => (((s0 as u64) * (s1 as u64) >> 32) as u32, None),
+                    46 => (
+                        (((s0 as i32 as i64 * s1 as i32 as i64) as u64) >> 32u64) as i32 as u32,
+                        None,
+                    ),
+                    48 => match *self.scc != 0 {
+                        true => (s0, None),
+                        false => (s1, None),
+                    },
+                    _ => todo_instr!(instruction)?,
+                };
+
+                self.write_to_sdst(sdst, ret.0);
+                if let Some(val) = ret.1 {
+                    *self.scc = val as u32
                 }
-            };
+            }
+        };
+        self.scalar = true;
+        Ok(())
+    }
 
-            let s = [32, 41, 50]
-                .iter()
-                .map(|x| ((instr >> x) & 0x1ff) as usize)
-                .collect::<Vec<_>>();
-            let src_parts = s.iter().map(|x| src(*x)).collect::<Vec<_>>();
-
-            let b = |i: usize| (instr >> i) & 0x1 != 0;
-            let neg_hi = ((instr >> 8) & 0x7) as usize;
-            let neg = ((instr >> 61) & 0x7) as usize;
-            let opsel = [b(11), b(12), b(13)];
-            let opsel_hi = [b(59), b(60), b(14)];
-            if *GLOBAL_DEBUG {
-                println!("{} op={op} vdst={vdst} src2={:?} opsel={:?} opsel_hi={:?} neg={:03b} neg_hi={:03b}", "VOPP".color("blue"), src_parts, opsel, opsel_hi, neg, neg_hi);
+    fn vopp(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands {
+            instr,
+            op,
+            src,
+            dst,
+            ..
+        } = self.operands;
+        let vdst = dst[0];
+        let clmp = (instr >> 15) & 0x1;
+        if clmp != 0 {
+            return todo_instr!(instruction);
+        }
+
+        let mut read = |x: usize| -> (u16, u16, u32) {
+            let val: u32 = self.val(x);
+            match x {
+                255 => {
+                    let val_lo: u16 = self.val(x);
+                    (val_lo, val_lo, val)
+                }
+                (240..=248) => {
+                    let val_lo: u16 = self.val(x);
+                    (val_lo, f16::from_bits(0).to_bits(), val)
+                }
+                _ => ((val & 0xffff) as u16, ((val >> 16) & 0xffff) as u16, val),
             }
+        };
 
-            match op {
-                0..=18 => {
-                    let fxn = |x, y, z| -> Result<u16, i32> {
-                        match op {
-                            1 => Ok(x * y),
-                            4 => Ok(y << (x & 0xf)),
-                            10 => Ok(x + y),
-                            9 => Ok(x * y + z),
-                            11 => Ok(x - y),
-                            _ => {
-                                let (x, y, z) =
-                                    (f16::from_bits(x), f16::from_bits(y), f16::from_bits(z));
-                                let ret = match op {
-                                    14 => Ok::<f16, i32>(f16::mul_add(x, y, z)),
-                                    15 => Ok(x + y),
-                                    16 => Ok(x * y),
-                                    17 => Ok(f16::min(x, y)),
-                                    18 => Ok(f16::max(x, y)),
-                                    _ => todo_instr!(instruction)?,
-                                }?;
-                                Ok(ret.to_bits())
-                            }
-                        }
-                    };
-                    let src = |opsel: [bool; 3]| {
-                        opsel
-                            .iter()
-                            .enumerate()
-                            .map(|(i, sel)| {
-                                if (14..=19).contains(&op) {
-                                    let half = |x, n| f16::from_bits(x).negate(i, n).to_bits();
-                                    match sel {
-                                        true => half(src_parts[i].1, neg),
-                                        false => half(src_parts[i].0, neg_hi),
-                                    }
-                                } else {
-                                    match sel {
-                                        true => src_parts[i].1,
-                                        false => src_parts[i].0,
-                                    }
-                                }
-                            })
-                            .collect::<Vec<u16>>()
-                    };
-                    let (src_hi, src_lo) = (src(opsel_hi), src(opsel));
-                    let ret = ((fxn(src_hi[0], src_hi[1], src_hi[2])? as u32) << 16)
-                        | (fxn(src_lo[0], src_lo[1], src_lo[2])? as u32);
+        let s = src[..3].to_vec();
+        let src_parts = s.iter().map(|x| read(*x)).collect::<Vec<_>>();
 
-                    if self.exec.read() {
-                        self.vec_reg[vdst] = ret;
+        let b = |i: usize| (instr >> i) & 0x1 != 0;
+        let neg_hi = ((instr >> 8) & 0x7) as usize;
+        let neg = ((instr >> 61) & 0x7) as usize;
+        let opsel = [b(11), b(12), b(13)];
+        let opsel_hi = [b(59), b(60), b(14)];
+        if *GLOBAL_DEBUG {
+            println!("{} op={op} vdst={vdst} src2={:?} opsel={:?} opsel_hi={:?} neg={:03b} neg_hi={:03b}", "VOPP".color("blue"), src_parts, opsel, opsel_hi, neg, neg_hi);
+        }
+
+        match op {
+            0..=18 => {
+                let fxn = |x, y, z| -> Result<u16, i32> {
+                    match op {
+                        1 => Ok(x * y),
+                        4 => Ok(y << (x & 0xf)),
+                        10 => Ok(x + y),
+                        9 => Ok(x * y + z),
+                        11 => Ok(x - y),
+                        _ => {
+                            let (x, y, z) =
+                                (f16::from_bits(x), f16::from_bits(y), f16::from_bits(z));
+                            let ret = match op {
+                                14 => Ok::<f16, i32>(f16::mul_add(x, y, z)),
+                                15 => Ok(x + y),
+                                16 => Ok(x * y),
+                                17 => Ok(f16::min(x, y)),
+                                18 => Ok(f16::max(x, y)),
+                                _ => todo_instr!(instruction)?,
+                            }?;
+                            Ok(ret.to_bits())
+                        }
                     }
-                }
-                32..=34 => {
-                    let src: Vec<f32> = src_parts
+                };
+                let src = |opsel: [bool; 3]| {
+                    opsel
                         .iter()
                         .enumerate()
-                        .map(|(i, (lo, hi, full))| {
-                            if !opsel_hi[i] {
-                                f32::from_bits(*full).absolute(i, neg_hi)
-                            } else if opsel[i] {
-                                f32::from(f16::from_bits(*hi)).absolute(i, neg_hi)
+                        .map(|(i, sel)| {
+                            if (14..=19).contains(&op) {
+                                let half = |x, n| f16::from_bits(x).negate(i, n).to_bits();
+                                match sel {
+                                    true => half(src_parts[i].1, neg),
+                                    false => half(src_parts[i].0, neg_hi),
+                                }
                             } else {
-                                f32::from(f16::from_bits(*lo)).absolute(i, neg_hi)
+                                match sel {
+                                    true => src_parts[i].1,
+                                    false => src_parts[i].0,
+                                }
                             }
                         })
-                        .collect();
-                    let ret = match op {
-                        32 => f32::mul_add(src[0], src[1], src[2]).to_bits(),
-                        33 | 34 => {
-                            let ret = f16::from_f32(f32::mul_add(src[0], src[1], src[2])).to_bits();
-                            match op {
-                                33 => (self.vec_reg[vdst] & 0xffff0000) | (ret as u32),
-                                34 => (self.vec_reg[vdst] & 0x0000ffff) | ((ret as u32) << 16),
-                                _ => todo_instr!(instruction)?,
-                            }
+                        .collect::<Vec<u16>>()
+                };
+                let (src_hi, src_lo) = (src(opsel_hi), src(opsel));
+                let ret = ((fxn(src_hi[0], src_hi[1], src_hi[2])? as u32) << 16)
+                    | (fxn(src_lo[0], src_lo[1], src_lo[2])? as u32);
+
+                if self.exec.read() {
+                    self.vec_reg[vdst] = ret;
+                }
+            }
+            32..=34 => {
+                let src: Vec<f32> = src_parts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (lo, hi, full))| {
+                        if !opsel_hi[i] {
+                            f32::from_bits(*full).absolute(i, neg_hi)
+                        } else if opsel[i] {
+                            f32::from(f16::from_bits(*hi)).absolute(i, neg_hi)
+                        } else {
+                            f32::from(f16::from_bits(*lo)).absolute(i, neg_hi)
+                        }
+                    })
+                    .collect();
+                let ret = match op {
+                    32 => f32::mul_add(src[0], src[1], src[2]).to_bits(),
+                    33 | 34 => {
+                        let ret = f16::from_f32(f32::mul_add(src[0], src[1], src[2])).to_bits();
+                        match op {
+                            33 => (self.vec_reg[vdst] & 0xffff0000) | (ret as u32),
+                            34 => (self.vec_reg[vdst] & 0x0000ffff) | ((ret as u32) << 16),
+                            _ => todo_instr!(instruction)?,
                         }
-                        _ => todo_instr!(instruction)?,
-                    };
-                    if self.exec.read() {
-                        self.vec_reg[vdst] = ret;
                     }
+                    _ => todo_instr!(instruction)?,
+                };
+                if self.exec.read() {
+                    self.vec_reg[vdst] = ret;
                 }
-                64..=69 => {
-                    if *PROFILE {
-                        GLOBAL_COUNTER.lock().unwrap().wmma += 1;
-                    }
-                    let f16_matrix = |vsrc: usize| {
-                        let values = (0..16)
-                            .flat_map(|lane_id| {
-                                let lane = self.vec_reg.get_lane(lane_id);
-                                (vsrc..=vsrc + 7).flat_map(move |v| {
-                                    let val = lane[v - VGPR_COUNT];
-                                    [
-                                        f16::from_bits((val & 0xffff) as u16),
-                                        f16::from_bits(((val >> 16) & 0xffff) as u16),
-                                    ]
-                                })
-                            })
-                            .collect::<Vec<_>>();
-                        Array::from_shape_vec((16, 16), values).unwrap()
-                    };
-                    let c_matrix = |v: usize| {
-                        let values = (0..256)
-                            .into_iter()
-                            .map(|i| {
-                                let val = self.vec_reg.get_lane(i % 32)[(i / 32) + v - VGPR_COUNT];
-                                val
+            }
+            64..=69 => {
+                if *PROFILE {
+                    GLOBAL_COUNTER.wmma.fetch_add(1, Relaxed);
+                }
+                let f16_matrix = |vsrc: usize| {
+                    let values = (0..16)
+                        .flat_map(|lane_id| {
+                            let lane = self.vec_reg.get_lane(lane_id);
+                            (vsrc..=vsrc + 7).flat_map(move |v| {
+                                let val = lane[v - VGPR_COUNT];
+                                [
+                                    f16::from_bits((val & 0xffff) as u16),
+                                    f16::from_bits(((val >> 16) & 0xffff) as u16),
+                                ]
                             })
-                            .collect::<Vec<_>>();
-                        Array::from_shape_vec((16, 16), values).unwrap()
-                    };
+                        })
+                        .collect::<Vec<_>>();
+                    Array::from_shape_vec((16, 16), values).unwrap()
+                };
+                let c_matrix = |v: usize| {
+                    let values = (0..256)
+                        .into_iter()
+                        .map(|i| {
+                            let val = self.vec_reg.get_lane(i % 32)[(i / 32) + v - VGPR_COUNT];
+                            val
+                        })
+                        .collect::<Vec<_>>();
+                    Array::from_shape_vec((16, 16), values).unwrap()
+                };
 
-                    match op {
-                        64 => {
-                            let (a, b, c) = (f16_matrix(s[0]), f16_matrix(s[1]), c_matrix(s[2]));
-                            let (a, b) = (a.mapv(|e| e.to_f32()), b.mapv(|e| e.to_f32()));
-                            let c = c.mapv(|e| f32::from_bits(e));
-
-                            let ret = a.dot(&b.t()) + &c;
-                            for (i, val) in ret.iter().cloned().enumerate() {
-                                let register = (i / 32) + vdst;
-                                let lane = i % 32;
-                                self.vec_reg.get_lane_mut(lane)[register] = val.to_bits()
-                            }
+                match op {
+                    64 => {
+                        let (a, b, c) = (f16_matrix(s[0]), f16_matrix(s[1]), c_matrix(s[2]));
+                        let (a, b) = (a.mapv(|e| e.to_f32()), b.mapv(|e| e.to_f32()));
+                        let c = c.mapv(|e| f32::from_bits(e));
+
+                        let ret = a.dot(&b.t()) + &c;
+                        for (i, val) in ret.iter().cloned().enumerate() {
+                            let register = (i / 32) + vdst;
+                            let lane = i % 32;
+                            self.vec_reg.get_lane_mut(lane)[register] = val.to_bits()
                         }
-                        66 => {
-                            let (a, b, c) = (f16_matrix(s[0]), f16_matrix(s[1]), c_matrix(s[2]));
-                            let c = c.mapv(|e| f16::from_bits(e as u16));
-                            let ret = a.dot(&b.t()) + &c;
-                            for (i, val) in ret.iter().cloned().enumerate() {
-                                let register = (i / 32) + vdst;
-                                let lane = i % 32;
-                                self.vec_reg.get_lane_mut(lane)[register].mut_lo16(val.to_bits());
-                            }
+                    }
+                    66 => {
+                        let (a, b, c) = (f16_matrix(s[0]), f16_matrix(s[1]), c_matrix(s[2]));
+                        let c = c.mapv(|e| f16::from_bits(e as u16));
+                        let ret = a.dot(&b.t()) + &c;
+                        for (i, val) in ret.iter().cloned().enumerate() {
+                            let register = (i / 32) + vdst;
+                            let lane = i % 32;
+                            self.vec_reg.get_lane_mut(lane)[register].mut_lo16(val.to_bits());
                         }
-                        _ => todo_instr!(instruction)?,
-                    };
-                    self.scalar = true;
-                }
-                _ => todo_instr!(instruction)?,
+                    }
+                    _ => todo_instr!(instruction)?,
+                };
+                self.scalar = true;
             }
+            _ => todo_instr!(instruction)?,
         }
-        // vop1
-        else if instruction >> 25 == 0b0111111 {
-            let s0 = (instruction & 0x1ff) as usize;
-            let op = (instruction >> 9) & 0xff;
-            let vdst = ((instruction >> 17) & 0xff) as usize;
-
-            if *GLOBAL_DEBUG {
-                println!("{} src={s0} op={op} vdst={vdst}", "VOP1".color("blue"),);
-            }
+        Ok(())
+    }
 
-            match op {
-                3 | 15 | 21 | 23 | 25 | 26 | 60 | 61 | 47 | 49 => {
-                    let s0: u64 = self.val(s0);
-                    match op {
-                        3 | 15 | 21 | 23 | 25 | 26 | 60 | 61 | 47 | 49 => {
-                            let s0 = f64::from_bits(s0);
-                            match op {
-                                23 | 25 | 26 | 61 | 47 | 49 => {
-                                    let ret = match op {
-                                        23 => f64::trunc(s0),
-                                        25 => {
-                                            let mut temp = f64::floor(s0 + 0.5);
-                                            if f64::floor(s0) % 2.0 != 0.0 && f64::fract(s0) == 0.5
-                                            {
-                                                temp -= 1.0;
-                                            }
-                                            temp
+    fn vop1(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands { op, src, dst, .. } = self.operands;
+        let (s0, vdst) = (src[0], dst[0]);
+
+        if *GLOBAL_DEBUG {
+            println!("{} src={s0} op={op} vdst={vdst}", "VOP1".color("blue"),);
+        }
+
+        match op {
+            3 | 15 | 21 | 23 | 25 | 26 | 60 | 61 | 47 | 49 => {
+                let s0: u64 = self.val(s0);
+                match op {
+                    3 | 15 | 21 | 23 | 25 | 26 | 60 | 61 | 47 | 49 => {
+                        let s0 = f64::from_bits(s0);
+                        match op {
+                            23 | 25 | 26 | 61 | 47 | 49 => {
+                                let ret = match op {
+                                    23 => f64::trunc(s0),
+                                    25 => {
+                                        let mut temp = f64::floor(s0 + 0.5);
+                                        if f64::floor(s0) % 2.0 != 0.0 && f64::fract(s0) == 0.5 {
+                                            temp -= 1.0;
                                         }
-                                        26 => f64::floor(s0),
-                                        47 => 1.0 / s0,
-                                        49 => 1.0 / f64::sqrt(s0),
-                                        61 => extract_mantissa(s0),
-                                        _ => todo_instr!(instruction)?,
-                                    };
-                                    if self.exec.read() {
-                                        self.vec_reg.write64(vdst, ret.to_bits())
+                                        temp
                                     }
+                                    26 => f64::floor(s0),
+                                    47 => 1.0 / s0,
+                                    49 => 1.0 / f64::sqrt(s0),
+                                    61 => extract_mantissa(s0),
+                                    _ => todo_instr!(instruction)?,
+                                };
+                                if self.exec.read() {
+                                    self.vec_reg.write64(vdst, ret.to_bits())
                                 }
-                                _ => {
-                                    let ret = match op {
-                                        3 => s0 as i32 as u32,
-                                        15 => (s0 as f32).to_bits(),
-                                        21 => s0 as u32,
-                                        60 => {
-                                            match (s0 == f64::INFINITY)
-                                                || (s0 == f64::NEG_INFINITY)
-                                                || s0.is_nan()
-                                            {
-                                                true => 0,
-                                                false => (s0.exponent() as i32 - 1023 + 1) as u32,
-                                            }
+                            }
+                            _ => {
+                                let ret = match op {
+                                    3 => s0 as i32 as u32,
+                                    15 => (s0 as f32).to_bits(),
+                                    21 => s0 as u32,
+                                    60 => {
+                                        match (s0 == f64::INFINITY)
+                                            || (s0 == f64::NEG_INFINITY)
+                                            || s0.is_nan()
+                                        {
+                                            true => 0,
+                                            false => (s0.exponent() as i32 - 1023 + 1) as u32,
                                         }
-                                        _ => todo_instr!(instruction)?,
-                                    };
-                                    if self.exec.read() {
-                                        self.vec_reg[vdst] = ret;
                                     }
+                                    _ => todo_instr!(instruction)?,
+                                };
+                                if self.exec.read() {
+                                    self.vec_reg[vdst] = ret;
                                 }
                             }
                         }
-                        _ => todo_instr!(instruction)?,
                     }
+                    _ => todo_instr!(instruction)?,
                 }
-                84..=97 => {
-                    let s0 = f16::from_bits(self.val(s0));
-                    let ret = match op {
-                        84 => f16::recip(s0),
-                        85 => f16::sqrt(s0),
-                        87 => f16::log2(s0),
-                        88 => f16::exp2(s0),
-                        _ => todo_instr!(instruction)?,
-                    };
-                    if self.exec.read() {
-                        self.vec_reg[vdst] = ret.to_bits() as u32;
-                    }
+            }
+            84..=97 => {
+                let s0 = f16::from_bits(self.val(s0));
+                let ret = match op {
+                    84 => f16::recip(s0),
+                    85 => f16::sqrt(s0),
+                    87 => f16::log2(s0),
+                    88 => f16::exp2(s0),
+                    _ => todo_instr!(instruction)?,
+                };
+                if self.exec.read() {
+                    self.vec_reg[vdst] = ret.to_bits() as u32;
                 }
-                _ => {
-                    let s0: u32 = self.val(s0);
-                    match op {
-                        4 | 16 | 22 => {
-                            let ret = match op {
-                                4 => (s0 as i32 as f64).to_bits(),
-                                22 => (s0 as f64).to_bits(),
-                                16 => (f32::from_bits(s0) as f64).to_bits(),
-                                _ => todo_instr!(instruction)?,
-                            };
-                            if self.exec.read() {
-                                self.vec_reg.write64(vdst, ret)
-                            }
-                        }
-                        2 => {
-                            let idx = self.exec.value.trailing_zeros() as usize;
-                            self.scalar_reg[vdst] = self.vec_reg.get_lane(idx)
-                                [(instruction & 0x1ff) as usize - VGPR_COUNT];
+            }
+            _ => {
+                let s0: u32 = self.val(s0);
+                match op {
+                    4 | 16 | 22 => {
+                        let ret = match op {
+                            4 => (s0 as i32 as f64).to_bits(),
+                            22 => (s0 as f64).to_bits(),
+                            16 => (f32::from_bits(s0) as f64).to_bits(),
+                            _ => todo_instr!(instruction)?,
+                        };
+                        if self.exec.read() {
+                            self.vec_reg.write64(vdst, ret)
                         }
-                        _ => {
-                            let ret = match op {
-                                1 => s0,
-                                5 => (s0 as i32 as f32).to_bits(),
-                                6 => (s0 as f32).to_bits(),
-                                7 => f32::from_bits(s0) as u32,
-                                8 => f32::from_bits(s0) as i32 as u32,
-                                10 => f16::from_f32(f32::from_bits(s0)).to_bits() as u32,
-                                11 => f32::from(f16::from_bits(s0 as u16)).to_bits(),
-                                17 => ((s0 & 0xff) as f32).to_bits(),
-                                18 => (((s0 >> 8) & 0xff) as f32).to_bits(),
-                                19 => (((s0 >> 16) & 0xff) as f32).to_bits(),
-                                20 => (((s0 >> 24) & 0xff) as f32).to_bits(),
-                                56 => s0.reverse_bits(),
-                                57 => self.clz_i32_u32(s0),
-                                35..=51 => {
-                                    let s0 = f32::from_bits(s0);
-                                    match op {
-                                        35 => {
-                                            let mut temp = f32::floor(s0 + 0.5);
-                                            if f32::floor(s0) % 2.0 != 0.0 && f32::fract(s0) == 0.5
-                                            {
-                                                temp -= 1.0;
-                                            }
-                                            temp
+                    }
+                    2 => {
+                        // reads lane 0 when no lane is active
+                        let idx = self.exec.value.trailing_zeros() as usize % 32;
+                        self.scalar_reg[vdst] = self.vec_reg.get_lane(idx)[src[0] - VGPR_COUNT];
+                    }
+                    _ => {
+                        let ret = match op {
+                            1 => s0,
+                            5 => (s0 as i32 as f32).to_bits(),
+                            6 => (s0 as f32).to_bits(),
+                            7 => f32::from_bits(s0) as u32,
+                            8 => f32::from_bits(s0) as i32 as u32,
+                            10 => f16::from_f32(f32::from_bits(s0)).to_bits() as u32,
+                            11 => f32::from(f16::from_bits(s0 as u16)).to_bits(),
+                            17 => ((s0 & 0xff) as f32).to_bits(),
+                            18 => (((s0 >> 8) & 0xff) as f32).to_bits(),
+                            19 => (((s0 >> 16) & 0xff) as f32).to_bits(),
+                            20 => (((s0 >> 24) & 0xff) as f32).to_bits(),
+                            56 => s0.reverse_bits(),
+                            57 => self.clz_i32_u32(s0),
+                            35..=51 => {
+                                let s0 = f32::from_bits(s0);
+                                match op {
+                                    35 => {
+                                        let mut temp = f32::floor(s0 + 0.5);
+                                        if f32::floor(s0) % 2.0 != 0.0 && f32::fract(s0) == 0.5 {
+                                            temp -= 1.0;
                                         }
-                                        37 => f32::exp2(s0),
-                                        39 => f32::log2(s0),
-                                        42 => 1.0 / s0,
-                                        43 => 1.0 / s0,
-                                        51 => f32::sqrt(s0),
-                                        _ => todo_instr!(instruction)?,
+                                        temp
                                     }
-                                    .to_bits()
+                                    37 => f32::exp2(s0),
+                                    39 => f32::log2(s0),
+                                    42 => 1.0 / s0,
+                                    43 => 1.0 / s0,
+                                    51 => f32::sqrt(s0),
+                                    _ => todo_instr!(instruction)?,
                                 }
-                                55 => !s0,
-                                59 => self.cls_i32(s0),
-                                80 => f16::from_f32(s0 as u16 as f32).to_bits() as u32,
-                                81 => f16::from_f32(s0 as i16 as f32).to_bits() as u32,
-                                82 => f32::from(f16::from_bits(s0 as u16)) as u32,
-                                83 => f32::from(f16::from_bits(s0 as u16)) as i16 as u32,
-                                _ => todo_instr!(instruction)?,
-                            };
-                            if self.exec.read() {
-                                self.vec_reg[vdst] = ret;
+                                .to_bits()
                             }
+                            55 => !s0,
+                            59 => self.cls_i32(s0),
+                            80 => f16::from_f32(s0 as u16 as f32).to_bits() as u32,
+                            81 => f16::from_f32(s0 as i16 as f32).to_bits() as u32,
+                            82 => f32::from(f16::from_bits(s0 as u16)) as u32,
+                            83 => f32::from(f16::from_bits(s0 as u16)) as i16 as u32,
+                            _ => todo_instr!(instruction)?,
+                        };
+                        if self.exec.read() {
+                            self.vec_reg[vdst] = ret;
                         }
                     }
                 }
             }
         }
-        // vopd
-        else if instruction >> 26 == 0b110010 {
-            let instr = self.u64_instr();
-            let sx = instr & 0x1ff;
-            let vx = (instr >> 9) & 0xff;
-            let srcx0 = self.val(sx as usize);
-            let vsrcx1 = self.vec_reg[(vx) as usize] as u32;
-            let opy = (instr >> 17) & 0x1f;
-            let sy = (instr >> 32) & 0x1ff;
-            let vy = (instr >> 41) & 0xff;
-            let opx = (instr >> 22) & 0xf;
-            let srcy0 = match sy {
-                255 => match sx {
-                    255 => srcx0,
-                    _ => self.val(sy as usize),
+        Ok(())
+    }
+
+    fn vopd(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands { op, src, dst, .. } = self.operands;
+        let [sx, vx, sy, vy] = src;
+        let srcx0 = self.val(sx);
+        let vsrcx1 = self.vec_reg[vx] as u32;
+        let (opx, opy) = (op >> 5, op & 0x1f);
+        let srcy0 = match sy {
+            255 => match sx {
+                255 => srcx0,
+                _ => self.val(sy),
+            },
+            _ => self.val(sy),
+        };
+        let vsrcy1 = self.vec_reg[vy];
+        let [vdstx, vdsty] = dst;
+
+        if *GLOBAL_DEBUG {
+            println!(
+                "{} X=[op={opx}, dest={vdstx} src({sx})={srcx0}, vsrc({vx})={vsrcx1}] Y=[op={opy}, dest={vdsty}, src({sy})={srcy0}, vsrc({vy})={vsrcy1}]",
+                "VOPD".color("blue"),
+            );
+        }
+
+        for (op, s0, s1, dst) in ([(opx, srcx0, vsrcx1, vdstx), (opy, srcy0, vsrcy1, vdsty)]).iter()
+        {
+            let ret = match *op {
+                0 | 1 | 2 | 3 | 4 | 5 | 6 | 10 | 11 => {
+                    let s0 = f32::from_bits(*s0 as u32);
+                    let s1 = f32::from_bits(*s1 as u32);
+                    match *op {
+                        0 => f32::mul_add(s0, s1, f32::from_bits(self.vec_reg[*dst])),
+                        1 => f32::mul_add(s0, s1, f32::from_bits(self.simm())),
+                        2 => f32::mul_add(s0, f32::from_bits(self.simm()), s1),
+                        3 => s0 * s1,
+                        4 => s0 + s1,
+                        5 => s0 - s1,
+                        6 => s1 - s0,
+                        10 => f32::max(s0, s1),
+                        11 => f32::min(s0, s1),
+                        _ => todo_instr!(instruction)?,
+                    }
+                    .to_bits()
+                }
+                8 => *s0,
+                9 => match self.vcc.read() {
+                    true => *s1,
+                    false => *s0,
                 },
-                _ => self.val(sy as usize),
+                16 => s0 + s1,
+                17 => s1 << s0,
+                18 => s0 & s1,
+                _ => todo_instr!(instruction)?,
             };
-            let vsrcy1 = self.vec_reg[(vy) as usize];
+            if self.exec.read() {
+                self.vec_reg[*dst] = ret;
+            };
+        }
+        Ok(())
+    }
 
-            let vdstx = ((instr >> 56) & 0xff) as usize;
-            // LSB is the opposite of VDSTX[0]
-            let vdsty = (((instr >> 49) & 0x7f) << 1 | ((vdstx as u64 & 1) ^ 1)) as usize;
+    fn vopc(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands { op, src, .. } = self.operands;
+        let (s0, s1) = (src[0], src[1]);
 
-            if *GLOBAL_DEBUG {
-                println!(
-                    "{} X=[op={opx}, dest={vdstx} src({sx})={srcx0}, vsrc({vx})={vsrcx1}] Y=[op={opy}, dest={vdsty}, src({sy})={srcy0}, vsrc({vy})={vsrcy1}]",
-                    "VOPD".color("blue"),
-                );
-            }
+        if *GLOBAL_DEBUG {
+            println!("{} src={:?} op={}", "VOPC".color("blue"), (s0, s1), op);
+        }
 
-            for (op, s0, s1, dst) in
-                ([(opx, srcx0, vsrcx1, vdstx), (opy, srcy0, vsrcy1, vdsty)]).iter()
-            {
-                let ret = match *op {
-                    0 | 1 | 2 | 3 | 4 | 5 | 6 | 10 | 11 => {
-                        let s0 = f32::from_bits(*s0 as u32);
-                        let s1 = f32::from_bits(*s1 as u32);
-                        match *op {
-                            0 => f32::mul_add(s0, s1, f32::from_bits(self.vec_reg[*dst])),
-                            1 => f32::mul_add(s0, s1, f32::from_bits(self.simm())),
-                            2 => f32::mul_add(s0, f32::from_bits(self.simm()), s1),
-                            3 => s0 * s1,
-                            4 => s0 + s1,
-                            5 => s0 - s1,
-                            6 => s1 - s0,
-                            10 => f32::max(s0, s1),
-                            11 => f32::min(s0, s1),
-                            _ => todo_instr!(instruction)?,
-                        }
-                        .to_bits()
+        let dest_offset = if op >= 128 { 128 } else { 0 };
+        let ret = match op {
+            (0..=15) | 125 | (128..=143) => {
+                let s0 = f16::from_bits(self.val(s0));
+                let s1 = f16::from_bits(self.vec_reg[s1] as u16);
+                match op {
+                    125 => self.cmp_class_f16(s0, s1.to_bits()),
+                    _ => self.cmpf(s0, s1, op - dest_offset),
+                }
+            }
+            (16..=31) | 126 | (144..=159) => {
+                let s0 = f32::from_bits(self.val(s0));
+                let s1 = f32::from_bits(self.vec_reg[s1]);
+                match op {
+                    126 => self.cmp_class_f32(s0, s1.to_bits()),
+                    _ => self.cmpf(s0, s1, op - 16 - dest_offset),
+                }
+            }
+            (32..=47) | 127 | (160..=174) => {
+                let s0 = f64::from_bits(self.val(s0));
+                match op {
+                    127 => {
+                        let s1 = self.val(s1);
+                        self.cmp_class_f64(s0, s1)
                     }
-                    8 => *s0,
-                    9 => match self.vcc.read() {
-                        true => *s1,
-                        false => *s0,
-                    },
-                    16 => s0 + s1,
-                    17 => s1 << s0,
-                    18 => s0 & s1,
+                    _ => {
+                        let s1 = f64::from_bits(self.vec_reg.read64(s1));
+                        self.cmpf(s0, s1, op - 32 - dest_offset)
+                    }
+                }
+            }
+            (49..=54) | (177..=182) => {
+                let (s0, s1): (u16, u16) = (self.val(s0), self.vec_reg[s1] as u16);
+                self.cmpi(s0 as i16, s1 as i16, op - 48 - dest_offset)
+            }
+            (57..=62) | (185..=190) => {
+                let (s0, s1): (u16, u16) = (self.val(s0), self.vec_reg[s1] as u16);
+                self.cmpi(s0, s1, op - 56 - dest_offset)
+            }
+            (64..=71) | (192..=199) => {
+                let (s0, s1): (u32, u32) = (self.val(s0), self.vec_reg[s1]);
+                self.cmpi(s0 as i32, s1 as i32, op - 64 - dest_offset)
+            }
+            (72..=79) | (200..=207) => {
+                let (s0, s1): (u32, u32) = (self.val(s0), self.vec_reg[s1]);
+                self.cmpi(s0, s1, op - 72 - dest_offset)
+            }
+            (80..=87) | (208..=215) => {
+                let (s0, s1): (u64, u64) = (self.val(s0), self.vec_reg.read64(s1));
+                self.cmpi(s0 as i64, s1 as i64, op - 80 - dest_offset)
+            }
+            (88..=95) | (216..=223) => {
+                let (s0, s1): (u64, u64) = (self.val(s0), self.vec_reg.read64(s1));
+                self.cmpi(s0, s1, op - 88 - dest_offset)
+            }
+            _ => todo_instr!(instruction)?,
+        };
+
+        match op >= 128 {
+            true => self.exec.set_lane(ret),
+            false => self.vcc.set_lane(ret),
+        };
+        Ok(())
+    }
+
+    fn vop2(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands { op, src, dst, .. } = self.operands;
+        let s0 = src[0];
+        let s1 = self.vec_reg[src[1]];
+        let vdst = dst[0];
+
+        if *GLOBAL_DEBUG {
+            println!(
+                "{} s0={s0} s1={s1} vdst={vdst} op={op}",
+                "VOP2".color("blue"),
+            );
+        }
+
+        match op {
+            (50..=60) => {
+                let (s0, s1) = (f16::from_bits(self.val(s0)), f16::from_bits(s1 as u16));
+                let ret = match op {
+                    50 => s0 + s1,
+                    51 => s0 - s1,
+                    53 => s0 * s1,
+                    54 => f16::mul_add(s0, s1, f16::from_bits(self.vec_reg[vdst] as u16)),
+                    55 => f16::mul_add(s0, f16::from_bits(self.simm() as u16), s1),
+                    56 => f16::mul_add(s0, s1, f16::from_bits(self.simm() as u16)),
+                    57 => f16::max(s0, s1),
+                    58 => f16::min(s0, s1),
                     _ => todo_instr!(instruction)?,
                 };
                 if self.exec.read() {
-                    self.vec_reg[*dst] = ret;
-                };
-            }
-        }
-        // vopc
-        else if instruction >> 25 == 0b0111110 {
-            let s0 = (instruction & 0x1ff) as usize;
-            let s1 = ((instruction >> 9) & 0xff) as usize;
-            let op = (instruction >> 17) & 0xff;
-
-            if *GLOBAL_DEBUG {
-                println!("{} src={:?} op={}", "VOPC".color("blue"), (s0, s1), op);
+                    self.vec_reg[vdst] = ret.to_bits() as u32;
+                }
             }
+            _ => {
+                let s0 = self.val(s0);
+                let ret = match op {
+                    1 => match self.vcc.read() {
+                        true => s1,
+                        false => s0,
+                    },
+                    2 => {
+                        let mut acc = f32::from_bits(self.vec_reg[vdst]);
+                        acc += f32::from(f16_lo(s0)) * f32::from(f16_lo(s1));
+                        acc += f32::from(f16_hi(s0)) * f32::from(f16_hi(s1));
+                        acc.to_bits()
+                    }
 
-            let dest_offset = if op >= 128 { 128 } else { 0 };
-            let ret = match op {
-                (0..=15) | 125 | (128..=143) => {
-                    let s0 = f16::from_bits(self.val(s0));
-                    let s1 = f16::from_bits(self.vec_reg[s1] as u16);
-                    match op {
-                        125 => self.cmp_class_f16(s0, s1.to_bits()),
-                        _ => self.cmpf(s0, s1, op - dest_offset),
+                    3 | 4 | 5 | 8 | 15 | 16 | 43 | 44 | 45 => {
+                        let (s0, s1) = (f32::from_bits(s0), f32::from_bits(s1));
+                        match op {
+                            3 => s0 + s1,
+                            4 => s0 - s1,
+                            5 => s1 - s0,
+                            8 => s0 * s1,
+                            15 => f32::min(s0, s1),
+                            16 => f32::max(s0, s1),
+                            43 => f32::mul_add(s0, s1, f32::from_bits(self.vec_reg[vdst])),
+                            44 => f32::mul_add(s0, f32::from_bits(self.simm()), s1),
+                            45 => f32::mul_add(s0, s1, f32::from_bits(self.simm())),
+                            _ => todo_instr!(instruction)?,
+                        }
+                        .to_bits()
                     }
-                }
-                (16..=31) | 126 | (144..=159) => {
-                    let s0 = f32::from_bits(self.val(s0));
-                    let s1 = f32::from_bits(self.vec_reg[s1]);
-                    match op {
-                        126 => self.cmp_class_f32(s0, s1.to_bits()),
-                        _ => self.cmpf(s0, s1, op - 16 - dest_offset),
+                    9 => {
+                        let s0 = sign_ext((s0 & 0xffffff) as u64, 24) as i32;
+                        let s1 = sign_ext((s1 & 0xffffff) as u64, 24) as i32;
+                        (s0 * s1) as u32
                     }
-                }
-                (32..=47) | 127 | (160..=174) => {
-                    let s0 = f64::from_bits(self.val(s0));
-                    match op {
-                        127 => {
-                            let s1 = self.val(s1);
-                            self.cmp_class_f64(s0, s1)
-                        }
-                        _ => {
-                            let s1 = f64::from_bits(self.vec_reg.read64(s1));
-                            self.cmpf(s0, s1, op - 32 - dest_offset)
-                        }
+                    18 | 26 => {
+                        let (s0, s1) = (s0 as i32, s1 as i32);
+                        (match op {
+                            18 => i32::max(s0, s1),
+                            26 => s1 >> s0,
+                            _ => todo_instr!(instruction)?,
+                        }) as u32
                     }
+                    32 => {
+                        let temp = s0 as u64 + s1 as u64 + self.vcc.read() as u64;
+                        self.vcc.set_lane(temp >= 0x100000000);
+                        temp as u32
+                    }
+                    33 | 34 => {
+                        let temp = match op {
+                            33 => s0 - s1 - self.vcc.read() as u32,
+                            34 => s1 - s0 - self.vcc.read() as u32,
+                            _ => todo_instr!(instruction)?,
+                        };
+                        self.vcc
+                            .set_lane((s1 as u64 + self.vcc.read() as u64) > s0 as u64);
+                        temp
+                    }
+                    11 => s0 * s1,
+                    19 => u32::min(s0, s1),
+                    20 => u32::max(s0, s1),
+                    24 => s1 << s0,
+                    25 => s1 >> s0,
+                    27 => s0 & s1,
+                    28 => s0 | s1,
+                    29 => s0 ^ s1,
+                    37 => s0 + s1,
+                    38 => s0 - s1,
+                    39 => s1 - s0,
+                    _ => todo_instr!(instruction)?,
+                };
+                if self.exec.read() {
+                    self.vec_reg[vdst] = ret;
                 }
-                (49..=54) | (177..=182) => {
-                    let (s0, s1): (u16, u16) = (self.val(s0), self.vec_reg[s1] as u16);
-                    self.cmpi(s0 as i16, s1 as i16, op - 48 - dest_offset)
-                }
-                (57..=62) | (185..=190) => {
-                    let (s0, s1): (u16, u16) = (self.val(s0), self.vec_reg[s1] as u16);
-                    self.cmpi(s0, s1, op - 56 - dest_offset)
-                }
-                (64..=71) | (192..=199) => {
-                    let (s0, s1): (u32, u32) = (self.val(s0), self.vec_reg[s1]);
-                    self.cmpi(s0 as i32, s1 as i32, op - 64 - dest_offset)
-                }
-                (72..=79) | (200..=207) => {
-                    let (s0, s1): (u32, u32) = (self.val(s0), self.vec_reg[s1]);
-                    self.cmpi(s0, s1, op - 72 - dest_offset)
-                }
-                (80..=87) | (208..=215) => {
-                    let (s0, s1): (u64, u64) = (self.val(s0), self.vec_reg.read64(s1));
-                    self.cmpi(s0 as i64, s1 as i64, op - 80 - dest_offset)
-                }
-                (88..=95) | (216..=223) => {
-                    let (s0, s1): (u64, u64) = (self.val(s0), self.vec_reg.read64(s1));
-                    self.cmpi(s0, s1, op - 88 - dest_offset)
-                }
-                _ => todo_instr!(instruction)?,
-            };
-
-            match op >= 128 {
-                true => self.exec.set_lane(ret),
-                false => self.vcc.set_lane(ret),
-            };
-        }
-        // vop2
-        else if instruction >> 31 == 0b0 {
-            let s0 = (instruction & 0x1FF) as usize;
-            let s1 = self.vec_reg[((instruction >> 9) & 0xFF) as usize];
-            let vdst = ((instruction >> 17) & 0xFF) as usize;
-            let op = (instruction >> 25) & 0x3F;
-
-            if *GLOBAL_DEBUG {
-                println!(
-                    "{} s0={s0} s1={s1} vdst={vdst} op={op}",
-                    "VOP2".color("blue"),
-                );
             }
+        };
+        Ok(())
+    }
 
-            match op {
-                (50..=60) => {
-                    let (s0, s1) = (f16::from_bits(self.val(s0)), f16::from_bits(s1 as u16));
-                    let ret = match op {
-                        50 => s0 + s1,
-                        51 => s0 - s1,
-                        53 => s0 * s1,
-                        54 => f16::mul_add(s0, s1, f16::from_bits(self.vec_reg[vdst] as u16)),
-                        55 => f16::mul_add(s0, f16::from_bits(self.simm() as u16), s1),
-                        56 => f16::mul_add(s0, s1, f16::from_bits(self.simm() as u16)),
-                        57 => f16::max(s0, s1),
-                        58 => f16::min(s0, s1),
-                        _ => todo_instr!(instruction)?,
-                    };
-                    if self.exec.read() {
-                        self.vec_reg[vdst] = ret.to_bits() as u32;
-                    }
+    fn vop3(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands {
+            instr,
+            op,
+            src,
+            dst,
+            ..
+        } = self.operands;
+        match op {
+            764 | 765 | 288 | 289 | 766 | 768 | 769 => {
+                let [vdst, sdst] = dst;
+                let (s0, s1, s2) = (src[0], src[1], src[2]);
+                let mut carry_in = WaveValue::new(self.val(s2), self.warp_size);
+                carry_in.default_lane = self.vcc.default_lane;
+                let omod = (instr >> 59) & 0x3;
+                let _neg = (instr >> 61) & 0x7;
+                let clmp = (instr >> 15) & 0x1;
+                if omod != 0 || clmp != 0 {
+                    return todo_instr!(instruction);
                 }
-                _ => {
-                    let s0 = self.val(s0);
-                    let ret = match op {
-                        1 => match self.vcc.read() {
-                            true => s1,
-                            false => s0,
-                        },
-                        2 => {
-                            let mut acc = f32::from_bits(self.vec_reg[vdst]);
-                            acc += f32::from(f16_lo(s0)) * f32::from(f16_lo(s1));
-                            acc += f32::from(f16_hi(s0)) * f32::from(f16_hi(s1));
-                            acc.to_bits()
-                        }
 
-                        3 | 4 | 5 | 8 | 15 | 16 | 43 | 44 | 45 => {
-                            let (s0, s1) = (f32::from_bits(s0), f32::from_bits(s1));
-                            match op {
-                                3 => s0 + s1,
-                                4 => s0 - s1,
-                                5 => s1 - s0,
-                                8 => s0 * s1,
-                                15 => f32::min(s0, s1),
-                                16 => f32::max(s0, s1),
-                                43 => f32::mul_add(s0, s1, f32::from_bits(self.vec_reg[vdst])),
-                                44 => f32::mul_add(s0, f32::from_bits(self.simm()), s1),
-                                45 => f32::mul_add(s0, s1, f32::from_bits(self.simm())),
-                                _ => todo_instr!(instruction)?,
-                            }
-                            .to_bits()
-                        }
-                        9 => {
-                            let s0 = sign_ext((s0 & 0xffffff) as u64, 24) as i32;
-                            let s1 = sign_ext((s1 & 0xffffff) as u64, 24) as i32;
-                            (s0 * s1) as u32
-                        }
-                        18 | 26 => {
-                            let (s0, s1) = (s0 as i32, s1 as i32);
-                            (match op {
-                                18 => i32::max(s0, s1),
-                                26 => s1 >> s0,
-                                _ => todo_instr!(instruction)?,
-                            }) as u32
+                if *GLOBAL_DEBUG {
+                    println!(
+                        "{} vdst={vdst} sdst={sdst} op={op} src={:?}",
+                        "VOPSD".color("blue"),
+                        (s0, s1, s2)
+                    );
+                }
+
+                let vcc = match op {
+                    766 => {
+                        let (s0, s1, s2): (u32, u32, u64) =
+                            (self.val(s0), self.val(s1), self.val(s2));
+                        let (mul_result, overflow_mul) = (s0 as u64).overflowing_mul(s1 as u64);
+                        let (ret, overflow_add) = mul_result.overflowing_add(s2);
+                        let overflowed = overflow_mul || overflow_add;
+                        if self.exec.read() {
+                            self.vec_reg.write64(vdst, ret);
                         }
-                        32 => {
-                            let temp = s0 as u64 + s1 as u64 + self.vcc.read() as u64;
-                            self.vcc.set_lane(temp >= 0x100000000);
-                            temp as u32
+                        overflowed
+                    }
+                    765 => {
+                        if f64::from_bits(self.val(s2)).exponent() > 1076 {
+                            return todo_instr!(instruction);
                         }
-                        33 | 34 => {
-                            let temp = match op {
-                                33 => s0 - s1 - self.vcc.read() as u32,
-                                34 => s1 - s0 - self.vcc.read() as u32,
-                                _ => todo_instr!(instruction)?,
-                            };
-                            self.vcc
-                                .set_lane((s1 as u64 + self.vcc.read() as u64) > s0 as u64);
-                            temp
+                        let ret = ldexp(f64::from_bits(self.val(s0)), 128);
+                        if self.exec.read() {
+                            self.vec_reg.write64(vdst, ret.to_bits());
                         }
-                        11 => s0 * s1,
-                        19 => u32::min(s0, s1),
-                        20 => u32::max(s0, s1),
-                        24 => s1 << s0,
-                        25 => s1 >> s0,
-                        27 => s0 & s1,
-                        28 => s0 | s1,
-                        29 => s0 ^ s1,
-                        37 => s0 + s1,
-                        38 => s0 - s1,
-                        39 => s1 - s0,
-                        _ => todo_instr!(instruction)?,
-                    };
-                    if self.exec.read() {
-                        self.vec_reg[vdst] = ret;
+                        false
                     }
-                }
-            };
-        }
-        // vop3
-        else if instruction >> 26 == 0b110101 {
-            let instr = self.u64_instr();
-
-            let op = ((instr >> 16) & 0x3ff) as u32;
-            match op {
-                764 | 765 | 288 | 289 | 766 | 768 | 769 => {
-                    let vdst = (instr & 0xff) as usize;
-                    let sdst = ((instr >> 8) & 0x7f) as usize;
-                    let f = |i: u32| -> usize { ((instr >> i) & 0x1ff) as usize };
-                    let (s0, s1, s2) = (f(32), f(41), f(50));
-                    let mut carry_in = WaveValue::new(self.val(s2), self.warp_size);
-                    carry_in.default_lane = self.vcc.default_lane;
-                    let omod = (instr >> 59) & 0x3;
-                    let _neg = (instr >> 61) & 0x7;
-                    let clmp = (instr >> 15) & 0x1;
-                    assert_eq!(omod, 0);
-                    assert_eq!(clmp, 0);
-
-                    if *GLOBAL_DEBUG {
-                        println!(
-                            "{} vdst={vdst} sdst={sdst} op={op} src={:?}",
-                            "VOPSD".color("blue"),
-                            (s0, s1, s2)
-                        );
-                    }
-
-                    let vcc = match op {
-                        766 => {
-                            let (s0, s1, s2): (u32, u32, u64) =
-                                (self.val(s0), self.val(s1), self.val(s2));
-                            let (mul_result, overflow_mul) = (s0 as u64).overflowing_mul(s1 as u64);
-                            let (ret, overflow_add) = mul_result.overflowing_add(s2);
-                            let overflowed = overflow_mul || overflow_add;
-                            if self.exec.read() {
-                                self.vec_reg.write64(vdst, ret);
+                    _ => {
+                        let (s0, s1, _s2): (u32, u32, u32) =
+                            (self.val(s0), self.val(s1), self.val(s2));
+                        let (ret, vcc) = match op {
+                            288 => {
+                                let ret = s0 as u64 + s1 as u64 + carry_in.read() as u64;
+                                (ret as u32, ret >= 0x100000000)
                             }
-                            overflowed
-                        }
-                        765 => {
-                            assert!(f64::from_bits(self.val(s2)).exponent() <= 1076);
-                            let ret = ldexp(f64::from_bits(self.val(s0)), 128);
-                            if self.exec.read() {
-                                self.vec_reg.write64(vdst, ret.to_bits());
+                            289 => {
+                                let ret = (s0 as u64)
+                                    .wrapping_sub(s1 as u64)
+                                    .wrapping_sub(carry_in.read() as u64);
+                                (ret as u32, s1 as u64 + (carry_in.read() as u64) > s0 as u64)
                             }
-                            false
-                        }
-                        _ => {
-                            let (s0, s1, _s2): (u32, u32, u32) =
-                                (self.val(s0), self.val(s1), self.val(s2));
-                            let (ret, vcc) = match op {
-                                288 => {
-                                    let ret = s0 as u64 + s1 as u64 + carry_in.read() as u64;
-                                    (ret as u32, ret >= 0x100000000)
-                                }
-                                289 => {
-                                    let ret = (s0 as u64)
-                                        .wrapping_sub(s1 as u64)
-                                        .wrapping_sub(carry_in.read() as u64);
-                                    (ret as u32, s1 as u64 + (carry_in.read() as u64) > s0 as u64)
-                                }
-                                764 => (0, false), // NOTE: div scaling isn't required
-                                768 => {
-                                    let ret = s0 as u64 + s1 as u64;
-                                    (ret as u32, ret >= 0x100000000)
-                                }
-                                769 => {
-                                    let ret = s0.wrapping_sub(s1);
-                                    (ret as u32, s1 > s0)
-                                }
-                                _ => todo_instr!(instruction)?,
-                            };
-                            if self.exec.read() {
-                                self.vec_reg[vdst] = ret;
+                            764 => (0, false), // NOTE: div scaling isn't required
+                            768 => {
+                                let ret = s0 as u64 + s1 as u64;
+                                (ret as u32, ret >= 0x100000000)
+                            }
+                            769 => {
+                                let ret = s0.wrapping_sub(s1);
+                                (ret as u32, s1 > s0)
                             }
-                            vcc
+                            _ => todo_instr!(instruction)?,
+                        };
+                        if self.exec.read() {
+                            self.vec_reg[vdst] = ret;
                         }
-                    };
-
-                    match sdst {
-                        106 => self.vcc.set_lane(vcc),
-                        124 => {}
-                        _ => self.set_sgpr_co(sdst, vcc),
+                        vcc
                     }
+                };
+
+                match sdst {
+                    106 => self.vcc.set_lane(vcc),
+                    124 => {}
+                    _ => self.set_sgpr_co(sdst, vcc),
+                }
+            }
+            _ => {
+                let vdst = dst[0];
+                let abs = ((instr >> 8) & 0x7) as usize;
+                let opsel = ((instr >> 11) & 0xf) as usize;
+                let cm = (instr >> 15) & 0x1;
+                let src = (src[0], src[1], src[2]);
+
+                let omod = (instr >> 59) & 0x3;
+                let neg = ((instr >> 61) & 0x7) as usize;
+                if omod != 0 || cm != 0 || opsel != 0 {
+                    return todo_instr!(instruction);
                 }
-                _ => {
-                    let vdst = (instr & 0xff) as usize;
-                    let abs = ((instr >> 8) & 0x7) as usize;
-                    let opsel = ((instr >> 11) & 0xf) as usize;
-                    let cm = (instr >> 15) & 0x1;
-
-                    let s = |n: usize| ((instr >> n) & 0x1ff) as usize;
-                    let src = (s(32), s(41), s(50));
-
-                    let omod = (instr >> 59) & 0x3;
-                    let neg = ((instr >> 61) & 0x7) as usize;
-                    assert_eq!(omod, 0);
-                    assert_eq!(cm, 0);
-                    assert_eq!(opsel, 0);
-
-                    if *GLOBAL_DEBUG {
-                        println!(
-                            "{} vdst={vdst} abs={abs} opsel={opsel} op={op} src={:?} neg=0b{:03b}",
-                            "VOP3".color("blue"),
-                            src,
-                            neg
-                        );
-                    }
 
-                    match op {
-                        // VOPC using VOP3 encoding
-                        0..=255 => {
-                            let dest_offset = if op >= 128 { 128 } else { 0 };
-                            let ret = match op {
-                                (0..=15) | 125 | (128..=143) => {
-                                    let (s0, s1) = (self.val(src.0), self.val(src.1));
-                                    let s0 = f16::from_bits(s0).negate(0, neg).absolute(0, abs);
-                                    let s1 = f16::from_bits(s1).negate(1, neg).absolute(1, abs);
-                                    match op {
-                                        125 => self.cmp_class_f16(s0, s1.to_bits()),
-                                        _ => self.cmpf(s0, s1, op - dest_offset),
-                                    }
+                if *GLOBAL_DEBUG {
+                    println!(
+                        "{} vdst={vdst} abs={abs} opsel={opsel} op={op} src={:?} neg=0b{:03b}",
+                        "VOP3".color("blue"),
+                        src,
+                        neg
+                    );
+                }
+
+                match op {
+                    // VOPC using VOP3 encoding
+                    0..=255 => {
+                        let dest_offset = if op >= 128 { 128 } else { 0 };
+                        let ret = match op {
+                            (0..=15) | 125 | (128..=143) => {
+                                let (s0, s1) = (self.val(src.0), self.val(src.1));
+                                let s0 = f16::from_bits(s0).negate(0, neg).absolute(0, abs);
+                                let s1 = f16::from_bits(s1).negate(1, neg).absolute(1, abs);
+                                match op {
+                                    125 => self.cmp_class_f16(s0, s1.to_bits()),
+                                    _ => self.cmpf(s0, s1, op - dest_offset),
                                 }
-                                (16..=31) | 126 | (144..=159) => {
-                                    let (s0, s1) = (self.val(src.0), self.val(src.1));
-                                    let s0 = f32::from_bits(s0).negate(0, neg).absolute(0, abs);
-                                    let s1 = f32::from_bits(s1).negate(1, neg).absolute(1, abs);
-                                    match op {
-                                        126 => self.cmp_class_f32(s0, s1.to_bits()),
-                                        _ => self.cmpf(s0, s1, op - 16 - dest_offset),
-                                    }
+                            }
+                            (16..=31) | 126 | (144..=159) => {
+                                let (s0, s1) = (self.val(src.0), self.val(src.1));
+                                let s0 = f32::from_bits(s0).negate(0, neg).absolute(0, abs);
+                                let s1 = f32::from_bits(s1).negate(1, neg).absolute(1, abs);
+                                match op {
+                                    126 => self.cmp_class_f32(s0, s1.to_bits()),
+                                    _ => self.cmpf(s0, s1, op - 16 - dest_offset),
                                 }
-                                (32..=47) | 127 | (160..=174) => {
-                                    let s0 = self.val(src.0);
-                                    let s0 = f64::from_bits(s0).negate(0, neg).absolute(0, abs);
-                                    match op {
-                                        127 => {
-                                            let s1 = self.val(src.1);
-                                            self.cmp_class_f64(s0, s1)
-                                        }
-                                        _ => {
-                                            let s1 = self.val(src.1);
-                                            let s1 =
-                                                f64::from_bits(s1).negate(1, neg).absolute(1, abs);
-                                            self.cmpf(s0, s1, op - 32 - dest_offset)
-                                        }
+                            }
+                            (32..=47) | 127 | (160..=174) => {
+                                let s0 = self.val(src.0);
+                                let s0 = f64::from_bits(s0).negate(0, neg).absolute(0, abs);
+                                match op {
+                                    127 => {
+                                        let s1 = self.val(src.1);
+                                        self.cmp_class_f64(s0, s1)
+                                    }
+                                    _ => {
+                                        let s1 = self.val(src.1);
+                                        let s1 = f64::from_bits(s1).negate(1, neg).absolute(1, abs);
+                                        self.cmpf(s0, s1, op - 32 - dest_offset)
                                     }
                                 }
-                                (49..=54) | (177..=182) => {
-                                    let (s0, s1): (u16, u16) = (self.val(src.0), self.val(src.1));
-                                    self.cmpi(s0 as i16, s1 as i16, op - 48 - dest_offset)
-                                }
-                                (57..=62) | (185..=190) => {
-                                    let (s0, s1): (u16, u16) = (self.val(src.0), self.val(src.1));
-                                    self.cmpi(s0, s1, op - 56 - dest_offset)
-                                }
-                                (64..=71) | (192..=199) => {
-                                    let (s0, s1): (u32, u32) = (self.val(src.0), self.val(src.1));
-                                    self.cmpi(s0 as i32, s1 as i32, op - 64 - dest_offset)
-                                }
-                                (72..=79) | (200..=207) => {
-                                    let (s0, s1): (u32, u32) = (self.val(src.0), self.val(src.1));
-                                    self.cmpi(s0, s1, op - 72 - dest_offset)
-                                }
-                                (80..=87) | (208..=215) => {
-                                    let (s0, s1): (u64, u64) = (self.val(src.0), self.val(src.1));
-                                    self.cmpi(s0 as i64, s1 as i64, op - 80 - dest_offset)
-                                }
-                                (88..=95) | (216..=223) => {
-                                    let (s0, s1): (u64, u64) = (self.val(src.0), self.val(src.1));
-                                    self.cmpi(s0, s1, op - 88 - dest_offset)
-                                }
-                                _ => todo_instr!(instruction)?,
-                            };
-
-                            match vdst {
-                                0..=SGPR_COUNT => self.set_sgpr_co(vdst, ret),
-                                106 => self.vcc.set_lane(ret),
-                                126 => self.exec.set_lane(ret),
-                                _ => todo_instr!(instruction)?,
                             }
-                        }
-                        828..=830 => {
-                            let (s0, s1, _s2): (u32, u64, u64) =
-                                (self.val(src.0), self.val(src.1), self.val(src.2));
-                            let shift = s0 & 0x3f;
-                            let ret = match op {
-                                828 => s1 << shift,
-                                829 => s1 >> shift,
-                                830 => ((s1 as i64) >> shift) as u64,
-                                _ => todo_instr!(instruction)?,
-                            };
-                            if self.exec.read() {
-                                self.vec_reg.write64(vdst, ret)
+                            (49..=54) | (177..=182) => {
+                                let (s0, s1): (u16, u16) = (self.val(src.0), self.val(src.1));
+                                self.cmpi(s0 as i16, s1 as i16, op - 48 - dest_offset)
                             }
-                        }
-                        407 | 532 | 552 | 568 | (807..=811) => {
-                            let (s0, s1, s2) = (
-                                f64::from_bits(self.val(src.0))
-                                    .negate(0, neg)
-                                    .absolute(0, abs),
-                                f64::from_bits(self.val(src.1))
-                                    .negate(1, neg)
-                                    .absolute(1, abs),
-                                f64::from_bits(self.val(src.2))
-                                    .negate(2, neg)
-                                    .absolute(2, abs),
-                            );
-                            let ret = match op {
-                                407 => f64::trunc(s0),
-                                532 => f64::mul_add(s0, s1, s2),
-                                552 => {
-                                    assert!(s0.is_normal());
-                                    s0
-                                }
-                                807 => s0 + s1,
-                                808 => s0 * s1,
-                                809 => f64::min(s0, s1),
-                                810 => f64::max(s0, s1),
-                                811 => {
-                                    let s1: u32 = self.val(src.1);
-                                    s0 * 2f64.powi(s1 as i32)
-                                }
-                                568 => {
-                                    assert!(!self.vcc.read());
-                                    f64::mul_add(s0, s1, s2)
-                                }
-                                _ => todo_instr!(instruction)?,
+                            (57..=62) | (185..=190) => {
+                                let (s0, s1): (u16, u16) = (self.val(src.0), self.val(src.1));
+                                self.cmpi(s0, s1, op - 56 - dest_offset)
+                            }
+                            (64..=71) | (192..=199) => {
+                                let (s0, s1): (u32, u32) = (self.val(src.0), self.val(src.1));
+                                self.cmpi(s0 as i32, s1 as i32, op - 64 - dest_offset)
                             }
-                            .to_bits();
-                            if self.exec.read() {
-                                self.vec_reg.write64(vdst, ret)
+                            (72..=79) | (200..=207) => {
+                                let (s0, s1): (u32, u32) = (self.val(src.0), self.val(src.1));
+                                self.cmpi(s0, s1, op - 72 - dest_offset)
                             }
-                        }
-                        306 | 313 | 596 | 584 | 585 | 588 => {
-                            let (s0, s1, s2) = (self.val(src.0), self.val(src.1), self.val(src.2));
-                            let s0 = f16::from_bits(s0).negate(0, neg).absolute(0, abs);
-                            let s1 = f16::from_bits(s1).negate(1, neg).absolute(1, abs);
-                            let s2 = f16::from_bits(s2).negate(1, neg).absolute(1, abs);
-                            let ret = match op {
-                                306 => s0 + s1,
-                                584 => f16::mul_add(s0, s1, s2),
-                                585 => f16::min(f16::min(s0, s1), s2),
-                                588 => f16::max(f16::max(s0, s1), s2),
-                                596 => s2 / s1,
-                                313 => f16::max(s0, s1),
-                                314 => f16::min(s0, s1),
-                                _ => todo_instr!(instruction)?,
+                            (80..=87) | (208..=215) => {
+                                let (s0, s1): (u64, u64) = (self.val(src.0), self.val(src.1));
+                                self.cmpi(s0 as i64, s1 as i64, op - 80 - dest_offset)
                             }
-                            .to_bits();
-                            if self.exec.read() {
-                                self.vec_reg[vdst] = ret as u32;
+                            (88..=95) | (216..=223) => {
+                                let (s0, s1): (u64, u64) = (self.val(src.0), self.val(src.1));
+                                self.cmpi(s0, s1, op - 88 - dest_offset)
                             }
+                            _ => todo_instr!(instruction)?,
+                        };
+
+                        match vdst {
+                            0..=SGPR_COUNT => self.set_sgpr_co(vdst, ret),
+                            106 => self.vcc.set_lane(ret),
+                            126 => self.exec.set_lane(ret),
+                            _ => todo_instr!(instruction)?,
                         }
-                        394 => {
-                            let s0 = f32::from_bits(self.val(src.0))
-                                .negate(0, neg)
-                                .absolute(0, abs);
-                            if self.exec.read() {
-                                self.vec_reg[vdst].mut_lo16(f16::from_f32(s0).to_bits());
-                            }
+                    }
+                    828..=830 => {
+                        let (s0, s1, _s2): (u32, u64, u64) =
+                            (self.val(src.0), self.val(src.1), self.val(src.2));
+                        let shift = s0 & 0x3f;
+                        let ret = match op {
+                            828 => s1 << shift,
+                            829 => s1 >> shift,
+                            830 => ((s1 as i64) >> shift) as u64,
+                            _ => todo_instr!(instruction)?,
+                        };
+                        if self.exec.read() {
+                            self.vec_reg.write64(vdst, ret)
                         }
-                        467 => {
-                            let s0 = f16::from_bits(self.val(src.0))
+                    }
+                    407 | 532 | 552 | 568 | (807..=811) => {
+                        let (s0, s1, s2) = (
+                            f64::from_bits(self.val(src.0))
                                 .negate(0, neg)
-                                .absolute(0, abs);
-                            if self.exec.read() {
-                                self.vec_reg[vdst] = s0.to_f32() as i16 as u32;
+                                .absolute(0, abs),
+                            f64::from_bits(self.val(src.1))
+                                .negate(1, neg)
+                                .absolute(1, abs),
+                            f64::from_bits(self.val(src.2))
+                                .negate(2, neg)
+                                .absolute(2, abs),
+                        );
+                        let ret = match op {
+                            407 => f64::trunc(s0),
+                            532 => f64::mul_add(s0, s1, s2),
+                            552 if s0.is_normal() => s0,
+                            807 => s0 + s1,
+                            808 => s0 * s1,
+                            809 => f64::min(s0, s1),
+                            810 => f64::max(s0, s1),
+                            811 => {
+                                let s1: u32 = self.val(src.1);
+                                s0 * 2f64.powi(s1 as i32)
                             }
+                            568 if !self.vcc.read() => f64::mul_add(s0, s1, s2),
+                            _ => todo_instr!(instruction)?,
                         }
-                        395 => {
-                            let s0 = f16::from_bits(self.val(src.0))
-                                .negate(0, neg)
-                                .absolute(0, abs);
-                            if self.exec.read() {
-                                self.vec_reg[vdst] = f32::from(s0).to_bits();
-                            }
+                        .to_bits();
+                        if self.exec.read() {
+                            self.vec_reg.write64(vdst, ret)
                         }
-                        785 => {
-                            let (s0, s1) = (self.val(src.0), self.val(src.1));
-                            if self.exec.read() {
-                                self.vec_reg[vdst] = (f16::from_bits(s1).to_bits() as u32) << 16
-                                    | f16::from_bits(s0).to_bits() as u32;
-                            }
+                    }
+                    306 | 313 | 596 | 584 | 585 | 588 => {
+                        let (s0, s1, s2) = (self.val(src.0), self.val(src.1), self.val(src.2));
+                        let s0 = f16::from_bits(s0).negate(0, neg).absolute(0, abs);
+                        let s1 = f16::from_bits(s1).negate(1, neg).absolute(1, abs);
+                        let s2 = f16::from_bits(s2).negate(1, neg).absolute(1, abs);
+                        let ret = match op {
+                            306 => s0 + s1,
+                            584 => f16::mul_add(s0, s1, s2),
+                            585 => f16::min(f16::min(s0, s1), s2),
+                            588 => f16::max(f16::max(s0, s1), s2),
+                            596 => s2 / s1,
+                            313 => f16::max(s0, s1),
+                            314 => f16::min(s0, s1),
+                            _ => todo_instr!(instruction)?,
                         }
-                        _ => {
-                            let (s0, s1, s2) = (self.val(src.0), self.val(src.1), self.val(src.2));
-                            match op {
-                                865 => {
-                                    if self.exec.read() {
-                                        self.vec_reg.get_lane_mut(s1 as usize)[vdst] = s0;
-                                    }
-                                    return Ok(());
-                                }
-                                864 => {
-                                    let val =
-                                        self.vec_reg.get_lane(s1 as usize)[src.0 - VGPR_COUNT];
-                                    self.write_to_sdst(vdst as u32, val);
-                                    return Ok(());
-                                }
-                                826 => {
-                                    if self.exec.read() {
-                                        self.vec_reg[vdst]
-                                            .mut_lo16(((s1 as i16) >> (s0 & 0xf)) as u16);
-                                    }
-                                    return Ok(());
+                        .to_bits();
+                        if self.exec.read() {
+                            self.vec_reg[vdst] = ret as u32;
+                        }
+                    }
+                    394 => {
+                        let s0 = f32::from_bits(self.val(src.0))
+                            .negate(0, neg)
+                            .absolute(0, abs);
+                        if self.exec.read() {
+                            self.vec_reg[vdst].mut_lo16(f16::from_f32(s0).to_bits());
+                        }
+                    }
+                    467 => {
+                        let s0 = f16::from_bits(self.val(src.0))
+                            .negate(0, neg)
+                            .absolute(0, abs);
+                        if self.exec.read() {
+                            self.vec_reg[vdst] = s0.to_f32() as i16 as u32;
+                        }
+                    }
+                    395 => {
+                        let s0 = f16::from_bits(self.val(src.0))
+                            .negate(0, neg)
+                            .absolute(0, abs);
+                        if self.exec.read() {
+                            self.vec_reg[vdst] = f32::from(s0).to_bits();
+                        }
+                    }
+                    785 => {
+                        let (s0, s1) = (self.val(src.0), self.val(src.1));
+                        if self.exec.read() {
+                            self.vec_reg[vdst] = (f16::from_bits(s1).to_bits() as u32) << 16
+                                | f16::from_bits(s0).to_bits() as u32;
+                        }
+                    }
+                    _ => {
+                        let (s0, s1, s2) = (self.val(src.0), self.val(src.1), self.val(src.2));
+                        match op {
+                            865 => {
+                                self.vec_reg.get_lane_mut(s1 as usize % 32)[vdst] = s0;
+                                return Ok(());
+                            }
+                            864 => {
+                                let val =
+                                    self.vec_reg.get_lane(s1 as usize % 32)[src.0 - VGPR_COUNT];
+                                self.write_to_sdst(vdst as u32, val);
+                                return Ok(());
+                            }
+                            826 => {
+                                if self.exec.read() {
+                                    self.vec_reg[vdst].mut_lo16(((s1 as i16) >> (s0 & 0xf)) as u16);
                                 }
-                                577 | 771 | 772 | 773 | 777 | 779 | 824 | 825 => {
-                                    let (s0, s1, s2) = (s0 as u16, s1 as u16, s2 as u16);
-                                    let ret = match op {
-                                        577 => s0 * s1 + s2,
-                                        771 => s0 + s1,
-                                        772 => s0 - s1,
-                                        773 => s0 * s1,
-                                        777 => u16::max(s0, s1),
-                                        779 => u16::min(s0, s1),
-                                        824 => s1 << s0,
-                                        825 => s1 >> s0,
-                                        _ => todo_instr!(instruction)?,
-                                    };
-                                    if self.exec.read() {
-                                        self.vec_reg[vdst].mut_lo16(ret);
-                                    }
-                                    return Ok(());
+                                return Ok(());
+                            }
+                            577 | 771 | 772 | 773 | 777 | 779 | 824 | 825 => {
+                                let (s0, s1, s2) = (s0 as u16, s1 as u16, s2 as u16);
+                                let ret = match op {
+                                    577 => s0 * s1 + s2,
+                                    771 => s0 + s1,
+                                    772 => s0 - s1,
+                                    773 => s0 * s1,
+                                    777 => u16::max(s0, s1),
+                                    779 => u16::min(s0, s1),
+                                    824 => s1 << s0,
+                                    825 => s1 >> s0,
+                                    _ => todo_instr!(instruction)?,
+                                };
+                                if self.exec.read() {
+                                    self.vec_reg[vdst].mut_lo16(ret);
                                 }
-                                778 | 780 | 781 | 782 => {
-                                    let (s0, s1, _s2) = (s0 as i16, s1 as i16, s2 as i16);
-                                    let ret = match op {
-                                        778 => i16::max(s0, s1),
-                                        780 => i16::min(s0, s1),
-                                        781 => s0 + s1,
-                                        782 => s0 - s1,
-                                        _ => todo_instr!(instruction)?,
-                                    };
-                                    if self.exec.read() {
-                                        self.vec_reg[vdst].mut_lo16(ret as u16);
-                                    }
-                                    return Ok(());
+                                return Ok(());
+                            }
+                            778 | 780 | 781 | 782 => {
+                                let (s0, s1, _s2) = (s0 as i16, s1 as i16, s2 as i16);
+                                let ret = match op {
+                                    778 => i16::max(s0, s1),
+                                    780 => i16::min(s0, s1),
+                                    781 => s0 + s1,
+                                    782 => s0 - s1,
+                                    _ => todo_instr!(instruction)?,
+                                };
+                                if self.exec.read() {
+                                    self.vec_reg[vdst].mut_lo16(ret as u16);
                                 }
-                                _ => {}
+                                return Ok(());
                             }
+                            _ => {}
+                        }
 
-                            let ret = match op {
-                                257 | 259 | 299 | 260 | 261 | 264 | 272 | 392 | 531 | 537 | 540
-                                | 551 | 567 | 796 => {
-                                    let s0 = f32::from_bits(s0).negate(0, neg).absolute(0, abs);
-                                    let s1 = f32::from_bits(s1).negate(1, neg).absolute(1, abs);
-                                    let s2 = f32::from_bits(s2).negate(2, neg).absolute(2, abs);
-                                    match op {
-                                        259 => s0 + s1,
-                                        260 => s0 - s1,
-                                        261 => s1 - s0,
-                                        264 => s0 * s1,
-                                        272 => f32::max(s0, s1),
-                                        299 => {
-                                            f32::mul_add(s0, s1, f32::from_bits(self.vec_reg[vdst]))
+                        let ret = match op {
+                            257 | 259 | 299 | 260 | 261 | 264 | 272 | 392 | 531 | 537 | 540
+                            | 551 | 567 | 796 => {
+                                let s0 = f32::from_bits(s0).negate(0, neg).absolute(0, abs);
+                                let s1 = f32::from_bits(s1).negate(1, neg).absolute(1, abs);
+                                let s2 = f32::from_bits(s2).negate(2, neg).absolute(2, abs);
+                                match op {
+                                    259 => s0 + s1,
+                                    260 => s0 - s1,
+                                    261 => s1 - s0,
+                                    264 => s0 * s1,
+                                    272 => f32::max(s0, s1),
+                                    299 => f32::mul_add(s0, s1, f32::from_bits(self.vec_reg[vdst])),
+                                    531 => f32::mul_add(s0, s1, s2),
+                                    537 => f32::min(f32::min(s0, s1), s2),
+                                    540 => f32::max(f32::max(s0, s1), s2),
+                                    551 => s2 / s1,
+                                    567 => {
+                                        let ret = f32::mul_add(s0, s1, s2);
+                                        match self.vcc.read() {
+                                            true => 2.0_f32.powi(32) * ret,
+                                            false => ret,
                                         }
-                                        531 => f32::mul_add(s0, s1, s2),
-                                        537 => f32::min(f32::min(s0, s1), s2),
-                                        540 => f32::max(f32::max(s0, s1), s2),
-                                        551 => s2 / s1,
-                                        567 => {
-                                            let ret = f32::mul_add(s0, s1, s2);
-                                            match self.vcc.read() {
-                                                true => 2.0_f32.powi(32) * ret,
-                                                false => ret,
-                                            }
-                                        }
-                                        796 => s0 * 2f32.powi(s1.to_bits() as i32),
-                                        // cnd_mask isn't a float only ALU but supports neg
-                                        257 => {
-                                            let mut cond =
-                                                WaveValue::new(s2.to_bits(), self.warp_size);
-                                            cond.default_lane = self.vcc.default_lane;
-                                            match cond.read() {
-                                                true => s1,
-                                                false => s0,
-                                            }
+                                    }
+                                    796 => s0 * 2f32.powi(s1.to_bits() as i32),
+                                    // cnd_mask isn't a float only ALU but supports neg
+                                    257 => {
+                                        let mut cond = WaveValue::new(s2.to_bits(), self.warp_size);
+                                        cond.default_lane = self.vcc.default_lane;
+                                        match cond.read() {
+                                            true => s1,
+                                            false => s0,
                                         }
-                                        392 => f32::from_bits(s0 as i32 as u32),
-                                        _ => todo_instr!(instruction)?,
                                     }
-                                    .to_bits()
+                                    392 => f32::from_bits(s0 as i32 as u32),
+                                    _ => todo_instr!(instruction)?,
+                                }
+                                .to_bits()
+                            }
+                            _ => {
+                                if neg != 0 {
+                                    todo_instr!(instruction)?
                                 }
-                                _ => {
-                                    if neg != 0 {
-                                        todo_instr!(instruction)?
+                                match op {
+                                    529 => {
+                                        let s0 = s0 as i32;
+                                        let shift = 32 - (s2 & 0x1f);
+                                        let mask: i32 = 1 << (s2 & 0x1f);
+                                        let ret = (s0 >> (s1 & 0x1f)) & (mask.wrapping_sub(1));
+                                        ((ret << shift) >> shift) as u32
                                     }
-                                    match op {
-                                        529 => {
-                                            let s0 = s0 as i32;
-                                            let shift = 32 - (s2 & 0x1f);
-                                            let mask: i32 = 1 << (s2 & 0x1f);
-                                            let ret = (s0 >> (s1 & 0x1f)) & (mask.wrapping_sub(1));
-                                            ((ret << shift) >> shift) as u32
-                                        }
-                                        522 | 541 | 544 | 814 => {
-                                            let (s0, s1, s2) = (s0 as i32, s1 as i32, s2 as i32);
-
-                                            (match op {
-                                                522 => {
-                                                    let s0 =
-                                                        sign_ext((s0 & 0xffffff) as u64, 24) as i32;
-                                                    let s1 =
-                                                        sign_ext((s1 & 0xffffff) as u64, 24) as i32;
-                                                    s0 * s1 + s2
-                                                }
-                                                541 => i32::max(i32::max(s0, s1), s2),
-                                                544 => {
-                                                    if (i32::max(i32::max(s0, s1), s2)) == s0 {
-                                                        i32::max(s1, s2)
-                                                    } else if (i32::max(i32::max(s0, s1), s2)) == s1
-                                                    {
-                                                        i32::max(s0, s2)
-                                                    } else {
-                                                        i32::max(s0, s1)
-                                                    }
-                                                }
-                                                814 => ((s0 as i64) * (s1 as i64) >> 32) as i32,
-                                                _ => todo_instr!(instruction)?,
-                                            }) as u32
-                                        }
-                                        283 => s0 & s1,
-                                        284 => s0 | s1,
-                                        285 => s0 ^ s1,
-                                        286 => !(s0 ^ s1),
-                                        523 => s0 * s1 + s2, // TODO 24 bit trunc
-                                        528 => (s0 >> s1) & ((1 << s2) - 1),
-                                        530 => (s0 & s1) | (!s0 & s2),
-                                        534 => {
-                                            let val = ((s0 as u64) << 32) | (s1 as u64);
-                                            let shift = (s2 & 0x1F) as u64;
-                                            ((val >> shift) & 0xffffffff) as u32
-                                        }
-                                        576 => s0 ^ s1 ^ s2,
-                                        580 => {
-                                            fn byte_permute(data: u64, sel: u32) -> u8 {
-                                                let bytes = data.to_ne_bytes();
-                                                match sel {
-                                                    13..=u32::MAX => 0xff,
-                                                    12 => 0x00,
-                                                    11 => ((bytes[7] & 0x80) != 0) as u8 * 0xff,
-                                                    10 => ((bytes[5] & 0x80) != 0) as u8 * 0xff,
-                                                    9 => ((bytes[3] & 0x80) != 0) as u8 * 0xff,
-                                                    8 => ((bytes[1] & 0x80) != 0) as u8 * 0xff,
-                                                    _ => bytes[sel as usize],
+                                    522 | 541 | 544 | 814 => {
+                                        let (s0, s1, s2) = (s0 as i32, s1 as i32, s2 as i32);
+
+                                        (match op {
+                                            522 => {
+                                                let s0 =
+                                                    sign_ext((s0 & 0xffffff) as u64, 24) as i32;
+                                                let s1 =
+                                                    sign_ext((s1 & 0xffffff) as u64, 24) as i32;
+                                                s0 * s1 + s2
+                                            }
+                                            541 => i32::max(i32::max(s0, s1), s2),
+                                            544 => {
+                                                if (i32::max(i32::max(s0, s1), s2)) == s0 {
+                                                    i32::max(s1, s2)
+                                                } else if (i32::max(i32::max(s0, s1), s2)) == s1 {
+                                                    i32::max(s0, s2)
+                                                } else {
+                                                    i32::max(s0, s1)
                                                 }
                                             }
-                                            let combined = ((s0 as u64) << 32) | s1 as u64;
-                                            let d0 = ((byte_permute(combined, s2 >> 24) as u32)
-                                                << 24)
-                                                | ((byte_permute(combined, (s2 >> 16) & 0xFF)
-                                                    as u32)
-                                                    << 16)
-                                                | ((byte_permute(combined, (s2 >> 8) & 0xFF)
-                                                    as u32)
-                                                    << 8)
-                                                | (byte_permute(combined, s2 & 0xFF) as u32);
-                                            d0
-                                        }
-                                        581 => (s0 ^ s1) + s2,
-                                        582 => (s0 << s1) + s2,
-                                        583 => (s0 + s1) << s2,
-                                        597 => s0 + s1 + s2,
-                                        598 => (s0 << s1) | s2,
-                                        599 => (s0 & s1) | s2,
-                                        600 => s0 | s1 | s2,
-                                        798 => {
-                                            let mut ret = s1;
-                                            (0..=31).into_iter().for_each(|i| ret += nth(s0, i));
-                                            ret
+                                            814 => ((s0 as i64) * (s1 as i64) >> 32) as i32,
+                                            _ => todo_instr!(instruction)?,
+                                        }) as u32
+                                    }
+                                    283 => s0 & s1,
+                                    284 => s0 | s1,
+                                    285 => s0 ^ s1,
+                                    286 => !(s0 ^ s1),
+                                    523 => s0 * s1 + s2, // TODO 24 bit trunc
+                                    528 => (s0 >> s1) & ((1 << s2) - 1),
+                                    530 => (s0 & s1) | (!s0 & s2),
+                                    534 => {
+                                        let val = ((s0 as u64) << 32) | (s1 as u64);
+                                        let shift = (s2 & 0x1F) as u64;
+                                        ((val >> shift) & 0xffffffff) as u32
+                                    }
+                                    576 => s0 ^ s1 ^ s2,
+                                    580 => {
+                                        fn byte_permute(data: u64, sel: u32) -> u8 {
+                                            let bytes = data.to_ne_bytes();
+                                            match sel {
+                                                13..=u32::MAX => 0xff,
+                                                12 => 0x00,
+                                                11 => ((bytes[7] & 0x80) != 0) as u8 * 0xff,
+                                                10 => ((bytes[5] & 0x80) != 0) as u8 * 0xff,
+                                                9 => ((bytes[3] & 0x80) != 0) as u8 * 0xff,
+                                                8 => ((bytes[1] & 0x80) != 0) as u8 * 0xff,
+                                                _ => bytes[sel as usize],
+                                            }
                                         }
-                                        812 => s0 * s1,
-                                        813 => ((s0 as u64) * (s1 as u64) >> 32) as u32,
-                                        _ => todo_instr!(instruction)?,
+                                        let combined = ((s0 as u64) << 32) | s1 as u64;
+                                        let d0 = ((byte_permute(combined, s2 >> 24) as u32) << 24)
+                                            | ((byte_permute(combined, (s2 >> 16) & 0xFF) as u32)
+                                                << 16)
+                                            | ((byte_permute(combined, (s2 >> 8) & 0xFF) as u32)
+                                                << 8)
+                                            | (byte_permute(combined, s2 & 0xFF) as u32);
+                                        d0
                                     }
+                                    581 => (s0 ^ s1) + s2,
+                                    582 => (s0 << s1) + s2,
+                                    583 => (s0 + s1) << s2,
+                                    597 => s0 + s1 + s2,
+                                    598 => (s0 << s1) | s2,
+                                    599 => (s0 & s1) | s2,
+                                    600 => s0 | s1 | s2,
+                                    798 => {
+                                        let mut ret = s1;
+                                        (0..=31).into_iter().for_each(|i| ret += nth(s0, i));
+                                        ret
+                                    }
+                                    812 => s0 * s1,
+                                    813 => ((s0 as u64) * (s1 as u64) >> 32) as u32,
+                                    _ => todo_instr!(instruction)?,
                                 }
-                            };
-                            if self.exec.read() {
-                                self.vec_reg[vdst] = ret;
                             }
+                        };
+                        if self.exec.read() {
+                            self.vec_reg[vdst] = ret;
                         }
-                    };
-                }
+                    }
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /* lds */
+    fn ds(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands {
+            instr,
+            op,
+            src,
+            dst,
+            ..
+        } = self.operands;
+        if !self.exec.read() {
+            return Ok(());
+        }
+        // gds
+        if (instr >> 17) & 0x1 != 0 {
+            return todo_instr!(instruction);
+        }
+        let [addr, data0, data1, _] = src;
+        let vdst = dst[0];
+        if *GLOBAL_DEBUG {
+            println!(
+                "{} op={op} addr={addr} data0={data0} data1={data1} vdst={vdst}",
+                "LDS".color("blue"),
+            );
+        }
+        if *PROFILE {
+            GLOBAL_COUNTER.lds_ops.fetch_add(1, Relaxed);
+        }
+
+        // past the end of LDS rather than wrapping around to its start
+        let lds_base = self.vec_reg[addr] as usize;
+        let single_addr = || lds_base + (instr & 0xffff) as usize;
+        let double_addr = |adj: usize| {
+            let addr0 = lds_base + (instr & 0xff) as usize * adj;
+            let addr1 = lds_base + ((instr >> 8) & 0xff) as usize * adj;
+            (addr0, addr1)
+        };
+
+        match op {
+            // load
+            54 | 118 | 254 | 255 => {
+                let dwords = match op {
+                    255 => 4,
+                    254 => 3,
+                    118 => 2,
+                    _ => 1,
+                };
+                self.loaded(Space::Lds, single_addr() as u64, 4 * dwords as u64);
+                (0..dwords).for_each(|i| {
+                    self.vec_reg[vdst + i] = self.lds.read(single_addr() + 4 * i);
+                });
+            }
+            57..=60 => {
+                let bytes = if op <= 58 { 1 } else { 2 };
+                self.loaded(Space::Lds, single_addr() as u64, bytes);
+                let val = self.lds.read_bytes(single_addr(), bytes as usize);
+                self.vec_reg[vdst] = match op {
+                    57 => val as i8 as u32,
+                    58 => val as u8 as u32,
+                    59 => val as i16 as u32,
+                    _ => val as u16 as u32,
+                };
             }
-        } else if instruction >> 26 == 0b110110 {
-            let instr = self.u64_instr();
-            if !self.exec.read() {
-                return Ok(());
+            55 => {
+                let (addr0, addr1) = double_addr(4);
+                self.loaded(Space::Lds, addr0 as u64, 4);
+                self.loaded(Space::Lds, addr1 as u64, 4);
+                self.vec_reg[vdst] = self.lds.read(addr0);
+                self.vec_reg[vdst + 1] = self.lds.read(addr1);
             }
-            let op = (instr >> 18) & 0xff;
-            assert_eq!((instr >> 17) & 0x1, 0);
-            let addr = ((instr >> 32) & 0xff) as usize;
-            let data0 = ((instr >> 40) & 0xff) as usize;
-            let data1 = ((instr >> 48) & 0xff) as usize;
-            let vdst = ((instr >> 56) & 0xff) as usize;
-            if *GLOBAL_DEBUG {
-                println!(
-                    "{} op={op} addr={addr} data0={data0} data1={data1} vdst={vdst}",
-                    "LDS".color("blue"),
-                );
+            119 => {
+                let (addr0, addr1) = double_addr(8);
+                self.loaded(Space::Lds, addr0 as u64, 8);
+                self.loaded(Space::Lds, addr1 as u64, 8);
+                self.vec_reg.write64(vdst, self.lds.read64(addr0));
+                self.vec_reg.write64(vdst + 2, self.lds.read64(addr1));
             }
-            if *PROFILE {
-                GLOBAL_COUNTER.lock().unwrap().lds_ops += 1;
+            // store
+            13 | 77 | 222 | 223 => {
+                let dwords = match op {
+                    223 => 4,
+                    222 => 3,
+                    77 => 2,
+                    _ => 1,
+                };
+                self.before_store(Space::Lds, single_addr() as u64, 4 * dwords as u64);
+                (0..dwords).for_each(|i| {
+                    self.lds
+                        .write(single_addr() + 4 * i, self.vec_reg[data0 + i]);
+                })
             }
+            30 | 31 => {
+                let bytes = op as usize - 29;
+                self.before_store(Space::Lds, single_addr() as u64, bytes as u64);
+                let data = self.vec_reg[data0].to_le_bytes();
+                self.lds.write_bytes(single_addr(), &data[..bytes]);
+            }
+            14 => {
+                let (addr0, addr1) = double_addr(4);
+                self.before_store(Space::Lds, addr0 as u64, 4);
+                self.before_store(Space::Lds, addr1 as u64, 4);
+                self.lds.write(addr0, self.vec_reg[data0]);
+                self.lds.write(addr1, self.vec_reg[data1]);
+            }
+            78 => {
+                let (addr0, addr1) = double_addr(8);
+                self.before_store(Space::Lds, addr0 as u64, 8);
+                self.before_store(Space::Lds, addr1 as u64, 8);
+                self.lds.write64(addr0, self.vec_reg.read64(data0));
+                self.lds.write64(addr1, self.vec_reg.read64(data1));
+            }
+            _ => todo_instr!(instruction)?,
+        }
+        Ok(())
+    }
 
-            let lds_base = self.vec_reg[addr];
-            let single_addr = || (lds_base + (instr & 0xffff) as u32) as usize;
-            let double_addr = |adj: u32| {
-                let addr0 = lds_base + (instr & 0xff) as u32 * adj;
-                let addr1 = lds_base + ((instr >> 8) & 0xff) as u32 * adj;
-                (addr0 as usize, addr1 as usize)
-            };
-
-            match op {
-                // load
-                54 | 118 | 255 => {
-                    let dwords = match op {
-                        255 => 4,
-                        118 => 2,
-                        _ => 1,
-                    };
-                    (0..dwords).for_each(|i| {
-                        self.vec_reg[vdst + i] = self.lds.read(single_addr() + 4 * i);
-                    });
-                }
-                60 => self.vec_reg[vdst] = self.lds.read(single_addr()) as u16 as u32,
-                55 => {
-                    let (addr0, addr1) = double_addr(4);
-                    self.vec_reg[vdst] = self.lds.read(addr0);
-                    self.vec_reg[vdst + 1] = self.lds.read(addr1);
-                }
-                119 => {
-                    let (addr0, addr1) = double_addr(8);
-                    self.vec_reg.write64(vdst, self.lds.read64(addr0));
-                    self.vec_reg.write64(vdst + 2, self.lds.read64(addr1));
+    /* global, flat and scratch */
+    fn flat(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        let Operands {
+            instr,
+            op,
+            src,
+            dst,
+            ..
+        } = self.operands;
+        if !self.exec.read() {
+            return Ok(());
+        }
+        let offset = sign_ext(instr & 0x1fff, 13);
+        let seg = (instr >> 16) & 0x3;
+        let op = op as usize;
+        let [addr, data, saddr, _] = src;
+        let vdst = dst[0];
+
+        let saddr_val: u32 = self.val(saddr);
+        let saddr_off = saddr_val == 0x7F || saddr as u32 == NULL_SRC;
+
+        match seg {
+            1 => {
+                let sve = ((instr >> 50) & 0x1) != 0;
+                if *GLOBAL_DEBUG {
+                    println!("{} offset={offset} op={op} addr={addr} data={data} saddr={saddr} vdst={vdst} sve={sve}", "SCRATCH".color("blue"));
                 }
-                // store
-                13 | 77 | 223 => {
-                    let dwords = match op {
-                        223 => 4,
-                        77 => 2,
-                        _ => 1,
-                    };
-                    (0..dwords).for_each(|i| {
-                        self.lds
-                            .write(single_addr() + 4 * i, self.vec_reg[data0 + i]);
-                    })
+                let addr = match (sve, saddr_off) {
+                    (true, true) => offset as u64 as usize,
+                    _ => todo_instr!(instruction)?,
+                };
+                match op {
+                    20..=23 => self.loaded(Space::Scratch, addr as u64, 4 * (op as u64 - 19)),
+                    26..=29 => self.before_store(Space::Scratch, addr as u64, 4 * (op as u64 - 25)),
+                    _ => {}
                 }
-                31 => {
-                    let addr = single_addr();
-                    if addr + 2 >= self.lds.data.len() {
-                        self.lds.data.resize(self.lds.data.len() + addr + 3, 0);
-                    }
-                    self.lds.data[addr..addr + 2]
-                        .iter_mut()
-                        .enumerate()
-                        .for_each(|(i, x)| {
-                            *x = (self.vec_reg[data0] as u16).to_le_bytes()[i];
-                        });
+                let sds = &mut self.sds[self.vec_reg.default_lane.unwrap()];
+                match op {
+                    // load
+                    20..=23 => (0..op - 19).for_each(|i| {
+                        self.vec_reg[vdst + i] = sds.read(addr + 4 * i);
+                    }),
+                    // store
+                    26..=29 => (0..op - 25).for_each(|i| {
+                        sds.write(addr + 4 * i, self.vec_reg[data + i]);
+                    }),
+                    _ => todo_instr!(instruction)?,
                 }
-                14 => {
-                    let (addr0, addr1) = double_addr(4);
-                    self.lds.write(addr0, self.vec_reg[data0]);
-                    self.lds.write(addr1, self.vec_reg[data1]);
+            }
+            2 => {
+                if *GLOBAL_DEBUG {
+                    println!("{} offset={offset} op={op} addr={addr} data={data} saddr={saddr} vdst={vdst}", "GLOBAL".color("blue"));
                 }
-                78 => {
-                    let (addr0, addr1) = double_addr(8);
-                    self.lds.write64(addr0, self.vec_reg.read64(data0));
-                    self.lds.write64(addr1, self.vec_reg.read64(data1));
+                if *PROFILE {
+                    GLOBAL_COUNTER.gds_ops.fetch_add(1, Relaxed);
                 }
-                _ => todo_instr!(instruction)?,
-            }
-        }
-        // global
-        // flat
-        else if instruction >> 26 == 0b110111 {
-            let instr = self.u64_instr();
-            if !self.exec.read() {
-                return Ok(());
-            }
-            let offset = sign_ext(instr & 0x1fff, 13);
-            let seg = (instr >> 16) & 0x3;
-            let op = ((instr >> 18) & 0x7f) as usize;
-            let addr = ((instr >> 32) & 0xff) as usize;
-            let data = ((instr >> 40) & 0xff) as usize;
-            let saddr = ((instr >> 48) & 0x7f) as usize;
-            let vdst = ((instr >> 56) & 0xff) as usize;
-
-            let saddr_val: u32 = self.val(saddr);
-            let saddr_off = saddr_val == 0x7F || saddr as u32 == NULL_SRC;
-
-            match seg {
-                1 => {
-                    let sve = ((instr >> 50) & 0x1) != 0;
-                    if *GLOBAL_DEBUG {
-                        println!("{} offset={offset} op={op} addr={addr} data={data} saddr={saddr} vdst={vdst} sve={sve}", "SCRATCH".color("blue"));
+
+                let addr = match saddr_off {
+                    true => self.vec_reg.read64(addr) as i64 + (offset as i64),
+                    false => {
+                        let scalar_addr = self.scalar_reg.read64(saddr);
+                        let vgpr_offset = self.vec_reg[addr];
+                        scalar_addr as i64 + vgpr_offset as i64 + offset
                     }
-                    let addr = match (sve, saddr_off) {
-                        (true, true) => offset as u64 as usize,
-                        _ => todo_instr!(instruction)?,
-                    };
+                } as u64;
+                let bytes = match op {
+                    16 | 17 | 24 => 1,
+                    18 | 19 | 25 | 35 | 37 => 2,
+                    20..=23 => 4 * (op as u64 - 19),
+                    26..=29 => 4 * (op as u64 - 25),
+                    _ => 4,
+                };
+                let store = matches!(op, 24..=29 | 37);
+                let Some(host) = self.mem_host(addr, bytes, store) else {
+                    return Ok(());
+                };
+                if store {
+                    self.before_store(Space::Global, addr, bytes);
+                }
+
+                unsafe {
                     match op {
                         // load
+                        16 => self.vec_reg[vdst] = *(host as *const u8) as u32,
+                        17 => self.vec_reg[vdst] = *(host as *const i8) as u32,
+                        18 => self.vec_reg[vdst] = *(host as *const u16) as u32,
+                        19 => self.vec_reg[vdst] = *(host as *const i16) as u32,
+
                         20..=23 => (0..op - 19).for_each(|i| {
-                            self.vec_reg[vdst + i] = self.sds.read(addr + 4 * i);
+                            self.vec_reg[vdst + i] = *(host.add(4 * i) as *const u32);
                         }),
+                        35 => self.vec_reg[vdst].mut_hi16(*(host as *const u16)),
                         // store
+                        24 => *host = self.vec_reg[data] as u8,
+                        25 => *(host as *mut u16) = self.vec_reg[data] as u16,
                         26..=29 => (0..op - 25).for_each(|i| {
-                            self.sds.write(addr + 4 * i, self.vec_reg[data + i]);
+                            *(host.add(4 * i) as *mut u32) = self.vec_reg[data + i];
                         }),
+                        37 => *(host as *mut u16) = ((self.vec_reg[data] >> 16) & 0xffff) as u16,
                         _ => todo_instr!(instruction)?,
-                    }
+                    };
                 }
-                2 => {
-                    if *GLOBAL_DEBUG {
-                        println!("{} offset={offset} op={op} addr={addr} data={data} saddr={saddr} vdst={vdst}", "GLOBAL".color("blue"));
-                    }
-                    if *PROFILE {
-                        GLOBAL_COUNTER.lock().unwrap().gds_ops += 1;
-                    }
+            }
+            _ => todo_instr!(instruction)?,
+        };
+        Ok(())
+    }
 
-                    let addr = match saddr_off {
-                        true => self.vec_reg.read64(addr) as i64 + (offset as i64),
-                        false => {
-                            let scalar_addr = self.scalar_reg.read64(saddr);
-                            let vgpr_offset = self.vec_reg[addr];
-                            scalar_addr as i64 + vgpr_offset as i64 + offset
-                        }
-                    } as u64;
+    fn illegal(&mut self) -> Result<(), i32> {
+        self.trap = Some(Trap::IllegalInstruction);
+        Ok(())
+    }
 
-                    unsafe {
-                        match op {
-                            // load
-                            16 => self.vec_reg[vdst] = *(addr as *const u8) as u32,
-                            17 => self.vec_reg[vdst] = *(addr as *const i8) as u32,
-                            18 => self.vec_reg[vdst] = *(addr as *const u16) as u32,
-                            19 => self.vec_reg[vdst] = *(addr as *const i16) as u32,
-
-                            20..=23 => (0..op - 19).for_each(|i| {
-                                self.vec_reg[vdst + i] = *((addr + 4 * i as u64) as *const u32);
-                            }),
-                            35 => self.vec_reg[vdst].mut_hi16(*(addr as *const u16)),
-                            // store
-                            24 => *(addr as *mut u8) = self.vec_reg[data] as u8,
-                            25 => *(addr as *mut u16) = self.vec_reg[data] as u16,
-                            26..=29 => (0..op - 25).for_each(|i| {
-                                *((addr + 4 * i as u64) as u64 as *mut u32) =
-                                    self.vec_reg[data + i];
-                            }),
-                            37 => {
-                                *(addr as *mut u16) = ((self.vec_reg[data] >> 16) & 0xffff) as u16
-                            }
-                            _ => todo_instr!(instruction)?,
-                        };
+    fn unimplemented(&mut self) -> Result<(), i32> {
+        let instruction = self.operands.instr as u32;
+        todo_instr!(instruction)
+    }
+
+    /* note an LDS or scratch access for CHECK_UNINIT */
+    fn access(&mut self, space: Space, addr: u64, len: u64, store: bool) {
+        if let Some(accesses) = self.accesses.as_mut() {
+            accesses.push(Access {
+                space,
+                lane: self.vec_reg.default_lane.unwrap_or(0),
+                addr,
+                len,
+                store,
+            });
+        }
+    }
+
+    fn loaded(&mut self, space: Space, addr: u64, len: u64) {
+        self.access(space, addr, len, false);
+    }
+
+    /* remember what a store is about to overwrite for the undo log, trace and watched ranges */
+    fn before_store(&mut self, space: Space, addr: u64, len: u64) {
+        if space != Space::Global {
+            self.access(space, addr, len, true);
+        }
+        if let Some(stores) = self.stores.take() {
+            let old = self.mem_bytes(Target::Memory { space, addr, len });
+            let lane = self.vec_reg.default_lane.unwrap_or(0);
+            stores.push(Delta::Memory {
+                space,
+                lane,
+                addr,
+                old,
+            });
+            self.stores = Some(stores);
+        }
+        let Some(watch) = self.watch.as_deref() else {
+            return;
+        };
+        for (point, what) in watch.overlaps(space, addr, len) {
+            let old = self.mem_bytes(what);
+            self.watch
+                .as_mut()
+                .unwrap()
+                .pending
+                .push((point, what, old));
+        }
+    }
+
+    /* watchpoint, location, old and new bytes of every watched store this lane made */
+    pub fn watched_stores(&mut self) -> Vec<(usize, Target, Vec<u8>, Vec<u8>)> {
+        let pending = match self.watch.as_deref_mut() {
+            Some(watch) => std::mem::take(&mut watch.pending),
+            None => return vec![],
+        };
+        let stores = pending.into_iter().map(|(point, what, old)| {
+            let new = self.mem_bytes(what);
+            (point, what, old, new)
+        });
+        stores.collect()
+    }
+
+    fn mem_bytes(&self, what: Target) -> Vec<u8> {
+        let Target::Memory { space, addr, len } = what else {
+            return vec![];
+        };
+        let store = match space {
+            Space::Global => {
+                return match self.memory.host(addr, len, false) {
+                    Some(host) => {
+                        unsafe { std::slice::from_raw_parts(host, len as usize) }.to_vec()
                     }
+                    None => vec![0; len as usize],
                 }
-                _ => todo_instr!(instruction)?,
-            };
-        } else {
-            todo_instr!(instruction)?;
+            }
+            Space::Lds => &*self.lds,
+            Space::Scratch => &self.sds[self.vec_reg.default_lane.unwrap()],
+        };
+        (addr..addr + len)
+            .map(|i| store.data.get(i as usize).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /* where a global or SMEM access goes, None once the instruction trapped */
+    fn mem_host(&mut self, addr: u64, bytes: u64, store: bool) -> Option<*mut u8> {
+        let host = self.memory.host(addr, bytes, store);
+        if host.is_none() {
+            self.trap = Some(Trap::MemoryViolation(addr));
         }
-        Ok(())
+        host.filter(|_| self.trap.is_none())
     }
 
     fn cmpf<T>(&self, s0: T, s1: T, offset: u32) -> bool
@@ -1659,6 +1889,7 @@ impl<'a> Thread<'a> {
             4 => s0 > s1,
             5 => s0 != s1,
             6 => s0 >= s1,
+            7 => !s0.is_nan() && !s1.is_nan(),
             8 => s0.is_nan() || s1.is_nan(),
             9 => !(s0 >= s1),
             10 => !(s0 != s1),
@@ -1779,18 +2010,47 @@ impl<'a> Thread<'a> {
         match code {
             106 => self.vcc.value,
             126 => self.exec.value,
+            107..=123 | 125 | 127 => self.scalar_reg[code as usize],
             128 => 0,
             124 => NULL_SRC,
+            // a 32-bit operand gets the high dword, where the aperture sits
+            235..=238 => (self._aperture(code) >> 32) as u32,
+            239 => 0,
+            251 => (self.vcc.value == 0) as u32,
+            252 => (self.exec.value == 0) as u32,
+            253 => *self.scc,
             255 => self.simm(),
-            _ => todo!("resolve_src={code}"),
+            _ => {
+                self.trap = Some(Trap::IllegalInstruction);
+                0
+            }
+        }
+    }
+    fn _aperture(&self, code: u32) -> u64 {
+        match code {
+            235 => SHARED_BASE,
+            236 => SHARED_BASE | 0xffffffff,
+            237 => PRIVATE_BASE,
+            _ => PRIVATE_BASE | 0xffffffff,
         }
     }
     fn write_to_sdst(&mut self, sdst_bf: u32, val: u32) {
         match sdst_bf as usize {
-            0..=SGPR_COUNT => self.scalar_reg[sdst_bf as usize] = val,
+            0..=SGPR_COUNT | 107..=123 | 125 | 127 => self.scalar_reg[sdst_bf as usize] = val,
             106 => self.vcc.value = val,
             126 => self.exec.value = val,
-            _ => todo!("write to sdst {}", sdst_bf),
+            124 => {}
+            _ => self.trap = Some(Trap::IllegalInstruction),
+        }
+    }
+    fn write_to_sdst64(&mut self, sdst_bf: u32, val: u64) {
+        match sdst_bf as usize {
+            106 | 126 => {
+                self.write_to_sdst(sdst_bf, val as u32);
+                self.write_to_sdst(sdst_bf + 1, (val >> 32) as u32);
+            }
+            124 => {}
+            _ => self.scalar_reg.write64(sdst_bf as usize, val),
         }
     }
     fn set_sgpr_co(&mut self, idx: usize, val: bool) {
@@ -1804,21 +2064,14 @@ impl<'a> Thread<'a> {
     }
 
     fn simm(&mut self) -> u32 {
-        if let Some(val) = self.simm {
-            val
-        } else {
-            let val = self.stream[self.pc_offset + 1];
-            self.simm = Some(val);
-            self.pc_offset += 1;
-            val
+        match self.operands.literal {
+            Some(val) => val,
+            None => {
+                self.trap = Some(Trap::IllegalInstruction);
+                0
+            }
         }
     }
-    fn u64_instr(&mut self) -> u64 {
-        let msb = self.stream[self.pc_offset + 1] as u64;
-        let instr = msb << 32 | self.stream[self.pc_offset] as u64;
-        self.pc_offset += 1;
-        return instr;
-    }
 }
 
 pub trait ALUSrc<T> {
@@ -1848,6 +2101,7 @@ impl ALUSrc<u16> for Thread<'_> {
                 .1,
             )
             .to_bits(),
+            248 => 0x3118,
             _ => self._common_srcs(code as u32) as u16,
         }
     }
@@ -1874,6 +2128,7 @@ impl ALUSrc<u32> for Thread<'_> {
             .unwrap()
             .1
             .to_bits(),
+            248 => 0x3e22f983,
             _ => self._common_srcs(code as u32),
         }
     }
@@ -1900,6 +2155,12 @@ impl ALUSrc<u64> for Thread<'_> {
             .unwrap()
             .1
             .to_bits(),
+            248 => 0x3fc45f306dc9c882,
+            106 | 126 => {
+                ((self.scalar_reg[code + 1] as u64) << 32) | self._common_srcs(code as u32) as u64
+            }
+            107..=123 | 125 | 127 => self.scalar_reg.read64(code),
+            235..=238 => self._aperture(code as u32),
             _ => self._common_srcs(code as u32) as u64,
         }
     }
@@ -1926,6 +2187,87 @@ mod test_alu_utils {
         assert_eq!(thread.vcc.value, 195935983);
     }
 
+    #[test]
+    fn test_write_to_sdst_special() {
+        let mut thread = _helper_test_thread();
+        thread.write_to_sdst(125, 42);
+        thread.write_to_sdst(107, 1);
+        thread.write_to_sdst(127, 2);
+        thread.write_to_sdst(110, 3);
+        thread.write_to_sdst(124, 4);
+        assert_eq!(thread.scalar_reg[125], 42);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 125), 42);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 107), 1);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 127), 2);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 110), 3);
+        assert_eq!(thread.scalar_reg[124], 0);
+    }
+
+    #[test]
+    fn test_write_to_sdst64_vcc() {
+        let mut thread = _helper_test_thread();
+        thread.write_to_sdst64(106, 0x1234_5678_9abc_def0);
+        assert_eq!(thread.vcc.value, 0x9abc_def0);
+        assert_eq!(thread.scalar_reg[107], 0x1234_5678);
+        assert_eq!(ALUSrc::<u64>::val(&mut thread, 106), 0x1234_5678_9abc_def0);
+    }
+
+    #[test]
+    fn test_src_scc_vccz_execz() {
+        let mut thread = _helper_test_thread();
+        *thread.scc = 1;
+        thread.vcc.value = 0;
+        thread.exec.value = 1;
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 253), 1);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 251), 1);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 252), 0);
+    }
+
+    #[test]
+    fn test_apertures() {
+        let mut thread = _helper_test_thread();
+        assert_eq!(ALUSrc::<u64>::val(&mut thread, 235), SHARED_BASE);
+        assert_eq!(
+            ALUSrc::<u64>::val(&mut thread, 236),
+            SHARED_BASE | 0xffffffff
+        );
+        assert_eq!(ALUSrc::<u64>::val(&mut thread, 237), PRIVATE_BASE);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 235), 0x10000);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 237), 0x20000);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 238), 0x20000);
+        assert_eq!(ALUSrc::<u32>::val(&mut thread, 239), 0);
+    }
+
+    #[test]
+    fn test_inline_inv_2pi() {
+        let mut thread = _helper_test_thread();
+        let val: u16 = thread.val(248);
+        assert_eq!(f16::from_bits(val), f16::from_f32(0.15915494));
+        let val: u32 = thread.val(248);
+        assert_eq!(f32::from_bits(val), 0.15915494);
+        let val: u64 = thread.val(248);
+        assert_eq!(f64::from_bits(val), 0.15915494309189532);
+    }
+
+    #[test]
+    fn test_inline_f64() {
+        let mut thread = _helper_test_thread();
+        [
+            (240, 0.5),
+            (241, -0.5),
+            (242, 1.0),
+            (245, -2.0),
+            (247, -4.0),
+        ]
+        .iter()
+        .for_each(|(code, expected)| {
+            let val: u64 = thread.val(*code);
+            assert_eq!(f64::from_bits(val), *expected);
+        });
+        let val: u64 = thread.val(193);
+        assert_eq!(val as i64, -1);
+    }
+
     #[test]
     fn test_clz_i32_u32() {
         let thread = _helper_test_thread();
@@ -1962,6 +2304,22 @@ mod test_alu_utils {
     }
 }
 
+#[cfg(test)]
+mod test_smem {
+    use super::*;
+
+    #[test]
+    fn test_unimplemented_op() {
+        let mut thread = _helper_test_thread();
+        // s_buffer_load_b32 and op 30, whose 4 << op size doesn't fit
+        for word in [0xF4200000, 0xF4780000] {
+            thread.operands = Operands::decode(&[word, 0xF8000000]);
+            assert!(thread.interpret().is_err());
+            assert_eq!(thread.trap, None);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_sop1 {
     use super::*;
@@ -1991,6 +2349,23 @@ mod test_sop1 {
         assert_eq!(thread.scalar_reg[0], 0b11111111110111111110111111111111);
     }
 
+    #[test]
+    fn test_s_mov_b32_m0() {
+        let mut thread = _helper_test_thread();
+        thread.scalar_reg[1] = 42;
+        r(&vec![0xBEFD0001, 0xBE82007D, END_PRG], &mut thread);
+        assert_eq!(thread.scalar_reg[125], 42);
+        assert_eq!(thread.scalar_reg[2], 42);
+    }
+
+    #[test]
+    fn test_s_mov_b64_vcc() {
+        let mut thread = _helper_test_thread();
+        r(&vec![0xBEEA01C1, END_PRG], &mut thread);
+        assert_eq!(thread.vcc.value, u32::MAX);
+        assert_eq!(thread.scalar_reg[107], u32::MAX);
+    }
+
     #[test]
     fn test_s_mov_b32() {
         let mut thread = _helper_test_thread();
@@ -2086,6 +2461,30 @@ mod test_sopk {
         r(&vec![0xB1862DB4, END_PRG], &mut thread);
         assert_eq!(*thread.scc, 1);
     }
+
+    #[test]
+    fn test_setreg_getreg_mode() {
+        let mut thread = _helper_test_thread();
+        // s_setreg_imm32_b32 hwreg(HW_REG_MODE, 12, 7), 0x5
+        r(&vec![0xB9803301, 0x5, END_PRG], &mut thread);
+        assert_eq!(thread.hw_reg[1], 0x5 << 12);
+        // s_getreg_b32 s4, hwreg(HW_REG_MODE, 14, 1)
+        r(&vec![0xB8840381, END_PRG], &mut thread);
+        assert_eq!(thread.scalar_reg[4], 1);
+        thread.scalar_reg[5] = 0xff;
+        // s_setreg_b32 hwreg(HW_REG_TRAPSTS, 0, 4), s5
+        r(&vec![0xB9051803, END_PRG], &mut thread);
+        assert_eq!(thread.hw_reg[3], 0xf);
+    }
+
+    #[test]
+    fn test_getreg_status() {
+        let mut thread = _helper_test_thread();
+        *thread.scc = 1;
+        // s_getreg_b32 s4, hwreg(HW_REG_STATUS)
+        r(&vec![0xB884F802, END_PRG], &mut thread);
+        assert_eq!(thread.scalar_reg[4], 1 | (1 << 10));
+    }
 }
 
 #[cfg(test)]
@@ -3430,6 +3829,14 @@ mod test_vopp {
         assert_eq!(thread.vec_reg[3], 3205627904);
     }
 
+    #[test]
+    fn test_pk_add_f16_inline_inv_2pi() {
+        let mut thread = _helper_test_thread();
+        thread.vec_reg[1] = 0;
+        r(&vec![0xCC0F4000, 0x0801F101, END_PRG], &mut thread);
+        assert_eq!(thread.vec_reg[0], 0x31183118);
+    }
+
     #[test]
     fn test_packed_opsel_000_op_000() {
         let mut thread = _helper_test_thread();
@@ -3634,6 +4041,14 @@ mod test_flat {
     use super::*;
     use std::alloc::{alloc, handle_alloc_error, Layout};
 
+    #[test]
+    fn test_global_load_null() {
+        let mut thread = _helper_test_thread();
+        // global_load_b32 v1, v[2:3], off
+        r(&vec![0xDC520000, 0x017C0002, END_PRG], &mut thread);
+        assert_eq!(thread.trap, Some(Trap::MemoryViolation(0)));
+    }
+
     #[test]
     fn test_scratch_swap_values() {
         let mut thread = _helper_test_thread();
@@ -3737,11 +4152,62 @@ mod test_lds {
         r(&vec![0xD83403E8, 0x00000900, END_PRG], &mut thread);
         assert_eq!(thread.lds.read(1000), 69);
     }
+
+    #[test]
+    fn test_ds_unaligned() {
+        let mut thread = _helper_test_thread();
+        thread.vec_reg[0] = 0;
+        thread.vec_reg[9] = 0xABCD80;
+        (4..7).for_each(|i| thread.vec_reg[i] = i as u32);
+        r(
+            &vec![
+                0xD8780001, 0x00000900, // ds_store_b8 v0, v9 offset:1
+                0xD8E40001, 0x01000000, // ds_load_i8 v1, v0 offset:1
+                0xD8E80001, 0x02000000, // ds_load_u8 v2, v0 offset:1
+                0xD87C0003, 0x00000900, // ds_store_b16 v0, v9 offset:3
+                0xD8EC0003, 0x03000000, // ds_load_i16 v3, v0 offset:3
+                0xDB780007, 0x00000400, // ds_store_b96 v0, v[4:6] offset:7
+                0xDBF80007, 0x0A000000, // ds_load_b96 v[10:12], v0 offset:7
+                END_PRG,
+            ],
+            &mut thread,
+        );
+        assert_eq!(thread.vec_reg[1], 0xFFFFFF80);
+        assert_eq!(thread.vec_reg[2], 0x80);
+        assert_eq!(thread.vec_reg[3], 0xFFFFCD80);
+        assert_eq!(&thread.lds.data[..8], [0, 0x80, 0, 0x80, 0xCD, 0, 0, 4]);
+        assert_eq!([10, 11, 12].map(|i| thread.vec_reg[i]), [4, 5, 6]);
+    }
+
+    #[test]
+    fn test_ds_out_of_range() {
+        let mut thread = _helper_test_thread();
+        *thread.lds = VecDataStore::fixed(16);
+        thread.vec_reg[0] = 8;
+        (4..8).for_each(|i| thread.vec_reg[i] = i as u32);
+        // ds_store_b128 v0, v[4:7] keeps the two dwords inside LDS
+        r(&vec![0xDB7C0000, 0x00000400, END_PRG], &mut thread);
+        assert_eq!(thread.lds.data.len(), 16);
+        assert_eq!((thread.lds.read(8), thread.lds.read(12)), (4, 5));
+        // ds_load_b64 v[10:11], v0 offset:4 reads zero past the end
+        r(&vec![0xD9D80004, 0x0A000000, END_PRG], &mut thread);
+        assert_eq!([thread.vec_reg[10], thread.vec_reg[11]], [5, 0]);
+        // ds_store_b64 v0, v[4:5] offset:2 drops the dword straddling the end whole
+        (thread.vec_reg[4], thread.vec_reg[5]) = (0x11223344, 0x55667788);
+        r(&vec![0xD9340002, 0x00000400, END_PRG], &mut thread);
+        assert_eq!(thread.lds.data[10..], [0x44, 0x33, 0x22, 0x11, 0, 0]);
+        // ds_load_b32 v10, v0 offset:5 doesn't mix LDS bytes with zeros
+        r(&vec![0xD8D80005, 0x0A000000, END_PRG], &mut thread);
+        assert_eq!(thread.vec_reg[10], 0);
+        thread.vec_reg[0] = u32::MAX;
+        r(&vec![0xD8D8FFFF, 0x0A000000, END_PRG], &mut thread);
+        assert_eq!(thread.vec_reg[10], 0);
+    }
 }
 #[allow(dead_code)]
 fn r(prg: &Vec<u32>, thread: &mut Thread) {
     let mut pc = 0;
-    let instructions = prg.to_vec();
+    let instructions: &'static [u32] = prg.clone().leak();
     thread.pc_offset = 0;
     if thread.exec.value == 0 {
         thread.exec.value = u32::MAX;
@@ -3756,9 +4222,8 @@ fn r(prg: &Vec<u32>, thread: &mut Thread) {
             continue;
         }
         thread.pc_offset = 0;
-        thread.stream = instructions[pc..instructions.len()].to_vec();
+        thread.operands = Operands::decode(&instructions[pc..]);
         thread.interpret().unwrap();
-        thread.simm = None;
         if thread.vcc.mutations.is_some() {
             thread.vcc.apply_muts();
             thread.vcc.mutations = None;
@@ -3771,18 +4236,22 @@ fn r(prg: &Vec<u32>, thread: &mut Thread) {
             wv.apply_muts();
             thread.scalar_reg[*idx] = wv.value;
         }
-        pc = ((pc as isize) + 1 + (thread.pc_offset as isize)) as usize;
+        let len = crate::decode::instr_len(&instructions[pc..]);
+        pc = ((pc + len) as isize + (thread.pc_offset as isize)) as usize;
     }
 }
 fn _helper_test_thread() -> Thread<'static> {
+    use crate::memory::Raw;
+    static RAW: Raw = Raw { allocations: None };
     let static_lds: &'static mut VecDataStore = Box::leak(Box::new(VecDataStore::new()));
     let static_sgpr: &'static mut Vec<u32> = Box::leak(Box::new(vec![0; 256]));
     let static_vgpr: &'static mut VGPR = Box::leak(Box::new(VGPR::new()));
     let static_scc: &'static mut u32 = Box::leak(Box::new(0));
     let static_exec: &'static mut WaveValue = Box::leak(Box::new(WaveValue::new(u32::MAX, 32)));
     let static_vcc: &'static mut WaveValue = Box::leak(Box::new(WaveValue::new(0, 32)));
-    let static_sds: &'static mut VecDataStore = Box::leak(Box::new(VecDataStore::new()));
+    let static_sds: &'static mut [VecDataStore] = vec![VecDataStore::new(); 32].leak();
     let static_co: &'static mut Option<(usize, WaveValue)> = Box::leak(Box::new(None));
+    let static_hw_reg: &'static mut [u32; 64] = Box::leak(Box::new([0; 64]));
 
     let thread = Thread {
         scalar_reg: static_sgpr,
@@ -3792,12 +4261,17 @@ fn _helper_test_thread() -> Thread<'static> {
         exec: static_exec,
         lds: static_lds,
         sds: static_sds,
-        simm: None,
         pc_offset: 0,
-        stream: vec![],
+        operands: Operands::default(),
         sgpr_co: static_co,
         warp_size: 32,
         scalar: false,
+        hw_reg: static_hw_reg,
+        trap: None,
+        watch: None,
+        stores: None,
+        accesses: None,
+        memory: &RAW,
     };
     thread.vec_reg.default_lane = Some(0);
     thread.vcc.default_lane = Some(0);
@@ -0,0 +1,656 @@
+/*
+ * TRACE=<file> writes one record per executed instruction: workgroup, wave, pc, instruction word,
+ * mnemonic, exec mask and the new value of every register and memory byte it wrote, per lane.
+ * Files ending in .bin get a compact binary form, anything else JSON Lines.
+ * TRACE_FILTER=workgroup:1.0.0,wave:0,lane:3,pc:0x10-0x40 keeps the matching instructions and,
+ * with a lane, only that lane's VGPR and memory writes. Launches run serially while tracing.
+ */
+use crate::capture::Reader;
+use crate::memory::{Memory, VecDataStore};
+use crate::undo::Delta;
+use crate::watch::{number, Space};
+use crate::work_group::WaveState;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::sync::{Arc, Mutex};
+
+const MAGIC: &[u8; 8] = b"REMUTRC1";
+
+lazy_static::lazy_static! {
+    pub static ref TRACE: Option<Trace> = std::env::var("TRACE").ok().map(|path| {
+        let filter = std::env::var("TRACE_FILTER")
+            .map_or(Some(Filter::default()), |f| Filter::parse(&f))
+            .expect("TRACE_FILTER must list workgroup:<x>[.<y>.<z>], wave:<n>, lane:<n> or pc:<start>[-<end>]");
+        Trace::open(&path, filter).unwrap_or_else(|e| panic!("can't open TRACE={path}: {e}"))
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg {
+    Sgpr(usize),
+    Vgpr(usize),
+    Scc,
+    Vcc,
+    Exec,
+    Hw(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Write {
+    /* lane is set for VGPRs */
+    Reg {
+        reg: Reg,
+        lane: Option<usize>,
+        value: u32,
+    },
+    Memory {
+        space: Space,
+        lane: usize,
+        addr: u64,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub workgroup: [u32; 3],
+    pub wave: usize,
+    /* byte offset into the kernel */
+    pub pc: usize,
+    pub word: u32,
+    pub mnemonic: String,
+    pub exec: u32,
+    pub writes: Vec<Write>,
+}
+
+/* which instructions and lanes get traced, every bound is optional and pc ranges include both ends */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub workgroup: Option<[u32; 3]>,
+    pub wave: Option<usize>,
+    pub lane: Option<usize>,
+    pub pc: Option<(usize, usize)>,
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut filter = Filter::default();
+        for term in s.split(',').filter(|t| !t.is_empty()) {
+            let (key, value) = term.split_once(':')?;
+            let n = |s: &str| number(s).map(|n| n as usize);
+            match key {
+                "workgroup" => {
+                    let mut id = [0; 3];
+                    let parts = value.split('.').collect::<Vec<_>>();
+                    if parts.len() > 3 {
+                        return None;
+                    }
+                    for (d, p) in id.iter_mut().zip(parts) {
+                        *d = number(p)? as u32;
+                    }
+                    filter.workgroup = Some(id);
+                }
+                "wave" => filter.wave = Some(n(value)?),
+                "lane" => filter.lane = Some(n(value).filter(|&l| l < 32)?),
+                "pc" => {
+                    let (start, end) = value.split_once('-').unwrap_or((value, value));
+                    filter.pc = Some((n(start)?, n(end)?));
+                }
+                _ => return None,
+            }
+        }
+        Some(filter)
+    }
+
+    pub fn keeps(&self, workgroup: [u32; 3], wave: usize, pc: usize) -> bool {
+        self.workgroup.is_none_or(|w| w == workgroup)
+            && self.wave.is_none_or(|w| w == wave)
+            && self
+                .pc
+                .is_none_or(|(start, end)| (start..=end).contains(&pc))
+    }
+
+    fn keeps_write(&self, write: &Write) -> bool {
+        let lane = match write {
+            Write::Reg { lane, .. } => *lane,
+            Write::Memory { lane, .. } => Some(*lane),
+        };
+        match (self.lane, lane) {
+            (Some(want), Some(lane)) => want == lane,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Trace {
+    pub filter: Filter,
+    binary: bool,
+    out: Arc<Mutex<dyn io::Write + Send>>,
+}
+
+impl Trace {
+    pub fn new(out: Arc<Mutex<dyn io::Write + Send>>, binary: bool, filter: Filter) -> Self {
+        if binary {
+            out.lock().unwrap().write_all(MAGIC).unwrap();
+        }
+        Trace {
+            filter,
+            binary,
+            out,
+        }
+    }
+
+    fn open(path: &str, filter: Filter) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Trace::new(
+            Arc::new(Mutex::new(file)),
+            path.ends_with(".bin"),
+            filter,
+        ))
+    }
+
+    pub fn record(&self, mut record: Record) {
+        record.writes.retain(|w| self.filter.keeps_write(w));
+        let bytes = match self.binary {
+            true => record.to_bytes(),
+            false => format!("{}\n", record.to_json()).into_bytes(),
+        };
+        if let Err(e) = self.out.lock().unwrap().write_all(&bytes) {
+            println!("[remu] trace: {e}");
+        }
+    }
+
+    pub fn flush(&self) {
+        if let Err(e) = self.out.lock().unwrap().flush() {
+            println!("[remu] trace: {e}");
+        }
+    }
+}
+
+/* the new values of everything the deltas of an instruction say it wrote */
+pub fn writes(
+    deltas: &[Delta],
+    wave: &WaveState,
+    lds: &VecDataStore,
+    memory: &dyn Memory,
+) -> Vec<Write> {
+    let reg = |reg, lane, value| Write::Reg { reg, lane, value };
+    let writes = deltas.iter().map(|delta| match *delta {
+        Delta::Sgpr(idx, _) => reg(Reg::Sgpr(idx), None, wave.scalar_reg[idx]),
+        Delta::Vgpr { lane, idx, .. } => {
+            reg(Reg::Vgpr(idx), Some(lane), wave.vec_reg.get_lane(lane)[idx])
+        }
+        Delta::Scc(_) => reg(Reg::Scc, None, wave.scc),
+        Delta::Vcc(_) => reg(Reg::Vcc, None, wave.vcc.value),
+        Delta::Exec(_) => reg(Reg::Exec, None, wave.exec.value),
+        Delta::HwReg(idx, _) => reg(Reg::Hw(idx), None, wave.hw_reg[idx]),
+        Delta::Memory {
+            space,
+            lane,
+            addr,
+            ref old,
+        } => {
+            let data = match space {
+                // the address was checked when the store ran
+                Space::Global => match memory.host(addr, old.len() as u64, false) {
+                    Some(host) => unsafe { std::slice::from_raw_parts(host, old.len()).to_vec() },
+                    None => vec![0; old.len()],
+                },
+                Space::Lds => bytes(lds, addr, old.len()),
+                Space::Scratch => bytes(&wave.sds[lane], addr, old.len()),
+            };
+            Write::Memory {
+                space,
+                lane,
+                addr,
+                data,
+            }
+        }
+    });
+    writes.collect()
+}
+
+fn bytes(store: &VecDataStore, addr: u64, len: usize) -> Vec<u8> {
+    (addr as usize..addr as usize + len)
+        .map(|i| store.data.get(i).copied().unwrap_or(0))
+        .collect()
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reg::Sgpr(idx) => write!(f, "s{idx}"),
+            Reg::Vgpr(idx) => write!(f, "v{idx}"),
+            Reg::Scc => write!(f, "scc"),
+            Reg::Vcc => write!(f, "vcc"),
+            Reg::Exec => write!(f, "exec"),
+            Reg::Hw(idx) => write!(f, "hwreg{idx}"),
+        }
+    }
+}
+
+fn space_name(space: Space) -> String {
+    format!("{space:?}").to_lowercase()
+}
+
+impl Record {
+    pub fn to_json(&self) -> String {
+        let writes = self.writes.iter().map(|w| match w {
+            Write::Reg {
+                reg,
+                lane: Some(lane),
+                value,
+            } => format!("{{\"reg\":\"{reg}\",\"lane\":{lane},\"value\":{value}}}"),
+            Write::Reg { reg, value, .. } => format!("{{\"reg\":\"{reg}\",\"value\":{value}}}"),
+            Write::Memory {
+                space,
+                lane,
+                addr,
+                data,
+            } => {
+                let data = data.iter().map(|b| format!("{b:02x}")).collect::<String>();
+                let space = space_name(*space);
+                format!(
+                    "{{\"mem\":\"{space}\",\"lane\":{lane},\"addr\":{addr},\"data\":\"{data}\"}}"
+                )
+            }
+        });
+        let [x, y, z] = self.workgroup;
+        format!(
+            "{{\"workgroup\":[{x},{y},{z}],\"wave\":{},\"pc\":{},\"word\":{},\"mnemonic\":\"{}\",\"exec\":{},\"writes\":[{}]}}",
+            self.wave,
+            self.pc,
+            self.word,
+            self.mnemonic,
+            self.exec,
+            writes.collect::<Vec<_>>().join(",")
+        )
+    }
+
+    /*
+     * workgroup x/y/z, wave, pc, word, exec as u32, the mnemonic as u16 length and bytes, u32 write
+     * count, then per write a kind byte, a lane byte (0xff for none) and a u16 that is the register
+     * index with a u32 value for registers or the length with a u64 address and the bytes for memory
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = vec![];
+        let words = [self.wave as u32, self.pc as u32, self.word, self.exec];
+        for w in self.workgroup.iter().chain(&words) {
+            b.extend(w.to_le_bytes());
+        }
+        b.extend((self.mnemonic.len() as u16).to_le_bytes());
+        b.extend(self.mnemonic.as_bytes());
+        b.extend((self.writes.len() as u32).to_le_bytes());
+        for w in &self.writes {
+            match w {
+                Write::Reg { reg, lane, value } => {
+                    let (kind, idx) = match *reg {
+                        Reg::Sgpr(idx) => (0, idx),
+                        Reg::Vgpr(idx) => (1, idx),
+                        Reg::Scc => (2, 0),
+                        Reg::Vcc => (3, 0),
+                        Reg::Exec => (4, 0),
+                        Reg::Hw(idx) => (5, idx),
+                    };
+                    b.extend([kind, lane.map_or(0xff, |l| l as u8)]);
+                    b.extend((idx as u16).to_le_bytes());
+                    b.extend(value.to_le_bytes());
+                }
+                Write::Memory {
+                    space,
+                    lane,
+                    addr,
+                    data,
+                } => {
+                    b.extend([6 + *space as u8, *lane as u8]);
+                    b.extend((data.len() as u16).to_le_bytes());
+                    b.extend(addr.to_le_bytes());
+                    b.extend(data);
+                }
+            }
+        }
+        b
+    }
+
+    fn from_reader(r: &mut Reader) -> Option<Record> {
+        let u16 = |r: &mut Reader| Some(u16::from_le_bytes(r.take(2)?.try_into().ok()?));
+        let workgroup = [r.u32()?, r.u32()?, r.u32()?];
+        let (wave, pc, word, exec) = (r.u32()?, r.u32()?, r.u32()?, r.u32()?);
+        let len = u16(r)?;
+        let mnemonic = String::from_utf8(r.take(len as usize)?.to_vec()).ok()?;
+        let writes = (0..r.u32()?)
+            .map(|_| {
+                let (kind, lane) = match r.take(2)? {
+                    &[kind, lane] => (kind, lane as usize),
+                    _ => return None,
+                };
+                let idx = u16(r)? as usize;
+                let space = match kind {
+                    0..=5 => {
+                        let reg = [
+                            Reg::Sgpr(idx),
+                            Reg::Vgpr(idx),
+                            Reg::Scc,
+                            Reg::Vcc,
+                            Reg::Exec,
+                            Reg::Hw(idx),
+                        ][kind as usize];
+                        let lane = (lane != 0xff).then_some(lane);
+                        let value = r.u32()?;
+                        return Some(Write::Reg { reg, lane, value });
+                    }
+                    6 => Space::Global,
+                    7 => Space::Lds,
+                    8 => Space::Scratch,
+                    _ => return None,
+                };
+                let addr = r.u64()?;
+                let data = r.take(idx)?.to_vec();
+                Some(Write::Memory {
+                    space,
+                    lane,
+                    addr,
+                    data,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Record {
+            workgroup,
+            wave: wave as usize,
+            pc: pc as usize,
+            word,
+            mnemonic,
+            exec,
+            writes,
+        })
+    }
+
+    fn from_json(line: &str) -> Option<Record> {
+        let json = Parser {
+            s: line.as_bytes(),
+            off: 0,
+        }
+        .value()?;
+        let num = |json: &Json, key| json.get(key)?.num();
+        let mut workgroup = [0; 3];
+        match json.get("workgroup")? {
+            Json::Arr(ids) if ids.len() == 3 => {
+                for (d, id) in workgroup.iter_mut().zip(ids) {
+                    *d = id.num()? as u32;
+                }
+            }
+            _ => return None,
+        }
+        let Json::Arr(writes) = json.get("writes")? else {
+            return None;
+        };
+        let writes = writes.iter().map(|w| match (w.get("reg"), w.get("mem")) {
+            (Some(Json::Str(reg)), _) => Some(Write::Reg {
+                reg: Reg::parse(reg)?,
+                lane: w.get("lane").and_then(Json::num).map(|l| l as usize),
+                value: num(w, "value")? as u32,
+            }),
+            (_, Some(Json::Str(space))) => {
+                let space = [Space::Global, Space::Lds, Space::Scratch]
+                    .into_iter()
+                    .find(|s| space_name(*s) == *space)?;
+                let Json::Str(data) = w.get("data")? else {
+                    return None;
+                };
+                let data = (0..data.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+                    .collect::<Option<_>>()?;
+                Some(Write::Memory {
+                    space,
+                    lane: num(w, "lane")? as usize,
+                    addr: num(w, "addr")?,
+                    data,
+                })
+            }
+            _ => None,
+        });
+        let Json::Str(mnemonic) = json.get("mnemonic")? else {
+            return None;
+        };
+        Some(Record {
+            workgroup,
+            wave: num(&json, "wave")? as usize,
+            pc: num(&json, "pc")? as usize,
+            word: num(&json, "word")? as u32,
+            mnemonic: mnemonic.clone(),
+            exec: num(&json, "exec")? as u32,
+            writes: writes.collect::<Option<_>>()?,
+        })
+    }
+}
+
+impl Reg {
+    fn parse(s: &str) -> Option<Reg> {
+        let idx = |p: &str| s.strip_prefix(p)?.parse().ok();
+        match s {
+            "scc" => Some(Reg::Scc),
+            "vcc" => Some(Reg::Vcc),
+            "exec" => Some(Reg::Exec),
+            _ if s.starts_with("hwreg") => idx("hwreg").map(Reg::Hw),
+            _ => idx("s").map(Reg::Sgpr).or_else(|| idx("v").map(Reg::Vgpr)),
+        }
+    }
+}
+
+/* every record of a binary or JSON Lines trace */
+pub fn parse(b: &[u8]) -> Option<Vec<Record>> {
+    let mut r = Reader { b, off: 0 };
+    if r.take(8) != Some(MAGIC) {
+        let text = std::str::from_utf8(b).ok()?;
+        let lines = text.lines().filter(|l| !l.trim().is_empty());
+        return lines.map(Record::from_json).collect();
+    }
+    let mut records = vec![];
+    while r.off < b.len() {
+        records.push(Record::from_reader(&mut r)?);
+    }
+    Some(records)
+}
+
+/* just enough JSON for trace records: objects, arrays, strings and unsigned integers */
+#[derive(Debug)]
+enum Json {
+    Num(u64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Obj(fields) => fields.iter().find(|(k, _)| k == key).map(|f| &f.1),
+            _ => None,
+        }
+    }
+
+    fn num(&self) -> Option<u64> {
+        match self {
+            Json::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    off: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<u8> {
+        while self.s.get(self.off)?.is_ascii_whitespace() {
+            self.off += 1;
+        }
+        self.s.get(self.off).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> Option<()> {
+        (self.peek()? == c).then(|| self.off += 1)
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        match self.peek()? {
+            b'{' => {
+                self.off += 1;
+                let mut fields = vec![];
+                while self.eat(b'}').is_none() {
+                    if !fields.is_empty() {
+                        self.eat(b',')?;
+                    }
+                    let Json::Str(key) = self.value()? else {
+                        return None;
+                    };
+                    self.eat(b':')?;
+                    fields.push((key, self.value()?));
+                }
+                Some(Json::Obj(fields))
+            }
+            b'[' => {
+                self.off += 1;
+                let mut items = vec![];
+                while self.eat(b']').is_none() {
+                    if !items.is_empty() {
+                        self.eat(b',')?;
+                    }
+                    items.push(self.value()?);
+                }
+                Some(Json::Arr(items))
+            }
+            b'"' => {
+                let start = self.off + 1;
+                let len = self.s[start..].iter().position(|&c| c == b'"')?;
+                self.off = start + len + 1;
+                let s = std::str::from_utf8(&self.s[start..start + len]).ok()?;
+                Some(Json::Str(s.to_string()))
+            }
+            _ => {
+                let start = self.off;
+                let len = self.s[start..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+                self.off += len;
+                std::str::from_utf8(&self.s[start..start + len])
+                    .ok()?
+                    .parse()
+                    .ok()
+                    .map(Json::Num)
+            }
+        }
+    }
+}
+
+pub fn flush() {
+    if let Some(trace) = &*TRACE {
+        trace.flush();
+    }
+}
+
+#[cfg(test)]
+mod test_trace {
+    use super::*;
+
+    fn record() -> Record {
+        Record {
+            workgroup: [1, 0, 0],
+            wave: 1,
+            pc: 8,
+            word: 0xD8340000,
+            mnemonic: "ds_store_b32".into(),
+            exec: 0x3,
+            writes: vec![
+                Write::Reg {
+                    reg: Reg::Vgpr(2),
+                    lane: Some(1),
+                    value: 7,
+                },
+                Write::Reg {
+                    reg: Reg::Scc,
+                    lane: None,
+                    value: 1,
+                },
+                Write::Memory {
+                    space: Space::Lds,
+                    lane: 1,
+                    addr: 16,
+                    data: vec![0x2a, 0, 0, 0],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let f = Filter::parse("workgroup:1,wave:0,lane:3,pc:0x10-0x40").unwrap();
+        assert_eq!(f.workgroup, Some([1, 0, 0]));
+        assert_eq!(f.pc, Some((0x10, 0x40)));
+        assert!(f.keeps([1, 0, 0], 0, 0x40));
+        assert!(!f.keeps([1, 0, 0], 0, 0x44));
+        assert!(!f.keeps([1, 0, 0], 1, 0x10));
+        assert!(!f.keeps([0, 1, 0], 0, 0x10));
+        assert_eq!(Filter::parse("pc:8").unwrap().pc, Some((8, 8)));
+        assert_eq!(Filter::parse(""), Some(Filter::default()));
+        assert_eq!(Filter::parse("lane:32"), None);
+        assert_eq!(Filter::parse("workgroup:1.2.3.4"), None);
+        assert_eq!(Filter::parse("thread:1"), None);
+    }
+
+    #[test]
+    fn test_json() {
+        let json = concat!(
+            r#"{"workgroup":[1,0,0],"wave":1,"pc":8,"word":3627286528,"mnemonic":"ds_store_b32","exec":3,"#,
+            r#""writes":[{"reg":"v2","lane":1,"value":7},{"reg":"scc","value":1},"#,
+            r#"{"mem":"lds","lane":1,"addr":16,"data":"2a000000"}]}"#
+        );
+        assert_eq!(record().to_json(), json);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let b = record().to_bytes();
+        assert_eq!(
+            &b[..28],
+            [1, 0, 0, 1, 8, 0xD8340000, 3]
+                .map(u32::to_le_bytes)
+                .concat()
+        );
+        assert_eq!(&b[28..46], b"\x0c\0ds_store_b32\x03\0\0\0");
+        assert_eq!(&b[46..54], [1, 1, 2, 0, 7, 0, 0, 0]);
+        assert_eq!(&b[54..62], [2, 0xff, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(
+            &b[62..],
+            [&[7, 1, 4, 0][..], &16u64.to_le_bytes(), &[0x2a, 0, 0, 0]].concat()
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut b = MAGIC.to_vec();
+        b.extend(record().to_bytes());
+        b.extend(record().to_bytes());
+        assert_eq!(parse(&b), Some(vec![record(), record()]));
+        assert_eq!(parse(&b[..b.len() - 1]), None);
+        let json = format!("{}\n\n{}\n", record().to_json(), record().to_json());
+        assert_eq!(parse(json.as_bytes()), Some(vec![record(), record()]));
+        assert_eq!(parse(&json.as_bytes()[..json.len() - 3]), None);
+        assert_eq!(parse(b"REMUCAP1"), None);
+    }
+
+    #[test]
+    fn test_lane_filter() {
+        let out = Arc::new(Mutex::new(vec![]));
+        let filter = Filter::parse("lane:0").unwrap();
+        Trace::new(out.clone(), false, filter).record(record());
+        let json = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(json.ends_with("\"writes\":[{\"reg\":\"scc\",\"value\":1}]}\n"));
+    }
+}
@@ -0,0 +1,228 @@
+/*
+ * Finds the first instruction where two traces written with TRACE stop agreeing. Records are
+ * aligned per workgroup and wave by how many instructions the wave ran, so the nth instruction of
+ * a wave in one trace is compared with the nth of the same wave in the other, whatever order the
+ * waves interleaved in. "First" is in the order of the first trace. Kernarg pointers are compared
+ * like any value, so traces of launches whose buffers sit at different addresses diverge at the
+ * first load of one.
+ */
+use crate::trace::{Record, Write};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/* remu_trace_diff result for a file that isn't a trace */
+pub const BAD_TRACE: i32 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub workgroup: [u32; 3],
+    pub wave: usize,
+    /* how many instructions the wave ran before this one */
+    pub index: usize,
+    /* None where a trace has no more instructions for the wave */
+    pub a: Option<Record>,
+    pub b: Option<Record>,
+    /* the instructions of the wave right before, the same in both */
+    pub context: Vec<Record>,
+}
+
+fn same(a: &Record, b: &Record) -> bool {
+    (a.pc, a.word, a.exec, &a.writes) == (b.pc, b.word, b.exec, &b.writes)
+}
+
+pub fn diff(a: &[Record], b: &[Record], context: usize) -> Option<Divergence> {
+    let waves = |records: &[Record]| {
+        let mut waves: HashMap<([u32; 3], usize), Vec<usize>> = HashMap::new();
+        for (i, r) in records.iter().enumerate() {
+            waves.entry((r.workgroup, r.wave)).or_default().push(i);
+        }
+        waves
+    };
+    let (waves_a, waves_b) = (waves(a), waves(b));
+    let divergence = |key: ([u32; 3], usize), index: usize| {
+        let at = |records: &[Record], waves: &HashMap<_, Vec<usize>>| {
+            let i = waves.get(&key)?.get(index)?;
+            Some(records[*i].clone())
+        };
+        let before = waves_a
+            .get(&key)
+            .map_or(&[][..], |w| &w[..index.min(w.len())]);
+        let before = &before[before.len().saturating_sub(context)..];
+        Divergence {
+            workgroup: key.0,
+            wave: key.1,
+            index,
+            a: at(a, &waves_a),
+            b: at(b, &waves_b),
+            context: before.iter().map(|&i| a[i].clone()).collect(),
+        }
+    };
+    let mut seen: HashMap<([u32; 3], usize), usize> = HashMap::new();
+    for r in a {
+        let key = (r.workgroup, r.wave);
+        let index = seen.entry(key).or_default();
+        let other = waves_b.get(&key).and_then(|w| w.get(*index));
+        if other.is_none_or(|&i| !same(r, &b[i])) {
+            return Some(divergence(key, *index));
+        }
+        *index += 1;
+    }
+    // the first trace ran out, the first record only the second has diverges
+    let mut seen: HashMap<([u32; 3], usize), usize> = HashMap::new();
+    for r in b {
+        let key = (r.workgroup, r.wave);
+        let index = seen.entry(key).or_default();
+        if waves_a.get(&key).is_none_or(|w| *index >= w.len()) {
+            return Some(divergence(key, *index));
+        }
+        *index += 1;
+    }
+    None
+}
+
+fn summary(r: &Record) -> String {
+    format!("pc=0x{:x} {} exec=0x{:08x}", r.pc, r.mnemonic, r.exec)
+}
+
+fn location(w: &Write) -> String {
+    match w {
+        Write::Reg {
+            reg,
+            lane: Some(lane),
+            ..
+        } => format!("{reg} lane {lane}"),
+        Write::Reg { reg, .. } => reg.to_string(),
+        Write::Memory {
+            space, lane, addr, ..
+        } => format!("{space:?} 0x{addr:x} lane {lane}").to_lowercase(),
+    }
+}
+
+fn value(w: &Write) -> String {
+    match w {
+        Write::Reg { value, .. } => format!("0x{value:08x}"),
+        Write::Memory { data, .. } => {
+            let digits = data.iter().rev().map(|b| format!("{b:02x}"));
+            format!("0x{}", digits.collect::<String>())
+        }
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let at = format!(
+            "{:?} wave {} instruction {}",
+            self.workgroup, self.wave, self.index
+        );
+        writeln!(f, "first divergence at {at}")?;
+        for r in &self.context {
+            writeln!(f, "    {}", summary(r))?;
+        }
+        let (a, b) = match (&self.a, &self.b) {
+            (Some(a), Some(b)) => (a, b),
+            (Some(a), None) => return write!(f, "  a: {}\n  b: wave ends", summary(a)),
+            (None, Some(b)) => return write!(f, "  a: wave ends\n  b: {}", summary(b)),
+            (None, None) => return Ok(()),
+        };
+        writeln!(f, "  a: {}", summary(a))?;
+        write!(f, "  b: {}", summary(b))?;
+        if (a.pc, a.word) != (b.pc, b.word) {
+            return Ok(());
+        }
+        // writes are compared by what they wrote to, then by value
+        let writes = |r: &Record| {
+            let map = r.writes.iter().map(|w| (location(w), value(w)));
+            map.collect::<Vec<_>>()
+        };
+        let (wa, wb) = (writes(a), writes(b));
+        let find = |ws: &[(String, String)], loc: &str| {
+            let w = ws.iter().find(|(l, _)| l == loc);
+            w.map_or("-".to_string(), |w| w.1.clone())
+        };
+        let mut shown = HashSet::new();
+        for (loc, _) in wa.iter().chain(&wb) {
+            let (va, vb) = (find(&wa, loc), find(&wb, loc));
+            if va != vb && shown.insert(loc) {
+                write!(f, "\n  {loc}: {va} != {vb}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_trace_diff {
+    use super::*;
+    use crate::trace::Reg;
+
+    fn record(wave: usize, pc: usize, value: u32) -> Record {
+        Record {
+            workgroup: [0, 0, 0],
+            wave,
+            pc,
+            word: 0x7E020281,
+            mnemonic: "v_mov_b32".into(),
+            exec: 3,
+            writes: vec![
+                Write::Reg {
+                    reg: Reg::Vgpr(1),
+                    lane: Some(0),
+                    value: 1,
+                },
+                Write::Reg {
+                    reg: Reg::Vgpr(1),
+                    lane: Some(1),
+                    value,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_interleaving() {
+        let a = [record(0, 0, 1), record(0, 4, 1), record(1, 0, 1)];
+        let b = [record(1, 0, 1), record(0, 0, 1), record(0, 4, 1)];
+        assert_eq!(diff(&a, &b, 2), None);
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let a = [
+            record(0, 0, 1),
+            record(1, 0, 1),
+            record(0, 4, 1),
+            record(0, 8, 1),
+        ];
+        let b = [
+            record(0, 0, 1),
+            record(0, 4, 1),
+            record(1, 0, 2),
+            record(0, 8, 3),
+        ];
+        let d = diff(&a, &b, 4).unwrap();
+        assert_eq!((d.wave, d.index, d.context.len()), (1, 0, 0));
+        assert_eq!(
+            d.to_string(),
+            "first divergence at [0, 0, 0] wave 1 instruction 0\n  \
+             a: pc=0x0 v_mov_b32 exec=0x00000003\n  \
+             b: pc=0x0 v_mov_b32 exec=0x00000003\n  \
+             v1 lane 1: 0x00000001 != 0x00000002"
+        );
+        // wave 0 alone agrees up to where the first trace stops
+        let d = diff(&[a[0].clone(), a[2].clone()], &b, 1).unwrap();
+        assert_eq!((d.wave, d.index, d.context.len()), (1, 0, 0));
+    }
+
+    #[test]
+    fn test_wave_ends() {
+        let a = [record(0, 0, 1)];
+        let b = [record(0, 0, 1), record(0, 4, 1)];
+        let d = diff(&a, &b, 1).unwrap();
+        assert_eq!((d.index, d.a.is_none(), d.context.len()), (1, true, 1));
+        assert!(d
+            .to_string()
+            .ends_with("a: wave ends\n  b: pc=0x4 v_mov_b32 exec=0x00000003"));
+        let d = diff(&b, &a, 1).unwrap();
+        assert!(d.to_string().ends_with("b: wave ends"));
+    }
+}
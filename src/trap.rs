@@ -0,0 +1,220 @@
+use crate::decode::{RegFile, RegRange};
+use std::fmt;
+
+/* run_asm result for a wave that trapped without a handler */
+pub const FAULT: i32 = 2;
+
+pub const HW_REG_MODE: usize = 1;
+pub const HW_REG_STATUS: usize = 2;
+pub const HW_REG_TRAPSTS: usize = 3;
+
+/* TRAPSTS.EXCP bits, MODE.EXCP_EN uses the same layout starting at MODE_EXCP_EN_SHIFT */
+pub const EXCP_INVALID: u32 = 1 << 0;
+pub const EXCP_INPUT_DENORM: u32 = 1 << 1;
+pub const EXCP_DIV0: u32 = 1 << 2;
+pub const EXCP_OVERFLOW: u32 = 1 << 3;
+pub const EXCP_UNDERFLOW: u32 = 1 << 4;
+pub const EXCP_MEM_VIOL: u32 = 1 << 8;
+pub const TRAPSTS_ILLEGAL_INST: u32 = 1 << 11;
+pub const MODE_EXCP_EN_SHIFT: u32 = 12;
+
+/* user space on the hosts remu runs on, anything else would fault the host process */
+const MIN_ADDR: u64 = 0x1000;
+const MAX_ADDR: u64 = 0x8000_0000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trap {
+    Software(u32),
+    Message(u32),
+    IllegalInstruction,
+    MemoryViolation(u64),
+    Float(u32),
+}
+
+impl Trap {
+    pub fn trapsts(&self) -> u32 {
+        match self {
+            Trap::Software(_) | Trap::Message(_) => 0,
+            Trap::IllegalInstruction => TRAPSTS_ILLEGAL_INST,
+            Trap::MemoryViolation(_) => EXCP_MEM_VIOL,
+            Trap::Float(excp) => *excp,
+        }
+    }
+    pub fn id(&self) -> u32 {
+        match self {
+            Trap::Software(id) => id & 0xff,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trap::Software(id) => write!(f, "s_trap {id}"),
+            Trap::Message(id) => write!(f, "s_sendmsg {id}"),
+            Trap::IllegalInstruction => write!(f, "illegal instruction"),
+            Trap::MemoryViolation(addr) => write!(f, "memory violation at 0x{addr:x}"),
+            Trap::Float(excp) => {
+                let names = [
+                    (EXCP_INVALID, "invalid"),
+                    (EXCP_INPUT_DENORM, "input denormal"),
+                    (EXCP_DIV0, "divide by zero"),
+                    (EXCP_OVERFLOW, "overflow"),
+                    (EXCP_UNDERFLOW, "underflow"),
+                ]
+                .iter()
+                .filter(|(bit, _)| excp & bit != 0)
+                .map(|(_, name)| *name)
+                .collect::<Vec<_>>();
+                write!(f, "floating point exception ({})", names.join(", "))
+            }
+        }
+    }
+}
+
+pub fn invalid_address(addr: u64, len: u64) -> bool {
+    addr < MIN_ADDR || addr.checked_add(len).is_none_or(|end| end > MAX_ADDR)
+}
+
+/* a register value at the width the instruction reads or writes it */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Float {
+    F32(f32),
+    F64(f64),
+}
+impl Float {
+    fn value(self) -> f64 {
+        match self {
+            Float::F32(x) => x as f64,
+            Float::F64(x) => x,
+        }
+    }
+    fn is_subnormal(self) -> bool {
+        match self {
+            Float::F32(x) => x.is_subnormal(),
+            Float::F64(x) => x.is_subnormal(),
+        }
+    }
+}
+
+/* per-lane f32/f64 values of the registers an instruction touches */
+pub fn lane_floats(regs: &[RegRange], scalar_reg: &[u32], lane: &[u32; 256]) -> Vec<Float> {
+    regs.iter()
+        .filter(|r| r.idx < 106 || r.file == RegFile::Vgpr)
+        .map(|r| {
+            let file: &[u32] = match r.file {
+                RegFile::Sgpr => scalar_reg,
+                RegFile::Vgpr => lane,
+            };
+            match r.len {
+                1 => Float::F32(f32::from_bits(file[r.idx])),
+                _ => Float::F64(f64::from_bits(
+                    ((file[r.idx + 1] as u64) << 32) | file[r.idx] as u64,
+                )),
+            }
+        })
+        .collect()
+}
+
+/* IEEE exceptions a float op raised, judged from its register inputs and results */
+pub fn float_exceptions(inputs: &[Float], outputs: &[Float]) -> u32 {
+    let mut excp = 0;
+    if inputs.iter().any(|x| x.is_subnormal()) {
+        excp |= EXCP_INPUT_DENORM;
+    }
+    let inputs = inputs.iter().map(|x| x.value()).collect::<Vec<_>>();
+    let finite = inputs.iter().all(|x| x.is_finite());
+    for out in outputs.iter().map(|x| x.value()) {
+        if out.is_nan() && !inputs.iter().any(|x| x.is_nan()) {
+            excp |= EXCP_INVALID;
+        }
+        if out.is_infinite() && finite {
+            excp |= match inputs.contains(&0.0) {
+                true => EXCP_DIV0,
+                false => EXCP_OVERFLOW,
+            };
+        }
+    }
+    excp
+}
+
+#[cfg(test)]
+mod test_trap {
+    use super::*;
+
+    #[test]
+    fn test_float_exceptions() {
+        use Float::{F32, F64};
+        assert_eq!(float_exceptions(&[F32(1.0), F32(2.0)], &[F32(3.0)]), 0);
+        assert_eq!(
+            float_exceptions(&[F32(0.0)], &[F32(f32::INFINITY)]),
+            EXCP_DIV0
+        );
+        assert_eq!(
+            float_exceptions(&[F64(-1.0)], &[F64(f64::NAN)]),
+            EXCP_INVALID
+        );
+        assert_eq!(float_exceptions(&[F32(f32::NAN)], &[F32(f32::NAN)]), 0);
+        assert_eq!(
+            float_exceptions(&[F32(f32::MAX), F32(2.0)], &[F32(f32::INFINITY)]),
+            EXCP_OVERFLOW
+        );
+        assert_eq!(
+            float_exceptions(&[F32(1.0e-40)], &[F32(1.0)]),
+            EXCP_INPUT_DENORM
+        );
+        assert_eq!(
+            float_exceptions(&[F64(1.0e-310)], &[F64(1.0)]),
+            EXCP_INPUT_DENORM
+        );
+        // normal as an f64 even though it's under the f32 range
+        assert_eq!(float_exceptions(&[F64(1.0e-40)], &[F64(1.0)]), 0);
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        assert!(invalid_address(0, 4));
+        assert!(invalid_address(0xffc, 4));
+        assert!(!invalid_address(0x1000, 4));
+        assert!(invalid_address(0x7fff_ffff_fffe, 4));
+        assert!(invalid_address(u64::MAX - 1, 4));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Trap::Software(2).to_string(), "s_trap 2");
+        assert_eq!(
+            Trap::Float(EXCP_INVALID | EXCP_DIV0).to_string(),
+            "floating point exception (invalid, divide by zero)"
+        );
+        assert_eq!(
+            Trap::MemoryViolation(0x10).to_string(),
+            "memory violation at 0x10"
+        );
+    }
+
+    #[test]
+    fn test_lane_floats() {
+        let mut lane = [0; 256];
+        lane[1] = 1.5f32.to_bits();
+        lane.iter_mut()
+            .skip(2)
+            .take(2)
+            .enumerate()
+            .for_each(|(i, x)| {
+                *x = (2.5f64.to_bits() >> (32 * i)) as u32;
+            });
+        let sgprs = vec![(-1.0f32).to_bits(); 106];
+        let regs = [
+            RegRange::vgpr(1, 1),
+            RegRange::vgpr(2, 2),
+            RegRange::sgpr(0, 1),
+            RegRange::sgpr(126, 1),
+        ];
+        assert_eq!(
+            lane_floats(&regs, &sgprs, &lane),
+            vec![Float::F32(1.5), Float::F64(2.5), Float::F32(-1.0)]
+        );
+    }
+}
@@ -0,0 +1,289 @@
+/*
+ * A bounded per-wave undo log for reverse debugging. Every instruction a recorded wave runs leaves
+ * an entry with its pc and the old value of everything it changed, registers from the ones its
+ * footprint writes and memory from the store paths. Stepping back restores one entry,
+ * stores other waves made in the meantime are not undone.
+ */
+use crate::decode::{footprint, RegFile, RegRange, EXEC, SCC, VCC};
+use crate::memory::{Memory, VecDataStore};
+use crate::watch::{Space, Target};
+use crate::work_group::{WaveState, WaveStatus, TTMP};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delta {
+    Sgpr(usize, u32),
+    Vgpr {
+        lane: usize,
+        idx: usize,
+        old: u32,
+    },
+    Scc(u32),
+    Vcc(u32),
+    Exec(u32),
+    HwReg(usize, u32),
+    Memory {
+        space: Space,
+        /* the lane whose scratch was written */
+        lane: usize,
+        addr: u64,
+        old: Vec<u8>,
+    },
+}
+
+impl Delta {
+    fn writes(&self, target: &Target) -> bool {
+        match (self, *target) {
+            (Delta::Sgpr(i, _), Target::Sgpr(idx)) => *i == idx,
+            (
+                Delta::Vgpr {
+                    lane: l, idx: i, ..
+                },
+                Target::Vgpr { idx, lane },
+            ) => *i == idx && lane.is_none_or(|lane| lane == *l),
+            (
+                Delta::Memory {
+                    space: s,
+                    addr: a,
+                    old,
+                    ..
+                },
+                Target::Memory { space, addr, len },
+            ) => *s == space && *a < addr + len && addr < a + old.len() as u64,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /* where the wave was before the instruction */
+    pub pc: usize,
+    pub status: WaveStatus,
+    pub deltas: Vec<Delta>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UndoLog {
+    limit: usize,
+    entries: VecDeque<Entry>,
+}
+
+impl UndoLog {
+    pub fn new(limit: usize) -> Self {
+        UndoLog {
+            limit,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn push(&mut self, entry: Entry) {
+        if self.entries.len() == self.limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn pop(&mut self) -> Option<Entry> {
+        self.entries.pop_back()
+    }
+
+    /* how many entries to undo to get back before the last write to target */
+    pub fn last_write(&self, target: &Target) -> Option<usize> {
+        let writes = |e: &Entry| e.deltas.iter().any(|d| d.writes(target));
+        self.entries.iter().rev().position(writes).map(|i| i + 1)
+    }
+}
+
+/* the registers an instruction can write, as they are before it */
+pub struct Snapshot {
+    pc: usize,
+    status: WaveStatus,
+    sgprs: Vec<(usize, u32)>,
+    /* every lane of each VGPR */
+    vgprs: Vec<(usize, Vec<u32>)>,
+    scc: u32,
+    vcc: u32,
+    pub exec: u32,
+    hw_reg: [u32; 64],
+}
+
+impl Snapshot {
+    pub fn of(wave: &WaveState, stream: &[u32]) -> Self {
+        let (mut sgprs, mut vgprs) = (vec![], vec![]);
+        let lanes = 0..wave.threads.len();
+        let writes = footprint(stream).writes;
+        // entering the trap handler writes the return address to ttmp
+        let ttmp = RegRange::sgpr(TTMP, 2);
+        for reg in writes.iter().chain([&ttmp]).flat_map(|r| r.regs()) {
+            match reg.file {
+                _ if matches!(reg.idx, VCC | EXEC | SCC) => {}
+                RegFile::Sgpr => {
+                    if let Some(&old) = wave.scalar_reg.get(reg.idx) {
+                        sgprs.push((reg.idx, old));
+                    }
+                }
+                RegFile::Vgpr if reg.idx < 256 => {
+                    let old = lanes.clone().map(|l| wave.vec_reg.get_lane(l)[reg.idx]);
+                    vgprs.push((reg.idx, old.collect()));
+                }
+                RegFile::Vgpr => {}
+            }
+        }
+        Snapshot {
+            pc: wave.pc,
+            status: wave.status,
+            sgprs,
+            vgprs,
+            scc: wave.scc,
+            vcc: wave.vcc.value,
+            exec: wave.exec.value,
+            hw_reg: wave.hw_reg,
+        }
+    }
+
+    /* the old value of every register the instruction changed since the snapshot */
+    pub fn deltas(&self, wave: &WaveState) -> Vec<Delta> {
+        let mut deltas = vec![];
+        for &(idx, old) in &self.sgprs {
+            if wave.scalar_reg[idx] != old && !deltas.contains(&Delta::Sgpr(idx, old)) {
+                deltas.push(Delta::Sgpr(idx, old));
+            }
+        }
+        for (idx, old) in &self.vgprs {
+            for (lane, &old) in old.iter().enumerate() {
+                let delta = Delta::Vgpr {
+                    lane,
+                    idx: *idx,
+                    old,
+                };
+                if wave.vec_reg.get_lane(lane)[*idx] != old && !deltas.contains(&delta) {
+                    deltas.push(delta);
+                }
+            }
+        }
+        for (idx, (&old, &new)) in self.hw_reg.iter().zip(&wave.hw_reg).enumerate() {
+            if old != new {
+                deltas.push(Delta::HwReg(idx, old));
+            }
+        }
+        let words = [
+            (self.scc, wave.scc, Delta::Scc(self.scc)),
+            (self.vcc, wave.vcc.value, Delta::Vcc(self.vcc)),
+            (self.exec, wave.exec.value, Delta::Exec(self.exec)),
+        ];
+        deltas.extend(words.into_iter().filter(|(o, n, _)| o != n).map(|w| w.2));
+        deltas
+    }
+
+    /* log the instruction's register deltas and stores for a recorded wave */
+    pub fn commit(self, wave: &mut WaveState, deltas: Vec<Delta>) {
+        let Some(log) = wave.undo.as_mut() else {
+            return;
+        };
+        log.push(Entry {
+            pc: self.pc,
+            status: self.status,
+            deltas,
+        });
+    }
+}
+
+/* put back what an entry changed and return the wave to where it was */
+pub fn restore(wave: &mut WaveState, lds: &mut VecDataStore, memory: &dyn Memory, entry: Entry) {
+    // stores of one instruction can overlap, the oldest bytes win
+    for delta in entry.deltas.into_iter().rev() {
+        match delta {
+            Delta::Sgpr(idx, old) => wave.scalar_reg[idx] = old,
+            Delta::Vgpr { lane, idx, old } => wave.vec_reg.get_lane_mut(lane)[idx] = old,
+            Delta::Scc(old) => wave.scc = old,
+            Delta::Vcc(old) => wave.vcc.value = old,
+            Delta::Exec(old) => wave.exec.value = old,
+            Delta::HwReg(idx, old) => wave.hw_reg[idx] = old,
+            Delta::Memory {
+                space,
+                lane,
+                addr,
+                old,
+            } => {
+                let store = match space {
+                    // the address was checked when the store ran
+                    Space::Global => {
+                        if let Some(ptr) = memory.host(addr, old.len() as u64, true) {
+                            unsafe { std::ptr::copy_nonoverlapping(old.as_ptr(), ptr, old.len()) };
+                        }
+                        continue;
+                    }
+                    Space::Lds => &mut *lds,
+                    Space::Scratch => &mut wave.sds[lane],
+                };
+                store.write_bytes(addr as usize, &old);
+            }
+        }
+    }
+    wave.pc = entry.pc;
+    wave.status = entry.status;
+}
+
+#[cfg(test)]
+mod test_undo {
+    use super::*;
+
+    fn entry(deltas: Vec<Delta>) -> Entry {
+        Entry {
+            pc: 0,
+            status: WaveStatus::Running,
+            deltas,
+        }
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut log = UndoLog::new(2);
+        for pc in 0..3 {
+            log.push(Entry {
+                pc,
+                ..entry(vec![])
+            });
+        }
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.pop().map(|e| e.pc), Some(2));
+        assert_eq!(log.pop().map(|e| e.pc), Some(1));
+        assert!(log.pop().is_none());
+    }
+
+    #[test]
+    fn test_last_write() {
+        let mut log = UndoLog::new(8);
+        log.push(entry(vec![Delta::Sgpr(5, 0)]));
+        log.push(entry(vec![Delta::Vgpr {
+            lane: 3,
+            idx: 1,
+            old: 0,
+        }]));
+        log.push(entry(vec![Delta::Memory {
+            space: Space::Lds,
+            lane: 0,
+            addr: 8,
+            old: vec![0; 8],
+        }]));
+        log.push(entry(vec![Delta::Scc(1)]));
+        assert_eq!(log.last_write(&Target::Sgpr(5)), Some(4));
+        assert_eq!(log.last_write(&Target::Sgpr(6)), None);
+        let v1 = |lane| Target::Vgpr { idx: 1, lane };
+        assert_eq!(log.last_write(&v1(None)), Some(3));
+        assert_eq!(log.last_write(&v1(Some(2))), None);
+        let lds = |addr, len| Target::Memory {
+            space: Space::Lds,
+            addr,
+            len,
+        };
+        assert_eq!(log.last_write(&lds(12, 4)), Some(2));
+        assert_eq!(log.last_write(&lds(4, 4)), None);
+        assert_eq!(log.last_write(&lds(16, 4)), None);
+    }
+}
@@ -0,0 +1,266 @@
+/*
+ * CHECK_UNINIT=1 keeps shadow bits of which SGPRs, VGPR lanes and LDS and scratch bytes hold
+ * something the kernel wrote, remu zero-fills them otherwise. A read of undefined data in an ALU op,
+ * a branch, an address or as store data is reported, the register counts as defined after that so
+ * one missing write isn't reported again by everything downstream of it. Loads of undefined LDS or
+ * scratch bytes leave their destination undefined, global memory always is defined.
+ */
+use crate::decode::{encoding, footprint, is_scalar, Encoding, RegFile, RegRange, EXEC};
+use crate::watch::Space;
+use std::fmt;
+
+const TTMP: std::ops::Range<usize> = 108..124;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Use {
+    Alu,
+    Branch,
+    Address,
+    Store,
+}
+impl fmt::Display for Use {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self {
+            Use::Alu => "in an ALU op",
+            Use::Branch => "in a branch",
+            Use::Address => "as an address",
+            Use::Store => "as store data",
+        };
+        write!(f, "{what}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uninit {
+    pub pc: usize,
+    pub reg: RegRange,
+    /* the first lane it's undefined in, None for SGPRs */
+    pub lane: Option<usize>,
+    pub by: Use,
+}
+impl fmt::Display for Uninit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pc=0x{:x} reads undefined {}", self.pc * 4, self.reg)?;
+        if let Some(lane) = self.lane {
+            write!(f, " lane {lane}")?;
+        }
+        write!(f, " {}", self.by)
+    }
+}
+
+/* an LDS or scratch access one lane made */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub space: Space,
+    pub lane: usize,
+    pub addr: u64,
+    pub len: u64,
+    pub store: bool,
+}
+
+/* the bytes of LDS or of a lane's scratch something was stored to */
+#[derive(Debug, Clone, Default)]
+pub struct DefinedBytes(Vec<bool>);
+
+impl DefinedBytes {
+    pub fn define(&mut self, addr: u64, len: u64) {
+        let end = (addr + len) as usize;
+        if end > self.0.len() {
+            self.0.resize(end, false);
+        }
+        self.0[addr as usize..end].fill(true);
+    }
+
+    pub fn defined(&self, addr: u64, len: u64) -> bool {
+        (addr..addr + len).all(|i| self.0.get(i as usize).copied().unwrap_or(false))
+    }
+}
+
+/* how an instruction uses a register it reads */
+fn use_of(stream: &[u32], reg: &RegRange) -> Use {
+    let addr = ((*stream.get(1).unwrap_or(&0)) & 0xff) as usize;
+    match encoding(stream[0]) {
+        Encoding::Smem => Use::Address,
+        Encoding::Sopp => Use::Branch,
+        Encoding::Ds | Encoding::Flat if reg.file == RegFile::Sgpr || reg.idx == addr => {
+            Use::Address
+        }
+        Encoding::Ds | Encoding::Flat => Use::Store,
+        _ => Use::Alu,
+    }
+}
+
+/* defined registers and scratch of a single wave */
+#[derive(Debug, Clone)]
+pub struct UninitTracker {
+    /* by index, vcc, m0, exec and scc included */
+    sgpr: Vec<bool>,
+    /* the lanes each VGPR is defined in */
+    vgpr: Vec<u32>,
+    scratch: Vec<DefinedBytes>,
+}
+
+impl UninitTracker {
+    /* a wave starts with exec, the trap temporaries and the registers the launch set up defined */
+    pub fn new(defined: &[RegRange], lanes: u32) -> Self {
+        let mut tracker = Self {
+            sgpr: vec![false; 256],
+            vgpr: vec![0; 256],
+            scratch: vec![DefinedBytes::default(); 32],
+        };
+        tracker.sgpr[EXEC..EXEC + 2].fill(true);
+        tracker.sgpr[TTMP].fill(true);
+        defined.iter().for_each(|r| tracker.define(r, lanes));
+        tracker
+    }
+
+    fn define(&mut self, reg: &RegRange, lanes: u32) {
+        // a malformed instruction can name registers past the last one
+        for r in reg.regs().filter(|r| r.idx < 256) {
+            match r.file {
+                RegFile::Sgpr => self.sgpr[r.idx] = true,
+                RegFile::Vgpr => self.vgpr[r.idx] |= lanes,
+            }
+        }
+    }
+
+    /* the first register of reg that isn't defined in every lane of lanes, with that lane */
+    fn undefined(&self, reg: &RegRange, lanes: u32) -> Option<(RegRange, Option<usize>)> {
+        reg.regs()
+            .filter(|r| r.idx < 256)
+            .find_map(|r| match r.file {
+                RegFile::Sgpr => (!self.sgpr[r.idx]).then_some((r, None)),
+                RegFile::Vgpr => {
+                    let missing = lanes & !self.vgpr[r.idx];
+                    (missing != 0).then(|| (r, Some(missing.trailing_zeros() as usize)))
+                }
+            })
+    }
+
+    /*
+     * advance the tracker over the instruction at `stream[0]` that ran on the lanes in active and
+     * made the LDS and scratch accesses, returning its reads of undefined data
+     */
+    pub fn step(
+        &mut self,
+        stream: &[u32],
+        pc: usize,
+        active: u32,
+        accesses: &[Access],
+        lds: &mut DefinedBytes,
+    ) -> Vec<Uninit> {
+        let fp = footprint(stream);
+        let mut found = vec![];
+        // a vector instruction without active lanes doesn't use its operands
+        if is_scalar(stream[0]) || active != 0 {
+            for reg in &fp.reads {
+                if let Some((r, lane)) = self.undefined(reg, active) {
+                    let by = use_of(stream, reg);
+                    found.push(Uninit {
+                        pc,
+                        reg: r,
+                        lane,
+                        by,
+                    });
+                    self.define(reg, active);
+                }
+            }
+        }
+        fp.writes.iter().for_each(|r| self.define(r, active));
+        for a in accesses {
+            let bytes = match a.space {
+                Space::Lds => &mut *lds,
+                _ => &mut self.scratch[a.lane],
+            };
+            if a.store {
+                bytes.define(a.addr, a.len);
+                continue;
+            }
+            if bytes.defined(a.addr, a.len) {
+                continue;
+            }
+            let dst = fp.writes.iter().filter(|r| r.file == RegFile::Vgpr);
+            for r in dst.flat_map(|r| r.regs()) {
+                self.vgpr[r.idx] &= !(1 << a.lane);
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod test_uninit {
+    use super::*;
+
+    fn access(space: Space, lane: usize, addr: u64, store: bool) -> Access {
+        Access {
+            space,
+            lane,
+            addr,
+            len: 4,
+            store,
+        }
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut t = UninitTracker::new(&[RegRange::vgpr(0, 1)], 0b11);
+        let mut lds = DefinedBytes::default();
+        // v_mov_b32 v1, 1 on lane 0 only
+        assert_eq!(t.step(&[0x7E020281], 0, 0b01, &[], &mut lds), []);
+        // v_add_f32 v2, v0, v1 on both lanes
+        let found = t.step(&[0x06040300], 1, 0b11, &[], &mut lds);
+        assert_eq!(
+            found,
+            [Uninit {
+                pc: 1,
+                reg: RegRange::vgpr(1, 1),
+                lane: Some(1),
+                by: Use::Alu,
+            }]
+        );
+        assert_eq!(
+            found[0].to_string(),
+            "pc=0x4 reads undefined v1 lane 1 in an ALU op"
+        );
+        // reported once
+        assert_eq!(t.step(&[0x06040300], 2, 0b11, &[], &mut lds), []);
+        // s_cbranch_scc1 before anything set scc
+        let found = t.step(&[0xBFA20001], 3, 0b11, &[], &mut lds);
+        assert_eq!(
+            found[0].to_string(),
+            "pc=0xc reads undefined scc in a branch"
+        );
+        // v_add_f32 v3, s4, v0 without active lanes
+        assert_eq!(t.step(&[0x06060004], 4, 0, &[], &mut lds), []);
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut t = UninitTracker::new(&[RegRange::vgpr(0, 1)], 0b11);
+        let mut lds = DefinedBytes::default();
+        // ds_store_b32 v0, v5 stores undefined data
+        let store = [0xD8340000, 0x00000500];
+        let found = t.step(&store, 0, 0b01, &[access(Space::Lds, 0, 0, true)], &mut lds);
+        assert_eq!(found[0].reg, RegRange::vgpr(5, 1));
+        assert_eq!(found[0].by, Use::Store);
+        assert!(lds.defined(0, 4) && !lds.defined(2, 4));
+        // ds_load_b32 v6, v7 with an undefined address
+        let load = [0xD8D80000, 0x06000007];
+        let found = t.step(&load, 1, 0b01, &[access(Space::Lds, 0, 0, false)], &mut lds);
+        assert_eq!(found[0].by, Use::Address);
+        // lane 1 loads bytes nothing stored, v6 stays undefined there
+        let accesses = [
+            access(Space::Lds, 0, 0, false),
+            access(Space::Lds, 1, 4, false),
+        ];
+        t.step(&[0xD8D80000, 0x06000000], 2, 0b11, &accesses, &mut lds);
+        // v_mov_b32 v8, v6
+        let found = t.step(&[0x7E100306], 3, 0b11, &[], &mut lds);
+        assert_eq!(found[0].lane, Some(1));
+        // scratch is per lane
+        let scratch = |lane, store| [access(Space::Scratch, lane, 0, store)];
+        t.step(&[0x7E100306], 4, 0b11, &scratch(0, true), &mut lds);
+        assert!(t.scratch[0].defined(0, 4) && !t.scratch[1].defined(0, 4));
+    }
+}
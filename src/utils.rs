@@ -1,8 +1,10 @@
+use crate::schedule::Schedule;
+use crate::watch::{self, Watchpoint};
 use half::f16;
-use lazy_static::lazy_static;
 use std::io::Write;
 use std::process::{Command, Stdio};
-use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::time::Duration;
 use std::{env, fs, str};
 
 pub const END_PRG: u32 = 0xbfb00000;
@@ -11,6 +13,40 @@ lazy_static::lazy_static! {
     pub static ref PROFILE: bool = env::var("PROFILE").map(|v| v == "1").unwrap_or(false);
     pub static ref OSX: bool = env::var("OSX").map(|v| v == "1").unwrap_or(false);
     pub static ref GLOBAL_DEBUG: bool = env::var("DEBUG").map(|v| v == "1").unwrap_or(false);
+    pub static ref CHECK_WAITCNT: bool = env::var("CHECK_WAITCNT").map(|v| v == "1").unwrap_or(false);
+    pub static ref CHECK_HAZARDS: bool = env::var("CHECK_HAZARDS").map(|v| v == "1").unwrap_or(false);
+    /* CHECK_LDS=1 reports LDS accesses past the end of what the launch allocated */
+    pub static ref CHECK_LDS: bool = env::var("CHECK_LDS").map(|v| v == "1").unwrap_or(false);
+    /* CHECK_UNINIT=1 reports uses of registers, LDS and scratch nothing wrote */
+    pub static ref CHECK_UNINIT: bool = env::var("CHECK_UNINIT").map(|v| v == "1").unwrap_or(false);
+    /* PERF=1 prints estimated cycles per wave and per kernel */
+    pub static ref PERF: bool = env::var("PERF").map(|v| v == "1").unwrap_or(false);
+    /* PREFLIGHT=1 fails a launch up front if the kernel has instructions remu can't execute */
+    pub static ref PREFLIGHT: bool = env::var("PREFLIGHT").map(|v| v == "1").unwrap_or(false);
+    /* TIMEOUT=<seconds> fails a launch that runs longer */
+    pub static ref LAUNCH_TIMEOUT: Option<Duration> = env::var("TIMEOUT")
+        .ok()
+        .map(|v| Duration::from_secs_f64(v.parse().expect("TIMEOUT must be a number of seconds")));
+    /* SERIAL=1 runs workgroups one at a time in launch order, whatever THREADS is */
+    pub static ref SERIAL: bool = env::var("SERIAL").map(|v| v == "1").unwrap_or(false);
+    /*
+     * THREADS=<n> runs workgroups on n workers, THREADS=0 on one per core. Serial by default, global
+     * loads and stores are plain host memory accesses, so workgroups on different workers that
+     * write memory another one reads or writes race.
+     */
+    pub static ref THREADS: usize = match env::var("THREADS").ok().and_then(|v| v.parse().ok()) {
+        Some(0) => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        Some(n) => n,
+        None => 1,
+    };
+    pub static ref SCHEDULE: Schedule = env::var("SCHEDULE")
+        .map_or(Some(Schedule::Wave), |v| Schedule::parse(&v))
+        .expect("SCHEDULE must be wave, round_robin, reverse or random[:seed]");
+    /* GDB=<host:port> or GDB=unix:<path> waits for a debugger before every launch */
+    pub static ref GDB: Option<String> = env::var("GDB").ok();
+    pub static ref WATCH: Vec<Watchpoint> = env::var("WATCH")
+        .map_or(Some(vec![]), |v| watch::parse_list(&v))
+        .expect("WATCH must list global:<addr>[+len], lds:<addr>[+len], scratch:<addr>[+len], s<n> or v<n>[:lane]");
 }
 
 pub fn nth(val: u32, pos: usize) -> u32 {
@@ -172,20 +208,19 @@ fn _read_literal_asm(lib: &Vec<u8>) -> (Vec<u32>, String) {
     return prg;
 }
 
+/* profiling counters, bumped from every worker thread */
 #[derive(Debug)]
 pub struct GlobalCounter {
-    pub vgpr_used: usize,
-    pub gds_ops: usize,
-    pub lds_ops: usize,
-    pub wmma: usize,
-    pub wave_syncs: usize,
-}
-lazy_static! {
-    pub static ref GLOBAL_COUNTER: Mutex<GlobalCounter> = Mutex::new(GlobalCounter {
-        vgpr_used: 0,
-        gds_ops: 0,
-        lds_ops: 0,
-        wmma: 0,
-        wave_syncs: 0
-    });
+    pub vgpr_used: AtomicUsize,
+    pub gds_ops: AtomicUsize,
+    pub lds_ops: AtomicUsize,
+    pub wmma: AtomicUsize,
+    pub wave_syncs: AtomicUsize,
 }
+pub static GLOBAL_COUNTER: GlobalCounter = GlobalCounter {
+    vgpr_used: AtomicUsize::new(0),
+    gds_ops: AtomicUsize::new(0),
+    lds_ops: AtomicUsize::new(0),
+    wmma: AtomicUsize::new(0),
+    wave_syncs: AtomicUsize::new(0),
+};
@@ -0,0 +1,279 @@
+use crate::decode::{encoding, footprint, Encoding, RegRange};
+use std::collections::VecDeque;
+use std::fmt;
+
+const NULL: usize = 124;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    Vm,
+    Vs,
+    Lgkm,
+    Exp,
+}
+impl fmt::Display for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Counter::Vm => "vmcnt",
+            Counter::Vs => "vscnt",
+            Counter::Lgkm => "lgkmcnt",
+            Counter::Exp => "expcnt",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    pc: usize,
+    dst: Vec<RegRange>,
+    // SMEM returns out of order, only a wait for zero guarantees it landed
+    out_of_order: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub pc: usize,
+    pub reg: RegRange,
+    pub counter: Counter,
+    pub issued_at: usize,
+    pub write: bool,
+}
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pc=0x{:x} {} {} before s_waitcnt {} (memory op at pc=0x{:x} still outstanding)",
+            self.pc * 4,
+            if self.write { "overwrites" } else { "reads" },
+            self.reg,
+            self.counter,
+            self.issued_at * 4,
+        )
+    }
+}
+
+/* outstanding memory operations of a single wave */
+#[derive(Debug, Clone, Default)]
+pub struct WaitcntTracker {
+    vm: VecDeque<Pending>,
+    vs: VecDeque<Pending>,
+    lgkm: VecDeque<Pending>,
+    exp: VecDeque<Pending>,
+}
+
+impl WaitcntTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn queue(&mut self, counter: Counter) -> &mut VecDeque<Pending> {
+        match counter {
+            Counter::Vm => &mut self.vm,
+            Counter::Vs => &mut self.vs,
+            Counter::Lgkm => &mut self.lgkm,
+            Counter::Exp => &mut self.exp,
+        }
+    }
+
+    pub fn wait(&mut self, counter: Counter, count: usize) {
+        let queue = self.queue(counter);
+        if count == 0 {
+            queue.clear();
+        } else if !queue.iter().any(|p| p.out_of_order) {
+            while queue.len() > count {
+                queue.pop_front();
+            }
+        }
+    }
+
+    fn issue(&mut self, counter: Counter, pc: usize, dst: Vec<RegRange>, out_of_order: bool) {
+        self.queue(counter).push_back(Pending {
+            pc,
+            dst,
+            out_of_order,
+        });
+    }
+
+    /*
+     * advance the tracker over the instruction at `stream[0]`, returning the hazards it exposes.
+     * sgpr reads the wave's SGPRs as they are before it.
+     */
+    pub fn step(
+        &mut self,
+        stream: &[u32],
+        pc: usize,
+        sgpr: impl Fn(usize) -> u32,
+    ) -> Vec<Violation> {
+        let instruction = stream[0];
+        match encoding(instruction) {
+            // s_waitcnt
+            Encoding::Sopp if (instruction >> 16) & 0x7f == 9 => {
+                let simm16 = (instruction & 0xffff) as usize;
+                self.wait(Counter::Vm, (simm16 >> 10) & 0x3f);
+                self.wait(Counter::Lgkm, (simm16 >> 4) & 0x3f);
+                self.wait(Counter::Exp, simm16 & 0x7);
+                return vec![];
+            }
+            // s_waitcnt_{vscnt,vmcnt,expcnt,lgkmcnt}
+            Encoding::Sopk if matches!((instruction >> 23) & 0x1f, 24..=27) => {
+                // the count is sdst plus simm16 unless sdst is null
+                let count = match ((instruction >> 16) & 0x7f) as usize {
+                    NULL => 0,
+                    sdst => sgpr(sdst) as usize,
+                };
+                let count = count.saturating_add((instruction & 0xffff) as usize);
+                let counter = match (instruction >> 23) & 0x1f {
+                    24 => Counter::Vs,
+                    25 => Counter::Vm,
+                    26 => Counter::Exp,
+                    _ => Counter::Lgkm,
+                };
+                self.wait(counter, count);
+                return vec![];
+            }
+            _ => {}
+        }
+
+        let fp = footprint(stream);
+        let mut violations = vec![];
+        for (counter, queue) in [
+            (Counter::Vm, &self.vm),
+            (Counter::Vs, &self.vs),
+            (Counter::Lgkm, &self.lgkm),
+            (Counter::Exp, &self.exp),
+        ] {
+            for pending in queue.iter() {
+                let accesses = fp.reads.iter().map(|r| (r, false));
+                for (reg, write) in accesses.chain(fp.writes.iter().map(|r| (r, true))) {
+                    for dst in pending.dst.iter() {
+                        if let Some(reg) = reg.regs().find(|r| r.overlaps(dst)) {
+                            violations.push(Violation {
+                                pc,
+                                reg,
+                                counter,
+                                issued_at: pending.pc,
+                                write,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        match encoding(instruction) {
+            Encoding::Smem => self.issue(Counter::Lgkm, pc, fp.writes, true),
+            Encoding::Ds => self.issue(Counter::Lgkm, pc, fp.writes, false),
+            Encoding::Flat => {
+                let seg = (stream[0] >> 16) & 0x3;
+                let op = (stream[0] >> 18) & 0x7f;
+                let counter = match (fp.writes.is_empty(), matches!(op, 24..=29 | 36 | 37)) {
+                    (_, true) | (true, false) => Counter::Vs,
+                    _ => Counter::Vm,
+                };
+                // flat can hit LDS as well as memory
+                if seg == 0 {
+                    self.issue(Counter::Lgkm, pc, fp.writes.clone(), false);
+                }
+                self.issue(counter, pc, fp.writes, false);
+            }
+            _ => {}
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod test_waitcnt {
+    use super::*;
+
+    const S_LOAD_B64: [u32; 2] = [0xF4040080, 0xF8000000]; // s_load_b64 s[2:3], s[0:1], null
+    const GLOBAL_LOAD: [u32; 2] = [0xDC520000, 0x01020000]; // global_load_b32 v1, v0, s[2:3]
+
+    #[test]
+    fn test_smem_read_before_wait() {
+        let mut t = WaitcntTracker::new();
+        assert!(t.step(&S_LOAD_B64, 0, |_| 0).is_empty());
+        // s_mov_b32 s4, s3
+        let v = t.step(&[0xBE840003], 2, |_| 0);
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].reg, RegRange::sgpr(3, 1));
+        assert_eq!(v[0].counter, Counter::Lgkm);
+        assert_eq!(v[0].issued_at, 0);
+        assert_eq!(
+            v[0].to_string(),
+            "pc=0x8 reads s3 before s_waitcnt lgkmcnt (memory op at pc=0x0 still outstanding)"
+        );
+    }
+
+    #[test]
+    fn test_smem_waited() {
+        let mut t = WaitcntTracker::new();
+        t.step(&S_LOAD_B64, 0, |_| 0);
+        // s_waitcnt lgkmcnt(0)
+        assert!(t.step(&[0xBF89FC07], 2, |_| 0).is_empty());
+        assert_eq!(t.lgkm.len(), 0);
+        assert!(t.step(&[0xBE840003], 3, |_| 0).is_empty());
+    }
+
+    #[test]
+    fn test_smem_out_of_order() {
+        let mut t = WaitcntTracker::new();
+        t.step(&S_LOAD_B64, 0, |_| 0);
+        t.step(&S_LOAD_B64, 2, |_| 0);
+        // s_waitcnt lgkmcnt(1) doesn't tell which of the two landed
+        t.step(&[0xBF89FC17], 4, |_| 0);
+        assert_eq!(t.lgkm.len(), 2);
+    }
+
+    #[test]
+    fn test_global_load_in_order() {
+        let mut t = WaitcntTracker::new();
+        t.step(&GLOBAL_LOAD, 0, |_| 0);
+        // global_load_b32 v2, v0, s[2:3]
+        t.step(&[0xDC520000, 0x02020000], 2, |_| 0);
+        // s_waitcnt vmcnt(1)
+        t.step(&[0xBF8907F7], 4, |_| 0);
+        assert_eq!(t.vm.len(), 1);
+        // v_mov_b32 v3, v1
+        assert!(t.step(&[0x7E060301], 5, |_| 0).is_empty());
+        // v_mov_b32 v3, v2
+        let v = t.step(&[0x7E060302], 6, |_| 0);
+        assert_eq!(v[0].reg, RegRange::vgpr(2, 1));
+        assert_eq!(v[0].counter, Counter::Vm);
+    }
+
+    #[test]
+    fn test_overwrite_pending_load() {
+        let mut t = WaitcntTracker::new();
+        t.step(&GLOBAL_LOAD, 0, |_| 0);
+        // v_mov_b32 v1, 0
+        let v = t.step(&[0x7E020280], 2, |_| 0);
+        assert!(v[0].write);
+    }
+
+    #[test]
+    fn test_store_vscnt() {
+        let mut t = WaitcntTracker::new();
+        // global_store_b32 v0, v1, s[2:3]
+        t.step(&[0xDC6A0000, 0x00020100], 0, |_| 0);
+        assert_eq!(t.vs.len(), 1);
+        assert_eq!(t.vm.len(), 0);
+        // s_waitcnt_vscnt null, 0
+        t.step(&[0xBC7C0000], 2, |_| 0);
+        assert_eq!(t.vs.len(), 0);
+    }
+
+    #[test]
+    fn test_wait_through_sgpr() {
+        let mut t = WaitcntTracker::new();
+        let store = [0xDC6A0000, 0x00020100];
+        (0..3).for_each(|pc| _ = t.step(&store, pc, |_| 0));
+        // s_waitcnt_vscnt s5, 0 with s5 = 2
+        t.step(&[0xBC050000], 3, |idx| if idx == 5 { 2 } else { 0 });
+        assert_eq!(t.vs.len(), 2);
+        // s_waitcnt_vscnt s5, 1 with s5 = 0
+        t.step(&[0xBC050001], 4, |_| 0);
+        assert_eq!(t.vs.len(), 1);
+    }
+}
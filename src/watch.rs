@@ -0,0 +1,241 @@
+/*
+ * Watchpoints on global, LDS and scratch ranges and on SGPRs/VGPRs. Memory watchpoints fire on
+ * every store into the range, register watchpoints whenever an instruction changes the register.
+ * WATCH=global:0x7f00+16,lds:0x100,s5,v17,v17:3 prints every hit, the debugger can also stop on one.
+ */
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    Global,
+    Lds,
+    Scratch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Memory { space: Space, addr: u64, len: u64 },
+    Sgpr(usize),
+    /* every lane of the VGPR without a lane */
+    Vgpr { idx: usize, lane: Option<usize> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub target: Target,
+    pub stop: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    pub workgroup: [u32; 3],
+    pub wave: usize,
+    pub lane: Option<usize>,
+    pub pc: usize,
+    /* the bytes or register that changed, a part of the watched range for memory */
+    pub what: Target,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+impl Watchpoint {
+    /* global:<addr>[+len], lds:<addr>[+len], scratch:<addr>[+len], s<n>, v<n>[:lane] */
+    pub fn parse(s: &str) -> Option<Self> {
+        let target = match s.split_once(':') {
+            Some((space @ ("global" | "lds" | "scratch"), range)) => {
+                let (addr, len) = range.split_once('+').unwrap_or((range, "4"));
+                let space = match space {
+                    "global" => Space::Global,
+                    "lds" => Space::Lds,
+                    _ => Space::Scratch,
+                };
+                Target::Memory {
+                    space,
+                    addr: number(addr)?,
+                    len: number(len).filter(|&l| l > 0)?,
+                }
+            }
+            Some((reg, lane)) => Target::Vgpr {
+                idx: reg.strip_prefix('v')?.parse().ok().filter(|&i| i < 256)?,
+                lane: Some(lane.parse().ok().filter(|&l| l < 32)?),
+            },
+            None => match s.split_at_checked(1)? {
+                ("s", idx) => Target::Sgpr(idx.parse().ok().filter(|&i| i < 128)?),
+                ("v", idx) => Target::Vgpr {
+                    idx: idx.parse().ok().filter(|&i| i < 256)?,
+                    lane: None,
+                },
+                _ => return None,
+            },
+        };
+        Some(Watchpoint {
+            target,
+            stop: false,
+        })
+    }
+
+    /* the part of a store of len bytes at addr that lands in this watchpoint */
+    pub fn overlap(&self, space: Space, addr: u64, len: u64) -> Option<(u64, u64)> {
+        match self.target {
+            Target::Memory {
+                space: s,
+                addr: a,
+                len: l,
+            } if s == space => {
+                let start = addr.max(a);
+                let end = (addr + len).min(a + l);
+                (start < end).then(|| (start, end - start))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+pub fn parse_list(s: &str) -> Option<Vec<Watchpoint>> {
+    s.split(',')
+        .filter(|w| !w.is_empty())
+        .map(Watchpoint::parse)
+        .collect()
+}
+
+/* the watchpoints of a workgroup and the stores of the running lane that hit them */
+#[derive(Debug, Clone, Default)]
+pub struct Watcher {
+    pub points: Vec<Watchpoint>,
+    /* watchpoint, the overwritten part of it and its bytes before the store */
+    pub pending: Vec<(usize, Target, Vec<u8>)>,
+}
+
+impl Watcher {
+    pub fn new(points: Vec<Watchpoint>) -> Self {
+        Watcher {
+            points,
+            pending: vec![],
+        }
+    }
+
+    pub fn memory(&self) -> bool {
+        self.points
+            .iter()
+            .any(|p| matches!(p.target, Target::Memory { .. }))
+    }
+
+    pub fn overlaps(&self, space: Space, addr: u64, len: u64) -> Vec<(usize, Target)> {
+        let overlaps = self.points.iter().enumerate().filter_map(|(i, p)| {
+            let (addr, len) = p.overlap(space, addr, len)?;
+            Some((i, Target::Memory { space, addr, len }))
+        });
+        overlaps.collect()
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Memory { space, addr, len } => {
+                let space = format!("{space:?}").to_lowercase();
+                write!(f, "{space} 0x{addr:x}+{len}")
+            }
+            Target::Sgpr(idx) => write!(f, "s{idx}"),
+            Target::Vgpr { idx, .. } => write!(f, "v{idx}"),
+        }
+    }
+}
+
+/* little endian bytes as one number */
+fn value(bytes: &[u8]) -> String {
+    let digits = bytes.iter().rev().map(|b| format!("{b:02x}"));
+    format!("0x{}", digits.collect::<String>())
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} wave {}", self.workgroup, self.wave)?;
+        if let Some(lane) = self.lane {
+            write!(f, " lane {lane}")?;
+        }
+        write!(
+            f,
+            " pc=0x{:x} {} {} -> {}",
+            self.pc,
+            self.what,
+            value(&self.old),
+            value(&self.new)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test_watch {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let points = parse_list("global:0x100+8,lds:16,s5,v17,v17:3").unwrap();
+        let targets = points.iter().map(|p| p.target).collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            [
+                Target::Memory {
+                    space: Space::Global,
+                    addr: 0x100,
+                    len: 8
+                },
+                Target::Memory {
+                    space: Space::Lds,
+                    addr: 16,
+                    len: 4
+                },
+                Target::Sgpr(5),
+                Target::Vgpr {
+                    idx: 17,
+                    lane: None
+                },
+                Target::Vgpr {
+                    idx: 17,
+                    lane: Some(3)
+                },
+            ]
+        );
+        assert_eq!(parse_list(""), Some(vec![]));
+        assert_eq!(Watchpoint::parse("v300"), None);
+        assert_eq!(Watchpoint::parse("v1:32"), None);
+        assert_eq!(Watchpoint::parse("lds:4+0"), None);
+        assert_eq!(Watchpoint::parse("x1"), None);
+    }
+
+    #[test]
+    fn test_overlap() {
+        let w = Watchpoint::parse("global:0x100+8").unwrap();
+        assert_eq!(w.overlap(Space::Global, 0xfc, 8), Some((0x100, 4)));
+        assert_eq!(w.overlap(Space::Global, 0x104, 16), Some((0x104, 4)));
+        assert_eq!(w.overlap(Space::Global, 0x108, 4), None);
+        assert_eq!(w.overlap(Space::Lds, 0x100, 4), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let hit = Hit {
+            workgroup: [1, 0, 0],
+            wave: 1,
+            lane: Some(3),
+            pc: 0x1c,
+            what: Target::Memory {
+                space: Space::Lds,
+                addr: 0x10,
+                len: 4,
+            },
+            old: vec![0, 0, 0, 0],
+            new: vec![0x2a, 0, 0, 0],
+        };
+        let s = "[1, 0, 0] wave 1 lane 3 pc=0x1c lds 0x10+4 0x00000000 -> 0x0000002a";
+        assert_eq!(hit.to_string(), s);
+    }
+}
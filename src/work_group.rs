@@ -1,55 +1,200 @@
-use crate::memory::VecDataStore;
+use crate::coverage::{Coverage, COVERAGE};
+use crate::debugger::Breakpoint;
+use crate::decode::{footprint, Encoding, RegFile, RegRange, EXEC, VCC};
+use crate::error::{Location, RemuError, TIMED_OUT};
+use crate::hazard::HazardTracker;
+use crate::memory::{Memory, Raw, VecDataStore};
+use crate::mnemonic::mnemonic;
+use crate::perf::PerfTracker;
+use crate::program::{Inst, Kind, Program};
+use crate::schedule::{Rng, Schedule};
 use crate::state::{Register, WaveValue, VGPR};
 use crate::thread::Thread;
-use crate::utils::{Colorize, CI, END_PRG, GLOBAL_COUNTER, GLOBAL_DEBUG, PROFILE};
-use std::collections::HashMap;
-
-struct WaveState(
-    Vec<u32>,
-    u32,
-    VGPR,
-    WaveValue,
-    WaveValue,
-    usize,
-    HashMap<usize, VecDataStore>,
-);
-pub struct WorkGroup<'a> {
+use crate::trace::{self, Record, Trace, TRACE};
+use crate::trap::{
+    float_exceptions, lane_floats, Trap, FAULT, HW_REG_MODE, HW_REG_TRAPSTS, MODE_EXCP_EN_SHIFT,
+};
+use crate::undo::{self, Delta, Snapshot, UndoLog};
+use crate::uninit::{DefinedBytes, Uninit, UninitTracker};
+use crate::utils::{
+    Colorize, CHECK_HAZARDS, CHECK_LDS, CHECK_UNINIT, CHECK_WAITCNT, GLOBAL_COUNTER, GLOBAL_DEBUG,
+    PERF, PROFILE, SCHEDULE, WATCH,
+};
+use crate::waitcnt::WaitcntTracker;
+use crate::watch::{Hit, Space, Target, Watcher};
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveStatus {
+    Running,
+    Barrier,
+    Done,
+}
+
+pub struct WaveState {
+    pub(crate) threads: Vec<[u32; 3]>,
+    pub(crate) scalar_reg: Vec<u32>,
+    pub(crate) scc: u32,
+    pub(crate) vec_reg: VGPR,
+    pub(crate) vcc: WaveValue,
+    pub(crate) exec: WaveValue,
+    pub(crate) pc: usize,
+    pub(crate) sds: Vec<VecDataStore>,
+    waitcnt: WaitcntTracker,
+    hazards: HazardTracker,
+    uninit: UninitTracker,
+    pub(crate) perf: PerfTracker,
+    pub(crate) hw_reg: [u32; 64],
+    pub(crate) status: WaveStatus,
+    /* set for waves recorded for reverse debugging */
+    pub(crate) undo: Option<UndoLog>,
+}
+pub struct WorkGroup {
     dispatch_dim: u32,
-    id: [u32; 3],
-    lds: VecDataStore,
-    kernel: &'a Vec<u32>,
+    pub(crate) id: [u32; 3],
+    pub(crate) lds: VecDataStore,
+    program: Arc<Program>,
     kernel_args: *const u64,
     launch_bounds: [u32; 3],
-    wave_state: HashMap<usize, WaveState>,
+    pub(crate) waves: Vec<WaveState>,
+    pub schedule: Schedule,
+    rng: Option<Rng>,
+    pub breakpoints: Vec<Breakpoint>,
+    /* the wave a breakpoint stopped, resumed waves pass the breakpoint they're at once */
+    stopped: Option<usize>,
+    resumed: Vec<usize>,
+    pub watcher: Watcher,
+    /* the stopping watchpoint hit that stopped a wave after its instruction */
+    pub watch_hit: Option<Hit>,
+    pub trace: Option<Trace>,
+    /* step every wave's performance model, PERF by default */
+    pub perf: bool,
+    /* the opcodes the waves ran, set when COVERAGE is */
+    pub coverage: Option<Coverage>,
+    /* old bytes of the stores the running instruction made, set while it's recorded or traced */
+    stores: Option<Vec<Delta>>,
+    /* why a run failed */
+    pub error: Option<RemuError>,
+    /* waves fail with TIMED_OUT once it passed */
+    pub deadline: Option<Instant>,
+    /* wave and pc of the instruction running, where a panic in it happened */
+    pub running: Option<(usize, usize)>,
+    /* global and SMEM accesses go through it, host pointers by default */
+    pub memory: Arc<dyn Memory>,
+    /* the uses of undefined data the waves made, set when CHECK_UNINIT is */
+    pub uninit: Option<Vec<Uninit>>,
+    lds_defined: DefinedBytes,
 }
 
-const SYNCS: [u32; 5] = [0xBF89FC07, 0xBFBD0000, 0xBC7C0000, 0xBF890007, 0xbFB60003];
-const BARRIERS: [[u32; 2]; 4] = [
-    [SYNCS[0], SYNCS[0]],
-    [SYNCS[0], SYNCS[1]],
-    [SYNCS[0], SYNCS[2]],
-    [SYNCS[3], SYNCS[1]],
-];
-impl<'a> WorkGroup<'a> {
+/* where a wave goes after an instruction */
+enum Next {
+    Pc(usize),
+    Status(WaveStatus),
+}
+
+pub const TTMP: usize = 108;
+impl WorkGroup {
     pub fn new(
         dispatch_dim: u32,
         id: [u32; 3],
         launch_bounds: [u32; 3],
-        kernel: &'a Vec<u32>,
+        program: Arc<Program>,
         kernel_args: *const u64,
+        lds: usize,
     ) -> Self {
         return Self {
             dispatch_dim,
             id,
-            kernel,
+            program,
             launch_bounds,
             kernel_args,
-            lds: VecDataStore::new(),
-            wave_state: HashMap::new(),
+            lds: VecDataStore::fixed(lds),
+            waves: vec![],
+            schedule: *SCHEDULE,
+            rng: None,
+            breakpoints: vec![],
+            stopped: None,
+            resumed: vec![],
+            watcher: Watcher::new(WATCH.clone()),
+            watch_hit: None,
+            trace: TRACE.clone(),
+            perf: *PERF,
+            coverage: COVERAGE.as_ref().map(|_| Coverage::default()),
+            stores: None,
+            error: None,
+            deadline: None,
+            running: None,
+            memory: Arc::new(Raw::default()),
+            uninit: CHECK_UNINIT.then(Vec::new),
+            lds_defined: DefinedBytes::default(),
         };
     }
 
+    fn init_wave(&self, threads: Vec<[u32; 3]>) -> WaveState {
+        let mut scalar_reg = vec![0; 256];
+        scalar_reg.write64(0, self.kernel_args as u64);
+        let [gx, gy, gz] = self.id;
+        let ids = match self.dispatch_dim {
+            3 => {
+                (scalar_reg[13], scalar_reg[14], scalar_reg[15]) = (gx, gy, gz);
+                RegRange::sgpr(13, 3)
+            }
+            2 => {
+                (scalar_reg[14], scalar_reg[15]) = (gx, gy);
+                RegRange::sgpr(14, 2)
+            }
+            _ => {
+                scalar_reg[15] = gx;
+                RegRange::sgpr(15, 1)
+            }
+        };
+        let mut vec_reg = VGPR::new();
+        for (lane_id, [x, y, z]) in threads.iter().enumerate() {
+            vec_reg.get_lane_mut(lane_id)[0] =
+                match (self.launch_bounds[1] != 1, self.launch_bounds[2] != 1) {
+                    (false, false) => *x,
+                    _ => (z << 20) | (y << 10) | x,
+                };
+        }
+        let active = match threads.len() == 32 {
+            true => u32::MAX,
+            false => (1 << threads.len()) - 1,
+        };
+        WaveState {
+            scalar_reg,
+            scc: 0,
+            vec_reg,
+            vcc: WaveValue::new(0, threads.len()),
+            exec: WaveValue::new(active, threads.len()),
+            pc: 0,
+            sds: vec![VecDataStore::new(); 32],
+            waitcnt: WaitcntTracker::new(),
+            hazards: HazardTracker::new(),
+            uninit: UninitTracker::new(&[RegRange::sgpr(0, 2), ids, RegRange::vgpr(0, 1)], active),
+            perf: PerfTracker::new(),
+            hw_reg: [0; 64],
+            status: WaveStatus::Running,
+            undo: None,
+            threads,
+        }
+    }
+
+    /* run every wave up to its next s_barrier or s_endpgm, release the barrier once all live waves arrived */
     pub fn exec_waves(&mut self) -> Result<(), i32> {
+        self.init_waves();
+        self.resume()?;
+        // coverage runs past unimplemented instructions, the launch still fails
+        if let Some(coverage) = &self.coverage {
+            if !coverage.missing().is_empty() {
+                return Err(1);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn init_waves(&mut self) {
         let mut blocks = vec![];
         for z in 0..self.launch_bounds[2] {
             for y in 0..self.launch_bounds[1] {
@@ -58,161 +203,546 @@ impl<'a> WorkGroup<'a> {
                 }
             }
         }
-        let waves = blocks.chunks(32).map(|w| w.to_vec()).collect::<Vec<_>>();
+        self.waves = blocks
+            .chunks(32)
+            .map(|w| self.init_wave(w.to_vec()))
+            .collect();
+    }
+
+    /* run until all waves are done or a breakpoint stops one, returns the stopped wave */
+    pub fn resume(&mut self) -> Result<Option<usize>, i32> {
+        let stopped = self.stopped.take();
+        // a watchpoint stops a wave after the instruction, there's no breakpoint to pass
+        if self.watch_hit.take().is_none() {
+            self.resumed.extend(stopped);
+        }
+        let mut waves = std::mem::take(&mut self.waves);
+        let ret = self.schedule(&mut waves);
+        self.waves = waves;
+        ret.map(|_| self.stopped)
+    }
 
-        let mut syncs = 0;
-        self.kernel.iter().enumerate().for_each(|(i, x)| {
-            if i != 0 && BARRIERS.contains(&[*x, self.kernel[i - 1]]) {
-                syncs += 1;
-                if *PROFILE {
-                    GLOBAL_COUNTER.lock().unwrap().wave_syncs += 1;
-                }
-            }
-        });
-        assert!(syncs <= 1);
-        for _ in 0..=syncs {
-            for w in waves.iter().enumerate() {
-                self.exec_wave(w)?
+    /* run one instruction of a wave past any breakpoint, a wave waiting at a barrier only moves once it releases */
+    pub fn step(&mut self, wave_id: usize) -> Result<(), i32> {
+        let mut waves = std::mem::take(&mut self.waves);
+        if waves.iter().all(|w| w.status != WaveStatus::Running) {
+            release_barrier(&mut waves);
+        }
+        let ret = match waves[wave_id].status {
+            WaveStatus::Running => {
+                self.resumed.push(wave_id);
+                let wave = &mut waves[wave_id];
+                self.exec_wave(wave_id, wave, 1)
+                    .map(|status| wave.status = status)
             }
+            _ => Ok(()),
+        };
+        self.waves = waves;
+        if self.stopped == Some(wave_id) {
+            self.stopped = None;
         }
-        Ok(())
+        ret
+    }
+
+    /* undo the last recorded instruction of a wave, false once its history is used up */
+    pub fn step_back(&mut self, wave_id: usize) -> bool {
+        let wave = &mut self.waves[wave_id];
+        let Some(entry) = wave.undo.as_mut().and_then(|u| u.pop()) else {
+            return false;
+        };
+        undo::restore(wave, &mut self.lds, &*self.memory, entry);
+        // going forward again runs the instruction the wave is back at
+        if self.stopped == Some(wave_id) {
+            self.stopped = None;
+            self.watch_hit = None;
+        }
+        if !self.resumed.contains(&wave_id) {
+            self.resumed.push(wave_id);
+        }
+        true
     }
 
-    fn exec_wave(&mut self, (wave_id, threads): (usize, &Vec<[u32; 3]>)) -> Result<(), i32> {
-        let wave_state = self.wave_state.get(&wave_id);
-        let mut sds = match wave_state {
-            Some(val) => val.6.clone(),
-            None => {
-                let mut sds = HashMap::new();
-                for i in 0..=31 {
-                    sds.insert(i, VecDataStore::new());
+    fn schedule(&mut self, waves: &mut [WaveState]) -> Result<(), i32> {
+        // a random schedule still gives every workgroup its own reproducible stream
+        let [x, y, z] = self.id.map(|i| i as u64);
+        let seed = match self.schedule {
+            Schedule::Random(seed) => seed,
+            _ => 0,
+        };
+        let mut rng = self
+            .rng
+            .take()
+            .unwrap_or_else(|| Rng::new(seed ^ (x << 42 | y << 21 | z)));
+        let ret = loop {
+            let running = (0..waves.len())
+                .filter(|&i| waves[i].status == WaveStatus::Running)
+                .collect::<Vec<_>>();
+            if running.is_empty() {
+                if waves.iter().all(|w| w.status == WaveStatus::Done) {
+                    break Ok(());
                 }
-                sds
+                release_barrier(waves);
+                continue;
             }
-        };
-        let (mut scalar_reg, mut scc, mut pc) = match wave_state {
-            Some(val) => (val.0.to_vec(), val.1, val.5),
-            None => {
-                let mut scalar_reg = vec![0; 256];
-                scalar_reg.write64(0, self.kernel_args as u64);
-                let [gx, gy, gz] = self.id;
-                match self.dispatch_dim {
-                    3 => (scalar_reg[13], scalar_reg[14], scalar_reg[15]) = (gx, gy, gz),
-                    2 => (scalar_reg[14], scalar_reg[15]) = (gx, gy),
-                    _ => scalar_reg[15] = gx,
+            let (order, budget) = match self.schedule {
+                Schedule::Wave => (running, usize::MAX),
+                Schedule::RoundRobin => (running, 1),
+                Schedule::Random(_) => (vec![running[rng.below(running.len())]], 1),
+                Schedule::Reverse => (running.into_iter().rev().collect(), usize::MAX),
+            };
+            for wave_id in order {
+                waves[wave_id].status = self.exec_wave(wave_id, &mut waves[wave_id], budget)?;
+                if self.stopped.is_some() {
+                    break;
                 }
-                (scalar_reg, 0, 0)
             }
-        };
-        let (mut vec_reg, mut vcc) = match wave_state {
-            Some(val) => (val.2.clone(), val.3.clone()),
-            None => (VGPR::new(), WaveValue::new(0, threads.len())),
-        };
-        let mut exec = match wave_state {
-            Some(val) => val.4.clone(),
-            None => {
-                let active = match threads.len() == 32 {
-                    true => u32::MAX,
-                    false => (1 << threads.len()) - 1,
-                };
-                WaveValue::new(active, threads.len())
+            if self.stopped.is_some() {
+                break Ok(());
             }
         };
+        self.rng = Some(rng);
+        ret
+    }
+
+    /* a breakpoint on this instruction stops the wave before it runs */
+    fn breaks(&mut self, wave_id: usize, inst: &Inst, code: &[u32]) -> bool {
+        if self.breakpoints.is_empty() {
+            return false;
+        }
+        if let Some(i) = self.resumed.iter().position(|&w| w == wave_id) {
+            self.resumed.swap_remove(i);
+            return false;
+        }
+        let id = self.id;
+        let hit = self
+            .breakpoints
+            .iter()
+            .any(|b| b.hits(id, wave_id, inst.pc, code));
+        if hit {
+            self.stopped = Some(wave_id);
+        }
+        hit
+    }
+
+    /* where the instruction that panicked was */
+    pub fn panicked(&self, message: String) -> RemuError {
+        let at = self.running.map(|(wave, pc)| Location {
+            pc: pc * 4,
+            word: self.program.code[pc],
+            workgroup: Some(self.id),
+            wave: Some(wave),
+            lane: None,
+        });
+        RemuError::Panic { message, at }
+    }
 
-        let mut seeded_lanes = vec![];
+    /* run a wave for at most budget instructions */
+    fn exec_wave(
+        &mut self,
+        wave_id: usize,
+        wave: &mut WaveState,
+        mut budget: usize,
+    ) -> Result<WaveStatus, i32> {
+        let program = self.program.clone();
         loop {
-            if self.kernel[pc] == END_PRG {
-                if *CI {
-                    self.wave_state.insert(
-                        wave_id,
-                        WaveState(scalar_reg, scc, vec_reg, vcc, exec, pc, sds),
-                    );
-                }
-                break Ok(());
-            }
-            if BARRIERS.contains(&[self.kernel[pc], self.kernel[pc + 1]]) && wave_state.is_none() {
-                self.wave_state.insert(
-                    wave_id,
-                    WaveState(scalar_reg, scc, vec_reg, vcc, exec, pc, sds),
-                );
-                break Ok(());
+            if self.deadline.is_some_and(|d| Instant::now() >= d) {
+                self.error = Some(RemuError::Timeout(Location {
+                    pc: wave.pc * 4,
+                    word: program.code.get(wave.pc).copied().unwrap_or(0),
+                    workgroup: Some(self.id),
+                    wave: Some(wave_id),
+                    lane: None,
+                }));
+                return Err(TIMED_OUT);
             }
-            if SYNCS.contains(&self.kernel[pc])
-                || self.kernel[pc] >> 20 == 0xbf8
-                || self.kernel[pc] == 0x7E000000
-            {
-                pc += 1;
+            // ran off the end of the kernel
+            if wave.pc >= program.code.len() {
+                wave.pc =
+                    self.enter_trap(wave_id, wave, Trap::IllegalInstruction, wave.pc, None)?;
                 continue;
             }
-
-            let mut sgpr_co = None;
-            for (lane_id, [x, y, z]) in threads.iter().enumerate() {
-                vec_reg.default_lane = Some(lane_id);
-                vcc.default_lane = Some(lane_id);
-                exec.default_lane = Some(lane_id);
-                if *GLOBAL_DEBUG {
-                    let lane = format!("{lane_id} {:08X} ", self.kernel[pc]);
-                    let state = match exec.read() {
-                        true => "green",
-                        false => "gray",
-                    };
-                    print!("{:?} {:?} {}", self.id, [x, y, z], lane.color(state));
+            // jumps into the middle of a decoded instruction run whatever is there
+            let single;
+            let block = match program.block(wave.pc) {
+                Some(block) => block,
+                None => {
+                    single = [Inst::decode(&program.code, wave.pc)];
+                    &single[..]
+                }
+            };
+            for inst in block {
+                let pc = inst.pc;
+                if self.breaks(wave_id, inst, &program.code) {
+                    return Ok(WaveStatus::Running);
                 }
-                if !seeded_lanes.contains(&lane_id) && self.wave_state.get(&wave_id).is_none() {
-                    match (self.launch_bounds[1] != 1, self.launch_bounds[2] != 1) {
-                        (false, false) => vec_reg[0] = *x,
-                        _ => vec_reg[0] = (z << 20) | (y << 10) | x,
+                let traced =
+                    (self.trace.as_ref()).is_some_and(|t| t.filter.keeps(self.id, wave_id, pc * 4));
+                let before = (traced || wave.undo.is_some())
+                    .then(|| Snapshot::of(wave, &program.code[pc..]));
+                self.stores = before.as_ref().map(|_| vec![]);
+                let next = self.exec_inst(wave_id, wave, inst, &program.code)?;
+                self.running = None;
+                if let Some(before) = before {
+                    let mut deltas = before.deltas(wave);
+                    deltas.extend(self.stores.take().unwrap_or_default());
+                    if let Some(trace) = self.trace.as_ref().filter(|_| traced) {
+                        trace.record(Record {
+                            workgroup: self.id,
+                            wave: wave_id,
+                            pc: pc * 4,
+                            word: inst.word,
+                            mnemonic: mnemonic(&program.code[pc..]),
+                            exec: before.exec,
+                            writes: trace::writes(&deltas, wave, &self.lds, &*self.memory),
+                        });
                     }
-                    seeded_lanes.push(lane_id);
+                    before.commit(wave, deltas);
                 }
-                let mut thread = Thread {
-                    scalar_reg: &mut scalar_reg,
-                    scc: &mut scc,
-                    vec_reg: &mut vec_reg,
-                    vcc: &mut vcc,
-                    exec: &mut exec,
-                    lds: &mut self.lds,
-                    sds: &mut sds.get_mut(&lane_id).unwrap(),
-                    pc_offset: 0,
-                    stream: self.kernel[pc..self.kernel.len()].to_vec(),
-                    scalar: false,
-                    simm: None,
-                    warp_size: threads.len(),
-                    sgpr_co: &mut sgpr_co,
-                };
-                thread.interpret()?;
-                if thread.scalar {
-                    pc = ((pc as isize) + 1 + (thread.pc_offset as isize)) as usize;
+                match next {
+                    Next::Status(status) => return Ok(status),
+                    Next::Pc(next) => wave.pc = next,
+                }
+                if self.watch_hit.is_some() {
+                    self.stopped = Some(wave_id);
+                    return Ok(WaveStatus::Running);
+                }
+                budget -= 1;
+                if budget == 0 {
+                    return Ok(WaveStatus::Running);
+                }
+                if wave.pc != pc + inst.len {
                     break;
                 }
-                if lane_id == threads.len() - 1 {
-                    pc = ((pc as isize) + 1 + (thread.pc_offset as isize)) as usize;
+            }
+        }
+    }
+
+    fn exec_inst(
+        &mut self,
+        wave_id: usize,
+        wave: &mut WaveState,
+        inst: &Inst,
+        code: &[u32],
+    ) -> Result<Next, i32> {
+        let (pc, instruction, enc) = (inst.pc, inst.word, inst.enc);
+        let stream = &code[pc..];
+        self.running = Some((wave_id, pc));
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(stream);
+        }
+        match inst.kind {
+            Kind::EndPgm => return Ok(Next::Status(WaveStatus::Done)),
+            Kind::Barrier => return Ok(Next::Status(WaveStatus::Barrier)),
+            _ => {}
+        }
+        if *CHECK_WAITCNT {
+            let sgpr = |idx| match idx {
+                VCC => wave.vcc.value,
+                EXEC => wave.exec.value,
+                idx => wave.scalar_reg[idx],
+            };
+            for v in wave.waitcnt.step(stream, pc, sgpr) {
+                println!("[remu] waitcnt: {:?} wave {wave_id} {v}", self.id);
+            }
+        }
+        if *CHECK_HAZARDS {
+            for h in wave.hazards.step(stream, pc) {
+                println!("[remu] hazard: {:?} wave {wave_id} {h}", self.id);
+            }
+        }
+        if self.perf {
+            wave.perf.step(stream);
+        }
+        match inst.kind {
+            Kind::Skip => return Ok(Next::Pc(pc + 1)),
+            Kind::Trap(trap) => {
+                return self
+                    .enter_trap(wave_id, wave, trap, pc + 1, None)
+                    .map(Next::Pc)
+            }
+            Kind::Rfe(sreg) => {
+                let ret = wave.scalar_reg.read64(sreg);
+                return Ok(Next::Pc((ret & 0xffff_ffff_ffff) as usize / 4));
+            }
+            _ => {}
+        }
+
+        let excp_en = (wave.hw_reg[HW_REG_MODE] >> MODE_EXCP_EN_SHIFT) & 0x7f;
+        let float_fp = match excp_en != 0 && inst.float {
+            true => Some(footprint(stream)),
+            false => None,
+        };
+        let scalar = inst.scalar;
+        let once = scalar || inst.ignores_exec;
+        let active = wave.exec.value;
+        let mut lanes_run = 0;
+        let mut trap_lane = None;
+        let mut sgpr_co = None;
+        let regs = self.watched_regs(wave);
+        let workgroup = self.id;
+        let hit = |what, lane, old, new| Hit {
+            workgroup,
+            wave: wave_id,
+            lane,
+            pc: pc * 4,
+            what,
+            old,
+            new,
+        };
+        let at = |lane| Location {
+            pc: pc * 4,
+            word: instruction,
+            workgroup: Some(workgroup),
+            wave: Some(wave_id),
+            lane,
+        };
+        let mut hits = vec![];
+        let mut accesses = vec![];
+        let watch = match self.watcher.memory() {
+            true => Some(&mut self.watcher),
+            false => None,
+        };
+        let mut thread = Thread {
+            scalar_reg: &mut wave.scalar_reg,
+            scc: &mut wave.scc,
+            vec_reg: &mut wave.vec_reg,
+            vcc: &mut wave.vcc,
+            exec: &mut wave.exec,
+            lds: &mut self.lds,
+            sds: &mut wave.sds,
+            pc_offset: 0,
+            operands: inst.operands,
+            scalar: false,
+            warp_size: wave.threads.len(),
+            sgpr_co: &mut sgpr_co,
+            hw_reg: &mut wave.hw_reg,
+            trap: None,
+            watch,
+            stores: self.stores.as_mut(),
+            accesses: (self.uninit.is_some() || *CHECK_LDS).then_some(&mut accesses),
+            memory: &*self.memory,
+        };
+        /*
+         * scalar instructions and the lane access ops run once per wave, vector instructions once
+         * per active lane
+         */
+        let lanes = wave.threads.iter().enumerate();
+        for (lane_id, [x, y, z]) in lanes.filter(|(l, _)| once || (active >> l) & 1 == 1) {
+            thread.set_lane(lane_id);
+            if *GLOBAL_DEBUG {
+                let lane = format!("{lane_id} {:08X} ", instruction);
+                print!("{:?} {:?} {}", self.id, [x, y, z], lane.color("green"));
+            }
+            let inputs = match &float_fp {
+                Some(fp) => Some(lane_floats(
+                    &fp.reads,
+                    thread.scalar_reg,
+                    &thread.vec_reg.get_lane(lane_id),
+                )),
+                _ => None,
+            };
+            // handlers only fail on instructions remu doesn't implement
+            if let Err(err) = (inst.handler)(&mut thread) {
+                let lane = (!scalar).then_some(lane_id);
+                self.error.get_or_insert(RemuError::Unimplemented(at(lane)));
+                match self.coverage.as_mut() {
+                    Some(coverage) => {
+                        coverage.unimplemented(stream);
+                        break;
+                    }
+                    None => return Err(err),
+                }
+            }
+            lanes_run += 1;
+            for (point, what, old, new) in thread.watched_stores() {
+                hits.push((point, hit(what, Some(lane_id), old, new)));
+            }
+            if let (Some(fp), Some(inputs)) = (&float_fp, inputs) {
+                let outputs = lane_floats(
+                    &fp.writes,
+                    thread.scalar_reg,
+                    &thread.vec_reg.get_lane(lane_id),
+                );
+                let excp = float_exceptions(&inputs, &outputs);
+                thread.hw_reg[HW_REG_TRAPSTS] |= excp;
+                if excp & excp_en != 0 {
+                    thread.trap = Some(Trap::Float(excp & excp_en));
                 }
             }
+            if thread.trap.is_some() && !scalar && !thread.scalar {
+                trap_lane = Some(lane_id);
+            }
+            if once || thread.scalar || thread.trap.is_some() {
+                break;
+            }
+        }
+        let (trap, pc_offset) = (thread.trap, thread.pc_offset);
 
-            if vcc.mutations.is_some() {
-                vcc.apply_muts();
-                vcc.mutations = None;
+        // compares and carry-outs still clear their lane mask when no lane is active
+        if lanes_run == 0 && matches!(enc, Encoding::Vopc | Encoding::Vop3) {
+            let op = (instruction >> 16) & 0x3ff;
+            if enc == Encoding::Vopc || matches!(op, 0..=255 | 288..=290 | 764..=770) {
+                for reg in footprint(stream).writes {
+                    match reg.idx {
+                        VCC => wave.vcc.value = 0,
+                        EXEC => wave.exec.value = 0,
+                        idx if reg.file == RegFile::Sgpr => wave.scalar_reg[idx] = 0,
+                        _ => {}
+                    }
+                }
             }
-            if exec.mutations.is_some() {
-                exec.apply_muts();
-                exec.mutations = None;
+        }
+        if wave.vcc.mutations.is_some() {
+            wave.vcc.apply_muts();
+            wave.vcc.mutations = None;
+        }
+        if wave.exec.mutations.is_some() {
+            wave.exec.apply_muts();
+            wave.exec.mutations = None;
+        }
+        if let Some((idx, mut wv)) = sgpr_co.take() {
+            wv.apply_muts();
+            wave.scalar_reg[idx] = wv.value;
+        }
+        if *CHECK_LDS {
+            let outside = accesses.iter().filter(|a| a.space == Space::Lds);
+            for a in outside.filter(|a| !self.lds.contains(a.addr, a.len)) {
+                // the dwords before the first one that doesn't fit still go through
+                let skip = (0..a.len / 4).take_while(|i| self.lds.contains(a.addr + i * 4, 4));
+                let start = a.addr + skip.count() as u64 * 4;
+                println!(
+                    "[remu] lds: {:?} wave {wave_id} lane {} pc=0x{:x} {} {} byte(s) at 0x{:x} past the {} byte(s) of LDS",
+                    self.id,
+                    a.lane,
+                    pc * 4,
+                    if a.store { "drops a store of" } else { "reads zero for" },
+                    a.addr + a.len - start,
+                    start,
+                    self.lds.data.len(),
+                );
+            }
+        }
+        if let Some(found) = self.uninit.as_mut() {
+            let lds = &mut self.lds_defined;
+            for u in wave.uninit.step(stream, pc, active, &accesses, lds) {
+                println!("[remu] uninit: {:?} wave {wave_id} {u}", self.id);
+                found.push(u);
             }
-            if let Some((idx, mut wv)) = sgpr_co.take() {
-                wv.apply_muts();
-                scalar_reg[idx] = wv.value;
+        }
+        for (point, what, lane, old) in regs {
+            let new = reg_value(wave, what, lane);
+            if new != old {
+                let (old, new) = (old.to_le_bytes().into(), new.to_le_bytes().into());
+                hits.push((point, hit(what, lane, old, new)));
+            }
+        }
+        for (point, hit) in hits {
+            println!("[remu] watch: {hit}");
+            if self.watcher.points[point].stop && self.watch_hit.is_none() {
+                self.watch_hit = Some(hit);
+            }
+        }
+        let next = match (trap, lanes_run) {
+            (Some(trap), _) => self.enter_trap(wave_id, wave, trap, pc, trap_lane)?,
+            (None, _) => ((pc + inst.len) as isize + (pc_offset as isize)) as usize,
+        };
+        Ok(Next::Pc(next))
+    }
+
+    /* the watched registers of a wave as they are before an instruction */
+    fn watched_regs(&self, wave: &WaveState) -> Vec<(usize, Target, Option<usize>, u32)> {
+        let mut regs = vec![];
+        for (point, w) in self.watcher.points.iter().enumerate() {
+            let lanes = match w.target {
+                Target::Sgpr(_) => vec![None],
+                Target::Vgpr { lane: Some(l), .. } if l < wave.threads.len() => vec![Some(l)],
+                Target::Vgpr { lane: None, .. } => (0..wave.threads.len()).map(Some).collect(),
+                _ => vec![],
+            };
+            for lane in lanes {
+                regs.push((point, w.target, lane, reg_value(wave, w.target, lane)));
+            }
+        }
+        regs
+    }
+
+    /* jump to the trap handler with the return pc in ttmp[0:1], or fault the launch */
+    fn enter_trap(
+        &mut self,
+        wave_id: usize,
+        wave: &mut WaveState,
+        trap: Trap,
+        ret: usize,
+        lane: Option<usize>,
+    ) -> Result<usize, i32> {
+        wave.hw_reg[HW_REG_TRAPSTS] |= trap.trapsts();
+        match self.program.trap_handler {
+            // a trap inside the handler can't be handled
+            Some(handler) if wave.pc < handler => {
+                let ret = ret as u64 * 4;
+                wave.scalar_reg[TTMP] = ret as u32;
+                wave.scalar_reg[TTMP + 1] = ((ret >> 32) as u32 & 0xffff) | (trap.id() << 16);
+                Ok(handler)
+            }
+            _ => {
+                let at = Location {
+                    pc: wave.pc * 4,
+                    word: self.program.code.get(wave.pc).copied().unwrap_or(0),
+                    workgroup: Some(self.id),
+                    wave: Some(wave_id),
+                    lane,
+                };
+                let mut err = RemuError::fault(at, trap);
+                if let RemuError::MemoryFault { addr, nearest, .. } = &mut err {
+                    *nearest = self.memory.nearest(*addr);
+                }
+                println!("[remu] fault: {err}");
+                self.error = Some(err);
+                Err(FAULT)
             }
         }
     }
 }
 
+fn reg_value(wave: &WaveState, target: Target, lane: Option<usize>) -> u32 {
+    match (target, lane) {
+        (Target::Sgpr(idx), _) => wave.scalar_reg[idx],
+        (Target::Vgpr { idx, .. }, Some(lane)) => wave.vec_reg.get_lane(lane)[idx],
+        _ => 0,
+    }
+}
+
+fn release_barrier(waves: &mut [WaveState]) {
+    if *PROFILE {
+        GLOBAL_COUNTER.wave_syncs.fetch_add(1, Relaxed);
+    }
+    // waves leave the barrier when the last one reaches it
+    let cycle = waves.iter().map(|w| w.perf.cycle).max().unwrap_or(0);
+    for wave in waves.iter_mut().filter(|w| w.status == WaveStatus::Barrier) {
+        wave.perf.cycle = cycle;
+        if let Some(undo) = wave.undo.as_mut() {
+            undo.push(undo::Entry {
+                pc: wave.pc,
+                status: WaveStatus::Barrier,
+                deltas: vec![],
+            });
+        }
+        wave.pc += 1;
+        wave.status = WaveStatus::Running;
+    }
+}
+
 #[cfg(test)]
 mod test_workgroup {
     use super::*;
+    use crate::allocation::{Allocation, Allocations, READ, WRITE};
+    use crate::memory::MAX_LDS;
+    use crate::program::S_BARRIER;
+    use crate::utils::END_PRG;
+    use crate::watch::Watchpoint;
 
     #[test]
     fn test_wave_value_state_vcc() {
-        assert!(*CI, "NOTE: this tests needs CI=1");
         let kernel = vec![
             0xBEEA00FF,
             0b11111111111111111111111111111111, // initial vcc state
@@ -221,15 +751,22 @@ mod test_workgroup {
             END_PRG,
         ];
         let args = vec![];
-        let mut wg = WorkGroup::new(1, [0, 0, 0], [3, 1, 1], &kernel, args.as_ptr());
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [3, 1, 1],
+            program.into(),
+            args.as_ptr(),
+            MAX_LDS,
+        );
         wg.exec_waves().unwrap();
-        let w0 = wg.wave_state.get(&0).unwrap();
-        assert_eq!(w0.3.value, 0b100);
+        let w0 = &wg.waves[0];
+        assert_eq!(w0.vcc.value, 0b100);
     }
 
     #[test]
     fn test_wave_value_state_exec() {
-        assert!(*CI, "NOTE: this tests needs CI=1");
         let kernel = vec![
             0xBEFE00FF,
             0b11111111111111111111111111111111,
@@ -238,15 +775,22 @@ mod test_workgroup {
             END_PRG,
         ];
         let args = vec![];
-        let mut wg = WorkGroup::new(1, [0, 0, 0], [4, 1, 1], &kernel, args.as_ptr());
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [4, 1, 1],
+            program.into(),
+            args.as_ptr(),
+            MAX_LDS,
+        );
         wg.exec_waves().unwrap();
-        let w0 = wg.wave_state.get(&0).unwrap();
-        assert_eq!(w0.4.value, 0b0111);
+        let w0 = &wg.waves[0];
+        assert_eq!(w0.exec.value, 0b0111);
     }
 
     #[test]
     fn test_wave_value_sgpr_co() {
-        assert!(*CI, "NOTE: this tests needs CI=1");
         let kernel = vec![
             0xBE8D00FF,
             0x7FFFFFFF,
@@ -257,9 +801,565 @@ mod test_workgroup {
             END_PRG,
         ];
         let args = vec![];
-        let mut wg = WorkGroup::new(1, [0, 0, 0], [5, 1, 1], &kernel, args.as_ptr());
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [5, 1, 1],
+            program.into(),
+            args.as_ptr(),
+            MAX_LDS,
+        );
+        wg.exec_waves().unwrap();
+        let w0 = &wg.waves[0];
+        assert_eq!(w0.scalar_reg[13], 0b11110);
+    }
+
+    #[test]
+    fn test_inactive_lanes_skipped() {
+        let kernel = vec![
+            0xBEFE0085, // s_mov_b32 exec_lo, 5
+            0x7C940100, // v_cmp_eq_u32 vcc, v0, v0
+            0xBE8A006A, // s_mov_b32 s10, vcc_lo
+            0x7E0202FF, // v_mov_b32 v1, 0x2a
+            0x0000002A, 0xBEFE0080, // s_mov_b32 exec_lo, 0
+            0x7C940100, // v_cmp_eq_u32 vcc, v0, v0
+            END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [4, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.exec_waves().unwrap();
+        let w0 = &wg.waves[0];
+        assert_eq!(w0.scalar_reg[10], 0b101);
+        assert_eq!(w0.vcc.value, 0);
+        let v1 = (0..4)
+            .map(|l| w0.vec_reg.get_lane(l)[1])
+            .collect::<Vec<_>>();
+        assert_eq!(v1, vec![42, 0, 42, 0]);
+    }
+
+    #[test]
+    fn test_exec_zero() {
+        let kernel = vec![
+            0xBE8800AA, // s_mov_b32 s8, 42
+            0xBE8C00C1, // s_mov_b32 s12, -1
+            0xBEFE0080, // s_mov_b32 exec_lo, 0
+            0xD7610004, // v_writelane_b32 v4, s8, 1
+            0x00010208, 0xD760000D, // v_readlane_b32 s13, v4, 1
+            0x00010304, 0xD7000C05, // v_add_co_u32 v5, s12, v0, v0
+            0x00020100, END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [4, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.exec_waves().unwrap();
+        let w0 = &wg.waves[0];
+        assert_eq!(w0.vec_reg.get_lane(1)[4], 42);
+        assert_eq!(w0.scalar_reg[13], 42);
+        assert_eq!(w0.scalar_reg[12], 0);
+    }
+
+    #[test]
+    fn test_barrier_exchange() {
+        let kernel = vec![
+            0x30020082, // v_lshlrev_b32 v1, 2, v0
+            0xD8340000, // ds_store_b32 v1, v0
+            0x00000001, S_BARRIER, 0x3A0402FF, // v_xor_b32 v2, 0x80, v1
+            0x00000080, 0xD8D80000, // ds_load_b32 v3, v2
+            0x03000002, S_BARRIER, 0xD8340000, // ds_store_b32 v1, v3
+            0x00000301, S_BARRIER, 0xD8D80000, // ds_load_b32 v4, v2
+            0x04000002, END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [64, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.exec_waves().unwrap();
+        for (wave_id, wave) in wg.waves.iter().enumerate() {
+            for lane in 0..32 {
+                let tid = (wave_id * 32 + lane) as u32;
+                assert_eq!(wave.vec_reg.get_lane(lane)[3], tid ^ 32);
+                assert_eq!(wave.vec_reg.get_lane(lane)[4], tid);
+            }
+        }
+    }
+
+    #[test]
+    fn test_barrier_in_loop() {
+        let kernel = vec![
+            0x30020082, // v_lshlrev_b32 v1, 2, v0
+            0x3A0402FF, // v_xor_b32 v2, 0x80, v1
+            0x00000080, 0xBE840080, // s_mov_b32 s4, 0
+            0x4A0A0004, // loop: v_add_nc_u32 v5, s4, v0
+            0xD8340000, // ds_store_b32 v1, v5
+            0x00000501, S_BARRIER, 0xD8D80000, // ds_load_b32 v3, v2
+            0x03000002, S_BARRIER, 0x80048104, // s_add_u32 s4, s4, 1
+            0xBF0A8304, // s_cmp_lt_u32 s4, 3
+            0xBFA2FFF6, // s_cbranch_scc1 loop
+            END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [64, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.exec_waves().unwrap();
+        for (wave_id, wave) in wg.waves.iter().enumerate() {
+            assert_eq!(wave.scalar_reg[4], 3);
+            for lane in 0..32 {
+                let tid = (wave_id * 32 + lane) as u32;
+                assert_eq!(wave.vec_reg.get_lane(lane)[3], (tid ^ 32) + 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_trap_without_handler_faults() {
+        // s_trap 2
+        let kernel = vec![0xBF900002, END_PRG];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [1, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        assert_eq!(wg.exec_waves(), Err(FAULT));
+    }
+
+    #[test]
+    fn test_trap_handler_returns() {
+        let kernel = vec![
+            0xBF900003, // s_trap 3
+            0xBE860087, // s_mov_b32 s6, 7
+            END_PRG, 0xBE85006D, // trap_handler: s_mov_b32 s5, ttmp1
+            0xBE804A6C, // s_rfe_b64 ttmp[0:1]
+        ];
+        let program = Program::new(kernel, Some(3));
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [1, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.exec_waves().unwrap();
+        let w0 = &wg.waves[0];
+        assert_eq!(w0.scalar_reg[5], 3 << 16);
+        assert_eq!(w0.scalar_reg[TTMP], 4);
+        assert_eq!(w0.scalar_reg[6], 7);
+    }
+
+    #[test]
+    fn test_illegal_instruction() {
+        let kernel = vec![0xFC000000, END_PRG];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [1, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        assert_eq!(wg.exec_waves(), Err(FAULT));
+        assert!(matches!(
+            wg.error,
+            Some(RemuError::Decode(Location {
+                word: 0xFC000000,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_allocation_bounds() {
+        let kernel = vec![
+            0xF4040080, // s_load_b64 s[2:3], s[0:1], null
+            0xF8000000, 0xBF89FC07, // s_waitcnt lgkmcnt(0)
+            0x7E020281, // v_mov_b32 v1, 1
+            0x30040082, // v_lshlrev_b32 v2, 2, v0
+            0xDC6A0000, // global_store_b32 v2, v1, s[2:3]
+            0x00020102, END_PRG,
+        ];
+        let program = Arc::new(Program::new(kernel, None));
+        let mut out = [0u32; 2];
+        let args = [out.as_mut_ptr() as u64];
+        let buffer = Allocation {
+            base: out.as_ptr() as u64,
+            size: 4,
+            perms: READ | WRITE,
+        };
+        let run = |allocations: Allocations| {
+            let mut wg = WorkGroup::new(
+                1,
+                [0, 0, 0],
+                [2, 1, 1],
+                program.clone(),
+                args.as_ptr(),
+                MAX_LDS,
+            );
+            wg.memory = Arc::new(Raw {
+                allocations: Some(Arc::new(allocations)),
+            });
+            (wg.exec_waves(), wg.error.map(|e| e.to_string()))
+        };
+        // the kernarg buffer isn't registered
+        let mut allocations = Allocations::default();
+        allocations.insert(buffer).unwrap();
+        let (ret, err) = run(allocations.clone());
+        assert_eq!(ret, Err(FAULT));
+        let err = err.unwrap();
+        assert!(err.starts_with(&format!(
+            "memory violation at 0x{:x} by s_load_b64",
+            args.as_ptr() as u64
+        )));
+        // lane 1 stores one past the end
+        allocations
+            .insert(Allocation {
+                base: args.as_ptr() as u64,
+                size: 8,
+                perms: READ,
+            })
+            .unwrap();
+        let (ret, err) = run(allocations);
+        assert_eq!(ret, Err(FAULT));
+        assert!(err
+            .unwrap()
+            .ends_with(&format!("lane 1, offset 4 of allocation {buffer}")));
+        assert_eq!(out, [1, 0]);
+    }
+
+    #[test]
+    fn test_malformed_kernels() {
+        let run = |kernel: Vec<u32>| {
+            let program = Program::new(kernel, None);
+            let mut wg = WorkGroup::new(
+                1,
+                [0, 0, 0],
+                [1, 1, 1],
+                program.into(),
+                std::ptr::null(),
+                MAX_LDS,
+            );
+            (wg.exec_waves(), wg.error.unwrap().to_string())
+        };
+        // s_mov_b32 s0 from a reserved source operand
+        let (ret, err) = run(vec![0xBE8000E0, END_PRG]);
+        assert_eq!(ret, Err(FAULT));
+        assert!(err.starts_with("illegal instruction s_mov_b32 (0xBE8000E0) at pc=0x0"));
+        // no s_endpgm, and a VOP3 cut off by the end of the kernel
+        let (ret, err) = run(vec![0x7E020281]);
+        assert_eq!(ret, Err(FAULT));
+        assert!(err.contains("at pc=0x4"));
+        let (ret, err) = run(vec![0x7E020281, 0xD5030000]);
+        assert_eq!(ret, Err(FAULT));
+        assert!(err.contains("(0xD5030000) at pc=0x4"));
+        // v_add_f32_e64 v0, v1, v2 mul:2
+        let (ret, err) = run(vec![0xD5030000, 0x08020501, END_PRG]);
+        assert_eq!(ret, Err(1));
+        assert!(err.starts_with("unimplemented instruction v_add_f32_e64"));
+    }
+
+    #[test]
+    fn test_panic_location() {
+        let program = Program::new(vec![0x7E020281, END_PRG], None);
+        let mut wg = WorkGroup::new(
+            1,
+            [1, 0, 0],
+            [1, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        let err = wg.panicked("boom".into());
+        assert_eq!(err.to_string(), "panic: boom");
+        wg.running = Some((0, 1));
+        let err = wg.panicked("boom".into());
+        assert_eq!(
+            err.to_string(),
+            "panic: boom at s_endpgm (0xBFB00000) at pc=0x4 workgroup [1, 0, 0] wave 0"
+        );
+    }
+
+    #[test]
+    fn test_float_exception_trap() {
+        // v_rcp_f32 v1, v0 with v0 = 0 in lane 0
+        let kernel = vec![0x7E025500, END_PRG];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [2, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.exec_waves().unwrap();
+        // s_setreg_imm32_b32 hwreg(HW_REG_MODE, 12, 7), EXCP_DIV0
+        let kernel = vec![0xB9803301, 0x4, 0x7E025500, END_PRG];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [2, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        assert_eq!(wg.exec_waves(), Err(FAULT));
+        assert_eq!(
+            wg.error.unwrap().to_string(),
+            "floating point exception (divide by zero) without a trap handler by v_rcp_f32 (0x7E025500) at pc=0x8 workgroup [0, 0, 0] wave 0 lane 0"
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        // loop: s_branch loop
+        let kernel = vec![0xBFA0FFFF, END_PRG];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [1, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.deadline = Some(Instant::now() + std::time::Duration::from_millis(10));
+        assert_eq!(wg.exec_waves(), Err(TIMED_OUT));
+        assert!(matches!(
+            wg.error,
+            Some(RemuError::Timeout(Location { pc: 0, .. }))
+        ));
+    }
+
+    /* every lane stores its v0 to lds[0] and reads it back without a barrier */
+    #[test]
+    fn test_watch_lds_store() {
+        let kernel = vec![
+            0x7E020280, // v_mov_b32 v1, 0
+            0xD8340000, // ds_store_b32 v1, v0
+            0x00000001, END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [64, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        let mut lds = Watchpoint::parse("lds:0").unwrap();
+        lds.stop = true;
+        wg.watcher = Watcher::new(vec![lds]);
+        wg.schedule = Schedule::Wave;
+        wg.init_waves();
+        assert_eq!(wg.resume(), Ok(Some(0)));
+        let hit = wg.watch_hit.clone().unwrap();
+        assert_eq!((hit.wave, hit.lane, hit.pc), (0, Some(0), 4));
+        assert_eq!(wg.waves[0].pc, 3);
+        assert_eq!(wg.lds.read(0), 31);
+        assert_eq!(wg.resume(), Ok(Some(1)));
+        assert_eq!(wg.watch_hit.as_ref().unwrap().old, 31u32.to_le_bytes());
+        assert_eq!(wg.resume(), Ok(None));
+        assert_eq!(wg.lds.read(0), 63);
+    }
+
+    #[test]
+    fn test_uninit() {
+        let kernel = vec![
+            0x7E020280, // v_mov_b32 v1, 0
+            0xD8340000, // ds_store_b32 v1, v0
+            0x00000001, 0xD8D80004, // ds_load_b32 v4, v1 offset:4
+            0x04000001, 0x4A040704, // v_add_nc_u32 v2, v4, v3
+            0xD8D80000, // ds_load_b32 v5, v1
+            0x05000001, 0x7E0C0305, // v_mov_b32 v6, v5
+            0x80060005, // s_add_u32 s6, s5, s0
+            END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [2, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.uninit = Some(vec![]);
+        wg.exec_waves().unwrap();
+        let found = wg
+            .uninit
+            .unwrap()
+            .iter()
+            .map(|u| u.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                "pc=0x14 reads undefined v4 lane 0 in an ALU op",
+                "pc=0x14 reads undefined v3 lane 0 in an ALU op",
+                "pc=0x24 reads undefined s5 in an ALU op",
+            ]
+        );
+    }
+
+    fn lds_race(schedule: Schedule) -> Vec<u32> {
+        let kernel = vec![
+            0x7E020280, // v_mov_b32 v1, 0
+            0xD8340000, // ds_store_b32 v1, v0
+            0x00000001, 0xD8D80000, // ds_load_b32 v4, v1
+            0x04000001, END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [64, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.schedule = schedule;
         wg.exec_waves().unwrap();
-        let w0 = wg.wave_state.get(&0).unwrap();
-        assert_eq!(w0.0[13], 0b11110);
+        wg.waves.iter().map(|w| w.vec_reg.get_lane(0)[4]).collect()
+    }
+
+    #[test]
+    fn test_schedule_interleaving() {
+        assert_eq!(lds_race(Schedule::Wave), vec![31, 63]);
+        assert_eq!(lds_race(Schedule::Reverse), vec![31, 63]);
+        // wave 1 stores between the store and load of wave 0
+        assert_eq!(lds_race(Schedule::RoundRobin), vec![63, 63]);
+    }
+
+    #[test]
+    fn test_random_schedule_reproducible() {
+        for seed in 0..8 {
+            let ret = lds_race(Schedule::Random(seed));
+            assert_eq!(ret, lds_race(Schedule::Random(seed)));
+            assert!(ret.iter().all(|v| *v == 31 || *v == 63));
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let kernel = vec![
+            0x7E020280, // v_mov_b32 v1, 0
+            0xD8340000, // ds_store_b32 v1, v0
+            0x00000001, 0xBE850087, // s_mov_b32 s5, 7
+            END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [2, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        let out = Arc::new(std::sync::Mutex::new(vec![]));
+        let filter = trace::Filter::parse("lane:1,pc:4-12").unwrap();
+        wg.trace = Some(Trace::new(out.clone(), false, filter));
+        wg.exec_waves().unwrap();
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines[0].contains(r#""pc":4,"word":3627286528,"mnemonic":"ds_store_b32","exec":3,"#)
+        );
+        // both lanes store to lds[0], the lane filter keeps lane 1
+        assert!(
+            lines[0].ends_with(r#""writes":[{"mem":"lds","lane":1,"addr":0,"data":"01000000"}]}"#)
+        );
+        assert!(lines[1].ends_with(r#""writes":[{"reg":"s5","value":7}]}"#));
+    }
+
+    #[test]
+    fn test_perf_barrier() {
+        let kernel = vec![
+            0xBF800000 | 9, // s_nop 9
+            S_BARRIER,
+            0x7E020281, // v_mov_b32 v1, 1
+            END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [64, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.perf = true;
+        wg.init_waves();
+        // wave 0 is 40 cycles behind, wave 1 waits for it at the barrier
+        wg.waves[0].perf.cycle = 40;
+        assert_eq!(wg.resume(), Ok(None));
+        let cycles = wg.waves.iter().map(|w| w.perf.cycle).collect::<Vec<_>>();
+        assert_eq!(cycles, [51, 51]);
+    }
+
+    #[test]
+    fn test_coverage_skips_unimplemented() {
+        let kernel = vec![
+            0xBE850801, // s_ctz_i32_b32 s5, s1
+            0xBE860087, // s_mov_b32 s6, 7
+            END_PRG,
+        ];
+        let program = Program::new(kernel, None);
+        let mut wg = WorkGroup::new(
+            1,
+            [0, 0, 0],
+            [2, 1, 1],
+            program.into(),
+            std::ptr::null(),
+            MAX_LDS,
+        );
+        wg.coverage = Some(Coverage::default());
+        assert_eq!(wg.exec_waves(), Err(1));
+        assert_eq!(wg.waves[0].scalar_reg[6], 7);
+        let at = Location {
+            pc: 0,
+            word: 0xBE850801,
+            workgroup: Some([0, 0, 0]),
+            wave: Some(0),
+            lane: None,
+        };
+        assert_eq!(wg.error, Some(RemuError::Unimplemented(at)));
+        let coverage = wg.coverage.unwrap();
+        assert_eq!(coverage.missing(), ["s_ctz_i32_b32"]);
+        let row = &coverage.rows[&("sop1".to_string(), 8)];
+        assert_eq!((row.executed, row.unimplemented), (1, 1));
     }
 }